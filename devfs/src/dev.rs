@@ -1,7 +1,8 @@
 use alloc::{string::String, sync::Arc, vec::Vec};
+use core::task::Waker;
 
 use unifs::{
-    inode::{basic_file_stat, UniFsInodeSame},
+    inode::{basic_file_stat, basic_set_attr, UniFsInodeSame},
     *,
 };
 use vfscore::{
@@ -64,6 +65,9 @@ impl<T: DevKernelProvider + 'static, R: VfsRawMutex + 'static> VfsFile for DevFs
     fn poll(&self, event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
         self.real_dev()?.poll(event)
     }
+    fn poll_wait(&self, event: VfsPollEvents, waker: &Waker) -> VfsResult<VfsPollEvents> {
+        self.real_dev()?.poll_wait(event, waker)
+    }
 
     fn ioctl(&self, _cmd: u32, _arg: usize) -> VfsResult<usize> {
         self.real_dev()?.ioctl(_cmd, _arg)
@@ -84,11 +88,17 @@ impl<T: DevKernelProvider + 'static, R: VfsRawMutex + 'static> VfsInode for DevF
     }
 
     fn node_perm(&self) -> VfsNodePerm {
-        self.basic.inner.lock().perm
+        self.basic.inner.lock().perm()
     }
 
-    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
-        todo!()
+    fn set_attr(&self, attr: InodeAttr) -> VfsResult<()> {
+        basic_set_attr(
+            &self.basic,
+            self.ty,
+            &attr,
+            self.basic.provider.current_time(),
+        );
+        Ok(())
     }
 
     fn get_attr(&self) -> VfsResult<VfsFileStat> {
@@ -96,11 +106,9 @@ impl<T: DevKernelProvider + 'static, R: VfsRawMutex + 'static> VfsInode for DevF
         let mut attr = basic_file_stat(&self.basic);
         attr.st_size = self.real_dev()?.get_attr()?.st_size;
         attr.st_blksize = self.real_dev()?.get_attr()?.st_blksize;
-        attr.st_mode = VfsInodeMode::from(
-            VfsNodePerm::from_bits_truncate(attr.st_mode as u16),
-            self.ty,
-        )
-        .bits();
+        attr.st_mode = VfsInodeMode::from_bits_truncate(attr.st_mode)
+            .with_type(self.ty)
+            .bits();
         Ok(attr)
     }
 
@@ -126,4 +134,8 @@ impl<T: DevKernelProvider + 'static, R: VfsRawMutex + 'static> VfsInode for DevF
         self.basic.inner.lock().ctime = now;
         Ok(())
     }
+    fn set_atime(&self, atime: VfsTimeSpec) -> VfsResult<()> {
+        self.basic.inner.lock().atime = atime;
+        Ok(())
+    }
 }
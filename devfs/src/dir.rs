@@ -127,18 +127,16 @@ impl<T: DevKernelProvider + 'static, R: VfsRawMutex + 'static> VfsInode for DevF
     }
 
     fn set_attr(&self, attr: InodeAttr) -> VfsResult<()> {
-        self.0.set_attr(attr)
+        self.0.set_attr(attr, self.0.basic.provider.current_time())
     }
 
     impl_dir_inode_default!();
 
     fn get_attr(&self) -> VfsResult<VfsFileStat> {
         self.0.get_attr().map(|mut attr| {
-            attr.st_mode = VfsInodeMode::from(
-                VfsNodePerm::from_bits_truncate(attr.st_mode as u16),
-                VfsNodeType::Dir,
-            )
-            .bits();
+            attr.st_mode = VfsInodeMode::from_bits_truncate(attr.st_mode)
+                .with_type(VfsNodeType::Dir)
+                .bits();
             attr
         })
     }
@@ -167,4 +165,7 @@ impl<T: DevKernelProvider + 'static, R: VfsRawMutex + 'static> VfsInode for DevF
     fn update_time(&self, time: VfsTime, now: VfsTimeSpec) -> VfsResult<()> {
         self.0.update_time(time, now)
     }
+    fn set_atime(&self, atime: VfsTimeSpec) -> VfsResult<()> {
+        self.0.set_atime(atime)
+    }
 }
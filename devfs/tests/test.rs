@@ -1,11 +1,131 @@
-#[test]
-fn test_link() {}
-
-#[test]
-fn test_symlink() {}
-
-#[test]
-fn test_unlink() {}
-
-#[test]
-fn test_rename() {}
+#[test]
+fn test_link() {}
+
+#[test]
+fn test_symlink() {}
+
+#[test]
+fn test_unlink() {}
+
+#[test]
+fn test_rename() {}
+
+mod poll {
+    use std::{
+        collections::VecDeque,
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+    };
+
+    use devfs::{DevFs, DevKernelProvider};
+    use spin::Mutex;
+    use vfscore::{
+        file::VfsFile,
+        fstype::VfsFsType,
+        inode::VfsInode,
+        poll::{waker_fn, VfsWaitQueue},
+        utils::{VfsNodePerm, VfsNodeType, VfsPollEvents, VfsTimeSpec},
+        VfsResult,
+    };
+
+    /// A character device which is readable when it has input
+    #[derive(Default)]
+    struct InputDev {
+        inner: Mutex<(VecDeque<u8>, VfsWaitQueue)>,
+    }
+
+    impl InputDev {
+        fn push(&self, byte: u8) {
+            let mut inner = self.inner.lock();
+            inner.0.push_back(byte);
+            inner.1.wake(VfsPollEvents::IN);
+        }
+    }
+
+    impl VfsFile for InputDev {
+        fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+            let mut inner = self.inner.lock();
+            let len = buf.len().min(inner.0.len());
+            for (dst, src) in buf.iter_mut().zip(inner.0.drain(..len)) {
+                *dst = src;
+            }
+            Ok(len)
+        }
+        fn poll(&self, event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
+            let inner = self.inner.lock();
+            let mut res = event & VfsPollEvents::OUT;
+            if !inner.0.is_empty() {
+                res |= event & VfsPollEvents::IN;
+            }
+            Ok(res)
+        }
+        fn poll_wait(
+            &self,
+            event: VfsPollEvents,
+            waker: &std::task::Waker,
+        ) -> VfsResult<VfsPollEvents> {
+            self.inner.lock().1.register(event, waker);
+            let res = self.poll(event)?;
+            if !res.is_empty() {
+                self.inner.lock().1.remove(waker);
+            }
+            Ok(res)
+        }
+    }
+
+    impl VfsInode for InputDev {
+        fn inode_type(&self) -> VfsNodeType {
+            VfsNodeType::CharDevice
+        }
+    }
+
+    #[derive(Clone)]
+    struct Provider(Arc<InputDev>);
+
+    impl DevKernelProvider for Provider {
+        fn current_time(&self) -> VfsTimeSpec {
+            VfsTimeSpec::new(0, 0)
+        }
+        fn rdev2device(&self, rdev: u64) -> Option<Arc<dyn VfsInode>> {
+            (rdev == 1).then(|| self.0.clone() as Arc<dyn VfsInode>)
+        }
+    }
+
+    #[test]
+    fn test_poll_wait() {
+        let dev = Arc::new(InputDev::default());
+        let devfs: Arc<dyn VfsFsType> = Arc::new(DevFs::<_, Mutex<()>>::new(Provider(dev.clone())));
+        let root = devfs.clone().mount(0, "/dev", None, &[]).unwrap();
+        let input = root
+            .inode()
+            .unwrap()
+            .create(
+                "input",
+                VfsNodeType::CharDevice,
+                VfsNodePerm::from_bits_truncate(0o666),
+                Some(1),
+            )
+            .unwrap();
+
+        let woken = Arc::new(AtomicBool::new(false));
+        let flag = woken.clone();
+        let waker = waker_fn(move || flag.store(true, Ordering::SeqCst));
+        assert!(input
+            .poll_wait(VfsPollEvents::IN, &waker)
+            .unwrap()
+            .is_empty());
+        assert!(!woken.load(Ordering::SeqCst));
+
+        dev.push(b'x');
+        assert!(woken.load(Ordering::SeqCst));
+        assert_eq!(
+            input.poll_wait(VfsPollEvents::IN, &waker).unwrap(),
+            VfsPollEvents::IN
+        );
+        let mut buf = [0; 4];
+        assert_eq!(input.read_at(0, &mut buf).unwrap(), 1);
+        assert_eq!(buf[0], b'x');
+    }
+}
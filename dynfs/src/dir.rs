@@ -89,9 +89,8 @@ impl<T: DynFsKernelProvider + 'static, R: VfsRawMutex + 'static> DynFsDirInode<T
             .ok_or(VfsError::NoEntry)?;
         let (_, inode_number) = children.remove(index);
         let sb = self.0.basic.sb.upgrade().unwrap();
+        drop(children);
         sb.remove_inode(inode_number);
-        sb.inode_count
-            .fetch_sub(1, core::sync::atomic::Ordering::SeqCst);
         Ok(())
     }
 }
@@ -143,16 +142,14 @@ impl<T: DynFsKernelProvider + 'static, R: VfsRawMutex + 'static> VfsInode for Dy
     impl_dir_inode_default!();
 
     fn set_attr(&self, attr: InodeAttr) -> VfsResult<()> {
-        self.0.set_attr(attr)
+        self.0.set_attr(attr, self.0.basic.provider.current_time())
     }
 
     fn get_attr(&self) -> VfsResult<VfsFileStat> {
         self.0.get_attr().map(|mut attr| {
-            attr.st_mode = VfsInodeMode::from(
-                VfsNodePerm::from_bits_truncate(attr.st_mode as u16),
-                VfsNodeType::Dir,
-            )
-            .bits();
+            attr.st_mode = VfsInodeMode::from_bits_truncate(attr.st_mode)
+                .with_type(VfsNodeType::Dir)
+                .bits();
             attr
         })
     }
@@ -178,4 +175,7 @@ impl<T: DynFsKernelProvider + 'static, R: VfsRawMutex + 'static> VfsInode for Dy
     fn update_time(&self, time: VfsTime, now: VfsTimeSpec) -> VfsResult<()> {
         self.0.update_time(time, now)
     }
+    fn set_atime(&self, atime: VfsTimeSpec) -> VfsResult<()> {
+        self.0.set_atime(atime)
+    }
 }
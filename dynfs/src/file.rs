@@ -1,7 +1,8 @@
 use alloc::{string::String, sync::Arc, vec::Vec};
+use core::task::Waker;
 
 use unifs::{
-    inode::{basic_file_stat, UniFsInodeSame},
+    inode::{basic_file_stat, basic_set_attr, UniFsInodeSame},
     *,
 };
 use vfscore::{
@@ -52,6 +53,9 @@ impl<T: DynFsKernelProvider + 'static, R: VfsRawMutex + 'static> VfsFile for Dyn
     fn poll(&self, event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
         self.real_inode()?.poll(event)
     }
+    fn poll_wait(&self, event: VfsPollEvents, waker: &Waker) -> VfsResult<VfsPollEvents> {
+        self.real_inode()?.poll_wait(event, waker)
+    }
 
     fn ioctl(&self, _cmd: u32, _arg: usize) -> VfsResult<usize> {
         self.real_inode()?.ioctl(_cmd, _arg)
@@ -72,10 +76,16 @@ impl<T: DynFsKernelProvider + 'static, R: VfsRawMutex + 'static> VfsInode for Dy
     }
 
     fn node_perm(&self) -> VfsNodePerm {
-        self.basic.inner.lock().perm
+        self.basic.inner.lock().perm()
     }
 
-    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+    fn set_attr(&self, attr: InodeAttr) -> VfsResult<()> {
+        basic_set_attr(
+            &self.basic,
+            VfsNodeType::File,
+            &attr,
+            self.basic.provider.current_time(),
+        );
         Ok(())
     }
 
@@ -83,11 +93,9 @@ impl<T: DynFsKernelProvider + 'static, R: VfsRawMutex + 'static> VfsInode for Dy
         let mut attr = basic_file_stat(&self.basic);
         let real_attr = self.real_inode()?.get_attr()?;
         attr.st_size = real_attr.st_size;
-        attr.st_mode = VfsInodeMode::from(
-            VfsNodePerm::from_bits_truncate(attr.st_mode as u16),
-            VfsNodeType::File,
-        )
-        .bits();
+        attr.st_mode = VfsInodeMode::from_bits_truncate(attr.st_mode)
+            .with_type(VfsNodeType::File)
+            .bits();
         Ok(attr)
     }
 
@@ -107,4 +115,7 @@ impl<T: DynFsKernelProvider + 'static, R: VfsRawMutex + 'static> VfsInode for Dy
     fn update_time(&self, time: VfsTime, now: VfsTimeSpec) -> VfsResult<()> {
         self.real_inode()?.update_time(time, now)
     }
+    fn set_atime(&self, atime: VfsTimeSpec) -> VfsResult<()> {
+        self.real_inode()?.set_atime(atime)
+    }
 }
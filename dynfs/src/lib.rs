@@ -16,7 +16,7 @@ use vfscore::{
     fstype::{FileSystemFlags, VfsFsType},
     inode::VfsInode,
     superblock::VfsSuperBlock,
-    utils::{VfsNodePerm, VfsTimeSpec},
+    utils::{VfsInodeMode, VfsNodePerm, VfsStatxAttr, VfsTimeSpec},
     VfsResult,
 };
 
@@ -96,10 +96,14 @@ impl<T: DynFsKernelProvider + 'static, R: VfsRawMutex + 'static> UniInodeSameNew
             provider,
             inner: lock_api::Mutex::new(UniFsInodeAttr {
                 link_count: 1,
+                uid: 0,
+                gid: 0,
                 atime: time,
                 mtime: time,
                 ctime: time,
-                perm,
+                btime: time,
+                mode: VfsInodeMode::from_bits_truncate(perm.bits() as u32),
+                flags: VfsStatxAttr::empty(),
             }),
         }
     }
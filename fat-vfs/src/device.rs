@@ -1,30 +1,59 @@
 use alloc::sync::Arc;
 
 use fatfs::*;
-use vfscore::inode::VfsInode;
+use lock_api::Mutex;
+use vfscore::{cache::BufferCache, inode::VfsInode, VfsResult};
 
-#[derive(Clone)]
-pub struct FatDevice {
+use crate::VfsRawMutex;
+
+pub struct FatDevice<R: VfsRawMutex> {
     pub pos: i64,
     pub device_file: Arc<dyn VfsInode>,
+    cache: Arc<Mutex<R, BufferCache>>,
 }
 
-impl FatDevice {
-    pub fn new(device: Arc<dyn VfsInode>) -> Self {
+impl<R: VfsRawMutex> Clone for FatDevice<R> {
+    fn clone(&self) -> Self {
         Self {
+            pos: self.pos,
+            device_file: self.device_file.clone(),
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<R: VfsRawMutex> FatDevice<R> {
+    pub fn new(device: Arc<dyn VfsInode>, cache_blocks: usize) -> VfsResult<Self> {
+        let stat = device.get_attr()?;
+        let blk_size = match stat.st_blksize {
+            0 => 512,
+            blk_size => blk_size as usize,
+        };
+        let block_count = stat.st_size.div_ceil(blk_size as u64);
+        let cache = BufferCache::new(device.clone(), blk_size, block_count, cache_blocks);
+        Ok(Self {
             pos: 0,
             device_file: device,
-        }
+            cache: Arc::new(Mutex::new(cache)),
+        })
+    }
+
+    /// Write the cached blocks back and sync the device
+    pub fn sync(&self) -> VfsResult<()> {
+        self.cache.lock().flush()?;
+        self.device_file.flush()?;
+        self.device_file.fsync()
     }
 }
 
-impl IoBase for FatDevice {
+impl<R: VfsRawMutex> IoBase for FatDevice<R> {
     type Error = ();
 }
-impl Write for FatDevice {
+impl<R: VfsRawMutex> Write for FatDevice<R> {
     fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
         let len = self
-            .device_file
+            .cache
+            .lock()
             .write_at(self.pos as u64, buf)
             .map_err(|_| ())?;
         self.pos += len as i64;
@@ -32,14 +61,16 @@ impl Write for FatDevice {
     }
 
     fn flush(&mut self) -> Result<(), Self::Error> {
+        self.cache.lock().flush().map_err(|_| ())?;
         self.device_file.flush().map_err(|_| ())
     }
 }
 
-impl Read for FatDevice {
+impl<R: VfsRawMutex> Read for FatDevice<R> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
         let len = self
-            .device_file
+            .cache
+            .lock()
             .read_at(self.pos as u64, buf)
             .map_err(|_| ())?;
         self.pos += len as i64;
@@ -47,7 +78,7 @@ impl Read for FatDevice {
     }
 }
 
-impl Seek for FatDevice {
+impl<R: VfsRawMutex> Seek for FatDevice<R> {
     fn seek(&mut self, pos: SeekFrom) -> Result<u64, Self::Error> {
         let pos = match pos {
             SeekFrom::Start(pos) => pos as i64,
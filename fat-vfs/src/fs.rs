@@ -8,6 +8,7 @@ use fatfs::FileSystem;
 use log::info;
 use unifs::dentry::UniFsDentry;
 use vfscore::{
+    cache::DEFAULT_CACHE_BLOCKS,
     dentry::VfsDentry,
     error::VfsError,
     fstype::{FileSystemFlags, VfsFsType},
@@ -24,6 +25,7 @@ pub struct FatFs<T: Send + Sync, R: VfsRawMutex> {
     #[allow(unused)]
     provider: T,
     fs_container: Mutex<R, BTreeMap<usize, Arc<FatFsSuperBlock<R>>>>,
+    cache_blocks: usize,
 }
 
 impl<T: Send + Sync, R: VfsRawMutex> FatFs<T, R> {
@@ -31,8 +33,15 @@ impl<T: Send + Sync, R: VfsRawMutex> FatFs<T, R> {
         Self {
             provider,
             fs_container: Mutex::new(BTreeMap::new()),
+            cache_blocks: DEFAULT_CACHE_BLOCKS,
         }
     }
+
+    /// Set the number of blocks in the buffer cache of the devices mounted later
+    pub fn with_cache_blocks(mut self, blocks: usize) -> Self {
+        self.cache_blocks = blocks;
+        self
+    }
 }
 
 impl<T: FatFsProvider + 'static, R: VfsRawMutex + 'static> VfsFsType for FatFs<T, R> {
@@ -52,7 +61,7 @@ impl<T: FatFsProvider + 'static, R: VfsRawMutex + 'static> VfsFsType for FatFs<T
         if let Some(sb) = self.fs_container.lock().get(&(dev_ino as usize)) {
             return sb.root_dentry(ab_mnt);
         }
-        let fat_dev = FatDevice::new(dev);
+        let fat_dev = FatDevice::new(dev, self.cache_blocks)?;
         let sb = FatFsSuperBlock::<R>::new(&(self.clone() as Arc<dyn VfsFsType>), fat_dev, ab_mnt);
         // we use dev_ino as the key to store the superblock
         self.fs_container
@@ -67,8 +76,7 @@ impl<T: FatFsProvider + 'static, R: VfsRawMutex + 'static> VfsFsType for FatFs<T
             let sb = self.fs_container.lock().remove(&(dev_ino as usize));
             if let Some(sb) = sb {
                 // todo!(call unmount)
-                sb.fat_dev.device_file.flush()?;
-                sb.fat_dev.device_file.fsync()?;
+                sb.fat_dev.sync()?;
                 info!("fatfs: kill_sb: remove sb for dev {}", dev_ino);
                 Ok(())
             } else {
@@ -89,15 +97,15 @@ impl<T: FatFsProvider + 'static, R: VfsRawMutex + 'static> VfsFsType for FatFs<T
 }
 
 pub struct FatFsSuperBlock<R: VfsRawMutex> {
-    fat_dev: FatDevice,
+    fat_dev: FatDevice<R>,
     fs_type: Weak<dyn VfsFsType>,
     root: Mutex<R, Option<Arc<dyn VfsInode>>>,
-    fs: FileSystem<FatDevice, DefaultTimeProvider, LossyOemCpConverter>,
+    fs: FileSystem<FatDevice<R>, DefaultTimeProvider, LossyOemCpConverter>,
     mnt_info: Mutex<R, BTreeMap<String, Arc<dyn VfsDentry>>>,
 }
 
 impl<R: VfsRawMutex + 'static> FatFsSuperBlock<R> {
-    pub fn new(fs_type: &Arc<dyn VfsFsType>, device: FatDevice, ab_mnt: &str) -> Arc<Self> {
+    pub fn new(fs_type: &Arc<dyn VfsFsType>, device: FatDevice<R>, ab_mnt: &str) -> Arc<Self> {
         let fs = FileSystem::new(device.clone(), fatfs::FsOptions::new()).unwrap();
         let root_disk_dir = Arc::new(Mutex::new(fs.root_dir()));
         let sb = Arc::new(Self {
@@ -136,9 +144,7 @@ impl<R: VfsRawMutex + 'static> FatFsSuperBlock<R> {
 
 impl<R: VfsRawMutex + 'static> VfsSuperBlock for FatFsSuperBlock<R> {
     fn sync_fs(&self, _wait: bool) -> VfsResult<()> {
-        self.fat_dev.device_file.flush()?;
-        self.fat_dev.device_file.fsync()?;
-        Ok(())
+        self.fat_dev.sync()
     }
 
     fn stat_fs(&self) -> VfsResult<VfsFsStat> {
@@ -13,29 +13,30 @@ use vfscore::{
     inode::{InodeAttr, VfsInode},
     superblock::VfsSuperBlock,
     utils::{
-        VfsDirEntry, VfsFileStat, VfsInodeMode, VfsNodePerm, VfsNodeType, VfsRenameFlag, VfsTime,
+        VfsDirEntry, VfsFileStat, VfsInodeMode, VfsNodePerm, VfsNodeType, VfsRenameFlag, VfsStatx,
+        VfsStatxMask, VfsTime,
     },
     VfsResult,
 };
 
 use crate::{
     fs::FatFsSuperBlock,
-    inode::{FatFsFileInode, FatFsInodeSame},
+    inode::{entry_btime, fat_time, FatFsFileInode, FatFsInodeSame},
     *,
 };
 
 pub struct FatFsDirInode<R: VfsRawMutex> {
     #[allow(unused)]
-    parent: Weak<Mutex<R, FatDir>>,
-    dir: Arc<Mutex<R, FatDir>>,
+    parent: Weak<Mutex<R, FatDir<R>>>,
+    dir: Arc<Mutex<R, FatDir<R>>>,
     attr: FatFsInodeSame<R>,
     inode_cache: Mutex<R, BTreeMap<String, Arc<dyn VfsInode>>>,
 }
 
 impl<R: VfsRawMutex + 'static> FatFsDirInode<R> {
     pub fn new(
-        parent: &Arc<Mutex<R, FatDir>>,
-        dir: Arc<Mutex<R, FatDir>>,
+        parent: &Arc<Mutex<R, FatDir<R>>>,
+        dir: Arc<Mutex<R, FatDir<R>>>,
         sb: &Arc<FatFsSuperBlock<R>>,
         perm: VfsNodePerm,
     ) -> Self {
@@ -61,7 +62,7 @@ impl<R: VfsRawMutex + 'static> FatFsDirInode<R> {
             None
         };
         if ty == VfsNodeType::File {
-            let action = |file: &mut FatFile| -> VfsResult<()> {
+            let action = |file: &mut FatFile<R>| -> VfsResult<()> {
                 file.seek(fatfs::SeekFrom::Start(0))
                     .map_err(|_| VfsError::IoError)?;
                 file.truncate().map_err(|_| VfsError::IoError)?;
@@ -153,6 +154,9 @@ impl<R: VfsRawMutex + 'static> VfsInode for FatFsDirInode<R> {
 
                 let inode =
                     FatFsDirInode::new(&self.dir, new_dir, &self.attr.sb.upgrade().unwrap(), perm);
+                if let Some(btime) = entry_btime(&self.dir.lock(), name) {
+                    inode.attr.inner.lock().btime = btime;
+                }
                 let inode = Arc::new(inode);
                 inode_cache.insert(name.to_string(), inode.clone());
                 Ok(inode)
@@ -220,6 +224,7 @@ impl<R: VfsRawMutex + 'static> VfsInode for FatFsDirInode<R> {
                     &self.attr.sb.upgrade().unwrap(),
                     VfsNodePerm::default_dir(),
                 );
+                inode.attr.inner.lock().btime = fat_time(entry.created());
                 let inode = Arc::new(inode);
                 inode_cache.insert(name.to_string(), inode.clone());
                 return Ok(inode);
@@ -276,6 +281,10 @@ impl<R: VfsRawMutex + 'static> VfsInode for FatFsDirInode<R> {
         })
     }
 
+    fn get_statx(&self, _mask: VfsStatxMask) -> VfsResult<VfsStatx> {
+        Ok(self.attr.statx(self.get_attr()?))
+    }
+
     impl_dir_inode_default!();
 
     fn list_xattr(&self) -> VfsResult<Vec<String>> {
@@ -320,12 +329,23 @@ impl<R: VfsRawMutex + 'static> VfsInode for FatFsDirInode<R> {
         Ok(())
     }
     fn update_time(&self, time: VfsTime, now: VfsTimeSpec) -> VfsResult<()> {
-        let mut attr = self.attr.inner.lock();
         match time {
-            VfsTime::AccessTime(t) => attr.atime = t,
-            VfsTime::ModifiedTime(t) => attr.mtime = t,
+            VfsTime::AccessTime(t) => self.attr.set_times(Some(t), None, now),
+            VfsTime::ModifiedTime(t) => self.attr.set_times(None, Some(t), now),
         }
-        attr.ctime = now;
+        Ok(())
+    }
+    fn set_atime(&self, atime: VfsTimeSpec) -> VfsResult<()> {
+        self.attr.set_atime(atime);
+        Ok(())
+    }
+    fn set_times(
+        &self,
+        atime: Option<VfsTimeSpec>,
+        mtime: Option<VfsTimeSpec>,
+        now: VfsTimeSpec,
+    ) -> VfsResult<()> {
+        self.attr.set_times(atime, mtime, now);
         Ok(())
     }
 }
@@ -7,16 +7,23 @@ use vfscore::{
     impl_file_inode_default,
     inode::{InodeAttr, VfsInode},
     superblock::VfsSuperBlock,
-    utils::{VfsFileStat, VfsInodeMode, VfsNodePerm, VfsNodeType, VfsRenameFlag, VfsTime},
+    utils::{
+        VfsFileStat, VfsInodeMode, VfsNodePerm, VfsNodeType, VfsRenameFlag, VfsStatx, VfsStatxMask,
+        VfsTime,
+    },
     VfsResult,
 };
 
-use crate::{fs::FatFsSuperBlock, inode::FatFsInodeSame, *};
+use crate::{
+    fs::FatFsSuperBlock,
+    inode::{fat_time, FatFsInodeSame},
+    *,
+};
 
 pub struct FatFsFileInode<R: VfsRawMutex> {
     #[allow(unused)]
-    parent: Weak<Mutex<R, FatDir>>,
-    file: Arc<Mutex<R, FatFile>>,
+    parent: Weak<Mutex<R, FatDir<R>>>,
+    file: Arc<Mutex<R, FatFile<R>>>,
     attr: FatFsInodeSame<R>,
     #[allow(unused)]
     name: String,
@@ -28,30 +35,35 @@ where
     R: VfsRawMutex,
 {
     pub fn new(
-        parent: &Arc<Mutex<R, FatDir>>,
-        file: Arc<Mutex<R, FatFile>>,
+        parent: &Arc<Mutex<R, FatDir<R>>>,
+        file: Arc<Mutex<R, FatFile<R>>>,
         sb: &Arc<FatFsSuperBlock<R>>,
         name: String,
         perm: VfsNodePerm,
     ) -> Self {
-        let size = parent
+        let (size, btime) = parent
             .lock()
             .iter()
             .find(|x| {
                 x.as_ref()
                     .is_ok_and(|x| x.is_file() && x.file_name() == name)
             })
-            .map(|e| e.unwrap().len())
-            .unwrap_or(0);
+            .map(|e| {
+                let e = e.unwrap();
+                (e.len(), fat_time(e.created()))
+            })
+            .unwrap_or_default();
+        let attr = FatFsInodeSame::new(sb, perm);
+        attr.inner.lock().btime = btime;
         Self {
             name,
             parent: Arc::downgrade(parent),
             file,
-            attr: FatFsInodeSame::new(sb, perm),
+            attr,
             size: Mutex::new(size),
         }
     }
-    pub fn raw_file(&self) -> Arc<Mutex<R, FatFile>> {
+    pub fn raw_file(&self) -> Arc<Mutex<R, FatFile<R>>> {
         self.file.clone()
     }
 }
@@ -146,6 +158,9 @@ impl<R: VfsRawMutex + 'static> VfsInode for FatFsFileInode<R> {
             unused: 0,
         })
     }
+    fn get_statx(&self, _mask: VfsStatxMask) -> VfsResult<VfsStatx> {
+        Ok(self.attr.statx(self.get_attr()?))
+    }
     fn list_xattr(&self) -> VfsResult<Vec<String>> {
         Err(VfsError::NoSys)
     }
@@ -168,13 +183,37 @@ impl<R: VfsRawMutex + 'static> VfsInode for FatFsFileInode<R> {
         *this_len = len;
         Ok(())
     }
+    fn append(&self, buf: &[u8]) -> VfsResult<(u64, usize)> {
+        // the file is locked while the end is found and written
+        let mut file = self.file.lock();
+        let offset = *self.size.lock();
+        if buf.is_empty() {
+            return Ok((offset, 0));
+        }
+        file.seek(fatfs::SeekFrom::Start(offset))
+            .map_err(|_| VfsError::IoError)?;
+        file.write_all(buf).map_err(|_| VfsError::NoSpace)?;
+        *self.size.lock() = offset + buf.len() as u64;
+        Ok((offset, buf.len()))
+    }
     fn update_time(&self, time: VfsTime, now: VfsTimeSpec) -> VfsResult<()> {
-        let mut attr = self.attr.inner.lock();
         match time {
-            VfsTime::AccessTime(t) => attr.atime = t,
-            VfsTime::ModifiedTime(t) => attr.mtime = t,
+            VfsTime::AccessTime(t) => self.attr.set_times(Some(t), None, now),
+            VfsTime::ModifiedTime(t) => self.attr.set_times(None, Some(t), now),
         }
-        attr.ctime = now;
+        Ok(())
+    }
+    fn set_atime(&self, atime: VfsTimeSpec) -> VfsResult<()> {
+        self.attr.set_atime(atime);
+        Ok(())
+    }
+    fn set_times(
+        &self,
+        atime: Option<VfsTimeSpec>,
+        mtime: Option<VfsTimeSpec>,
+        now: VfsTimeSpec,
+    ) -> VfsResult<()> {
+        self.attr.set_times(atime, mtime, now);
         Ok(())
     }
 }
@@ -5,7 +5,7 @@ use alloc::sync::Weak;
 
 pub use dir::*;
 pub use file::*;
-use vfscore::utils::VfsNodePerm;
+use vfscore::utils::{VfsFileStat, VfsNodePerm, VfsStatx, VfsStatxMask};
 
 use crate::{fs::FatFsSuperBlock, *};
 
@@ -17,6 +17,8 @@ struct FatFsInodeAttr {
     pub atime: VfsTimeSpec,
     pub mtime: VfsTimeSpec,
     pub ctime: VfsTimeSpec,
+    /// The creation time in the directory entry
+    pub btime: VfsTimeSpec,
     pub perm: VfsNodePerm,
 }
 
@@ -28,8 +30,61 @@ impl<R: VfsRawMutex> FatFsInodeSame<R> {
                 atime: VfsTimeSpec::new(0, 0),
                 mtime: VfsTimeSpec::new(0, 0),
                 ctime: VfsTimeSpec::new(0, 0),
+                btime: VfsTimeSpec::new(0, 0),
                 perm,
             }),
         }
     }
+
+    /// Set the access and modification times, `None` is left unchanged
+    pub fn set_times(
+        &self,
+        atime: Option<VfsTimeSpec>,
+        mtime: Option<VfsTimeSpec>,
+        now: VfsTimeSpec,
+    ) {
+        let mut attr = self.inner.lock();
+        if let Some(atime) = atime {
+            attr.atime = atime;
+        }
+        if let Some(mtime) = mtime {
+            attr.mtime = mtime;
+        }
+        attr.ctime = now;
+    }
+
+    /// Set the access time without changing the ctime
+    pub fn set_atime(&self, atime: VfsTimeSpec) {
+        self.inner.lock().atime = atime;
+    }
+
+    /// Extend the `stat` of the inode with the creation time
+    pub fn statx(&self, stat: VfsFileStat) -> VfsStatx {
+        let mut stx = VfsStatx::from(stat);
+        stx.stx_mask |= VfsStatxMask::STATX_BTIME;
+        stx.stx_btime = self.inner.lock().btime;
+        stx
+    }
+}
+
+/// Get the creation time of the entry `name` in `dir`
+fn entry_btime<R: VfsRawMutex>(dir: &FatDir<R>, name: &str) -> Option<VfsTimeSpec> {
+    dir.iter()
+        .filter_map(|entry| entry.ok())
+        .find(|entry| entry.file_name() == name)
+        .map(|entry| fat_time(entry.created()))
+}
+
+/// Convert the date and time of FAT to the time since the Unix epoch
+fn fat_time(date_time: fatfs::DateTime) -> VfsTimeSpec {
+    let (date, time) = (date_time.date, date_time.time);
+    let (month, day) = (date.month as u64, date.day as u64);
+    // count the days from 0000-03-01, so the leap day is the last day of a year
+    let year = date.year as u64 - (month <= 2) as u64;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let days = year * 365 + year / 4 - year / 100 + year / 400 + day_of_year;
+    // 1970-01-01 is the 719468th day
+    let sec =
+        (days - 719468) * 86400 + time.hour as u64 * 3600 + time.min as u64 * 60 + time.sec as u64;
+    VfsTimeSpec::new(sec, time.millis as u64 * 1_000_000)
 }
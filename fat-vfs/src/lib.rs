@@ -49,5 +49,5 @@ impl<T: FatFsProvider> TimeProvider for TimeProviderImpl<T> {
     }
 }
 
-type FatDir = Dir<FatDevice, DefaultTimeProvider, LossyOemCpConverter>;
-type FatFile = File<FatDevice, DefaultTimeProvider, LossyOemCpConverter>;
+type FatDir<R> = Dir<FatDevice<R>, DefaultTimeProvider, LossyOemCpConverter>;
+type FatFile<R> = File<FatDevice<R>, DefaultTimeProvider, LossyOemCpConverter>;
@@ -1,18 +1,29 @@
 use alloc::sync::Arc;
 
+use lock_api::Mutex;
 use lwext4_rs::{BlockDeviceConfig, BlockDeviceInterface};
-use vfscore::{inode::VfsInode, VfsResult};
+use vfscore::{cache::BufferCache, inode::VfsInode, VfsResult};
 
-use crate::types::from_vfs;
+use crate::{types::from_vfs, VfsRawMutex};
 
-#[derive(Clone)]
-pub struct ExtDevice {
+pub struct ExtDevice<R: VfsRawMutex> {
     pub device_file: Arc<dyn VfsInode>,
     pub config: BlockDeviceConfig,
+    cache: Arc<Mutex<R, BufferCache>>,
 }
 
-impl ExtDevice {
-    pub fn new(device: Arc<dyn VfsInode>) -> VfsResult<Self> {
+impl<R: VfsRawMutex> Clone for ExtDevice<R> {
+    fn clone(&self) -> Self {
+        Self {
+            device_file: self.device_file.clone(),
+            config: self.config.clone(),
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<R: VfsRawMutex> ExtDevice<R> {
+    pub fn new(device: Arc<dyn VfsInode>, cache_blocks: usize) -> VfsResult<Self> {
         let stat = device.get_attr()?;
         let size = stat.st_size;
         let blk_size = stat.st_blksize;
@@ -22,14 +33,28 @@ impl ExtDevice {
             part_size: size,
             part_offset: 0,
         };
+        let cache = BufferCache::new(
+            device.clone(),
+            blk_size as usize,
+            res.block_count,
+            cache_blocks,
+        );
         Ok(Self {
             device_file: device,
             config: res,
+            cache: Arc::new(Mutex::new(cache)),
         })
     }
+
+    /// Write the cached blocks back and sync the device
+    pub fn sync(&self) -> VfsResult<()> {
+        self.cache.lock().flush()?;
+        self.device_file.flush()?;
+        self.device_file.fsync()
+    }
 }
 
-impl BlockDeviceInterface for ExtDevice {
+impl<R: VfsRawMutex> BlockDeviceInterface for ExtDevice<R> {
     fn open(&mut self) -> lwext4_rs::Result<BlockDeviceConfig> {
         Ok(self.config.clone())
     }
@@ -42,7 +67,8 @@ impl BlockDeviceInterface for ExtDevice {
     ) -> lwext4_rs::Result<usize> {
         let blk_size = self.config.block_size as usize;
         assert_eq!(buf.len(), blk_size * block_count as usize);
-        self.device_file
+        self.cache
+            .lock()
             .read_at(block_id * blk_size as u64, buf)
             .map_err(from_vfs)
     }
@@ -55,13 +81,14 @@ impl BlockDeviceInterface for ExtDevice {
     ) -> lwext4_rs::Result<usize> {
         let blk_size = self.config.block_size as usize;
         assert_eq!(buf.len(), blk_size * block_count as usize);
-        self.device_file
+        self.cache
+            .lock()
             .write_at(block_id * blk_size as u64, buf)
             .map_err(from_vfs)
     }
 
     fn close(&mut self) -> lwext4_rs::Result<()> {
-        self.device_file.flush().map_err(from_vfs)
+        self.sync().map_err(from_vfs)
     }
 
     fn lock(&mut self) -> lwext4_rs::Result<()> {
@@ -6,7 +6,7 @@ use alloc::{
 
 use lock_api::Mutex;
 use log::{debug, info, warn};
-use lwext4_rs::{FileTimes, MetaDataExt, Permissions, ReadDir, Time};
+use lwext4_rs::{MetaDataExt, Permissions, ReadDir};
 use vfscore::{
     error::VfsError,
     file::VfsFile,
@@ -15,6 +15,7 @@ use vfscore::{
     superblock::VfsSuperBlock,
     utils::{
         VfsDirEntry, VfsFileStat, VfsNodePerm, VfsNodeType, VfsRenameFlag, VfsTime, VfsTimeSpec,
+        VfsXattrFlag,
     },
     VfsResult,
 };
@@ -114,8 +115,14 @@ impl<R: VfsRawMutex + 'static> VfsInode for ExtDirInode<R> {
                 Ok(Arc::new(dir) as Arc<dyn VfsInode>)
             }
             VfsNodeType::SymLink => Err(VfsError::Invalid),
-            VfsNodeType::BlockDevice | VfsNodeType::CharDevice => {
-                let rdev = rdev.ok_or(VfsError::Invalid)?;
+            VfsNodeType::BlockDevice
+            | VfsNodeType::CharDevice
+            | VfsNodeType::Fifo
+            | VfsNodeType::Socket => {
+                let rdev = match ty {
+                    VfsNodeType::Fifo | VfsNodeType::Socket => 0,
+                    _ => rdev.ok_or(VfsError::Invalid)?,
+                };
                 sb.fs
                     .mknod(&path, into_file_type(ty)?, rdev as u32)
                     .map_err(into_vfs)?;
@@ -151,15 +158,15 @@ impl<R: VfsRawMutex + 'static> VfsInode for ExtDirInode<R> {
                     .map_err(|_x| VfsError::Invalid)?;
                 link.path()
             }
-            VfsNodeType::CharDevice | VfsNodeType::BlockDevice => {
+            VfsNodeType::CharDevice
+            | VfsNodeType::BlockDevice
+            | VfsNodeType::Fifo
+            | VfsNodeType::Socket => {
                 let special = src
                     .downcast_arc::<ExtSpecialInode<R>>()
                     .map_err(|_x| VfsError::Invalid)?;
                 special.path()
             }
-            VfsNodeType::Socket | VfsNodeType::Fifo => {
-                return Err(VfsError::NoSys);
-            }
             _ => {
                 return Err(VfsError::NoSys);
             }
@@ -180,7 +187,12 @@ impl<R: VfsRawMutex + 'static> VfsInode for ExtDirInode<R> {
             .map_err(|_x| VfsError::Invalid)?;
         let path = self.path() + name;
         info!("[unlink] path: {}", path);
-        sb.fs.remove_file(path).map_err(into_vfs)
+        let meta = sb.fs.metadata(&path).map_err(into_vfs)?;
+        sb.fs.remove_file(path).map_err(into_vfs)?;
+        if meta.nlink() <= 1 {
+            sb.release_special(meta.ino());
+        }
+        Ok(())
     }
     fn symlink(&self, name: &str, sy_name: &str) -> VfsResult<Arc<dyn VfsInode>> {
         let sb = self
@@ -210,10 +222,10 @@ impl<R: VfsRawMutex + 'static> VfsInode for ExtDirInode<R> {
                 warn!("[extfs] lookup: unknown file type {:?}", entry.file_type());
                 Err(VfsError::Invalid)
             }
-            VfsNodeType::Fifo | VfsNodeType::Socket => {
-                unimplemented!()
-            }
-            VfsNodeType::CharDevice | VfsNodeType::BlockDevice => {
+            VfsNodeType::CharDevice
+            | VfsNodeType::BlockDevice
+            | VfsNodeType::Fifo
+            | VfsNodeType::Socket => {
                 let path = entry.path();
                 let meta = sb.fs.metadata(&path).map_err(into_vfs)?;
                 let file =
@@ -307,7 +319,20 @@ impl<R: VfsRawMutex + 'static> VfsInode for ExtDirInode<R> {
         })
     }
     fn list_xattr(&self) -> VfsResult<Vec<String>> {
-        unimplemented!()
+        let sb = self.sb.upgrade().unwrap();
+        super::list_xattr(&sb, &self.path())
+    }
+    fn get_xattr(&self, key: &str) -> VfsResult<Vec<u8>> {
+        let sb = self.sb.upgrade().unwrap();
+        super::get_xattr(&sb, &self.path(), key)
+    }
+    fn set_xattr(&self, key: &str, value: &[u8], flag: VfsXattrFlag) -> VfsResult<()> {
+        let sb = self.sb.upgrade().unwrap();
+        super::set_xattr(&sb, &self.path(), key, value, flag)
+    }
+    fn remove_xattr(&self, key: &str) -> VfsResult<()> {
+        let sb = self.sb.upgrade().unwrap();
+        super::remove_xattr(&sb, &self.path(), key)
     }
     fn inode_type(&self) -> VfsNodeType {
         VfsNodeType::Dir
@@ -332,23 +357,25 @@ impl<R: VfsRawMutex + 'static> VfsInode for ExtDirInode<R> {
         sb.fs.rename(old_path, new_path).map_err(into_vfs)
     }
     fn update_time(&self, time: VfsTime, now: VfsTimeSpec) -> VfsResult<()> {
+        let (atime, mtime) = super::split_time(time);
+        self.set_times(atime, mtime, now)
+    }
+    fn set_atime(&self, atime: VfsTimeSpec) -> VfsResult<()> {
         let dir = self.dir.lock();
         let mut file = dir.as_file();
-        let times = FileTimes::new();
-        let mut attr_times = self.times.lock();
-        match time {
-            VfsTime::AccessTime(t) => {
-                attr_times.atime = t;
-                times.set_accessed(Time::from_extra(t.sec as u32, Some(t.nsec as u32)));
-            }
-            VfsTime::ModifiedTime(t) => {
-                attr_times.mtime = t;
-                times.set_modified(Time::from_extra(t.sec as u32, Some(t.nsec as u32)));
-            }
-        }
-        // times.set_modified(Time::from_extra(now.sec as u32, Some(now.nsec as u32)));
-        info!("[update_time] path: {:?}, times: {:?}", file.path(), times);
-        attr_times.ctime = now;
+        let times = super::file_times(&mut self.times.lock(), Some(atime), None, None);
+        file.set_times(times).map_err(into_vfs)
+    }
+    fn set_times(
+        &self,
+        atime: Option<VfsTimeSpec>,
+        mtime: Option<VfsTimeSpec>,
+        now: VfsTimeSpec,
+    ) -> VfsResult<()> {
+        let dir = self.dir.lock();
+        let mut file = dir.as_file();
+        let times = super::file_times(&mut self.times.lock(), atime, mtime, Some(now));
+        info!("[set_times] path: {:?}, times: {:?}", file.path(), times);
         file.set_times(times).map_err(into_vfs)
     }
 }
@@ -8,14 +8,16 @@ use alloc::{
 use embedded_io::{Read, Seek, SeekFrom, Write};
 use lock_api::Mutex;
 use log::info;
-use lwext4_rs::{File, FileTimes, MetaDataExt, Time};
+use lwext4_rs::{File, MetaDataExt};
 use vfscore::{
     error::VfsError,
     file::VfsFile,
     impl_file_inode_default,
-    inode::{InodeAttr, VfsInode},
+    inode::{generic_copy_file_range, InodeAttr, VfsInode, COPY_BUF_SIZE},
     superblock::VfsSuperBlock,
-    utils::{VfsFileStat, VfsNodePerm, VfsNodeType, VfsRenameFlag, VfsTime, VfsTimeSpec},
+    utils::{
+        VfsFileStat, VfsNodePerm, VfsNodeType, VfsRenameFlag, VfsTime, VfsTimeSpec, VfsXattrFlag,
+    },
     VfsResult,
 };
 
@@ -64,6 +66,21 @@ impl<R: VfsRawMutex + 'static> VfsFile for ExtFileInode<R> {
         }
         file.write(buf).map_err(into_vfs)
     }
+    fn readv_at(&self, offset: u64, bufs: &mut [&mut [u8]]) -> VfsResult<usize> {
+        let mut file = self.file.lock();
+        if file.stream_position().map_err(into_vfs)? != offset {
+            file.seek(SeekFrom::Start(offset)).map_err(into_vfs)?;
+        }
+        let mut count = 0;
+        for buf in bufs.iter_mut() {
+            let len = file.read(buf).map_err(into_vfs)?;
+            count += len;
+            if len < buf.len() {
+                break;
+            }
+        }
+        Ok(count)
+    }
     fn ioctl(&self, _cmd: u32, _arg: usize) -> VfsResult<usize> {
         Err(VfsError::NoTTY)
     }
@@ -123,7 +140,20 @@ impl<R: VfsRawMutex + 'static> VfsInode for ExtFileInode<R> {
         })
     }
     fn list_xattr(&self) -> VfsResult<Vec<String>> {
-        unimplemented!()
+        let sb = self.sb.upgrade().unwrap();
+        super::list_xattr(&sb, &self.path())
+    }
+    fn get_xattr(&self, key: &str) -> VfsResult<Vec<u8>> {
+        let sb = self.sb.upgrade().unwrap();
+        super::get_xattr(&sb, &self.path(), key)
+    }
+    fn set_xattr(&self, key: &str, value: &[u8], flag: VfsXattrFlag) -> VfsResult<()> {
+        let sb = self.sb.upgrade().unwrap();
+        super::set_xattr(&sb, &self.path(), key, value, flag)
+    }
+    fn remove_xattr(&self, key: &str) -> VfsResult<()> {
+        let sb = self.sb.upgrade().unwrap();
+        super::remove_xattr(&sb, &self.path(), key)
     }
     fn inode_type(&self) -> VfsNodeType {
         VfsNodeType::File
@@ -131,23 +161,81 @@ impl<R: VfsRawMutex + 'static> VfsInode for ExtFileInode<R> {
     fn truncate(&self, len: u64) -> VfsResult<()> {
         self.file.lock().set_len(len).map_err(into_vfs)
     }
-    fn update_time(&self, time: VfsTime, now: VfsTimeSpec) -> VfsResult<()> {
+    fn append(&self, buf: &[u8]) -> VfsResult<(u64, usize)> {
+        // the file is locked while the end is found and written
         let mut file = self.file.lock();
-        let times = FileTimes::new();
-        let mut attr_times = self.times.lock();
-        match time {
-            VfsTime::AccessTime(t) => {
-                attr_times.atime = t;
-                times.set_accessed(Time::from_extra(t.sec as u32, Some(t.nsec as u32)));
+        let offset = file.seek(SeekFrom::End(0)).map_err(into_vfs)?;
+        let len = file.write(buf).map_err(into_vfs)?;
+        Ok((offset, len))
+    }
+    fn copy_file_range(
+        &self,
+        offset_in: u64,
+        dst: Arc<dyn VfsInode>,
+        offset_out: u64,
+        len: usize,
+    ) -> VfsResult<usize> {
+        // the ranges of the same file may overlap, copy them through a buffer
+        if Arc::as_ptr(&dst) as *const () == self as *const Self as *const () {
+            return generic_copy_file_range(self, offset_in, dst.as_ref(), offset_out, len);
+        }
+        let dst = match dst.downcast_arc::<Self>() {
+            Ok(dst) => dst,
+            Err(dst) => {
+                return generic_copy_file_range(self, offset_in, dst.as_ref(), offset_out, len)
             }
-            VfsTime::ModifiedTime(t) => {
-                attr_times.mtime = t;
-                times.set_modified(Time::from_extra(t.sec as u32, Some(t.nsec as u32)));
+        };
+        // lock the files in the order of their addresses to avoid deadlock
+        let (mut src_file, mut dst_file) = if (self as *const Self) < Arc::as_ptr(&dst) {
+            let src_file = self.file.lock();
+            (src_file, dst.file.lock())
+        } else {
+            let dst_file = dst.file.lock();
+            (self.file.lock(), dst_file)
+        };
+        let dst_size = dst_file.metadata().map_err(into_vfs)?.size();
+        if dst_size < offset_out {
+            // the gap reads as zero
+            dst_file.set_len(offset_out).map_err(into_vfs)?;
+        }
+        // both files are read and written sequentially with one seek
+        src_file
+            .seek(SeekFrom::Start(offset_in))
+            .map_err(into_vfs)?;
+        dst_file
+            .seek(SeekFrom::Start(offset_out))
+            .map_err(into_vfs)?;
+        let mut buf = vec![0; len.min(COPY_BUF_SIZE)];
+        let mut count = 0;
+        while count < len {
+            let want = (len - count).min(buf.len());
+            let n = src_file.read(&mut buf[..want]).map_err(into_vfs)?;
+            if n == 0 {
+                break;
             }
+            dst_file.write_all(&buf[..n]).map_err(into_vfs)?;
+            count += n;
         }
-        // times.set_modified(Time::from_extra(now.sec as u32, Some(now.nsec as u32)));
-        info!("[update_time] path: {:?}, times: {:?}", file.path(), times);
-        attr_times.ctime = now;
+        Ok(count)
+    }
+    fn update_time(&self, time: VfsTime, now: VfsTimeSpec) -> VfsResult<()> {
+        let (atime, mtime) = super::split_time(time);
+        self.set_times(atime, mtime, now)
+    }
+    fn set_atime(&self, atime: VfsTimeSpec) -> VfsResult<()> {
+        let mut file = self.file.lock();
+        let times = super::file_times(&mut self.times.lock(), Some(atime), None, None);
+        file.set_times(times).map_err(into_vfs)
+    }
+    fn set_times(
+        &self,
+        atime: Option<VfsTimeSpec>,
+        mtime: Option<VfsTimeSpec>,
+        now: VfsTimeSpec,
+    ) -> VfsResult<()> {
+        let mut file = self.file.lock();
+        let times = super::file_times(&mut self.times.lock(), atime, mtime, Some(now));
+        info!("[set_times] path: {:?}, times: {:?}", file.path(), times);
         file.set_times(times).map_err(into_vfs)
     }
 }
@@ -7,14 +7,16 @@ use core::cmp::max;
 
 use lock_api::Mutex;
 use log::trace;
-use lwext4_rs::{FileTimes, MetaDataExt, Time};
+use lwext4_rs::MetaDataExt;
 use vfscore::{
     error::VfsError,
     file::VfsFile,
     impl_common_inode_default,
     inode::{InodeAttr, VfsInode},
     superblock::VfsSuperBlock,
-    utils::{VfsFileStat, VfsNodePerm, VfsNodeType, VfsRenameFlag, VfsTime, VfsTimeSpec},
+    utils::{
+        VfsFileStat, VfsNodePerm, VfsNodeType, VfsRenameFlag, VfsTime, VfsTimeSpec, VfsXattrFlag,
+    },
     VfsResult,
 };
 
@@ -115,31 +117,48 @@ impl<R: VfsRawMutex + 'static> VfsInode for ExtLinkInode<R> {
         })
     }
     fn list_xattr(&self) -> VfsResult<Vec<String>> {
-        unimplemented!()
+        let sb = self.sb.upgrade().unwrap();
+        super::list_xattr(&sb, &self.path())
+    }
+    fn get_xattr(&self, key: &str) -> VfsResult<Vec<u8>> {
+        let sb = self.sb.upgrade().unwrap();
+        super::get_xattr(&sb, &self.path(), key)
+    }
+    fn set_xattr(&self, key: &str, value: &[u8], flag: VfsXattrFlag) -> VfsResult<()> {
+        let sb = self.sb.upgrade().unwrap();
+        super::set_xattr(&sb, &self.path(), key, value, flag)
+    }
+    fn remove_xattr(&self, key: &str) -> VfsResult<()> {
+        let sb = self.sb.upgrade().unwrap();
+        super::remove_xattr(&sb, &self.path(), key)
     }
     fn inode_type(&self) -> VfsNodeType {
         VfsNodeType::SymLink
     }
     fn update_time(&self, time: VfsTime, now: VfsTimeSpec) -> VfsResult<()> {
+        let (atime, mtime) = super::split_time(time);
+        self.set_times(atime, mtime, now)
+    }
+    fn set_atime(&self, atime: VfsTimeSpec) -> VfsResult<()> {
         let sb = self
             .get_super_block()?
             .downcast_arc::<ExtFsSuperBlock<R>>()
             .map_err(|_x| VfsError::Invalid)?;
-        let times = FileTimes::new();
-        let mut attr_times = self.times.lock();
-        match time {
-            VfsTime::AccessTime(t) => {
-                attr_times.atime = t;
-                times.set_accessed(Time::from_extra(t.sec as u32, Some(t.nsec as u32)));
-            }
-            VfsTime::ModifiedTime(t) => {
-                attr_times.mtime = t;
-                times.set_modified(Time::from_extra(t.sec as u32, Some(t.nsec as u32)));
-            }
-        }
-        // times.set_modified(Time::from_extra(now.sec as u32, Some(now.nsec as u32)));
-        trace!("[update_time] path: {:?}, times: {:?}", self.path, times);
-        attr_times.ctime = now;
+        let times = super::file_times(&mut self.times.lock(), Some(atime), None, None);
+        sb.fs.set_times(&self.path, times).map_err(into_vfs)
+    }
+    fn set_times(
+        &self,
+        atime: Option<VfsTimeSpec>,
+        mtime: Option<VfsTimeSpec>,
+        now: VfsTimeSpec,
+    ) -> VfsResult<()> {
+        let sb = self
+            .get_super_block()?
+            .downcast_arc::<ExtFsSuperBlock<R>>()
+            .map_err(|_x| VfsError::Invalid)?;
+        let times = super::file_times(&mut self.times.lock(), atime, mtime, Some(now));
+        trace!("[set_times] path: {:?}, times: {:?}", self.path, times);
         sb.fs.set_times(&self.path, times).map_err(into_vfs)
     }
 }
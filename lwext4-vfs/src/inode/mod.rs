@@ -1,4 +1,17 @@
-use vfscore::utils::VfsTimeSpec;
+use alloc::{
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use lwext4_rs::{FileTimes, Time};
+use vfscore::{
+    error::VfsError,
+    utils::{VfsTime, VfsTimeSpec, VfsXattrFlag},
+    VfsResult,
+};
+
+use crate::{types::into_vfs, ExtFsSuperBlock, VfsRawMutex};
 
 pub mod dir;
 pub mod file;
@@ -11,3 +24,87 @@ struct ExtFsInodeAttr {
     pub mtime: VfsTimeSpec,
     pub ctime: VfsTimeSpec,
 }
+
+/// Record the access, modification and change times in `attr`, `None` is left unchanged, and
+/// collect the access and modification times to be written to the disk
+fn file_times(
+    attr: &mut ExtFsInodeAttr,
+    atime: Option<VfsTimeSpec>,
+    mtime: Option<VfsTimeSpec>,
+    ctime: Option<VfsTimeSpec>,
+) -> FileTimes {
+    let times = FileTimes::new();
+    if let Some(t) = atime {
+        attr.atime = t;
+        times.set_accessed(Time::from_extra(t.sec as u32, Some(t.nsec as u32)));
+    }
+    if let Some(t) = mtime {
+        attr.mtime = t;
+        times.set_modified(Time::from_extra(t.sec as u32, Some(t.nsec as u32)));
+    }
+    if let Some(t) = ctime {
+        attr.ctime = t;
+    }
+    times
+}
+
+/// Split a [`VfsTime`] into the access and modification times of `set_times`
+fn split_time(time: VfsTime) -> (Option<VfsTimeSpec>, Option<VfsTimeSpec>) {
+    match time {
+        VfsTime::AccessTime(t) => (Some(t), None),
+        VfsTime::ModifiedTime(t) => (None, Some(t)),
+    }
+}
+
+/// The max size of the list of extended attribute names
+const XATTR_LIST_MAX: usize = 65536;
+/// The max size of the value of an extended attribute
+const XATTR_SIZE_MAX: usize = 65536;
+
+fn list_xattr<R: VfsRawMutex>(sb: &ExtFsSuperBlock<R>, path: &str) -> VfsResult<Vec<String>> {
+    let mut buf = vec![0; XATTR_LIST_MAX];
+    let len = sb.fs.list_xattr(path, &mut buf).map_err(into_vfs)?;
+    let names = buf[..len]
+        .split(|c| *c == 0)
+        .filter(|name| !name.is_empty())
+        .map(|name| String::from_utf8_lossy(name).to_string())
+        .collect();
+    Ok(names)
+}
+
+fn get_xattr<R: VfsRawMutex>(sb: &ExtFsSuperBlock<R>, path: &str, key: &str) -> VfsResult<Vec<u8>> {
+    if !list_xattr(sb, path)?.iter().any(|name| name == key) {
+        return Err(VfsError::NoData);
+    }
+    let mut buf = vec![0; XATTR_SIZE_MAX];
+    let len = sb.fs.get_xattr(path, key, &mut buf).map_err(into_vfs)?;
+    buf.truncate(len);
+    Ok(buf)
+}
+
+fn set_xattr<R: VfsRawMutex>(
+    sb: &ExtFsSuperBlock<R>,
+    path: &str,
+    key: &str,
+    value: &[u8],
+    flag: VfsXattrFlag,
+) -> VfsResult<()> {
+    if value.len() > XATTR_SIZE_MAX {
+        return Err(VfsError::NoSpace);
+    }
+    let exist = list_xattr(sb, path)?.iter().any(|name| name == key);
+    if flag.contains(VfsXattrFlag::XATTR_CREATE) && exist {
+        return Err(VfsError::EExist);
+    }
+    if flag.contains(VfsXattrFlag::XATTR_REPLACE) && !exist {
+        return Err(VfsError::NoData);
+    }
+    sb.fs.set_xattr(path, key, value).map_err(into_vfs)
+}
+
+fn remove_xattr<R: VfsRawMutex>(sb: &ExtFsSuperBlock<R>, path: &str, key: &str) -> VfsResult<()> {
+    if !list_xattr(sb, path)?.iter().any(|name| name == key) {
+        return Err(VfsError::NoData);
+    }
+    sb.fs.remove_xattr(path, key).map_err(into_vfs)
+}
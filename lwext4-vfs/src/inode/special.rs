@@ -3,10 +3,11 @@ use alloc::{
     sync::{Arc, Weak},
     vec::Vec,
 };
+use core::any::Any;
 
 use lock_api::Mutex;
 use log::info;
-use lwext4_rs::{FileTimes, MetaDataExt, Time};
+use lwext4_rs::MetaDataExt;
 use vfscore::{
     error::VfsError,
     file::VfsFile,
@@ -14,7 +15,8 @@ use vfscore::{
     inode::{InodeAttr, VfsInode},
     superblock::VfsSuperBlock,
     utils::{
-        VfsFileStat, VfsNodePerm, VfsNodeType, VfsPollEvents, VfsRenameFlag, VfsTime, VfsTimeSpec,
+        VfsAccessMode, VfsFileStat, VfsNodePerm, VfsNodeType, VfsPollEvents, VfsRenameFlag,
+        VfsTime, VfsTimeSpec, VfsXattrFlag,
     },
     VfsResult,
 };
@@ -93,6 +95,41 @@ impl<R: VfsRawMutex + 'static> VfsFile for ExtSpecialInode<R> {
 impl<R: VfsRawMutex + 'static> VfsInode for ExtSpecialInode<R> {
     impl_common_inode_default!();
 
+    fn open_fifo(&self, mode: VfsAccessMode) -> VfsResult<Arc<dyn VfsFile>> {
+        if self.ty != VfsNodeType::Fifo {
+            return Err(VfsError::Invalid);
+        }
+        let sb = self.sb.upgrade().unwrap();
+        let meta = sb.fs.metadata(self.path.as_str()).map_err(into_vfs)?;
+        Ok(sb.open_fifo_pipe(
+            meta.ino(),
+            mode.contains(VfsAccessMode::R_OK),
+            mode.contains(VfsAccessMode::W_OK),
+        ))
+    }
+
+    fn bind_socket(&self, socket: Weak<dyn Any + Send + Sync>) -> VfsResult<()> {
+        if self.ty != VfsNodeType::Socket {
+            return Err(VfsError::Invalid);
+        }
+        let sb = self.sb.upgrade().unwrap();
+        let meta = sb.fs.metadata(self.path.as_str()).map_err(into_vfs)?;
+        let mut sockets = sb.sockets.lock();
+        sockets.retain(|_, socket| socket.strong_count() > 0);
+        if sockets.contains_key(&meta.ino()) {
+            return Err(VfsError::EBUSY);
+        }
+        sockets.insert(meta.ino(), socket);
+        Ok(())
+    }
+
+    fn get_socket(&self) -> VfsResult<Arc<dyn Any + Send + Sync>> {
+        let sb = self.sb.upgrade().unwrap();
+        let meta = sb.fs.metadata(self.path.as_str()).map_err(into_vfs)?;
+        let socket = sb.sockets.lock().get(&meta.ino()).and_then(Weak::upgrade);
+        socket.ok_or(VfsError::ECONNREFUSED)
+    }
+
     fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
         Ok(self.sb.upgrade().unwrap())
     }
@@ -147,31 +184,48 @@ impl<R: VfsRawMutex + 'static> VfsInode for ExtSpecialInode<R> {
         })
     }
     fn list_xattr(&self) -> VfsResult<Vec<String>> {
-        unimplemented!()
+        let sb = self.sb.upgrade().unwrap();
+        super::list_xattr(&sb, &self.path())
+    }
+    fn get_xattr(&self, key: &str) -> VfsResult<Vec<u8>> {
+        let sb = self.sb.upgrade().unwrap();
+        super::get_xattr(&sb, &self.path(), key)
+    }
+    fn set_xattr(&self, key: &str, value: &[u8], flag: VfsXattrFlag) -> VfsResult<()> {
+        let sb = self.sb.upgrade().unwrap();
+        super::set_xattr(&sb, &self.path(), key, value, flag)
+    }
+    fn remove_xattr(&self, key: &str) -> VfsResult<()> {
+        let sb = self.sb.upgrade().unwrap();
+        super::remove_xattr(&sb, &self.path(), key)
     }
     fn inode_type(&self) -> VfsNodeType {
         self.ty
     }
     fn update_time(&self, time: VfsTime, now: VfsTimeSpec) -> VfsResult<()> {
+        let (atime, mtime) = super::split_time(time);
+        self.set_times(atime, mtime, now)
+    }
+    fn set_atime(&self, atime: VfsTimeSpec) -> VfsResult<()> {
         let sb = self
             .get_super_block()?
             .downcast_arc::<ExtFsSuperBlock<R>>()
             .map_err(|_x| VfsError::Invalid)?;
-        let times = FileTimes::new();
-        let mut attr_times = self.times.lock();
-        match time {
-            VfsTime::AccessTime(t) => {
-                attr_times.atime = t;
-                times.set_accessed(Time::from_extra(t.sec as u32, Some(t.nsec as u32)));
-            }
-            VfsTime::ModifiedTime(t) => {
-                attr_times.mtime = t;
-                times.set_modified(Time::from_extra(t.sec as u32, Some(t.nsec as u32)));
-            }
-        }
-        // times.set_modified(Time::from_extra(now.sec as u32, Some(now.nsec as u32)));
-        info!("[update_time] path: {:?}, times: {:?}", self.path, times);
-        attr_times.ctime = now;
+        let times = super::file_times(&mut self.times.lock(), Some(atime), None, None);
+        sb.fs.set_times(&self.path, times).map_err(into_vfs)
+    }
+    fn set_times(
+        &self,
+        atime: Option<VfsTimeSpec>,
+        mtime: Option<VfsTimeSpec>,
+        now: VfsTimeSpec,
+    ) -> VfsResult<()> {
+        let sb = self
+            .get_super_block()?
+            .downcast_arc::<ExtFsSuperBlock<R>>()
+            .map_err(|_x| VfsError::Invalid)?;
+        let times = super::file_times(&mut self.times.lock(), atime, mtime, Some(now));
+        info!("[set_times] path: {:?}, times: {:?}", self.path, times);
         sb.fs.set_times(&self.path, times).map_err(into_vfs)
     }
 }
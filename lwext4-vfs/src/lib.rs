@@ -13,14 +13,19 @@ use alloc::{
     string::{String, ToString},
     sync::{Arc, Weak},
 };
+use core::any::Any;
 
 pub use inode::special::ExtDevProvider;
 use lock_api::Mutex;
 use log::info;
 pub use lwext4_rs::FsType as ExtFsType;
 use lwext4_rs::{BlockDevice, FsType, MountHandle, RegisterHandle};
-use unifs::dentry::UniFsDentry;
+use unifs::{
+    dentry::UniFsDentry,
+    pipe::{UniFsPipe, UniFsPipeFile, PIPE_SIZE},
+};
 use vfscore::{
+    cache::DEFAULT_CACHE_BLOCKS,
     dentry::VfsDentry,
     error::VfsError,
     fstype::{FileSystemFlags, VfsFsType},
@@ -37,12 +42,13 @@ use crate::{
 };
 pub trait VfsRawMutex = lock_api::RawMutex + Send + Sync;
 
-type FileSystem = lwext4_rs::FileSystem<ExtDevice>;
+type FileSystem<R> = lwext4_rs::FileSystem<ExtDevice<R>>;
 
 pub struct ExtFs<T, R: VfsRawMutex> {
     ty: ExtFsType,
     fs_container: Mutex<R, BTreeMap<usize, Arc<ExtFsSuperBlock<R>>>>,
     provider: Arc<T>,
+    cache_blocks: usize,
 }
 
 impl<T, R: VfsRawMutex> ExtFs<T, R> {
@@ -51,8 +57,15 @@ impl<T, R: VfsRawMutex> ExtFs<T, R> {
             ty,
             fs_container: Mutex::new(BTreeMap::new()),
             provider: Arc::new(provider),
+            cache_blocks: DEFAULT_CACHE_BLOCKS,
         }
     }
+
+    /// Set the number of blocks in the buffer cache of the devices mounted later
+    pub fn with_cache_blocks(mut self, blocks: usize) -> Self {
+        self.cache_blocks = blocks;
+        self
+    }
 }
 
 impl<T: ExtDevProvider + 'static, R: VfsRawMutex + 'static> VfsFsType for ExtFs<T, R> {
@@ -74,7 +87,7 @@ impl<T: ExtDevProvider + 'static, R: VfsRawMutex + 'static> VfsFsType for ExtFs<
             return sb.root_dentry(ab_mnt);
         }
         info!("extfs: mount: mp: {}", ab_mnt);
-        let ext_dev = ExtDevice::new(dev)?;
+        let ext_dev = ExtDevice::new(dev, self.cache_blocks)?;
         let sb = ExtFsSuperBlock::<R>::new(
             &(self.clone() as Arc<dyn VfsFsType>),
             ext_dev,
@@ -119,12 +132,16 @@ impl<T: ExtDevProvider + 'static, R: VfsRawMutex + 'static> VfsFsType for ExtFs<
 }
 
 struct ExtFsSuperBlock<R: VfsRawMutex> {
-    ext_dev: ExtDevice,
+    ext_dev: ExtDevice<R>,
     fs_type: Weak<dyn VfsFsType>,
     root: Mutex<R, Option<Arc<dyn VfsInode>>>,
-    fs: FileSystem,
+    fs: FileSystem<R>,
     mnt_info: Mutex<R, BTreeMap<String, Arc<dyn VfsDentry>>>,
     provider: Arc<dyn ExtDevProvider>,
+    /// The pipes of the opened fifos, indexed by the inode number
+    pipes: Mutex<R, BTreeMap<u64, Arc<UniFsPipe<R>>>>,
+    /// The sockets bound to the socket nodes, indexed by the inode number
+    sockets: Mutex<R, BTreeMap<u64, Weak<dyn Any + Send + Sync>>>,
 }
 
 unsafe impl<R: VfsRawMutex> Send for ExtFsSuperBlock<R> {}
@@ -133,7 +150,7 @@ unsafe impl<R: VfsRawMutex> Sync for ExtFsSuperBlock<R> {}
 impl<R: VfsRawMutex + 'static> ExtFsSuperBlock<R> {
     fn new(
         fs_type: &Arc<dyn VfsFsType>,
-        device: ExtDevice,
+        device: ExtDevice<R>,
         ab_mnt: &str,
         provider: Arc<dyn ExtDevProvider>,
     ) -> VfsResult<Arc<Self>> {
@@ -154,6 +171,8 @@ impl<R: VfsRawMutex + 'static> ExtFsSuperBlock<R> {
             fs,
             mnt_info: Mutex::new(BTreeMap::new()),
             provider,
+            pipes: Mutex::new(BTreeMap::new()),
+            sockets: Mutex::new(BTreeMap::new()),
         });
 
         let dir = Arc::new(Mutex::new(dir));
@@ -166,6 +185,23 @@ impl<R: VfsRawMutex + 'static> ExtFsSuperBlock<R> {
         sb.mnt_info.lock().insert(ab_mnt.into(), root_dt.clone());
         Ok(sb)
     }
+    /// Open the pipe of the fifo `ino`, the pipes without any open end are dropped
+    ///
+    /// The end is opened under the lock of the pipes, so the pipe can't be dropped by another
+    /// open before it has an open end.
+    fn open_fifo_pipe(&self, ino: u64, readable: bool, writable: bool) -> Arc<UniFsPipeFile<R>> {
+        let mut pipes = self.pipes.lock();
+        pipes.retain(|_, pipe| pipe.readers() + pipe.writers() > 0);
+        pipes
+            .entry(ino)
+            .or_insert_with(|| UniFsPipe::new(PIPE_SIZE))
+            .open(readable, writable)
+    }
+    /// Forget the pipe and the socket of the inode `ino` when its last link is removed
+    fn release_special(&self, ino: u64) {
+        self.pipes.lock().remove(&ino);
+        self.sockets.lock().remove(&ino);
+    }
     pub fn root_dentry(&self, ab_mnt: &str) -> VfsResult<Arc<dyn VfsDentry>> {
         self.mnt_info.lock().get(ab_mnt).map_or_else(
             || {
@@ -182,9 +218,7 @@ impl<R: VfsRawMutex + 'static> ExtFsSuperBlock<R> {
 
 impl<R: VfsRawMutex + 'static> VfsSuperBlock for ExtFsSuperBlock<R> {
     fn sync_fs(&self, _wait: bool) -> VfsResult<()> {
-        self.ext_dev.device_file.flush()?;
-        self.ext_dev.device_file.fsync()?;
-        Ok(())
+        self.ext_dev.sync()
     }
 
     fn stat_fs(&self) -> VfsResult<VfsFsStat> {
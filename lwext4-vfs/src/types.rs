@@ -12,11 +12,13 @@ pub fn from_vfs(err: VfsError) -> Error {
         VfsError::NoEntry => Error::NoEntry,
         VfsError::EINTR => Error::InvalidError,
         VfsError::IoError => Error::Io,
+        VfsError::ENXIO => Error::InvalidError,
         VfsError::EAGAIN => Error::InvalidError,
         VfsError::NoMem => Error::OutOfMemory,
         VfsError::Access => Error::PermissionDenied,
         VfsError::EBUSY => Error::InvalidError,
         VfsError::EExist => Error::FileExists,
+        VfsError::CrossDevice => Error::InvalidError,
         VfsError::NotDir => Error::NotDirectory,
         VfsError::Invalid => Error::InvalidArgument,
         VfsError::NoDev => Error::NoDevice,
@@ -24,10 +26,17 @@ pub fn from_vfs(err: VfsError) -> Error {
         VfsError::NoTTY => Error::InvalidError,
         VfsError::NoSpace => Error::NoSpace,
         VfsError::ESPIPE => Error::InvalidError,
+        VfsError::ReadOnlyFs => Error::InvalidError,
         VfsError::EPIPE => Error::InvalidError,
+        VfsError::EDEADLK => Error::InvalidError,
         VfsError::NameTooLong => Error::TooBig,
         VfsError::NoSys => Error::NotSupported,
         VfsError::NotEmpty => Error::InvalidError,
+        VfsError::SymLinkLoop => Error::InvalidError,
+        VfsError::EBADF => Error::InvalidError,
+        VfsError::EMFILE => Error::InvalidError,
+        VfsError::NoData => Error::InvalidError,
+        VfsError::ECONNREFUSED => Error::InvalidError,
     }
 }
 
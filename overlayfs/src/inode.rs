@@ -0,0 +1,624 @@
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+use core::{any::Any, task::Waker};
+
+use unifs::VfsRawMutex;
+use vfscore::{
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, InodeAttrValid, VfsInode},
+    superblock::VfsSuperBlock,
+    utils::{
+        VfsAccessMode, VfsDirEntry, VfsFallocateMode, VfsFileStat, VfsNodePerm, VfsNodeType,
+        VfsPollEvents, VfsRenameFlag, VfsTime, VfsTimeSpec, VfsXattrFlag,
+    },
+    VfsResult,
+};
+
+use crate::OverlaySuperBlock;
+
+/// The xattr which marks an upper directory as opaque
+const OPAQUE_XATTR: &str = "trusted.overlay.opaque";
+/// The prefix of the xattrs used by overlayfs, they are hidden from the user
+const OVERLAY_XATTR_PREFIX: &str = "trusted.overlay.";
+
+/// An inode of overlayfs, it merges the inode of the upper layer and the lower layer
+pub struct OverlayInode<R: VfsRawMutex> {
+    this: Weak<OverlayInode<R>>,
+    sb: Weak<OverlaySuperBlock<R>>,
+    /// The parent directory and the name in it, used to copy up, `None` for the root
+    parent: Option<(Arc<OverlayInode<R>>, String)>,
+    upper: lock_api::Mutex<R, Option<Arc<dyn VfsInode>>>,
+    lower: Option<Arc<dyn VfsInode>>,
+    ty: VfsNodeType,
+    /// The children found in the directory, so all users of a child share its upper inode
+    children: lock_api::Mutex<R, BTreeMap<String, Weak<OverlayInode<R>>>>,
+    /// The merged entries of the directory, cleared when the directory is changed
+    listing: lock_api::Mutex<R, Option<Arc<Vec<VfsDirEntry>>>>,
+}
+
+impl<R: VfsRawMutex + 'static> OverlayInode<R> {
+    pub(crate) fn new(
+        sb: &Arc<OverlaySuperBlock<R>>,
+        parent: Option<(Arc<OverlayInode<R>>, String)>,
+        upper: Option<Arc<dyn VfsInode>>,
+        lower: Option<Arc<dyn VfsInode>>,
+    ) -> Arc<Self> {
+        let ty = upper.as_ref().or(lower.as_ref()).unwrap().inode_type();
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            sb: Arc::downgrade(sb),
+            parent,
+            upper: lock_api::Mutex::new(upper),
+            lower,
+            ty,
+            children: lock_api::Mutex::new(BTreeMap::new()),
+            listing: lock_api::Mutex::new(None),
+        })
+    }
+
+    /// Get the inode of the upper layer if it exists
+    pub fn upper(&self) -> Option<Arc<dyn VfsInode>> {
+        self.upper.lock().clone()
+    }
+
+    /// Get the inode of the lower layer if it exists
+    pub fn lower(&self) -> Option<Arc<dyn VfsInode>> {
+        self.lower.clone()
+    }
+
+    /// The inode which is visible to the user, the upper one hides the lower one
+    fn real(&self) -> Arc<dyn VfsInode> {
+        self.upper().or_else(|| self.lower.clone()).unwrap()
+    }
+
+    fn sb(&self) -> VfsResult<Arc<OverlaySuperBlock<R>>> {
+        self.sb.upgrade().ok_or(VfsError::Invalid)
+    }
+
+    /// Whether the lower directory is hidden by the upper one
+    fn is_opaque(&self) -> bool {
+        self.upper().is_some_and(|upper| is_opaque(&upper))
+    }
+
+    /// Find the child `name`, the child which is still in use is returned again
+    fn lookup_child(&self, name: &str) -> VfsResult<Arc<OverlayInode<R>>> {
+        if !self.ty.is_dir() {
+            return Err(VfsError::NotDir);
+        }
+        let mut children = self.children.lock();
+        if let Some(child) = children.get(name).and_then(Weak::upgrade) {
+            return Ok(child);
+        }
+        let upper = match self.upper() {
+            Some(upper) => match upper.lookup(name) {
+                Ok(child) if is_whiteout(&child) => return Err(VfsError::NoEntry),
+                Ok(child) => Some(child),
+                Err(VfsError::NoEntry) => None,
+                Err(e) => return Err(e),
+            },
+            None => None,
+        };
+        // only directories are merged
+        let merge = upper
+            .as_ref()
+            .is_none_or(|upper| upper.inode_type().is_dir() && !is_opaque(upper));
+        let lower = match &self.lower {
+            Some(lower) if merge && !self.is_opaque() => match lower.lookup(name) {
+                Ok(child) => Some(child),
+                Err(VfsError::NoEntry) => None,
+                Err(e) => return Err(e),
+            },
+            _ => None,
+        };
+        let lower = match &upper {
+            Some(upper) => lower.filter(|lower| lower.inode_type() == upper.inode_type()),
+            None => lower,
+        };
+        if upper.is_none() && lower.is_none() {
+            return Err(VfsError::NoEntry);
+        }
+        let parent = self.this.upgrade().unwrap();
+        let child = OverlayInode::new(&self.sb()?, Some((parent, name.to_string())), upper, lower);
+        children.insert(name.to_string(), Arc::downgrade(&child));
+        Ok(child)
+    }
+
+    /// Forget the child `name` and the merged entries after the directory is changed
+    fn changed(&self, name: &str) {
+        self.children.lock().remove(name);
+        self.listing.lock().take();
+    }
+
+    /// The merged entries of the directory, they are cached until the directory is changed
+    fn listing(&self) -> VfsResult<Arc<Vec<VfsDirEntry>>> {
+        let mut listing = self.listing.lock();
+        if let Some(entries) = listing.as_ref() {
+            return Ok(entries.clone());
+        }
+        let entries = Arc::new(self.entries()?);
+        listing.replace(entries.clone());
+        Ok(entries)
+    }
+
+    /// Read the merged entries of the directory from the layers
+    fn entries(&self) -> VfsResult<Vec<VfsDirEntry>> {
+        let mut entries = Vec::new();
+        // the names in the upper layer, including the whiteouts
+        let mut names = BTreeSet::new();
+        if let Some(upper) = self.upper() {
+            for entry in read_dir(&upper)? {
+                names.insert(entry.name.clone());
+                if entry.ty == VfsNodeType::CharDevice && is_whiteout(&upper.lookup(&entry.name)?) {
+                    continue;
+                }
+                entries.push(entry);
+            }
+        }
+        if let Some(lower) = &self.lower {
+            if !self.is_opaque() {
+                for entry in read_dir(lower)? {
+                    if !names.contains(&entry.name) {
+                        entries.push(entry);
+                    }
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Copy the inode to the upper layer if it only exists in the lower layer
+    ///
+    /// The parent directories are copied up first.
+    fn copy_up(&self) -> VfsResult<Arc<dyn VfsInode>> {
+        if let Some(upper) = self.upper() {
+            return Ok(upper);
+        }
+        let (parent, name) = self.parent.as_ref().ok_or(VfsError::ReadOnlyFs)?;
+        let parent_upper = parent.copy_up()?;
+        let mut upper = self.upper.lock();
+        if let Some(upper) = upper.as_ref() {
+            return Ok(upper.clone());
+        }
+        let lower = self.lower.as_ref().unwrap();
+        let perm = lower.node_perm();
+        let new = match self.ty {
+            VfsNodeType::Dir => parent_upper.create(name, VfsNodeType::Dir, perm, None)?,
+            VfsNodeType::File => {
+                let file = parent_upper.create(name, VfsNodeType::File, perm, None)?;
+                copy_data(lower, &file)?;
+                file
+            }
+            VfsNodeType::SymLink => {
+                let mut buf = vec![0; 4096];
+                let len = lower.readlink(&mut buf)?;
+                let target = core::str::from_utf8(&buf[..len]).map_err(|_| VfsError::Invalid)?;
+                parent_upper.symlink(name, target)?
+            }
+            ty => {
+                let rdev = lower.get_attr()?.st_rdev;
+                parent_upper.create(name, ty, perm, Some(rdev))?
+            }
+        };
+        copy_attr(lower, &new)?;
+        upper.replace(new.clone());
+        Ok(new)
+    }
+
+    /// Make sure `name` can be created in the upper directory
+    ///
+    /// Return whether a whiteout was removed, the new directory should be opaque then.
+    fn prepare_create(&self, upper: &Arc<dyn VfsInode>, name: &str) -> VfsResult<bool> {
+        if self.lookup_child(name).is_ok() {
+            return Err(VfsError::EExist);
+        }
+        match upper.lookup(name) {
+            Ok(child) if is_whiteout(&child) => {
+                upper.unlink(name)?;
+                Ok(true)
+            }
+            Ok(_) => Err(VfsError::EExist),
+            Err(VfsError::NoEntry) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn new_child(&self, name: &str, upper: Arc<dyn VfsInode>) -> VfsResult<Arc<dyn VfsInode>> {
+        let parent = self.this.upgrade().unwrap();
+        let child = OverlayInode::new(
+            &self.sb()?,
+            Some((parent, name.to_string())),
+            Some(upper),
+            None,
+        );
+        self.changed(name);
+        self.children
+            .lock()
+            .insert(name.to_string(), Arc::downgrade(&child));
+        Ok(child)
+    }
+}
+
+impl<R: VfsRawMutex + 'static> VfsFile for OverlayInode<R> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        self.real().read_at(offset, buf)
+    }
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        self.copy_up()?.write_at(offset, buf)
+    }
+    fn readdir(&self, start_index: usize) -> VfsResult<Option<VfsDirEntry>> {
+        if !self.ty.is_dir() {
+            return Err(VfsError::NotDir);
+        }
+        Ok(self.listing()?.get(start_index).cloned())
+    }
+    fn poll(&self, event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
+        self.real().poll(event)
+    }
+    fn poll_wait(&self, event: VfsPollEvents, waker: &Waker) -> VfsResult<VfsPollEvents> {
+        self.real().poll_wait(event, waker)
+    }
+    fn ioctl(&self, cmd: u32, arg: usize) -> VfsResult<usize> {
+        self.real().ioctl(cmd, arg)
+    }
+    fn flush(&self) -> VfsResult<()> {
+        match self.upper() {
+            Some(upper) => upper.flush(),
+            None => Ok(()),
+        }
+    }
+    fn fsync(&self) -> VfsResult<()> {
+        match self.upper() {
+            Some(upper) => upper.fsync(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<R: VfsRawMutex + 'static> VfsInode for OverlayInode<R> {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Ok(self.sb()?)
+    }
+
+    fn node_perm(&self) -> VfsNodePerm {
+        self.real().node_perm()
+    }
+
+    fn create(
+        &self,
+        name: &str,
+        ty: VfsNodeType,
+        perm: VfsNodePerm,
+        rdev: Option<u64>,
+    ) -> VfsResult<Arc<dyn VfsInode>> {
+        let upper = self.copy_up()?;
+        let whiteout = self.prepare_create(&upper, name)?;
+        let child = upper.create(name, ty, perm, rdev)?;
+        if whiteout && ty.is_dir() {
+            child.set_xattr(OPAQUE_XATTR, b"y", VfsXattrFlag::empty())?;
+        }
+        self.new_child(name, child)
+    }
+
+    fn link(&self, name: &str, src: Arc<dyn VfsInode>) -> VfsResult<Arc<dyn VfsInode>> {
+        let src = src
+            .downcast_arc::<OverlayInode<R>>()
+            .map_err(|_| VfsError::CrossDevice)?;
+        let src_upper = src.copy_up()?;
+        let upper = self.copy_up()?;
+        self.prepare_create(&upper, name)?;
+        upper.link(name, src_upper)?;
+        self.changed(name);
+        self.children
+            .lock()
+            .insert(name.to_string(), Arc::downgrade(&src));
+        Ok(src)
+    }
+
+    fn unlink(&self, name: &str) -> VfsResult<()> {
+        let child = self.lookup_child(name)?;
+        if child.ty.is_dir() {
+            return Err(VfsError::IsDir);
+        }
+        let upper = self.copy_up()?;
+        if child.upper().is_some() {
+            upper.unlink(name)?;
+        }
+        if child.lower.is_some() {
+            create_whiteout(&upper, name)?;
+        }
+        self.changed(name);
+        Ok(())
+    }
+
+    fn symlink(&self, name: &str, target: &str) -> VfsResult<Arc<dyn VfsInode>> {
+        let upper = self.copy_up()?;
+        self.prepare_create(&upper, name)?;
+        let child = upper.symlink(name, target)?;
+        self.new_child(name, child)
+    }
+
+    fn lookup(&self, name: &str) -> VfsResult<Arc<dyn VfsInode>> {
+        let child = self.lookup_child(name)?;
+        Ok(child)
+    }
+
+    fn rmdir(&self, name: &str) -> VfsResult<()> {
+        let child = self.lookup_child(name)?;
+        if !child.ty.is_dir() {
+            return Err(VfsError::NotDir);
+        }
+        if !child.listing()?.is_empty() {
+            return Err(VfsError::NotEmpty);
+        }
+        let upper = self.copy_up()?;
+        if let Some(child_upper) = child.upper() {
+            // only whiteouts are left in the directory
+            for entry in read_dir(&child_upper)? {
+                child_upper.unlink(&entry.name)?;
+            }
+            upper.rmdir(name)?;
+        }
+        if child.lower.is_some() {
+            create_whiteout(&upper, name)?;
+        }
+        self.changed(name);
+        Ok(())
+    }
+
+    fn readlink(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        self.real().readlink(buf)
+    }
+
+    fn set_attr(&self, attr: InodeAttr) -> VfsResult<()> {
+        self.copy_up()?.set_attr(attr)
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        self.real().get_attr()
+    }
+
+    fn list_xattr(&self) -> VfsResult<Vec<String>> {
+        let mut list = self.real().list_xattr()?;
+        list.retain(|key| !key.starts_with(OVERLAY_XATTR_PREFIX));
+        Ok(list)
+    }
+
+    fn get_xattr(&self, key: &str) -> VfsResult<Vec<u8>> {
+        if key.starts_with(OVERLAY_XATTR_PREFIX) {
+            return Err(VfsError::NoData);
+        }
+        self.real().get_xattr(key)
+    }
+
+    fn set_xattr(&self, key: &str, value: &[u8], flag: VfsXattrFlag) -> VfsResult<()> {
+        if key.starts_with(OVERLAY_XATTR_PREFIX) {
+            return Err(VfsError::PermissionDenied);
+        }
+        self.copy_up()?.set_xattr(key, value, flag)
+    }
+
+    fn remove_xattr(&self, key: &str) -> VfsResult<()> {
+        if key.starts_with(OVERLAY_XATTR_PREFIX) {
+            return Err(VfsError::PermissionDenied);
+        }
+        self.copy_up()?.remove_xattr(key)
+    }
+
+    fn inode_type(&self) -> VfsNodeType {
+        self.ty
+    }
+
+    fn truncate(&self, len: u64) -> VfsResult<()> {
+        self.copy_up()?.truncate(len)
+    }
+
+    fn append(&self, buf: &[u8]) -> VfsResult<(u64, usize)> {
+        self.copy_up()?.append(buf)
+    }
+
+    fn fallocate(&self, mode: VfsFallocateMode, offset: u64, len: u64) -> VfsResult<()> {
+        self.copy_up()?.fallocate(mode, offset, len)
+    }
+
+    fn seek_data(&self, offset: u64) -> VfsResult<u64> {
+        self.real().seek_data(offset)
+    }
+
+    fn seek_hole(&self, offset: u64) -> VfsResult<u64> {
+        self.real().seek_hole(offset)
+    }
+
+    fn open_fifo(&self, mode: VfsAccessMode) -> VfsResult<Arc<dyn VfsFile>> {
+        self.real().open_fifo(mode)
+    }
+
+    fn bind_socket(&self, socket: Weak<dyn Any + Send + Sync>) -> VfsResult<()> {
+        self.real().bind_socket(socket)
+    }
+
+    fn get_socket(&self) -> VfsResult<Arc<dyn Any + Send + Sync>> {
+        self.real().get_socket()
+    }
+
+    fn rename_to(
+        &self,
+        old_name: &str,
+        new_parent: Arc<dyn VfsInode>,
+        new_name: &str,
+        flag: VfsRenameFlag,
+    ) -> VfsResult<()> {
+        let new_parent = new_parent
+            .downcast_arc::<OverlayInode<R>>()
+            .map_err(|_| VfsError::CrossDevice)?;
+        let child = self.lookup_child(old_name)?;
+        // the directories of the lower layer can't be moved, like linux without `redirect_dir`
+        if child.ty.is_dir() && child.lower.is_some() {
+            return Err(VfsError::CrossDevice);
+        }
+        let target = match new_parent.lookup_child(new_name) {
+            Ok(target) => Some(target),
+            Err(VfsError::NoEntry) => None,
+            Err(e) => return Err(e),
+        };
+        let exchange = flag.contains(VfsRenameFlag::RENAME_EXCHANGE);
+        if let Some(target) = &target {
+            if flag.contains(VfsRenameFlag::RENAME_NOREPLACE) {
+                return Err(VfsError::EExist);
+            }
+            if exchange && target.ty.is_dir() && target.lower.is_some() {
+                return Err(VfsError::CrossDevice);
+            }
+            if !exchange && target.ty.is_dir() {
+                if !child.ty.is_dir() {
+                    return Err(VfsError::IsDir);
+                }
+                if !target.listing()?.is_empty() {
+                    return Err(VfsError::NotEmpty);
+                }
+            }
+        } else if exchange {
+            return Err(VfsError::NoEntry);
+        }
+
+        let upper = self.copy_up()?;
+        let child_upper = child.copy_up()?;
+        let new_upper = new_parent.copy_up()?;
+        if let Some(target) = &target {
+            if exchange {
+                target.copy_up()?;
+            } else if let Some(target_upper) = target.upper() {
+                if target.ty.is_dir() {
+                    // remove the whiteouts, the directory is empty for the user
+                    for entry in read_dir(&target_upper)? {
+                        target_upper.unlink(&entry.name)?;
+                    }
+                    new_upper.rmdir(new_name)?;
+                }
+            }
+        }
+        // the moved directory should hide the lower directory with the same name
+        if child.ty.is_dir() && new_parent.lower_has(new_name)? {
+            child_upper.set_xattr(OPAQUE_XATTR, b"y", VfsXattrFlag::empty())?;
+        }
+        let mut upper_flag = flag - VfsRenameFlag::RENAME_WHITEOUT;
+        if !exchange && child.lower.is_some() {
+            upper_flag |= VfsRenameFlag::RENAME_WHITEOUT;
+        }
+        upper.rename_to(old_name, new_upper, new_name, upper_flag)?;
+        self.changed(old_name);
+        new_parent.changed(new_name);
+        Ok(())
+    }
+
+    fn update_time(&self, time: VfsTime, now: VfsTimeSpec) -> VfsResult<()> {
+        match (time, self.upper()) {
+            (_, Some(upper)) => upper.update_time(time, now),
+            // don't copy up the file only because it is read
+            (VfsTime::AccessTime(_), None) => Ok(()),
+            (_, None) => self.copy_up()?.update_time(time, now),
+        }
+    }
+    fn set_atime(&self, atime: VfsTimeSpec) -> VfsResult<()> {
+        match self.upper() {
+            Some(upper) => upper.set_atime(atime),
+            // don't copy up the file only because it is read
+            None => Ok(()),
+        }
+    }
+}
+
+impl<R: VfsRawMutex + 'static> OverlayInode<R> {
+    /// Whether the lower directory has `name` which is visible in the overlay
+    fn lower_has(&self, name: &str) -> VfsResult<bool> {
+        match &self.lower {
+            Some(lower) if !self.is_opaque() => match lower.lookup(name) {
+                Ok(_) => Ok(true),
+                Err(VfsError::NoEntry) => Ok(false),
+                Err(e) => Err(e),
+            },
+            _ => Ok(false),
+        }
+    }
+}
+
+fn is_whiteout(inode: &Arc<dyn VfsInode>) -> bool {
+    inode.inode_type() == VfsNodeType::CharDevice
+        && inode.get_attr().is_ok_and(|stat| stat.st_rdev == 0)
+}
+
+fn is_opaque(inode: &Arc<dyn VfsInode>) -> bool {
+    inode
+        .get_xattr(OPAQUE_XATTR)
+        .is_ok_and(|value| value == b"y")
+}
+
+fn create_whiteout(dir: &Arc<dyn VfsInode>, name: &str) -> VfsResult<()> {
+    dir.create(name, VfsNodeType::CharDevice, VfsNodePerm::empty(), Some(0))?;
+    Ok(())
+}
+
+fn read_dir(dir: &Arc<dyn VfsInode>) -> VfsResult<Vec<VfsDirEntry>> {
+    let mut entries = Vec::new();
+    while let Some(entry) = dir.readdir(entries.len())? {
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+fn copy_data(src: &Arc<dyn VfsInode>, dst: &Arc<dyn VfsInode>) -> VfsResult<()> {
+    let mut buf = vec![0; 4096];
+    let mut offset = 0;
+    loop {
+        let len = src.read_at(offset, &mut buf)?;
+        if len == 0 {
+            return Ok(());
+        }
+        dst.write_at(offset, &buf[..len])?;
+        offset += len as u64;
+    }
+}
+
+/// Copy the owner, mode, times and xattrs to the inode in the upper layer
+fn copy_attr(src: &Arc<dyn VfsInode>, dst: &Arc<dyn VfsInode>) -> VfsResult<()> {
+    let stat = src.get_attr()?;
+    let owner = InodeAttr {
+        valid: InodeAttrValid::UID
+            | InodeAttrValid::GID
+            | InodeAttrValid::ATIME
+            | InodeAttrValid::MTIME,
+        uid: stat.st_uid,
+        gid: stat.st_gid,
+        atime: stat.st_atime,
+        mtime: stat.st_mtime,
+        ..Default::default()
+    };
+    // the mode is set after the owner, changing the owner clears the set-user-ID bit, and
+    // the node permission doesn't have the set-user-ID, set-group-ID and sticky bits
+    let mode = InodeAttr {
+        valid: InodeAttrValid::MODE,
+        mode: stat.st_mode & 0o7777,
+        ..Default::default()
+    };
+    for attr in [owner, mode] {
+        match dst.set_attr(attr) {
+            Ok(()) | Err(VfsError::NoSys) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let keys = match src.list_xattr() {
+        Ok(keys) => keys,
+        Err(VfsError::NoSys) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    for key in keys {
+        let value = src.get_xattr(&key)?;
+        dst.set_xattr(&key, &value, VfsXattrFlag::empty())?;
+    }
+    Ok(())
+}
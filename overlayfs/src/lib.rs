@@ -0,0 +1,150 @@
+//! A union file system which stacks a writable upper directory on a read-only lower one
+//!
+//! The layers are given by the mount data like linux, e.g. `lowerdir=/lower,upperdir=/upper`.
+//! If `upperdir` is missing, the overlay is read-only.
+//!
+//! - Files are copied up to the upper layer before they are modified.
+//! - Removing a file of the lower layer leaves a whiteout, a character device with device
+//!   number 0, in the upper layer.
+//! - A directory created over a whiteout is marked as opaque by the xattr
+//!   `trusted.overlay.opaque`, so the lower directory is not merged into it.
+#![cfg_attr(not(test), no_std)]
+extern crate alloc;
+
+mod inode;
+
+use alloc::{
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+pub use inode::OverlayInode;
+use log::info;
+use unifs::{dentry::UniFsDentry, VfsRawMutex};
+use vfscore::{
+    dentry::VfsDentry,
+    error::VfsError,
+    fstype::{FileSystemFlags, VfsFsType},
+    inode::VfsInode,
+    superblock::{SuperType, VfsSuperBlock},
+    utils::VfsFsStat,
+    VfsResult,
+};
+
+pub trait OverlayFsProvider: Send + Sync + Clone {
+    /// Find the directory `path` which is used as a layer
+    fn lookup(&self, path: &str) -> VfsResult<Arc<dyn VfsDentry>>;
+}
+
+pub struct OverlayFs<T: Send + Sync, R: VfsRawMutex> {
+    provider: T,
+    sb_container: lock_api::Mutex<R, Vec<Arc<OverlaySuperBlock<R>>>>,
+}
+
+impl<T: OverlayFsProvider, R: VfsRawMutex + 'static> OverlayFs<T, R> {
+    pub fn new(provider: T) -> Self {
+        Self {
+            provider,
+            sb_container: lock_api::Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl<T: OverlayFsProvider + 'static, R: VfsRawMutex + 'static> VfsFsType for OverlayFs<T, R> {
+    fn mount(
+        self: Arc<Self>,
+        _flags: u32,
+        _ab_mnt: &str,
+        _dev: Option<Arc<dyn VfsInode>>,
+        data: &[u8],
+    ) -> VfsResult<Arc<dyn VfsDentry>> {
+        let data = core::str::from_utf8(data).map_err(|_| VfsError::Invalid)?;
+        let mut lower = None;
+        let mut upper = None;
+        for option in data.split(',').filter(|option| !option.is_empty()) {
+            match option.split_once('=') {
+                Some(("lowerdir", path)) => lower = Some(self.provider.lookup(path)?.inode()?),
+                Some(("upperdir", path)) => upper = Some(self.provider.lookup(path)?.inode()?),
+                _ => return Err(VfsError::Invalid),
+            }
+        }
+        let lower = lower.ok_or(VfsError::Invalid)?;
+        if !lower.inode_type().is_dir() || upper.as_ref().is_some_and(|u| !u.inode_type().is_dir())
+        {
+            return Err(VfsError::NotDir);
+        }
+        let sb = Arc::new(OverlaySuperBlock::<R> {
+            fs_type: Arc::downgrade(&(self.clone() as Arc<dyn VfsFsType>)),
+            root: lock_api::Mutex::new(None),
+            upper_sb: upper.as_ref().map(|u| u.get_super_block()).transpose()?,
+            lower_sb: lower.get_super_block()?,
+        });
+        let root = OverlayInode::new(&sb, None, upper, Some(lower));
+        sb.root.lock().replace(root.clone());
+        self.sb_container.lock().push(sb);
+        let parent = Weak::<UniFsDentry<R>>::new();
+        Ok(Arc::new(UniFsDentry::<R>::root(root, parent)))
+    }
+
+    fn kill_sb(&self, sb: Arc<dyn VfsSuperBlock>) -> VfsResult<()> {
+        let sb = sb
+            .downcast_arc::<OverlaySuperBlock<R>>()
+            .map_err(|_| VfsError::Invalid)?;
+        let mut sb_container = self.sb_container.lock();
+        let index = sb_container
+            .iter()
+            .position(|isb| Arc::ptr_eq(isb, &sb))
+            .ok_or(VfsError::Invalid)?;
+        sb.sync_fs(true)?;
+        sb.root.lock().take();
+        sb_container.remove(index);
+        info!("kill overlay sb success");
+        Ok(())
+    }
+
+    fn fs_flag(&self) -> FileSystemFlags {
+        FileSystemFlags::empty()
+    }
+
+    fn fs_name(&self) -> String {
+        "overlay".to_string()
+    }
+}
+
+pub struct OverlaySuperBlock<R: VfsRawMutex> {
+    fs_type: Weak<dyn VfsFsType>,
+    root: lock_api::Mutex<R, Option<Arc<OverlayInode<R>>>>,
+    upper_sb: Option<Arc<dyn VfsSuperBlock>>,
+    lower_sb: Arc<dyn VfsSuperBlock>,
+}
+
+impl<R: VfsRawMutex + 'static> VfsSuperBlock for OverlaySuperBlock<R> {
+    fn sync_fs(&self, wait: bool) -> VfsResult<()> {
+        match &self.upper_sb {
+            Some(sb) => sb.sync_fs(wait),
+            None => Ok(()),
+        }
+    }
+
+    fn stat_fs(&self) -> VfsResult<VfsFsStat> {
+        // the free space is the space of the upper layer
+        self.upper_sb.as_ref().unwrap_or(&self.lower_sb).stat_fs()
+    }
+
+    fn super_type(&self) -> SuperType {
+        SuperType::Independent
+    }
+
+    fn fs_type(&self) -> Arc<dyn VfsFsType> {
+        self.fs_type.upgrade().unwrap()
+    }
+
+    fn root_inode(&self) -> VfsResult<Arc<dyn VfsInode>> {
+        self.root
+            .lock()
+            .clone()
+            .map(|root| root as Arc<dyn VfsInode>)
+            .ok_or(VfsError::Invalid)
+    }
+}
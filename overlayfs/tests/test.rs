@@ -0,0 +1,242 @@
+use std::sync::Arc;
+
+use overlayfs::{OverlayFs, OverlayFsProvider};
+use ramfs::{RamFs, RamFsProvider};
+use spin::mutex::Mutex;
+use vfscore::{
+    dentry::VfsDentry,
+    error::VfsError,
+    fstype::VfsFsType,
+    inode::{InodeAttr, InodeAttrValid, VfsInode},
+    utils::{VfsNodeType, VfsRenameFlag, VfsTimeSpec},
+    VfsResult,
+};
+
+#[derive(Clone)]
+struct RamFsProviderImpl;
+impl RamFsProvider for RamFsProviderImpl {
+    fn current_time(&self) -> VfsTimeSpec {
+        Default::default()
+    }
+}
+
+#[derive(Clone)]
+struct Layers {
+    /// Keep the layers alive
+    _ramfs: Arc<dyn VfsFsType>,
+    lower: Arc<dyn VfsDentry>,
+    upper: Arc<dyn VfsDentry>,
+}
+
+impl OverlayFsProvider for Layers {
+    fn lookup(&self, path: &str) -> VfsResult<Arc<dyn VfsDentry>> {
+        match path {
+            "/lower" => Ok(self.lower.clone()),
+            "/upper" => Ok(self.upper.clone()),
+            _ => Err(VfsError::NoEntry),
+        }
+    }
+}
+
+struct Overlay {
+    /// Keep the overlay alive
+    _fs: Arc<dyn VfsFsType>,
+    lower: Arc<dyn VfsInode>,
+    upper: Arc<dyn VfsInode>,
+    root: Arc<dyn VfsInode>,
+}
+
+/// Create the lower layer with `/a`, `/b` and `/dir/c` and mount the overlay
+fn make_overlay() -> Overlay {
+    let ramfs: Arc<dyn VfsFsType> = Arc::new(RamFs::<_, Mutex<()>>::new(RamFsProviderImpl));
+    let lower = ramfs.clone().mount(0, "/lower", None, &[]).unwrap();
+    let upper = ramfs.clone().mount(0, "/upper", None, &[]).unwrap();
+    let lower_root = lower.inode().unwrap();
+    let a = lower_root
+        .create("a", VfsNodeType::File, "rw-r--r--".into(), None)
+        .unwrap();
+    a.write_at(0, b"lower").unwrap();
+    lower_root
+        .create("b", VfsNodeType::File, "rw-r--r--".into(), None)
+        .unwrap();
+    let dir = lower_root
+        .create("dir", VfsNodeType::Dir, "rwxr-xr-x".into(), None)
+        .unwrap();
+    dir.create("c", VfsNodeType::File, "rw-r--r--".into(), None)
+        .unwrap();
+    let upper_root = upper.inode().unwrap();
+    let layers = Layers {
+        _ramfs: ramfs,
+        lower,
+        upper,
+    };
+    let fs: Arc<dyn VfsFsType> = Arc::new(OverlayFs::<_, Mutex<()>>::new(layers));
+    let root = fs
+        .clone()
+        .mount(0, "/", None, b"lowerdir=/lower,upperdir=/upper")
+        .unwrap();
+    Overlay {
+        _fs: fs,
+        lower: lower_root,
+        upper: upper_root,
+        root: root.inode().unwrap(),
+    }
+}
+
+fn names(dir: &Arc<dyn VfsInode>) -> Vec<String> {
+    let mut names = Vec::new();
+    while let Some(entry) = dir.readdir(names.len()).unwrap() {
+        names.push(entry.name);
+    }
+    names.sort();
+    names
+}
+
+fn is_whiteout(dir: &Arc<dyn VfsInode>, name: &str) -> bool {
+    let inode = dir.lookup(name).unwrap();
+    inode.inode_type() == VfsNodeType::CharDevice && inode.get_attr().unwrap().st_rdev == 0
+}
+
+#[test]
+fn test_copy_up() {
+    let Overlay {
+        _fs,
+        lower,
+        upper,
+        root,
+    } = make_overlay();
+    let a = root.lookup("a").unwrap();
+    let mut buf = [0; 5];
+    a.read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf, b"lower");
+    assert!(upper.lookup("a").is_err());
+
+    a.write_at(0, b"upper").unwrap();
+    a.read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf, b"upper");
+    lower.lookup("a").unwrap().read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf, b"lower");
+    upper.lookup("a").unwrap().read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf, b"upper");
+
+    // the parent directories are copied up too
+    let c = root.lookup("dir").unwrap().lookup("c").unwrap();
+    c.truncate(10).unwrap();
+    assert_eq!(
+        upper
+            .lookup("dir")
+            .unwrap()
+            .lookup("c")
+            .unwrap()
+            .get_attr()
+            .unwrap()
+            .st_size,
+        10
+    );
+
+    // the users of a file share its upper inode
+    let b1 = root.lookup("b").unwrap();
+    let b2 = root.lookup("b").unwrap();
+    b1.write_at(0, b"new").unwrap();
+    let mut buf = [0; 3];
+    assert_eq!(b2.read_at(0, &mut buf).unwrap(), 3);
+    assert_eq!(&buf, b"new");
+}
+
+#[test]
+fn test_copy_up_mode() {
+    let Overlay {
+        _fs,
+        lower,
+        upper,
+        root,
+    } = make_overlay();
+    lower
+        .lookup("a")
+        .unwrap()
+        .set_attr(InodeAttr {
+            valid: InodeAttrValid::MODE | InodeAttrValid::UID,
+            mode: 0o4755,
+            uid: 1000,
+            ..Default::default()
+        })
+        .unwrap();
+    root.lookup("a").unwrap().truncate(1).unwrap();
+    let stat = upper.lookup("a").unwrap().get_attr().unwrap();
+    assert_eq!(stat.st_mode & 0o7777, 0o4755);
+    assert_eq!(stat.st_uid, 1000);
+}
+
+#[test]
+fn test_whiteout() {
+    let Overlay {
+        _fs,
+        lower,
+        upper,
+        root,
+    } = make_overlay();
+    root.unlink("a").unwrap();
+    assert!(matches!(root.lookup("a"), Err(VfsError::NoEntry)));
+    assert!(is_whiteout(&upper, "a"));
+    assert!(lower.lookup("a").is_ok());
+    assert_eq!(names(&root), ["b", "dir"]);
+
+    // a new file replaces the whiteout
+    root.create("a", VfsNodeType::File, "rw-r--r--".into(), None)
+        .unwrap();
+    assert_eq!(root.lookup("a").unwrap().get_attr().unwrap().st_size, 0);
+    assert_eq!(upper.lookup("a").unwrap().inode_type(), VfsNodeType::File);
+}
+
+#[test]
+fn test_opaque_dir() {
+    let Overlay {
+        _fs, upper, root, ..
+    } = make_overlay();
+    let dir = root.lookup("dir").unwrap();
+    assert!(matches!(root.rmdir("dir"), Err(VfsError::NotEmpty)));
+    dir.unlink("c").unwrap();
+    assert!(names(&dir).is_empty());
+    root.rmdir("dir").unwrap();
+    assert!(is_whiteout(&upper, "dir"));
+
+    // the lower directory is not merged into the new one
+    let dir = root
+        .create("dir", VfsNodeType::Dir, "rwxr-xr-x".into(), None)
+        .unwrap();
+    assert!(names(&dir).is_empty());
+    assert!(dir.lookup("c").is_err());
+    assert!(dir.list_xattr().unwrap().is_empty());
+}
+
+#[test]
+fn test_merged_readdir() {
+    let Overlay { _fs, root, .. } = make_overlay();
+    root.create("d", VfsNodeType::File, "rw-r--r--".into(), None)
+        .unwrap();
+    root.lookup("a").unwrap().write_at(0, b"x").unwrap();
+    assert_eq!(names(&root), ["a", "b", "d", "dir"]);
+    let dir = root.lookup("dir").unwrap();
+    dir.create("e", VfsNodeType::File, "rw-r--r--".into(), None)
+        .unwrap();
+    assert_eq!(names(&dir), ["c", "e"]);
+}
+
+#[test]
+fn test_rename() {
+    let Overlay {
+        _fs, upper, root, ..
+    } = make_overlay();
+    root.rename_to("a", root.clone(), "f", VfsRenameFlag::empty())
+        .unwrap();
+    assert!(matches!(root.lookup("a"), Err(VfsError::NoEntry)));
+    assert!(is_whiteout(&upper, "a"));
+    let mut buf = [0; 5];
+    root.lookup("f").unwrap().read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf, b"lower");
+    assert_eq!(names(&root), ["b", "dir", "f"]);
+    assert!(matches!(
+        root.rename_to("dir", root.clone(), "g", VfsRenameFlag::empty()),
+        Err(VfsError::CrossDevice)
+    ));
+}
@@ -3,24 +3,25 @@ use alloc::{
     vec::Vec,
 };
 
-use unifs::inode::{basic_file_stat, UniFsDirInode};
+use unifs::inode::{basic_file_stat, basic_statx, UniFsDirInode};
 use vfscore::{
     error::VfsError,
     file::VfsFile,
     impl_dir_inode_default,
-    inode::{InodeAttr, VfsInode},
+    inode::{InodeAttr, InodeAttrValid, VfsInode},
     superblock::VfsSuperBlock,
     utils::{
-        VfsDirEntry, VfsInodeMode, VfsNodePerm, VfsNodeType, VfsRenameFlag, VfsTime, VfsTimeSpec,
+        VfsDirEntry, VfsInodeMode, VfsNodePerm, VfsNodeType, VfsRenameFlag, VfsStatx, VfsStatxMask,
+        VfsTime, VfsTimeSpec, VfsXattrFlag,
     },
     VfsResult,
 };
 
 use super::*;
-use crate::inode::{file::RamFsFileInode, symlink::RamFsSymLinkInode};
+use crate::inode::{file::RamFsFileInode, special::RamFsSpecialInode, symlink::RamFsSymLinkInode};
 pub struct RamFsDirInode<T: Send + Sync, R: VfsRawMutex> {
     inode: UniFsDirInode<T, R>,
-    ext_attr: lock_api::Mutex<R, BTreeMap<String, String>>,
+    ext_attr: lock_api::Mutex<R, ExtAttr>,
 }
 
 impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> RamFsDirInode<T, R> {
@@ -66,7 +67,7 @@ impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> VfsInode for RamFsDir
         name: &str,
         ty: VfsNodeType,
         perm: VfsNodePerm,
-        _rdev: Option<u64>,
+        rdev: Option<u64>,
     ) -> VfsResult<Arc<dyn VfsInode>> {
         let sb = self
             .get_super_block()?
@@ -91,6 +92,17 @@ impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> VfsInode for RamFsDir
                 inode_number,
                 perm,
             )),
+            VfsNodeType::CharDevice
+            | VfsNodeType::BlockDevice
+            | VfsNodeType::Fifo
+            | VfsNodeType::Socket => Arc::new(RamFsSpecialInode::<_, R>::new(
+                &sb,
+                self.inode.basic.provider.clone(),
+                inode_number,
+                perm,
+                ty,
+                rdev.unwrap_or(0),
+            )),
             _ => {
                 return Err(VfsError::Invalid);
             }
@@ -102,22 +114,42 @@ impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> VfsInode for RamFsDir
             .push((name.to_string(), inode_number));
         Ok(inode)
     }
-    fn link(&self, name: &str, src: Arc<dyn VfsInode>) -> VfsResult<Arc<dyn VfsInode>> {
+    fn tmpfile(&self, perm: VfsNodePerm) -> VfsResult<Arc<dyn VfsInode>> {
         let sb = self
             .get_super_block()?
             .downcast_arc::<UniFsSuperBlock<R>>()
             .map_err(|_| VfsError::Invalid)?;
+        let inode_number = sb
+            .inode_index
+            .fetch_add(1, core::sync::atomic::Ordering::SeqCst);
         sb.inode_count
             .fetch_add(1, core::sync::atomic::Ordering::SeqCst);
-
+        let inode =
+            RamFsFileInode::<_, R>::new(&sb, self.inode.basic.provider.clone(), inode_number, perm);
+        // the file is not in any directory, it is freed when it is dropped
+        inode.update_metadata(|meta| meta.inner.lock().link_count = 0);
+        let inode: Arc<dyn VfsInode> = Arc::new(inode);
+        sb.insert_orphan(inode_number, &inode);
+        Ok(inode)
+    }
+    fn link(&self, name: &str, src: Arc<dyn VfsInode>) -> VfsResult<Arc<dyn VfsInode>> {
         let inode = src
             .downcast_arc::<RamFsFileInode<T, R>>()
             .map_err(|_| VfsError::Invalid)?;
 
-        let inode_number = inode.update_metadata(|meta| {
-            meta.inner.lock().link_count += 1;
-            meta.inode_number
+        let (inode_number, link_count) = inode.update_metadata(|meta| {
+            let mut inner = meta.inner.lock();
+            inner.link_count += 1;
+            (meta.inode_number, inner.link_count)
         });
+        if link_count == 1 {
+            // an unnamed file gets its first link
+            let sb = self
+                .get_super_block()?
+                .downcast_arc::<UniFsSuperBlock<R>>()
+                .map_err(|_| VfsError::Invalid)?;
+            sb.insert_inode(inode_number, inode.clone());
+        }
         self.inode
             .children
             .lock()
@@ -157,16 +189,17 @@ impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> VfsInode for RamFsDir
             gen!(RamFsFileInode)
         } else if inode.inode_type() == VfsNodeType::SymLink {
             gen!(RamFsSymLinkInode)
+        } else if inode.inode_type() != VfsNodeType::Dir {
+            gen!(RamFsSpecialInode)
         } else {
             return Err(VfsError::Invalid);
         };
 
+        self.inode.children.lock().remove(index);
+        // the inode is freed when it is not open any more
         if link_count == 0 {
-            sb.inode_count
-                .fetch_sub(1, core::sync::atomic::Ordering::SeqCst);
             sb.remove_inode(inode_number);
-        } // delete inode from sb
-        self.inode.children.lock().remove(index);
+        }
         Ok(())
     }
 
@@ -198,27 +231,60 @@ impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> VfsInode for RamFsDir
     }
 
     fn rmdir(&self, name: &str) -> VfsResult<()> {
-        self.unlink(name)
+        let sb = self
+            .get_super_block()?
+            .downcast_arc::<UniFsSuperBlock<R>>()
+            .map_err(|_| VfsError::Invalid)?;
+        let dir = self
+            .inode
+            .lookup(name)?
+            .downcast_arc::<RamFsDirInode<T, R>>()
+            .map_err(|_| VfsError::NotDir)?;
+        if !dir.inode.children.lock().is_empty() {
+            return Err(VfsError::NotEmpty);
+        }
+        let mut children = self.inode.children.lock();
+        let index = children
+            .iter()
+            .position(|(n, _)| n == name)
+            .ok_or(VfsError::NoEntry)?;
+        let (_, inode_number) = children.remove(index);
+        drop(children);
+        sb.remove_inode(inode_number);
+        Ok(())
     }
 
     fn set_attr(&self, attr: InodeAttr) -> VfsResult<()> {
-        set_attr(&self.inode.basic, attr);
+        if attr.valid.contains(InodeAttrValid::SIZE) {
+            return Err(VfsError::IsDir);
+        }
+        set_attr(&self.inode.basic, VfsNodeType::Dir, &attr);
         Ok(())
     }
     fn get_attr(&self) -> VfsResult<VfsFileStat> {
         let mut stat = basic_file_stat(&self.inode.basic);
         stat.st_size = 4096;
-        stat.st_mode = VfsInodeMode::from(
-            VfsNodePerm::from_bits_truncate(stat.st_mode as u16),
-            VfsNodeType::Dir,
-        )
-        .bits();
+        stat.st_mode = VfsInodeMode::from_bits_truncate(stat.st_mode)
+            .with_type(VfsNodeType::Dir)
+            .bits();
         Ok(stat)
     }
+    fn get_statx(&self, _mask: VfsStatxMask) -> VfsResult<VfsStatx> {
+        Ok(basic_statx(&self.inode.basic, self.get_attr()?))
+    }
     fn list_xattr(&self) -> VfsResult<Vec<String>> {
         let res = self.ext_attr.lock().keys().cloned().collect();
         Ok(res)
     }
+    fn get_xattr(&self, key: &str) -> VfsResult<Vec<u8>> {
+        get_xattr(&self.ext_attr.lock(), key)
+    }
+    fn set_xattr(&self, key: &str, value: &[u8], flag: VfsXattrFlag) -> VfsResult<()> {
+        set_xattr(&mut self.ext_attr.lock(), key, value, flag)
+    }
+    fn remove_xattr(&self, key: &str) -> VfsResult<()> {
+        remove_xattr(&mut self.ext_attr.lock(), key)
+    }
 
     fn inode_type(&self) -> VfsNodeType {
         VfsNodeType::Dir
@@ -233,8 +299,37 @@ impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> VfsInode for RamFsDir
         let new_parent = new_parent
             .downcast_arc::<RamFsDirInode<T, R>>()
             .map_err(|_| VfsError::Invalid)?;
-        self.inode
-            .rename_to(old_name, &new_parent.inode, new_name, flag)
+        if !flag.contains(VfsRenameFlag::RENAME_EXCHANGE) {
+            // the replaced inode loses a link, it may have other links or still be open
+            let src = self.inode.lookup(old_name)?;
+            if let Ok(target) = new_parent.inode.lookup(new_name) {
+                if Arc::ptr_eq(&src, &target) {
+                    // the two names are links to the same inode
+                    return Ok(());
+                }
+                if target.inode_type() == VfsNodeType::Dir {
+                    new_parent.rmdir(new_name)?;
+                } else {
+                    new_parent.unlink(new_name)?;
+                }
+            }
+        }
+        self.inode.rename_to(
+            old_name,
+            &new_parent.inode,
+            new_name,
+            flag - VfsRenameFlag::RENAME_WHITEOUT,
+        )?;
+        if flag.contains(VfsRenameFlag::RENAME_WHITEOUT) {
+            // leave a whiteout, a character device with device number 0, at the old name
+            self.create(
+                old_name,
+                VfsNodeType::CharDevice,
+                VfsNodePerm::empty(),
+                Some(0),
+            )?;
+        }
+        Ok(())
     }
 
     impl_dir_inode_default!();
@@ -242,4 +337,15 @@ impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> VfsInode for RamFsDir
     fn update_time(&self, time: VfsTime, now: VfsTimeSpec) -> VfsResult<()> {
         self.inode.update_time(time, now)
     }
+    fn set_atime(&self, atime: VfsTimeSpec) -> VfsResult<()> {
+        self.inode.set_atime(atime)
+    }
+    fn set_times(
+        &self,
+        atime: Option<VfsTimeSpec>,
+        mtime: Option<VfsTimeSpec>,
+        now: VfsTimeSpec,
+    ) -> VfsResult<()> {
+        self.inode.set_times(atime, mtime, now)
+    }
 }
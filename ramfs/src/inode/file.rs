@@ -1,15 +1,19 @@
-use alloc::{sync::Arc, vec::Vec};
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::{cell::UnsafeCell, ptr};
 
-use unifs::inode::basic_file_stat;
+use unifs::inode::{
+    basic_file_stat, basic_set_atime, basic_set_times, basic_statx, basic_update_time,
+};
 use vfscore::{
+    cache::PAGE_SIZE,
     error::VfsError,
-    file::VfsFile,
+    file::{VfsFile, VfsMmapPage},
     impl_file_inode_default,
-    inode::{InodeAttr, VfsInode},
+    inode::{generic_copy_file_range, InodeAttr, InodeAttrValid, VfsInode},
     superblock::VfsSuperBlock,
     utils::{
-        VfsFileStat, VfsInodeMode, VfsNodePerm, VfsNodeType, VfsPollEvents, VfsRenameFlag, VfsTime,
-        VfsTimeSpec,
+        VfsFallocateMode, VfsFileStat, VfsInodeMode, VfsNodePerm, VfsNodeType, VfsPollEvents,
+        VfsRenameFlag, VfsStatx, VfsStatxMask, VfsTime, VfsTimeSpec, VfsXattrFlag,
     },
     VfsResult,
 };
@@ -19,10 +23,200 @@ use crate::RamFsProvider;
 pub struct RamFsFileInode<T: Send + Sync, R: VfsRawMutex> {
     basic: UniFsInodeSame<T, R>,
     inner: lock_api::Mutex<R, RamFsFileInodeInner>,
-    ext_attr: lock_api::Mutex<R, BTreeMap<String, String>>,
+    ext_attr: lock_api::Mutex<R, ExtAttr>,
 }
+/// The data of the file, the pages which are not allocated are holes which read as zero
 struct RamFsFileInodeInner {
-    data: Vec<u8>,
+    pages: BTreeMap<u64, Arc<RamFsPage>>,
+    size: u64,
+}
+
+/// A page of the file data, it is shared with the mappings of the file
+struct RamFsPage(UnsafeCell<[u8; PAGE_SIZE]>);
+
+// The page is accessed under the lock of the file, except by the mappings which share it
+// like the memory of a `MAP_SHARED` mapping.
+unsafe impl Send for RamFsPage {}
+unsafe impl Sync for RamFsPage {}
+
+impl RamFsPage {
+    fn new() -> Arc<Self> {
+        Arc::new(Self(UnsafeCell::new([0; PAGE_SIZE])))
+    }
+
+    /// Copy the data at `start` into `buf`, the caller holds the lock of the file
+    fn read(&self, start: usize, buf: &mut [u8]) {
+        assert!(start + buf.len() <= PAGE_SIZE);
+        unsafe {
+            let src = self.0.get().cast::<u8>().add(start);
+            ptr::copy_nonoverlapping(src, buf.as_mut_ptr(), buf.len());
+        }
+    }
+
+    /// Copy `buf` into the data at `start`, the caller holds the lock of the file
+    fn write(&self, start: usize, buf: &[u8]) {
+        assert!(start + buf.len() <= PAGE_SIZE);
+        unsafe {
+            let dst = self.0.get().cast::<u8>().add(start);
+            ptr::copy_nonoverlapping(buf.as_ptr(), dst, buf.len());
+        }
+    }
+
+    /// Zero the data in `start..end`, the caller holds the lock of the file
+    fn zero(&self, start: usize, end: usize) {
+        assert!(start <= end && end <= PAGE_SIZE);
+        unsafe {
+            let dst = self.0.get().cast::<u8>().add(start);
+            ptr::write_bytes(dst, 0, end - start);
+        }
+    }
+}
+
+impl RamFsFileInodeInner {
+    /// Change the size of the file, the data beyond the old size is zero
+    fn resize(&mut self, size: u64) {
+        // the tail of the last page may be written by the mappings
+        let tail = self.size.min(size);
+        if !tail.is_multiple_of(PAGE_SIZE as u64) {
+            self.zero(tail, PAGE_SIZE as u64);
+        }
+        if size < self.size {
+            let first = size.div_ceil(PAGE_SIZE as u64);
+            self.pages.split_off(&first);
+        }
+        self.size = size;
+    }
+
+    /// Zero the allocated pages in `offset..offset + len` within the page of `offset`
+    fn zero(&mut self, offset: u64, len: u64) {
+        let start = (offset % PAGE_SIZE as u64) as usize;
+        let end = (start as u64 + len).min(PAGE_SIZE as u64) as usize;
+        if let Some(page) = self.pages.get(&(offset / PAGE_SIZE as u64)) {
+            page.zero(start, end);
+        }
+    }
+
+    fn page(&mut self, index: u64) -> &Arc<RamFsPage> {
+        self.pages.entry(index).or_insert_with(RamFsPage::new)
+    }
+
+    /// Copy the data at `pos` within its page into `buf`, a hole reads as zero
+    fn read_page(&self, pos: u64, buf: &mut [u8]) {
+        match self.pages.get(&(pos / PAGE_SIZE as u64)) {
+            Some(page) => page.read((pos % PAGE_SIZE as u64) as usize, buf),
+            None => buf.fill(0),
+        }
+    }
+
+    fn read(&self, offset: u64, buf: &mut [u8]) -> usize {
+        let offset = offset.min(self.size);
+        let len = (self.size - offset).min(buf.len() as u64) as usize;
+        let mut count = 0;
+        while count < len {
+            let pos = offset + count as u64;
+            let start = (pos % PAGE_SIZE as u64) as usize;
+            let n = (PAGE_SIZE - start).min(len - count);
+            self.read_page(pos, &mut buf[count..count + n]);
+            count += n;
+        }
+        len
+    }
+
+    fn write(&mut self, offset: u64, buf: &[u8]) -> usize {
+        let end = offset + buf.len() as u64;
+        if end > self.size {
+            self.resize(end);
+        }
+        let mut count = 0;
+        while count < buf.len() {
+            let pos = offset + count as u64;
+            let start = (pos % PAGE_SIZE as u64) as usize;
+            let n = (PAGE_SIZE - start).min(buf.len() - count);
+            self.page(pos / PAGE_SIZE as u64)
+                .write(start, &buf[count..count + n]);
+            count += n;
+        }
+        buf.len()
+    }
+
+    fn fallocate(&mut self, mode: VfsFallocateMode, offset: u64, len: u64) -> VfsResult<()> {
+        if len == 0 {
+            return Err(VfsError::Invalid);
+        }
+        let end = offset.checked_add(len).ok_or(VfsError::Invalid)?;
+        let punch = mode.contains(VfsFallocateMode::FALLOC_FL_PUNCH_HOLE);
+        if punch && !mode.contains(VfsFallocateMode::FALLOC_FL_KEEP_SIZE) {
+            return Err(VfsError::Invalid);
+        }
+        if punch || mode.contains(VfsFallocateMode::FALLOC_FL_ZERO_RANGE) {
+            // zero the partial pages at both ends and the whole pages in the middle
+            let first = offset.div_ceil(PAGE_SIZE as u64);
+            let last = end / PAGE_SIZE as u64;
+            if first > last {
+                self.zero(offset, len);
+            } else {
+                self.zero(offset, first * PAGE_SIZE as u64 - offset);
+                if !end.is_multiple_of(PAGE_SIZE as u64) {
+                    self.zero(last * PAGE_SIZE as u64, end % PAGE_SIZE as u64);
+                }
+                let pages = self
+                    .pages
+                    .range(first..last)
+                    .map(|(index, _)| *index)
+                    .collect::<Vec<_>>();
+                for index in pages {
+                    if punch {
+                        self.pages.remove(&index);
+                    } else {
+                        self.pages[&index].zero(0, PAGE_SIZE);
+                    }
+                }
+            }
+        }
+        if !punch {
+            for index in offset / PAGE_SIZE as u64..end.div_ceil(PAGE_SIZE as u64) {
+                self.page(index);
+            }
+            if !mode.contains(VfsFallocateMode::FALLOC_FL_KEEP_SIZE) && end > self.size {
+                // the allocated pages beyond the old size are already zero
+                self.size = end;
+            }
+        }
+        Ok(())
+    }
+
+    /// Find the first offset from `offset` which is data or a hole
+    fn seek(&self, offset: u64, data: bool) -> VfsResult<u64> {
+        if offset >= self.size {
+            return Err(VfsError::ENXIO);
+        }
+        let first = offset / PAGE_SIZE as u64;
+        let mut pages = self.pages.range(first..).map(|(index, _)| *index);
+        let index = if data {
+            // the first allocated page
+            pages.next().unwrap_or(u64::MAX)
+        } else {
+            // the first gap between the allocated pages
+            let mut index = first;
+            for page in pages {
+                if page != index {
+                    break;
+                }
+                index += 1;
+            }
+            index
+        };
+        let pos = index.saturating_mul(PAGE_SIZE as u64).max(offset);
+        if pos >= self.size {
+            // there is an implicit hole at the end of the file
+            return if data {
+                Err(VfsError::ENXIO)
+            } else {
+                Ok(self.size)
+            };
+        }
+        Ok(pos)
+    }
 }
 
 impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> RamFsFileInode<T, R> {
@@ -34,7 +228,10 @@ impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> RamFsFileInode<T, R>
     ) -> Self {
         Self {
             basic: UniFsInodeSame::new(sb, provider, inode_number, perm),
-            inner: lock_api::Mutex::new(RamFsFileInodeInner { data: Vec::new() }),
+            inner: lock_api::Mutex::new(RamFsFileInodeInner {
+                pages: BTreeMap::new(),
+                size: 0,
+            }),
             ext_attr: lock_api::Mutex::new(BTreeMap::new()),
         }
     }
@@ -48,31 +245,59 @@ impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> RamFsFileInode<T, R>
 
 impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> VfsFile for RamFsFileInode<T, R> {
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
-        let inner = self.inner.lock();
-        let size = inner.data.len() as u64;
-        let offset = offset.min(size);
-        let len = (size - offset).min(buf.len() as u64) as usize;
-        let data = inner.data.as_slice();
-        buf[..len].copy_from_slice(&data[offset as usize..offset as usize + len]);
-        Ok(len)
+        Ok(self.inner.lock().read(offset, buf))
     }
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
         if buf.is_empty() {
             return Ok(0);
         }
+        Ok(self.inner.lock().write(offset, buf))
+    }
+    fn readv_at(&self, offset: u64, bufs: &mut [&mut [u8]]) -> VfsResult<usize> {
+        let inner = self.inner.lock();
+        let mut count = 0;
+        for buf in bufs.iter_mut() {
+            let len = inner.read(offset + count as u64, buf);
+            count += len;
+            if len < buf.len() {
+                break;
+            }
+        }
+        Ok(count)
+    }
+    fn writev_at(&self, offset: u64, bufs: &[&[u8]]) -> VfsResult<usize> {
+        let mut inner = self.inner.lock();
+        let mut count = 0;
+        for buf in bufs {
+            count += inner.write(offset + count as u64, buf);
+        }
+        Ok(count)
+    }
+    fn get_page(&self, offset: u64) -> VfsResult<VfsMmapPage> {
+        if !offset.is_multiple_of(PAGE_SIZE as u64) {
+            return Err(VfsError::Invalid);
+        }
         let mut inner = self.inner.lock();
-        let buf_len = buf.len();
-        let offset = offset as usize;
-        let content = &mut inner.data;
-        if offset + buf_len > content.len() {
-            content.resize(offset + buf_len, 0);
+        // like linux, the pages beyond the end of the file can't be mapped
+        if offset >= inner.size {
+            return Err(VfsError::Invalid);
         }
-        let dst = &mut content[offset..offset + buf_len];
-        dst.copy_from_slice(&buf[..dst.len()]);
-        Ok(buf.len())
+        let page = inner.page(offset / PAGE_SIZE as u64);
+        Ok(VfsMmapPage::Direct {
+            addr: page.0.get() as usize,
+            owner: page.clone(),
+        })
+    }
+    fn writeback_page(&self, _offset: u64, _page: &VfsMmapPage) -> VfsResult<()> {
+        let now = self.basic.provider.current_time();
+        let mut basic = self.basic.inner.lock();
+        basic.mtime = now;
+        basic.ctime = now;
+        Ok(())
     }
-    fn poll(&self, _event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
-        todo!()
+    fn poll(&self, event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
+        // a regular file never blocks
+        Ok(event & (VfsPollEvents::IN | VfsPollEvents::OUT))
     }
     fn ioctl(&self, _cmd: u32, _arg: usize) -> VfsResult<usize> {
         // let cmd = pconst::io::TeletypeCommand::try_from(cmd).map_err(|_| VfsError::Invalid)?;
@@ -88,50 +313,128 @@ impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> VfsInode for RamFsFil
     }
 
     fn node_perm(&self) -> VfsNodePerm {
-        self.basic.inner.lock().perm
+        self.basic.inner.lock().perm()
     }
 
     fn set_attr(&self, attr: InodeAttr) -> VfsResult<()> {
-        set_attr(&self.basic, attr);
+        if attr.valid.contains(InodeAttrValid::SIZE) {
+            self.truncate(attr.size)?;
+        }
+        set_attr(&self.basic, VfsNodeType::File, &attr);
         Ok(())
     }
 
     fn get_attr(&self) -> VfsResult<VfsFileStat> {
         let basic = &self.basic;
         let mut stat = basic_file_stat(basic);
-        stat.st_size = self.inner.lock().data.len() as u64;
-        stat.st_mode = VfsInodeMode::from(
-            VfsNodePerm::from_bits_truncate(stat.st_mode as u16),
-            VfsNodeType::File,
-        )
-        .bits();
+        let inner = self.inner.lock();
+        stat.st_size = inner.size;
+        stat.st_blocks = (inner.pages.len() * PAGE_SIZE / 512) as u64;
+        stat.st_mode = VfsInodeMode::from_bits_truncate(stat.st_mode)
+            .with_type(VfsNodeType::File)
+            .bits();
         Ok(stat)
     }
+    fn get_statx(&self, _mask: VfsStatxMask) -> VfsResult<VfsStatx> {
+        Ok(basic_statx(&self.basic, self.get_attr()?))
+    }
 
     fn list_xattr(&self) -> VfsResult<Vec<String>> {
         let res = self.ext_attr.lock().keys().cloned().collect();
         Ok(res)
     }
+    fn get_xattr(&self, key: &str) -> VfsResult<Vec<u8>> {
+        get_xattr(&self.ext_attr.lock(), key)
+    }
+    fn set_xattr(&self, key: &str, value: &[u8], flag: VfsXattrFlag) -> VfsResult<()> {
+        set_xattr(&mut self.ext_attr.lock(), key, value, flag)
+    }
+    fn remove_xattr(&self, key: &str) -> VfsResult<()> {
+        remove_xattr(&mut self.ext_attr.lock(), key)
+    }
     fn inode_type(&self) -> VfsNodeType {
         VfsNodeType::File
     }
 
     fn truncate(&self, len: u64) -> VfsResult<()> {
+        self.inner.lock().resize(len);
+        Ok(())
+    }
+    fn append(&self, buf: &[u8]) -> VfsResult<(u64, usize)> {
         let mut inner = self.inner.lock();
-        if len < inner.data.len() as u64 {
-            inner.data.truncate(len as _);
+        let offset = inner.size;
+        if buf.is_empty() {
+            return Ok((offset, 0));
+        }
+        Ok((offset, inner.write(offset, buf)))
+    }
+    fn fallocate(&self, mode: VfsFallocateMode, offset: u64, len: u64) -> VfsResult<()> {
+        self.inner.lock().fallocate(mode, offset, len)
+    }
+    fn seek_data(&self, offset: u64) -> VfsResult<u64> {
+        self.inner.lock().seek(offset, true)
+    }
+    fn seek_hole(&self, offset: u64) -> VfsResult<u64> {
+        self.inner.lock().seek(offset, false)
+    }
+    fn copy_file_range(
+        &self,
+        offset_in: u64,
+        dst: Arc<dyn VfsInode>,
+        offset_out: u64,
+        len: usize,
+    ) -> VfsResult<usize> {
+        let dst = match dst.downcast_arc::<Self>() {
+            Ok(dst) => dst,
+            Err(dst) => {
+                return generic_copy_file_range(self, offset_in, dst.as_ref(), offset_out, len)
+            }
+        };
+        if core::ptr::eq(self, dst.as_ref()) {
+            let mut inner = self.inner.lock();
+            let mut buf = vec![0; len.min((inner.size.saturating_sub(offset_in)) as usize)];
+            let len = inner.read(offset_in, &mut buf);
+            return Ok(inner.write(offset_out, &buf[..len]));
+        }
+        // lock the files in the order of their addresses to avoid deadlock
+        let (src_inner, mut dst_inner) = if (self as *const Self) < Arc::as_ptr(&dst) {
+            let src_inner = self.inner.lock();
+            (src_inner, dst.inner.lock())
         } else {
-            inner.data.resize(len as _, 0);
+            let dst_inner = dst.inner.lock();
+            (self.inner.lock(), dst_inner)
+        };
+        let offset_in = offset_in.min(src_inner.size);
+        let len = (src_inner.size - offset_in).min(len as u64) as usize;
+        let mut count = 0;
+        // copy page by page without allocating a buffer of the whole range
+        let mut data = [0; PAGE_SIZE];
+        while count < len {
+            let pos = offset_in + count as u64;
+            let start = (pos % PAGE_SIZE as u64) as usize;
+            let n = (PAGE_SIZE - start).min(len - count);
+            src_inner.read_page(pos, &mut data[..n]);
+            dst_inner.write(offset_out + count as u64, &data[..n]);
+            count += n;
         }
-        Ok(())
+        Ok(len)
     }
     impl_file_inode_default!();
     fn update_time(&self, time: VfsTime, now: VfsTimeSpec) -> VfsResult<()> {
-        match time {
-            VfsTime::ModifiedTime(t) => self.basic.inner.lock().mtime = t,
-            VfsTime::AccessTime(t) => self.basic.inner.lock().atime = t,
-        }
-        self.basic.inner.lock().ctime = now;
+        basic_update_time(&self.basic, time, now);
+        Ok(())
+    }
+    fn set_atime(&self, atime: VfsTimeSpec) -> VfsResult<()> {
+        basic_set_atime(&self.basic, atime);
+        Ok(())
+    }
+    fn set_times(
+        &self,
+        atime: Option<VfsTimeSpec>,
+        mtime: Option<VfsTimeSpec>,
+        now: VfsTimeSpec,
+    ) -> VfsResult<()> {
+        basic_set_times(&self.basic, atime, mtime, now);
         Ok(())
     }
 }
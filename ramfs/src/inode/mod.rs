@@ -1,18 +1,22 @@
 mod dir;
 mod file;
+mod special;
 mod symlink;
 
-use alloc::{collections::BTreeMap, string::String, sync::Arc};
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec::Vec};
 
 pub use dir::RamFsDirInode;
 pub use file::RamFsFileInode;
+pub use special::RamFsSpecialInode;
 use unifs::{
-    inode::{UniFsInodeAttr, UniFsInodeSame},
+    inode::{basic_set_attr, UniFsInodeAttr, UniFsInodeSame},
     UniFsSuperBlock,
 };
 use vfscore::{
+    error::VfsError,
     inode::InodeAttr,
-    utils::{VfsFileStat, VfsNodePerm},
+    utils::{VfsFileStat, VfsInodeMode, VfsNodePerm, VfsNodeType, VfsStatxAttr, VfsXattrFlag},
+    VfsResult,
 };
 
 use super::VfsRawMutex;
@@ -39,18 +43,45 @@ impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> RamFsInodeSameNew<T,
             provider,
             inner: lock_api::Mutex::new(UniFsInodeAttr {
                 link_count: 1,
+                uid: 0,
+                gid: 0,
                 atime: time,
                 mtime: time,
                 ctime: time,
-                perm,
+                btime: time,
+                mode: VfsInodeMode::from_bits_truncate(perm.bits() as u32),
+                flags: VfsStatxAttr::empty(),
             }),
         }
     }
 }
 
-fn set_attr<T: Send + Sync, R: VfsRawMutex>(basic: &UniFsInodeSame<T, R>, attr: InodeAttr) {
-    let mut inner = basic.inner.lock();
-    inner.atime = attr.atime;
-    inner.mtime = attr.mtime;
-    inner.ctime = attr.ctime;
+fn set_attr<T: RamFsProvider, R: VfsRawMutex>(
+    basic: &UniFsInodeSame<T, R>,
+    ty: VfsNodeType,
+    attr: &InodeAttr,
+) {
+    basic_set_attr(basic, ty, attr, basic.provider.current_time());
+}
+
+type ExtAttr = BTreeMap<String, Vec<u8>>;
+
+fn get_xattr(ext_attr: &ExtAttr, key: &str) -> VfsResult<Vec<u8>> {
+    ext_attr.get(key).cloned().ok_or(VfsError::NoData)
+}
+
+fn set_xattr(ext_attr: &mut ExtAttr, key: &str, value: &[u8], flag: VfsXattrFlag) -> VfsResult<()> {
+    let exist = ext_attr.contains_key(key);
+    if flag.contains(VfsXattrFlag::XATTR_CREATE) && exist {
+        return Err(VfsError::EExist);
+    }
+    if flag.contains(VfsXattrFlag::XATTR_REPLACE) && !exist {
+        return Err(VfsError::NoData);
+    }
+    ext_attr.insert(key.into(), value.to_vec());
+    Ok(())
+}
+
+fn remove_xattr(ext_attr: &mut ExtAttr, key: &str) -> VfsResult<()> {
+    ext_attr.remove(key).map(|_| ()).ok_or(VfsError::NoData)
 }
@@ -0,0 +1,167 @@
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::any::Any;
+
+use unifs::{
+    inode::{basic_file_stat, basic_set_atime, basic_set_times, basic_statx, basic_update_time},
+    pipe::{UniFsPipe, PIPE_SIZE},
+};
+use vfscore::{
+    error::VfsError,
+    file::VfsFile,
+    impl_common_inode_default,
+    inode::{InodeAttr, InodeAttrValid, VfsInode},
+    superblock::VfsSuperBlock,
+    utils::{
+        VfsAccessMode, VfsFileStat, VfsInodeMode, VfsNodePerm, VfsNodeType, VfsRenameFlag,
+        VfsStatx, VfsStatxMask, VfsTime, VfsTimeSpec, VfsXattrFlag,
+    },
+    VfsResult,
+};
+
+use super::*;
+use crate::RamFsProvider;
+
+/// The inode of device files, fifos and sockets
+///
+/// Ramfs only records the type and the device number, the kernel should find the real
+/// device by the device number. Every fifo has a pipe which is shared by all its opens, and a
+/// socket node keeps the socket bound to it.
+pub struct RamFsSpecialInode<T: Send + Sync, R: VfsRawMutex> {
+    basic: UniFsInodeSame<T, R>,
+    ty: VfsNodeType,
+    rdev: u64,
+    ext_attr: lock_api::Mutex<R, ExtAttr>,
+    pipe: Option<Arc<UniFsPipe<R>>>,
+    socket: lock_api::Mutex<R, Option<Weak<dyn Any + Send + Sync>>>,
+}
+
+impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> RamFsSpecialInode<T, R> {
+    pub fn new(
+        sb: &Arc<UniFsSuperBlock<R>>,
+        provider: T,
+        inode_number: u64,
+        perm: VfsNodePerm,
+        ty: VfsNodeType,
+        rdev: u64,
+    ) -> Self {
+        Self {
+            basic: UniFsInodeSame::new(sb, provider, inode_number, perm),
+            ty,
+            rdev,
+            ext_attr: lock_api::Mutex::new(BTreeMap::new()),
+            pipe: (ty == VfsNodeType::Fifo).then(|| UniFsPipe::new(PIPE_SIZE)),
+            socket: lock_api::Mutex::new(None),
+        }
+    }
+    pub fn update_metadata<F, Res>(&self, f: F) -> Res
+    where
+        F: FnOnce(&UniFsInodeSame<T, R>) -> Res,
+    {
+        f(&self.basic)
+    }
+}
+
+impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> VfsFile for RamFsSpecialInode<T, R> {}
+
+impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> VfsInode for RamFsSpecialInode<T, R> {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        let res = self.basic.sb.upgrade().unwrap();
+        Ok(res)
+    }
+
+    fn node_perm(&self) -> VfsNodePerm {
+        self.basic.inner.lock().perm()
+    }
+
+    fn readlink(&self, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::Invalid)
+    }
+
+    fn set_attr(&self, attr: InodeAttr) -> VfsResult<()> {
+        if attr.valid.contains(InodeAttrValid::SIZE) {
+            return Err(VfsError::Invalid);
+        }
+        set_attr(&self.basic, self.ty, &attr);
+        Ok(())
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        let mut basic = basic_file_stat(&self.basic);
+        basic.st_rdev = self.rdev;
+        basic.st_mode = VfsInodeMode::from_bits_truncate(basic.st_mode)
+            .with_type(self.ty)
+            .bits();
+        Ok(basic)
+    }
+    fn get_statx(&self, _mask: VfsStatxMask) -> VfsResult<VfsStatx> {
+        Ok(basic_statx(&self.basic, self.get_attr()?))
+    }
+    fn list_xattr(&self) -> VfsResult<Vec<String>> {
+        let res = self.ext_attr.lock().keys().cloned().collect();
+        Ok(res)
+    }
+    fn get_xattr(&self, key: &str) -> VfsResult<Vec<u8>> {
+        get_xattr(&self.ext_attr.lock(), key)
+    }
+    fn set_xattr(&self, key: &str, value: &[u8], flag: VfsXattrFlag) -> VfsResult<()> {
+        set_xattr(&mut self.ext_attr.lock(), key, value, flag)
+    }
+    fn remove_xattr(&self, key: &str) -> VfsResult<()> {
+        remove_xattr(&mut self.ext_attr.lock(), key)
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        self.ty
+    }
+
+    impl_common_inode_default!();
+
+    fn open_fifo(&self, mode: VfsAccessMode) -> VfsResult<Arc<dyn VfsFile>> {
+        let pipe = self.pipe.as_ref().ok_or(VfsError::Invalid)?;
+        Ok(pipe.open(
+            mode.contains(VfsAccessMode::R_OK),
+            mode.contains(VfsAccessMode::W_OK),
+        ))
+    }
+
+    fn bind_socket(&self, socket: Weak<dyn Any + Send + Sync>) -> VfsResult<()> {
+        if self.ty != VfsNodeType::Socket {
+            return Err(VfsError::Invalid);
+        }
+        let mut bound = self.socket.lock();
+        if bound.as_ref().is_some_and(|old| old.strong_count() > 0) {
+            return Err(VfsError::EBUSY);
+        }
+        *bound = Some(socket);
+        Ok(())
+    }
+
+    fn get_socket(&self) -> VfsResult<Arc<dyn Any + Send + Sync>> {
+        self.socket
+            .lock()
+            .as_ref()
+            .and_then(Weak::upgrade)
+            .ok_or(VfsError::ECONNREFUSED)
+    }
+
+    fn update_time(&self, time: VfsTime, now: VfsTimeSpec) -> VfsResult<()> {
+        basic_update_time(&self.basic, time, now);
+        Ok(())
+    }
+    fn set_atime(&self, atime: VfsTimeSpec) -> VfsResult<()> {
+        basic_set_atime(&self.basic, atime);
+        Ok(())
+    }
+    fn set_times(
+        &self,
+        atime: Option<VfsTimeSpec>,
+        mtime: Option<VfsTimeSpec>,
+        now: VfsTimeSpec,
+    ) -> VfsResult<()> {
+        basic_set_times(&self.basic, atime, mtime, now);
+        Ok(())
+    }
+}
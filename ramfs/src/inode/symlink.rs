@@ -1,14 +1,17 @@
 use alloc::{string::String, sync::Arc, vec::Vec};
 
-use unifs::inode::basic_file_stat;
+use unifs::inode::{
+    basic_file_stat, basic_set_atime, basic_set_times, basic_statx, basic_update_time,
+};
 use vfscore::{
     error::VfsError,
     file::VfsFile,
     impl_common_inode_default,
-    inode::{InodeAttr, VfsInode},
+    inode::{InodeAttr, InodeAttrValid, VfsInode},
     superblock::VfsSuperBlock,
     utils::{
-        VfsFileStat, VfsInodeMode, VfsNodePerm, VfsNodeType, VfsRenameFlag, VfsTime, VfsTimeSpec,
+        VfsFileStat, VfsInodeMode, VfsNodePerm, VfsNodeType, VfsRenameFlag, VfsStatx, VfsStatxMask,
+        VfsTime, VfsTimeSpec, VfsXattrFlag,
     },
     VfsResult,
 };
@@ -18,7 +21,7 @@ use crate::RamFsProvider;
 pub struct RamFsSymLinkInode<T: Send + Sync, R: VfsRawMutex> {
     basic: UniFsInodeSame<T, R>,
     inner: lock_api::Mutex<R, String>,
-    ext_attr: lock_api::Mutex<R, BTreeMap<String, String>>,
+    ext_attr: lock_api::Mutex<R, ExtAttr>,
 }
 
 impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> RamFsSymLinkInode<T, R> {
@@ -56,7 +59,7 @@ impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> VfsInode for RamFsSym
     }
 
     fn node_perm(&self) -> VfsNodePerm {
-        self.basic.inner.lock().perm
+        self.basic.inner.lock().perm()
     }
 
     fn readlink(&self, buf: &mut [u8]) -> VfsResult<usize> {
@@ -68,24 +71,37 @@ impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> VfsInode for RamFsSym
     }
 
     fn set_attr(&self, attr: InodeAttr) -> VfsResult<()> {
-        set_attr(&self.basic, attr);
+        if attr.valid.contains(InodeAttrValid::SIZE) {
+            return Err(VfsError::Invalid);
+        }
+        set_attr(&self.basic, VfsNodeType::SymLink, &attr);
         Ok(())
     }
 
     fn get_attr(&self) -> VfsResult<VfsFileStat> {
         let mut basic = basic_file_stat(&self.basic);
         basic.st_size = self.inner.lock().as_bytes().len() as u64;
-        basic.st_mode = VfsInodeMode::from(
-            VfsNodePerm::from_bits_truncate(basic.st_mode as u16),
-            VfsNodeType::SymLink,
-        )
-        .bits();
+        basic.st_mode = VfsInodeMode::from_bits_truncate(basic.st_mode)
+            .with_type(VfsNodeType::SymLink)
+            .bits();
         Ok(basic)
     }
+    fn get_statx(&self, _mask: VfsStatxMask) -> VfsResult<VfsStatx> {
+        Ok(basic_statx(&self.basic, self.get_attr()?))
+    }
     fn list_xattr(&self) -> VfsResult<Vec<String>> {
         let res = self.ext_attr.lock().keys().cloned().collect();
         Ok(res)
     }
+    fn get_xattr(&self, key: &str) -> VfsResult<Vec<u8>> {
+        get_xattr(&self.ext_attr.lock(), key)
+    }
+    fn set_xattr(&self, key: &str, value: &[u8], flag: VfsXattrFlag) -> VfsResult<()> {
+        set_xattr(&mut self.ext_attr.lock(), key, value, flag)
+    }
+    fn remove_xattr(&self, key: &str) -> VfsResult<()> {
+        remove_xattr(&mut self.ext_attr.lock(), key)
+    }
     fn inode_type(&self) -> VfsNodeType {
         VfsNodeType::SymLink
     }
@@ -93,11 +109,20 @@ impl<T: RamFsProvider + 'static, R: VfsRawMutex + 'static> VfsInode for RamFsSym
     impl_common_inode_default!();
 
     fn update_time(&self, time: VfsTime, now: VfsTimeSpec) -> VfsResult<()> {
-        match time {
-            VfsTime::ModifiedTime(t) => self.basic.inner.lock().mtime = t,
-            VfsTime::AccessTime(t) => self.basic.inner.lock().atime = t,
-        }
-        self.basic.inner.lock().ctime = now;
+        basic_update_time(&self.basic, time, now);
+        Ok(())
+    }
+    fn set_atime(&self, atime: VfsTimeSpec) -> VfsResult<()> {
+        basic_set_atime(&self.basic, atime);
+        Ok(())
+    }
+    fn set_times(
+        &self,
+        atime: Option<VfsTimeSpec>,
+        mtime: Option<VfsTimeSpec>,
+        now: VfsTimeSpec,
+    ) -> VfsResult<()> {
+        basic_set_times(&self.basic, atime, mtime, now);
         Ok(())
     }
 }
@@ -1,12 +1,20 @@
-use std::sync::Arc;
+use std::sync::{atomic::Ordering, Arc};
 
 use ramfs::{RamFs, RamFsProvider};
 use spin::{mutex::Mutex, Lazy};
+use unifs::{dcache::UniFsDentryCache, UniFsSuperBlock};
 use vfscore::{
-    dentry::VfsDentry,
+    cache::PAGE_SIZE,
+    dentry::{VfsDentry, VfsDentryCache},
+    error::VfsError,
+    file::VfsMmapPage,
     fstype::VfsFsType,
-    path::DirIter,
-    utils::{VfsNodeType, VfsTimeSpec},
+    inode::splice,
+    path::{DirIter, VfsPath},
+    utils::{
+        VfsAccessMode, VfsFallocateMode, VfsInodeMode, VfsNodePerm, VfsNodeType, VfsPollEvents,
+        VfsRenameFlag, VfsTimeSpec, VfsXattrFlag,
+    },
     VfsResult,
 };
 
@@ -76,7 +84,375 @@ fn test_symlink() {
 }
 
 #[test]
-fn test_unlink() {}
+fn test_xattr() {
+    let root = make_ramfs().unwrap();
+    let f1 = root
+        .inode()
+        .unwrap()
+        .create("f1", VfsNodeType::File, "rwxrwxrwx".into(), None)
+        .unwrap();
+    assert_eq!(f1.get_xattr("user.a"), Err(VfsError::NoData));
+    assert_eq!(
+        f1.set_xattr("user.a", b"1", VfsXattrFlag::XATTR_REPLACE),
+        Err(VfsError::NoData)
+    );
+    f1.set_xattr("user.a", b"1", VfsXattrFlag::XATTR_CREATE)
+        .unwrap();
+    assert_eq!(
+        f1.set_xattr("user.a", b"2", VfsXattrFlag::XATTR_CREATE),
+        Err(VfsError::EExist)
+    );
+    f1.set_xattr("user.a", b"2", VfsXattrFlag::XATTR_REPLACE)
+        .unwrap();
+    f1.set_xattr("user.b", b"", VfsXattrFlag::empty()).unwrap();
+    assert_eq!(f1.get_xattr("user.a").unwrap(), b"2");
+    assert_eq!(f1.list_xattr().unwrap(), vec!["user.a", "user.b"]);
+    f1.remove_xattr("user.a").unwrap();
+    assert_eq!(f1.remove_xattr("user.a"), Err(VfsError::NoData));
+    assert_eq!(f1.list_xattr().unwrap(), vec!["user.b"]);
+}
+
+#[test]
+fn test_mmap_page() {
+    let root = make_ramfs().unwrap();
+    let f1 = root
+        .inode()
+        .unwrap()
+        .create("f1", VfsNodeType::File, "rwxrwxrwx".into(), None)
+        .unwrap();
+    f1.write_at(PAGE_SIZE as u64 + 10, b"hello").unwrap();
+    let mut page = f1.get_page(PAGE_SIZE as u64).unwrap();
+    assert!(matches!(page, VfsMmapPage::Direct { .. }));
+    assert_eq!(
+        f1.get_page(2 * PAGE_SIZE as u64).err(),
+        Some(VfsError::Invalid)
+    );
+    assert_eq!(f1.get_page(1).err(), Some(VfsError::Invalid));
+
+    // the mapping and the file share the memory
+    let mem = unsafe { std::slice::from_raw_parts_mut(page.as_mut_ptr(), PAGE_SIZE) };
+    assert_eq!(&mem[10..15], b"hello");
+    mem[10..15].copy_from_slice(b"world");
+    f1.writeback_page(PAGE_SIZE as u64, &page).unwrap();
+    let mut buf = [0; 5];
+    f1.read_at(PAGE_SIZE as u64 + 10, &mut buf).unwrap();
+    assert_eq!(&buf, b"world");
+
+    // the data written beyond the end of the file is dropped
+    mem[20] = 1;
+    f1.truncate(PAGE_SIZE as u64 + 30).unwrap();
+    f1.read_at(PAGE_SIZE as u64 + 20, &mut buf).unwrap();
+    assert_eq!(buf, [0; 5]);
+}
+
+#[test]
+fn test_copy_file_range() {
+    let root = make_ramfs().unwrap();
+    let root_inode = root.inode().unwrap();
+    let f1 = root_inode
+        .create("f1", VfsNodeType::File, "rwxrwxrwx".into(), None)
+        .unwrap();
+    let f2 = root_inode
+        .create("f2", VfsNodeType::File, "rwxrwxrwx".into(), None)
+        .unwrap();
+    assert_eq!(f1.writev_at(0, &[b"hello", b" ", b"world"]).unwrap(), 11);
+    let (mut a, mut b) = ([0; 3], [0; 10]);
+    assert_eq!(f1.readv_at(2, &mut [&mut a, &mut b]).unwrap(), 9);
+    assert_eq!(&a, b"llo");
+    assert_eq!(&b[..6], b" world");
+
+    // the copy crosses the page boundary of the destination
+    let offset = PAGE_SIZE as u64 - 3;
+    assert_eq!(f1.copy_file_range(6, f2.clone(), offset, 100).unwrap(), 5);
+    assert_eq!(f2.get_attr().unwrap().st_size, offset + 5);
+    f2.read_at(offset, &mut b).unwrap();
+    assert_eq!(&b[..5], b"world");
+    f1.copy_file_range(0, f1.clone(), 6, 5).unwrap();
+    f1.read_at(0, &mut b).unwrap();
+    assert_eq!(&b, b"hello hell");
+
+    let d1 = root_inode
+        .create("d1", VfsNodeType::Dir, "rwxrwxrwx".into(), None)
+        .unwrap();
+    assert_eq!(splice(&f1, Some(0), &d1, Some(0), 1), Err(VfsError::IsDir));
+    assert_eq!(splice(&f1, Some(6), &f2, Some(0), 5).unwrap(), 5);
+    f2.read_at(0, &mut b).unwrap();
+    assert_eq!(&b[..5], b"hello");
+
+    // a regular file is always ready
+    let events = VfsPollEvents::IN | VfsPollEvents::OUT;
+    assert_eq!(f1.poll(events).unwrap(), events);
+    assert_eq!(f1.poll(VfsPollEvents::IN).unwrap(), VfsPollEvents::IN);
+}
+
+#[test]
+fn test_sparse_file() {
+    let root = make_ramfs().unwrap();
+    let f1 = root
+        .inode()
+        .unwrap()
+        .create("f1", VfsNodeType::File, "rwxrwxrwx".into(), None)
+        .unwrap();
+    // only the written page is allocated
+    let offset = 4 << 30;
+    f1.write_at(offset, b"data").unwrap();
+    let stat = f1.get_attr().unwrap();
+    assert_eq!(stat.st_size, offset + 4);
+    assert_eq!(stat.st_blocks, 8);
+    let mut buf = [1; 4];
+    f1.read_at(100, &mut buf).unwrap();
+    assert_eq!(buf, [0; 4]);
+    assert_eq!(f1.seek_data(0).unwrap(), offset);
+    assert_eq!(f1.seek_hole(0).unwrap(), 0);
+    assert_eq!(f1.seek_hole(offset).unwrap(), offset + 4);
+    assert_eq!(f1.seek_data(offset + 4), Err(VfsError::ENXIO));
+
+    let page = PAGE_SIZE as u64;
+    f1.fallocate(VfsFallocateMode::empty(), 0, 2 * page)
+        .unwrap();
+    assert_eq!(f1.seek_hole(0).unwrap(), 2 * page);
+    assert_eq!(
+        f1.fallocate(VfsFallocateMode::FALLOC_FL_PUNCH_HOLE, 0, page),
+        Err(VfsError::Invalid)
+    );
+    f1.write_at(0, &[1; 2 * PAGE_SIZE]).unwrap();
+    let punch = VfsFallocateMode::FALLOC_FL_PUNCH_HOLE | VfsFallocateMode::FALLOC_FL_KEEP_SIZE;
+    f1.fallocate(punch, 10, page).unwrap();
+    assert_eq!(f1.seek_hole(0).unwrap(), 2 * page);
+    f1.read_at(page + 8, &mut buf).unwrap();
+    assert_eq!(buf, [0, 0, 1, 1]);
+    f1.fallocate(punch, page, page).unwrap();
+    assert_eq!(f1.seek_hole(0).unwrap(), page);
+
+    // keep size allocates beyond the end of the file
+    f1.truncate(10).unwrap();
+    f1.fallocate(VfsFallocateMode::FALLOC_FL_KEEP_SIZE, 0, 3 * page)
+        .unwrap();
+    let stat = f1.get_attr().unwrap();
+    assert_eq!(stat.st_size, 10);
+    assert_eq!(stat.st_blocks, 24);
+    f1.fallocate(VfsFallocateMode::FALLOC_FL_ZERO_RANGE, 5, 10)
+        .unwrap();
+    assert_eq!(f1.get_attr().unwrap().st_size, 15);
+    f1.read_at(3, &mut buf).unwrap();
+    assert_eq!(buf, [1, 1, 0, 0]);
+}
+
+#[test]
+fn test_fifo() {
+    let root = make_ramfs().unwrap();
+    root.inode()
+        .unwrap()
+        .create("fifo", VfsNodeType::Fifo, "rw-rw-rw-".into(), None)
+        .unwrap();
+    // the opens of the fifo share the pipe
+    let fifo = root.inode().unwrap().lookup("fifo").unwrap();
+    let reader = fifo.open_fifo(VfsAccessMode::R_OK).unwrap();
+    let fifo = root.inode().unwrap().lookup("fifo").unwrap();
+    let writer = fifo.open_fifo(VfsAccessMode::W_OK).unwrap();
+    let mut buf = [0; 8];
+    assert_eq!(reader.read_at(0, &mut buf), Err(VfsError::EAGAIN));
+    assert_eq!(writer.write_at(0, b"hello").unwrap(), 5);
+    assert_eq!(reader.poll(VfsPollEvents::IN).unwrap(), VfsPollEvents::IN);
+    assert_eq!(reader.read_at(0, &mut buf[..2]).unwrap(), 2);
+    assert_eq!(&buf[..2], b"he");
+
+    // fill the pipe, the data wraps around the ring buffer
+    let big = vec![7; 1 << 20];
+    let n = writer.write_at(0, &big).unwrap();
+    assert_eq!(
+        writer.poll(VfsPollEvents::OUT).unwrap(),
+        VfsPollEvents::empty()
+    );
+    assert_eq!(writer.write_at(0, b"x"), Err(VfsError::EAGAIN));
+    assert_eq!(reader.read_at(0, &mut buf[..3]).unwrap(), 3);
+    assert_eq!(&buf[..3], b"llo");
+    assert_eq!(writer.write_at(0, b"end").unwrap(), 3);
+    let mut rest = vec![0; n + 3];
+    assert_eq!(reader.read_at(0, &mut rest).unwrap(), n + 3);
+    assert!(rest[..n].iter().all(|&b| b == 7));
+    assert_eq!(&rest[n..], b"end");
+
+    // closing the write end hangs up the reader
+    drop(writer);
+    assert_eq!(reader.poll(VfsPollEvents::IN).unwrap(), VfsPollEvents::HUP);
+    assert_eq!(reader.read_at(0, &mut buf).unwrap(), 0);
+    let writer = fifo.open_fifo(VfsAccessMode::W_OK).unwrap();
+    drop(reader);
+    assert_eq!(writer.write_at(0, b"x"), Err(VfsError::EPIPE));
+    assert_eq!(
+        writer.poll(VfsPollEvents::OUT).unwrap(),
+        VfsPollEvents::OUT | VfsPollEvents::ERR
+    );
+}
+
+#[test]
+fn test_dcache() {
+    let fs: Arc<dyn VfsFsType> = Arc::new(RamFs::<_, Mutex<()>>::new(RamFsProviderImpl));
+    let root = fs.clone().mount(0, "/", None, &[]).unwrap();
+    let dcache = Arc::new(UniFsDentryCache::<Mutex<()>>::new(16));
+    let path = VfsPath::new(root.clone(), root.clone()).with_dcache(dcache.clone());
+    let dir_mode = VfsInodeMode::DIR | VfsInodeMode::from_bits_truncate(0o755);
+    let file_mode = VfsInodeMode::FILE | VfsInodeMode::from_bits_truncate(0o644);
+
+    // a missing name is remembered as a negative entry
+    assert_eq!(
+        path.join("f").unwrap().open(None).err(),
+        Some(VfsError::NoEntry)
+    );
+    assert_eq!(
+        path.join("f").unwrap().open(None).err(),
+        Some(VfsError::NoEntry)
+    );
+    let stats = dcache.stats();
+    assert_eq!((stats.misses, stats.negative_hits), (1, 1));
+    // creating the file replaces the negative entry
+    path.join("f").unwrap().open(Some(file_mode)).unwrap();
+    let stats = dcache.stats();
+    let f = path.join("f").unwrap().open(None).unwrap();
+    assert_eq!(dcache.stats().hits, stats.hits + 1);
+    assert_eq!(dcache.stats().negative_hits, stats.negative_hits);
+    path.join("f").unwrap().unlink().unwrap();
+    assert_eq!(
+        path.join("f").unwrap().open(None).err(),
+        Some(VfsError::NoEntry)
+    );
+    assert_eq!(dcache.stats().negative_hits, stats.negative_hits + 1);
+
+    // the unused dentries are evicted, the used ones are kept
+    let d = path.join("d").unwrap().open(Some(dir_mode)).unwrap();
+    path.join("d/a").unwrap().open(Some(file_mode)).unwrap();
+    let b = path.join("d/b").unwrap().open(Some(file_mode)).unwrap();
+    drop(d);
+    dcache.shrink(usize::MAX);
+    assert!(root.find("d").is_some());
+    let d = root.find("d").unwrap();
+    assert!(d.find("a").is_none());
+    assert!(d.find("b").is_some());
+    assert_eq!(path.join("d/a").unwrap().open(None).unwrap().name(), "a");
+    drop((d, b, f));
+    // the directory is evicted after its children
+    dcache.shrink(usize::MAX);
+    assert!(root.find("d").is_none());
+    assert!(dcache.is_empty());
+    // the evicted dentries are looked up again
+    assert_eq!(path.join("d/b").unwrap().open(None).unwrap().name(), "b");
+
+    // the least recently used entries are evicted over the limit
+    for i in 0..8 {
+        path.join(format!("d/{i}"))
+            .unwrap()
+            .open(Some(file_mode))
+            .unwrap();
+    }
+    let evictions = dcache.stats().evictions;
+    dcache.set_limit(4);
+    assert_eq!(dcache.len(), 4);
+    assert!(dcache.stats().evictions > evictions);
+    assert!(root.find("d").unwrap().find("0").is_none());
+    assert!(root.find("d").unwrap().find("7").is_some());
+    // the new entries evict the least recently used ones
+    for i in 8..16 {
+        path.join(format!("d/{i}"))
+            .unwrap()
+            .open(Some(file_mode))
+            .unwrap();
+    }
+    assert_eq!(dcache.len(), 4);
+    assert!(root.find("d").unwrap().find("8").is_none());
+    assert!(root.find("d").unwrap().find("15").is_some());
+
+    // a child created in the inode is hidden by the negative entry until it is invalidated
+    let d = root.find("d").unwrap();
+    assert_eq!(
+        path.join("d/n").unwrap().open(None).err(),
+        Some(VfsError::NoEntry)
+    );
+    let perm = VfsNodePerm::from_bits_truncate(0o644);
+    d.inode()
+        .unwrap()
+        .create("n", VfsNodeType::File, perm, None)
+        .unwrap();
+    assert_eq!(
+        path.join("d/n").unwrap().open(None).err(),
+        Some(VfsError::NoEntry)
+    );
+    dcache.invalidate(&d, "n");
+    assert_eq!(path.join("d/n").unwrap().open(None).unwrap().name(), "n");
+    drop(d);
+
+    let sb = root.inode().unwrap().get_super_block().unwrap();
+    assert!(dcache.invalidate_sb(&sb) > 0);
+    assert!(dcache.is_empty());
+}
+
+#[test]
+fn test_unlink() {
+    let fs: Arc<dyn VfsFsType> = Arc::new(RamFs::<_, Mutex<()>>::new(RamFsProviderImpl));
+    let root = fs.clone().mount(0, "/", None, &[]).unwrap();
+    let root_inode = root.inode().unwrap();
+    let sb = root_inode
+        .get_super_block()
+        .unwrap()
+        .downcast_arc::<UniFsSuperBlock<Mutex<()>>>()
+        .ok()
+        .unwrap();
+    let inodes = || sb.inode_count.load(Ordering::SeqCst);
+    let perm = "rw-rw-rw-".into();
+    let file = root_inode
+        .create("f", VfsNodeType::File, perm, None)
+        .unwrap();
+    file.write_at(0, b"hello").unwrap();
+    root_inode.link("g", file.clone()).unwrap();
+    assert_eq!(inodes(), 2);
+    assert_eq!(file.get_attr().unwrap().st_nlink, 2);
+
+    // the inode lives while it has a link
+    root_inode.unlink("f").unwrap();
+    assert_eq!(sb.orphan_count(), 0);
+    assert_eq!(file.get_attr().unwrap().st_nlink, 1);
+    // an unlinked file which is still open is an orphan
+    root_inode.unlink("g").unwrap();
+    assert_eq!(root_inode.lookup("g").err(), Some(VfsError::NoEntry));
+    assert_eq!(sb.orphan_count(), 1);
+    assert_eq!(inodes(), 2);
+    let mut buf = [0; 5];
+    assert_eq!(file.read_at(0, &mut buf).unwrap(), 5);
+    assert_eq!(&buf, b"hello");
+    let ino = file.get_attr().unwrap().st_ino;
+    assert!(sb.get_orphan(ino).is_some());
+    // the last user evicts it
+    drop(file);
+    assert_eq!(sb.orphan_count(), 0);
+    assert!(sb.get_orphan(ino).is_none());
+    assert_eq!(inodes(), 1);
+
+    // a replaced file loses a link
+    let a = root_inode
+        .create("a", VfsNodeType::File, perm, None)
+        .unwrap();
+    root_inode.link("b", a.clone()).unwrap();
+    root_inode
+        .create("c", VfsNodeType::File, perm, None)
+        .unwrap();
+    root_inode
+        .rename_to("c", root_inode.clone(), "a", VfsRenameFlag::empty())
+        .unwrap();
+    assert_eq!(a.get_attr().unwrap().st_nlink, 1);
+    assert!(Arc::ptr_eq(&root_inode.lookup("b").unwrap(), &a));
+    drop(a);
+    assert_eq!(inodes(), 3);
+    root_inode
+        .rename_to("a", root_inode.clone(), "b", VfsRenameFlag::empty())
+        .unwrap();
+    assert_eq!(inodes(), 2);
+    root_inode
+        .create("d", VfsNodeType::Dir, perm, None)
+        .unwrap();
+    root_inode.rmdir("d").unwrap();
+    assert_eq!(inodes(), 2);
+    assert_eq!(sb.orphan_count(), 0);
+}
 
 #[test]
 fn test_rename() {}
@@ -0,0 +1,317 @@
+//! A global dentry cache with negative dentries and LRU eviction
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+
+use vfscore::{
+    dentry::{VfsDentry, VfsDentryCache, VfsDentryLookup},
+    superblock::VfsSuperBlock,
+};
+
+use crate::VfsRawMutex;
+
+/// The default max number of entries of the cache
+pub const DCACHE_SIZE: usize = 4096;
+
+/// The max number of entries looked at to evict an entry when the cache is full
+const PUT_SCAN: usize = 32;
+
+/// The statistics of a [`UniFsDentryCache`]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct UniFsDentryCacheStats {
+    /// The lookups which found the child
+    pub hits: u64,
+    /// The lookups which found a negative entry
+    pub negative_hits: u64,
+    /// The lookups which had to ask the inode
+    pub misses: u64,
+    /// The entries evicted by the limit, the shrinker or the invalidation
+    pub evictions: u64,
+}
+
+/// The key of an entry, the address of the parent and the name of the child
+type DcacheKey = (usize, String);
+
+struct DcacheEntry {
+    /// The entry keeps the parent alive, so the address in the key stays unique
+    parent: Arc<dyn VfsDentry>,
+    /// `None` for a negative entry
+    dentry: Option<Arc<dyn VfsDentry>>,
+    /// The last time the entry was used, the key in the lru list
+    stamp: u64,
+}
+
+impl DcacheEntry {
+    /// Whether the entry can be dropped, a positive entry can only be dropped if nobody else
+    /// uses the dentry
+    fn evictable(&self) -> bool {
+        match &self.dentry {
+            None => true,
+            // the cache and the children of the parent
+            Some(dentry) => {
+                Arc::strong_count(dentry) <= 2 && !dentry.is_mount_point() && !dentry.has_children()
+            }
+        }
+    }
+}
+
+struct DcacheInner {
+    entries: BTreeMap<DcacheKey, DcacheEntry>,
+    /// The entries ordered by the last time they were used
+    lru: BTreeMap<u64, DcacheKey>,
+    clock: u64,
+    limit: usize,
+    stats: UniFsDentryCacheStats,
+}
+
+fn dcache_key(parent: &Arc<dyn VfsDentry>, name: &str) -> DcacheKey {
+    (Arc::as_ptr(parent) as *const () as usize, name.to_string())
+}
+
+impl DcacheInner {
+    fn touch(&mut self, key: &DcacheKey) {
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.lru.remove(&entry.stamp);
+            self.clock += 1;
+            entry.stamp = self.clock;
+            self.lru.insert(self.clock, key.clone());
+        }
+    }
+
+    fn put(
+        &mut self,
+        key: DcacheKey,
+        parent: &Arc<dyn VfsDentry>,
+        dentry: Option<Arc<dyn VfsDentry>>,
+    ) {
+        self.clock += 1;
+        let entry = DcacheEntry {
+            parent: parent.clone(),
+            dentry,
+            stamp: self.clock,
+        };
+        if let Some(old) = self.entries.insert(key.clone(), entry) {
+            self.lru.remove(&old.stamp);
+        }
+        self.lru.insert(self.clock, key);
+        if self.entries.len() > self.limit {
+            let count = self.entries.len() - self.limit;
+            // don't look at the new entry
+            let scan = PUT_SCAN.min(self.lru.len() - 1);
+            self.evict_lru(count, scan);
+        }
+    }
+
+    /// Drop the entry and remove its dentry from the parent
+    fn evict(&mut self, key: &DcacheKey) {
+        let entry = self.entries.remove(key).unwrap();
+        self.lru.remove(&entry.stamp);
+        if let Some(dentry) = entry.dentry {
+            let name = key.1.as_str();
+            if entry
+                .parent
+                .find(name)
+                .is_some_and(|child| Arc::ptr_eq(&child, &dentry))
+            {
+                entry.parent.remove(name);
+            }
+        }
+        self.stats.evictions += 1;
+    }
+
+    /// Evict at most `count` entries from the least recently used ones, looking at no more
+    /// than `scan` entries
+    ///
+    /// The entries which can't be evicted are moved to the end of the list, so they are not
+    /// looked at again before the other entries.
+    fn evict_lru(&mut self, count: usize, scan: usize) -> usize {
+        let mut evicted = 0;
+        for _ in 0..scan {
+            if evicted == count {
+                break;
+            }
+            let Some((_, key)) = self.lru.pop_first() else {
+                break;
+            };
+            if self.entries[&key].evictable() {
+                self.evict(&key);
+                evicted += 1;
+            } else {
+                self.clock += 1;
+                self.entries.get_mut(&key).unwrap().stamp = self.clock;
+                self.lru.insert(self.clock, key);
+            }
+        }
+        evicted
+    }
+
+    /// Evict at most `count` entries from the least recently used ones
+    ///
+    /// Evicting the children of a directory may make the directory evictable, so the list is
+    /// walked again until nothing can be evicted.
+    fn shrink(&mut self, count: usize) -> usize {
+        let mut evicted = 0;
+        while evicted < count {
+            let n = self.evict_lru(count - evicted, self.lru.len());
+            if n == 0 {
+                break;
+            }
+            evicted += n;
+        }
+        evicted
+    }
+
+    /// Forget the entry without touching the dentry tree
+    fn forget(&mut self, key: &DcacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.lru.remove(&entry.stamp);
+        }
+    }
+}
+
+/// A [`VfsDentryCache`] for the dentry trees of all file systems
+///
+/// The positive entries are the dentries in the children of their parents, evicting an entry
+/// removes the dentry from its parent. A dentry is only evicted if nobody else holds it, it
+/// is not a mount point and none of its children is cached. The cache evicts the least
+/// recently used entries when it is full, and [`UniFsDentryCache::shrink`] can be called
+/// under memory pressure. If the entries at the head of the list are all in use, the cache
+/// stays over the limit until they are released.
+///
+/// An evicted inode may be loaded again as a new object, so the file locks and the inotify
+/// watches identify the inodes by [`inode_id`](vfscore::lock::inode_id) instead of their
+/// addresses.
+pub struct UniFsDentryCache<R: VfsRawMutex> {
+    inner: lock_api::Mutex<R, DcacheInner>,
+}
+
+impl<R: VfsRawMutex + 'static> UniFsDentryCache<R> {
+    /// Create a cache which keeps at most `limit` entries
+    pub fn new(limit: usize) -> Self {
+        Self {
+            inner: lock_api::Mutex::new(DcacheInner {
+                entries: BTreeMap::new(),
+                lru: BTreeMap::new(),
+                clock: 0,
+                limit: limit.max(1),
+                stats: UniFsDentryCacheStats::default(),
+            }),
+        }
+    }
+
+    /// Change the max number of entries, the entries over the limit are evicted
+    pub fn set_limit(&self, limit: usize) {
+        let mut inner = self.inner.lock();
+        inner.limit = limit.max(1);
+        let count = inner.entries.len().saturating_sub(inner.limit);
+        inner.shrink(count);
+    }
+
+    /// Evict at most `count` unused entries, returns the number of evicted entries
+    pub fn shrink(&self, count: usize) -> usize {
+        self.inner.lock().shrink(count)
+    }
+
+    /// Evict the entries of the file system `sb`, e.g. when it is unmounted or its contents
+    /// are changed behind the cache, returns the number of evicted entries
+    ///
+    /// The dentries which are still in use stay in the tree but are forgotten by the cache.
+    pub fn invalidate_sb(&self, sb: &Arc<dyn VfsSuperBlock>) -> usize {
+        let mut inner = self.inner.lock();
+        let in_sb = |entry: &DcacheEntry| {
+            entry
+                .parent
+                .inode()
+                .and_then(|inode| inode.get_super_block())
+                .is_ok_and(|other| Arc::ptr_eq(&other, sb))
+        };
+        let mut count = 0;
+        loop {
+            let keys: Vec<DcacheKey> = inner
+                .lru
+                .values()
+                .filter(|key| {
+                    let entry = &inner.entries[*key];
+                    in_sb(entry) && entry.evictable()
+                })
+                .cloned()
+                .collect();
+            if keys.is_empty() {
+                break;
+            }
+            keys.iter().for_each(|key| inner.evict(key));
+            count += keys.len();
+        }
+        let keys: Vec<DcacheKey> = inner
+            .entries
+            .iter()
+            .filter(|(_, entry)| in_sb(entry))
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.iter().for_each(|key| inner.forget(key));
+        count
+    }
+
+    /// The statistics since the cache was created
+    pub fn stats(&self) -> UniFsDentryCacheStats {
+        self.inner.lock().stats
+    }
+
+    /// The number of entries including the negative ones
+    pub fn len(&self) -> usize {
+        self.inner.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<R: VfsRawMutex + 'static> VfsDentryCache for UniFsDentryCache<R> {
+    fn lookup(&self, parent: &Arc<dyn VfsDentry>, name: &str) -> VfsDentryLookup {
+        let key = dcache_key(parent, name);
+        let mut inner = self.inner.lock();
+        // the dentry tree is the truth, the children may be inserted without the cache
+        if let Some(dentry) = parent.find(name) {
+            inner.stats.hits += 1;
+            match inner.entries.get(&key) {
+                Some(entry)
+                    if entry
+                        .dentry
+                        .as_ref()
+                        .is_some_and(|old| Arc::ptr_eq(old, &dentry)) =>
+                {
+                    inner.touch(&key)
+                }
+                _ => inner.put(key, parent, Some(dentry.clone())),
+            }
+            return VfsDentryLookup::Found(dentry);
+        }
+        let negative = inner
+            .entries
+            .get(&key)
+            .is_some_and(|entry| entry.dentry.is_none());
+        if negative {
+            inner.stats.negative_hits += 1;
+            inner.touch(&key);
+            return VfsDentryLookup::Negative;
+        }
+        // the dentry was removed behind the cache
+        inner.forget(&key);
+        inner.stats.misses += 1;
+        VfsDentryLookup::Miss
+    }
+
+    fn insert(&self, parent: &Arc<dyn VfsDentry>, name: &str, dentry: Option<&Arc<dyn VfsDentry>>) {
+        let key = dcache_key(parent, name);
+        self.inner.lock().put(key, parent, dentry.cloned());
+    }
+
+    fn invalidate(&self, parent: &Arc<dyn VfsDentry>, name: &str) {
+        let key = dcache_key(parent, name);
+        self.inner.lock().forget(&key);
+    }
+}
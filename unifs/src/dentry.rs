@@ -50,6 +50,11 @@ impl<R: VfsRawMutex + 'static> UniFsDentry<R> {
             }),
         }
     }
+
+    /// Create a dentry without a name, which is not a child of `parent`
+    pub fn unnamed(inode: Arc<dyn VfsInode>, parent: Weak<dyn VfsDentry>) -> Self {
+        Self::new(inode, parent, String::new())
+    }
 }
 
 impl<R: VfsRawMutex + 'static> VfsDentry for UniFsDentry<R> {
@@ -129,6 +134,11 @@ impl<R: VfsRawMutex + 'static> VfsDentry for UniFsDentry<R> {
             .map_or(Ok(child), |_| Err(VfsError::EExist))
     }
 
+    fn detached(self: Arc<Self>, child: Arc<dyn VfsInode>) -> VfsResult<Arc<dyn VfsDentry>> {
+        let parent = Arc::downgrade(&(self as Arc<dyn VfsDentry>));
+        Ok(Arc::new(UniFsDentry::<R>::unnamed(child, parent)))
+    }
+
     fn remove(&self, name: &str) -> Option<Arc<dyn VfsDentry>> {
         let mut inner = self.inner.lock();
         inner
@@ -139,6 +149,14 @@ impl<R: VfsRawMutex + 'static> VfsDentry for UniFsDentry<R> {
             .map(|x| x as Arc<dyn VfsDentry>)
     }
 
+    fn has_children(&self) -> bool {
+        self.inner
+            .lock()
+            .children
+            .as_ref()
+            .is_some_and(|children| !children.is_empty())
+    }
+
     fn parent(&self) -> Option<Arc<dyn VfsDentry>> {
         self.inner.lock().parent.upgrade()
     }
@@ -5,8 +5,11 @@ use alloc::{
 };
 
 use vfscore::{
-    inode::InodeAttr,
-    utils::{VfsDirEntry, VfsFileStat, VfsNodePerm, VfsNodeType, VfsRenameFlag, VfsTime},
+    inode::{InodeAttr, InodeAttrValid},
+    utils::{
+        VfsDirEntry, VfsFileStat, VfsInodeMode, VfsNodePerm, VfsNodeType, VfsRenameFlag, VfsStatx,
+        VfsStatxAttr, VfsStatxMask, VfsTime,
+    },
 };
 
 use crate::{UniFsSuperBlock, *};
@@ -18,12 +21,41 @@ pub struct UniFsInodeSame<T: Send + Sync, R: VfsRawMutex> {
     pub inner: lock_api::Mutex<R, UniFsInodeAttr>,
 }
 
+impl<T: Send + Sync, R: VfsRawMutex> Drop for UniFsInodeSame<T, R> {
+    fn drop(&mut self) {
+        // the super block is already gone when the whole file system is dropped
+        if let Some(sb) = self.sb.upgrade() {
+            let _ = sb.evict_inode(self.inode_number);
+        }
+    }
+}
+
 pub struct UniFsInodeAttr {
     pub link_count: u32,
+    pub uid: u32,
+    pub gid: u32,
     pub atime: VfsTimeSpec,
     pub mtime: VfsTimeSpec,
     pub ctime: VfsTimeSpec,
-    pub perm: VfsNodePerm,
+    /// The creation time
+    pub btime: VfsTimeSpec,
+    /// The permission bits, including set-user-ID, set-group-ID and sticky bits
+    pub mode: VfsInodeMode,
+    /// The inode flags set by `FS_IOC_SETFLAGS`
+    pub flags: VfsStatxAttr,
+}
+
+/// The inode flags which can be set on the inodes of unifs
+pub const UNIFS_INODE_FLAGS: VfsStatxAttr = VfsStatxAttr::from_bits_truncate(
+    VfsStatxAttr::STATX_ATTR_IMMUTABLE.bits()
+        | VfsStatxAttr::STATX_ATTR_APPEND.bits()
+        | VfsStatxAttr::STATX_ATTR_NODUMP.bits(),
+);
+
+impl UniFsInodeAttr {
+    pub fn perm(&self) -> VfsNodePerm {
+        VfsNodePerm::from(self.mode)
+    }
 }
 
 pub fn basic_file_stat<T: Send + Sync, R: VfsRawMutex>(
@@ -33,10 +65,10 @@ pub fn basic_file_stat<T: Send + Sync, R: VfsRawMutex>(
     VfsFileStat {
         st_dev: 0,
         st_ino: basic.inode_number,
-        st_mode: inner.perm.bits() as u32,
+        st_mode: inner.mode.bits(),
         st_nlink: inner.link_count,
-        st_uid: 0,
-        st_gid: 0,
+        st_uid: inner.uid,
+        st_gid: inner.gid,
         st_rdev: 0,
         __pad: 0,
         st_size: 4096,
@@ -50,6 +82,104 @@ pub fn basic_file_stat<T: Send + Sync, R: VfsRawMutex>(
     }
 }
 
+/// Set the access and modification times under one lock, `None` is left unchanged, and
+/// set the ctime to `now`
+pub fn basic_set_times<T: Send + Sync, R: VfsRawMutex>(
+    basic: &UniFsInodeSame<T, R>,
+    atime: Option<VfsTimeSpec>,
+    mtime: Option<VfsTimeSpec>,
+    now: VfsTimeSpec,
+) {
+    let mut inner = basic.inner.lock();
+    if let Some(atime) = atime {
+        inner.atime = atime;
+    }
+    if let Some(mtime) = mtime {
+        inner.mtime = mtime;
+    }
+    inner.ctime = now;
+}
+
+/// Set the access time without changing the ctime, used when the file is read
+pub fn basic_set_atime<T: Send + Sync, R: VfsRawMutex>(
+    basic: &UniFsInodeSame<T, R>,
+    atime: VfsTimeSpec,
+) {
+    basic.inner.lock().atime = atime;
+}
+
+/// Set one of the access and modification times, and set the ctime to `now`
+pub fn basic_update_time<T: Send + Sync, R: VfsRawMutex>(
+    basic: &UniFsInodeSame<T, R>,
+    time: VfsTime,
+    now: VfsTimeSpec,
+) {
+    match time {
+        VfsTime::AccessTime(t) => basic_set_times(basic, Some(t), None, now),
+        VfsTime::ModifiedTime(t) => basic_set_times(basic, None, Some(t), now),
+    }
+}
+
+/// Extend the `stat` of the inode with the creation time and the inode flags
+pub fn basic_statx<T: Send + Sync, R: VfsRawMutex>(
+    basic: &UniFsInodeSame<T, R>,
+    stat: VfsFileStat,
+) -> VfsStatx {
+    let inner = basic.inner.lock();
+    let mut stx = VfsStatx::from(stat);
+    stx.stx_mask |= VfsStatxMask::STATX_BTIME;
+    stx.stx_btime = inner.btime;
+    stx.stx_attributes = inner.flags;
+    stx.stx_attributes_mask = UNIFS_INODE_FLAGS;
+    stx
+}
+
+/// Apply the mode, owner, times and flags in `attr` to the inode
+///
+/// The size should be handled by the caller. If anything is changed and `attr` doesn't
+/// carry a ctime, the ctime will be set to `now`.
+pub fn basic_set_attr<T: Send + Sync, R: VfsRawMutex>(
+    basic: &UniFsInodeSame<T, R>,
+    ty: VfsNodeType,
+    attr: &InodeAttr,
+    now: VfsTimeSpec,
+) {
+    let valid = attr.valid;
+    let mut inner = basic.inner.lock();
+    if valid.intersects(InodeAttrValid::UID | InodeAttrValid::GID) {
+        if valid.contains(InodeAttrValid::UID) {
+            inner.uid = attr.uid;
+        }
+        if valid.contains(InodeAttrValid::GID) {
+            inner.gid = attr.gid;
+        }
+        // chown(2) clears the set-user-ID and set-group-ID bits of executable files
+        if !ty.is_dir() {
+            inner.mode.remove(VfsInodeMode::SET_UID);
+            if inner.mode.contains(VfsInodeMode::GROUP_EXEC) {
+                inner.mode.remove(VfsInodeMode::SET_GID);
+            }
+        }
+    }
+    if valid.contains(InodeAttrValid::FLAGS) {
+        inner.flags = attr.flags & UNIFS_INODE_FLAGS;
+    }
+    if valid.contains(InodeAttrValid::MODE) {
+        inner.mode = VfsInodeMode::from_bits_truncate(attr.mode) & !VfsInodeMode::TYPE_MASK;
+    }
+    if valid.contains(InodeAttrValid::ATIME) {
+        inner.atime = attr.atime;
+    }
+    if valid.contains(InodeAttrValid::MTIME) {
+        inner.mtime = attr.mtime;
+    }
+    if valid.contains(InodeAttrValid::CTIME) {
+        inner.ctime = attr.ctime;
+    } else if !valid.is_empty() {
+        inner.ctime = now;
+    }
+}
+
 pub struct UniFsDirInode<T: Send + Sync, R: VfsRawMutex> {
     pub basic: UniFsInodeSame<T, R>,
     pub children: lock_api::Mutex<R, Vec<(String, u64)>>,
@@ -96,7 +226,11 @@ impl<T: Send + Sync + 'static, R: VfsRawMutex + 'static> UniFsDirInode<T, R> {
     }
 
     #[inline]
-    pub fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+    pub fn set_attr(&self, attr: InodeAttr, now: VfsTimeSpec) -> VfsResult<()> {
+        if attr.valid.contains(InodeAttrValid::SIZE) {
+            return Err(VfsError::IsDir);
+        }
+        basic_set_attr(&self.basic, VfsNodeType::Dir, &attr, now);
         Ok(())
     }
     #[inline]
@@ -109,16 +243,24 @@ impl<T: Send + Sync + 'static, R: VfsRawMutex + 'static> UniFsDirInode<T, R> {
     }
 
     pub fn node_perm(&self) -> VfsNodePerm {
-        self.basic.inner.lock().perm
+        self.basic.inner.lock().perm()
     }
 
     pub fn update_time(&self, time: VfsTime, now: VfsTimeSpec) -> VfsResult<()> {
-        let mut inner = self.basic.inner.lock();
-        match time {
-            VfsTime::AccessTime(t) => inner.atime = t,
-            VfsTime::ModifiedTime(t) => inner.mtime = t,
-        }
-        inner.ctime = now;
+        basic_update_time(&self.basic, time, now);
+        Ok(())
+    }
+    pub fn set_atime(&self, atime: VfsTimeSpec) -> VfsResult<()> {
+        basic_set_atime(&self.basic, atime);
+        Ok(())
+    }
+    pub fn set_times(
+        &self,
+        atime: Option<VfsTimeSpec>,
+        mtime: Option<VfsTimeSpec>,
+        now: VfsTimeSpec,
+    ) -> VfsResult<()> {
+        basic_set_times(&self.basic, atime, mtime, now);
         Ok(())
     }
     pub fn rename_to(
@@ -148,16 +290,11 @@ impl<T: Send + Sync + 'static, R: VfsRawMutex + 'static> UniFsDirInode<T, R> {
                 .position(|(n, _)| n == new_name)
                 .ok_or(VfsError::NoEntry)?;
 
-            let (_, old_inode_number) = self.children.lock().remove(old_inode_index);
-            let (_, new_inode_number) = new_inode.children.lock().remove(new_inode_index);
-
-            self.children
-                .lock()
-                .push((new_name.to_string(), new_inode_number));
-            new_inode
-                .children
-                .lock()
-                .push((old_name.to_string(), old_inode_number));
+            // swap in place, the two names may be in the same directory
+            let old_inode_number = self.children.lock()[old_inode_index].1;
+            let new_inode_number = new_inode.children.lock()[new_inode_index].1;
+            self.children.lock()[old_inode_index].1 = new_inode_number;
+            new_inode.children.lock()[new_inode_index].1 = old_inode_number;
         } else {
             let (_, old_inode_number) = self.children.lock().remove(old_inode_index);
             // the new_name may exist or not
@@ -171,7 +308,8 @@ impl<T: Send + Sync + 'static, R: VfsRawMutex + 'static> UniFsDirInode<T, R> {
                 let (_, new_inode_number) = new_inode.children.lock().remove(new_inode_index);
                 sb.remove_inode(new_inode_number);
             }
-            self.children
+            new_inode
+                .children
                 .lock()
                 .push((new_name.to_string(), old_inode_number));
         }
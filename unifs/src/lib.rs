@@ -1,8 +1,9 @@
-#![feature(trait_alias)]
 #![cfg_attr(not(test), no_std)]
 
+pub mod dcache;
 pub mod dentry;
 pub mod inode;
+pub mod pipe;
 
 extern crate alloc;
 
@@ -14,6 +15,7 @@ use alloc::{
 use core::sync::atomic::{AtomicU64, AtomicUsize};
 
 use log::info;
+pub use vfscore::VfsRawMutex;
 use vfscore::{
     dentry::VfsDentry,
     error::VfsError,
@@ -26,7 +28,6 @@ use vfscore::{
 
 use crate::dentry::UniFsDentry;
 
-pub trait VfsRawMutex = lock_api::RawMutex + Send + Sync;
 pub struct UniFs<T: Send + Sync, R: VfsRawMutex> {
     real_fs: &'static str,
     pub provider: T,
@@ -73,7 +74,10 @@ pub struct UniFsSuperBlock<R: VfsRawMutex> {
     pub root: lock_api::Mutex<R, Option<Arc<dyn VfsInode>>>,
     pub inode_index: AtomicU64,
     pub inode_count: AtomicUsize,
+    /// The inodes which have links, the cache keeps them alive
     inode_cache: lock_api::Mutex<R, BTreeMap<u64, Arc<dyn VfsInode>>>,
+    /// The inodes which are unlinked but still used, e.g. by an open file
+    orphans: lock_api::Mutex<R, BTreeMap<u64, Weak<dyn VfsInode>>>,
     pub mnt_info: lock_api::Mutex<R, BTreeMap<String, Arc<dyn VfsDentry>>>,
 }
 
@@ -86,21 +90,49 @@ impl<R: VfsRawMutex + 'static> UniFsSuperBlock<R> {
             inode_index: AtomicU64::new(0),
             inode_count: AtomicUsize::new(0),
             inode_cache: lock_api::Mutex::new(BTreeMap::new()),
+            orphans: lock_api::Mutex::new(BTreeMap::new()),
             mnt_info: lock_api::Mutex::new(BTreeMap::new()),
         })
     }
+    /// Cache the inode which has links, an orphan which gets a link is cached again
     pub fn insert_inode(&self, inode_number: u64, inode: Arc<dyn VfsInode>) {
+        self.orphans.lock().remove(&inode_number);
         let mut cache = self.inode_cache.lock();
         cache.insert(inode_number, inode);
     }
+    /// Record an inode which is created without any link, e.g. the file of `O_TMPFILE`
+    pub fn insert_orphan(&self, inode_number: u64, inode: &Arc<dyn VfsInode>) {
+        self.orphans
+            .lock()
+            .insert(inode_number, Arc::downgrade(inode));
+    }
+    /// Remove the inode whose last link is gone
+    ///
+    /// If the inode is still used it becomes an orphan, it is evicted and `inode_count` is
+    /// decreased when the last user drops it.
     pub fn remove_inode(&self, inode_number: u64) {
-        let mut cache = self.inode_cache.lock();
-        cache.remove(&inode_number);
+        let inode = self.inode_cache.lock().remove(&inode_number);
+        if let Some(inode) = inode {
+            self.orphans
+                .lock()
+                .insert(inode_number, Arc::downgrade(&inode));
+            // the inode may be evicted here, so the locks must not be held
+            drop(inode);
+        }
     }
     pub fn get_inode(&self, inode_number: u64) -> Option<Arc<dyn VfsInode>> {
         let cache = self.inode_cache.lock();
         cache.get(&inode_number).cloned()
     }
+    /// Get an unlinked inode which is still used
+    pub fn get_orphan(&self, inode_number: u64) -> Option<Arc<dyn VfsInode>> {
+        let orphans = self.orphans.lock();
+        orphans.get(&inode_number).and_then(Weak::upgrade)
+    }
+    /// The number of unlinked inodes which are still used
+    pub fn orphan_count(&self) -> usize {
+        self.orphans.lock().len()
+    }
     pub fn root_dentry(&self, ab_mnt: &str) -> VfsResult<Arc<dyn VfsDentry>> {
         let mut mnt_info = self.mnt_info.lock();
         let res = mnt_info.get(ab_mnt).cloned();
@@ -156,4 +188,14 @@ impl<R: VfsRawMutex + 'static> VfsSuperBlock for UniFsSuperBlock<R> {
             Err(VfsError::Invalid)
         }
     }
+
+    fn evict_inode(&self, inode_number: u64) -> VfsResult<()> {
+        // only the removed inodes are counted off, the inodes in the cache die with the
+        // super block
+        if self.orphans.lock().remove(&inode_number).is_some() {
+            self.inode_count
+                .fetch_sub(1, core::sync::atomic::Ordering::SeqCst);
+        }
+        Ok(())
+    }
 }
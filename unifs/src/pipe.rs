@@ -0,0 +1,227 @@
+//! Pipes for pipe(2) and named fifos
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::task::Waker;
+
+use vfscore::{
+    error::VfsError, file::VfsFile, poll::VfsWaitQueue, utils::VfsPollEvents, VfsResult,
+};
+
+use crate::VfsRawMutex;
+
+/// The default capacity of a pipe
+pub const PIPE_SIZE: usize = 16 * 4096;
+/// Writes of at most `PIPE_BUF` bytes are atomic
+pub const PIPE_BUF: usize = 4096;
+
+struct PipeInner {
+    /// The ring buffer
+    buf: Vec<u8>,
+    head: usize,
+    len: usize,
+    readers: usize,
+    writers: usize,
+    wait: VfsWaitQueue,
+}
+
+impl PipeInner {
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let len = buf.len().min(self.len);
+        let capacity = self.buf.len();
+        let first = len.min(capacity - self.head);
+        buf[..first].copy_from_slice(&self.buf[self.head..self.head + first]);
+        buf[first..len].copy_from_slice(&self.buf[..len - first]);
+        self.head = (self.head + len) % capacity;
+        self.len -= len;
+        len
+    }
+
+    fn write(&mut self, buf: &[u8]) -> usize {
+        let capacity = self.buf.len();
+        let len = buf.len().min(capacity - self.len);
+        let tail = (self.head + self.len) % capacity;
+        let first = len.min(capacity - tail);
+        self.buf[tail..tail + first].copy_from_slice(&buf[..first]);
+        self.buf[..len - first].copy_from_slice(&buf[first..len]);
+        self.len += len;
+        len
+    }
+
+    fn events(&self) -> VfsPollEvents {
+        let mut events = VfsPollEvents::empty();
+        if self.len > 0 {
+            events |= VfsPollEvents::IN;
+        }
+        if self.len < self.buf.len() {
+            events |= VfsPollEvents::OUT;
+        }
+        if self.writers == 0 {
+            events |= VfsPollEvents::HUP;
+        }
+        if self.readers == 0 {
+            events |= VfsPollEvents::ERR;
+        }
+        events
+    }
+}
+
+/// A pipe with a bounded ring buffer
+///
+/// The data is read and written through the ends opened by [`UniFsPipe::open`]. The pipe
+/// never blocks, the callers wait by [`VfsFile::poll_wait`] when `EAGAIN` is returned.
+pub struct UniFsPipe<R: VfsRawMutex> {
+    inner: lock_api::Mutex<R, PipeInner>,
+}
+
+impl<R: VfsRawMutex + 'static> UniFsPipe<R> {
+    pub fn new(capacity: usize) -> Arc<Self> {
+        Arc::new(Self {
+            inner: lock_api::Mutex::new(PipeInner {
+                buf: vec![0; capacity.max(1)],
+                head: 0,
+                len: 0,
+                readers: 0,
+                writers: 0,
+                wait: VfsWaitQueue::new(),
+            }),
+        })
+    }
+
+    /// Create an anonymous pipe for pipe(2), returns the read end and the write end
+    pub fn pair() -> (Arc<UniFsPipeFile<R>>, Arc<UniFsPipeFile<R>>) {
+        let pipe = Self::new(PIPE_SIZE);
+        (pipe.open(true, false), pipe.open(false, true))
+    }
+
+    /// Open the ends of the pipe, the peers are woken up
+    pub fn open(self: &Arc<Self>, readable: bool, writable: bool) -> Arc<UniFsPipeFile<R>> {
+        let mut inner = self.inner.lock();
+        inner.readers += readable as usize;
+        inner.writers += writable as usize;
+        inner.wait.wake(VfsPollEvents::IN | VfsPollEvents::OUT);
+        Arc::new(UniFsPipeFile {
+            pipe: self.clone(),
+            readable,
+            writable,
+        })
+    }
+
+    /// The number of open read ends
+    pub fn readers(&self) -> usize {
+        self.inner.lock().readers
+    }
+
+    /// The number of open write ends
+    pub fn writers(&self) -> usize {
+        self.inner.lock().writers
+    }
+
+    /// The number of bytes in the pipe
+    pub fn len(&self) -> usize {
+        self.inner.lock().len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// An open end of a [`UniFsPipe`], the end is closed when it is dropped
+///
+/// - Reading an empty pipe returns `EAGAIN`, or 0 if no write end is open.
+/// - Writing a pipe without any read end returns `EPIPE`.
+/// - Writing a full pipe returns `EAGAIN`. Writes of at most [`PIPE_BUF`] bytes are never
+///   split.
+pub struct UniFsPipeFile<R: VfsRawMutex> {
+    pipe: Arc<UniFsPipe<R>>,
+    readable: bool,
+    writable: bool,
+}
+
+impl<R: VfsRawMutex + 'static> UniFsPipeFile<R> {
+    pub fn pipe(&self) -> &Arc<UniFsPipe<R>> {
+        &self.pipe
+    }
+
+    fn ready(&self, inner: &PipeInner, event: VfsPollEvents) -> VfsPollEvents {
+        let events = inner.events();
+        let mut res = VfsPollEvents::empty();
+        if self.readable {
+            res |= events & (event & VfsPollEvents::IN | VfsPollEvents::HUP);
+        }
+        if self.writable {
+            res |= events & (event & VfsPollEvents::OUT | VfsPollEvents::ERR);
+        }
+        res
+    }
+}
+
+impl<R: VfsRawMutex + 'static> VfsFile for UniFsPipeFile<R> {
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        if !self.readable {
+            return Err(VfsError::PermissionDenied);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut inner = self.pipe.inner.lock();
+        if inner.len == 0 {
+            return match inner.writers {
+                0 => Ok(0),
+                _ => Err(VfsError::EAGAIN),
+            };
+        }
+        let len = inner.read(buf);
+        inner.wait.wake(VfsPollEvents::OUT);
+        Ok(len)
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        if !self.writable {
+            return Err(VfsError::PermissionDenied);
+        }
+        let mut inner = self.pipe.inner.lock();
+        if inner.readers == 0 {
+            return Err(VfsError::EPIPE);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let free = inner.buf.len() - inner.len;
+        if free == 0 || (buf.len() <= PIPE_BUF && free < buf.len()) {
+            return Err(VfsError::EAGAIN);
+        }
+        let len = inner.write(buf);
+        inner.wait.wake(VfsPollEvents::IN);
+        Ok(len)
+    }
+
+    fn poll(&self, event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
+        Ok(self.ready(&self.pipe.inner.lock(), event))
+    }
+
+    fn poll_wait(&self, event: VfsPollEvents, waker: &Waker) -> VfsResult<VfsPollEvents> {
+        // the state can't change between the check and the registration
+        let mut inner = self.pipe.inner.lock();
+        let res = self.ready(&inner, event);
+        if res.is_empty() {
+            inner.wait.register(event, waker);
+        }
+        Ok(res)
+    }
+}
+
+impl<R: VfsRawMutex> Drop for UniFsPipeFile<R> {
+    fn drop(&mut self) {
+        let mut inner = self.pipe.inner.lock();
+        inner.readers -= self.readable as usize;
+        inner.writers -= self.writable as usize;
+        let mut events = VfsPollEvents::empty();
+        if self.writable && inner.writers == 0 {
+            events |= VfsPollEvents::HUP;
+        }
+        if self.readable && inner.readers == 0 {
+            events |= VfsPollEvents::ERR;
+        }
+        inner.wait.wake(events);
+    }
+}
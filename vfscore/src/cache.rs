@@ -0,0 +1,622 @@
+use alloc::{
+    collections::{btree_map::Entry, BTreeMap},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::ops::RangeInclusive;
+
+use crate::{inode::VfsInode, superblock::VfsSuperBlock, VfsResult};
+
+pub const PAGE_SIZE: usize = 4096;
+
+/// The page cache of the kernel which the VFS writes back when it syncs the files, see
+/// [`PageCache`]
+///
+/// The kernel implements it for its locked [`PageCache`].
+pub trait VfsPageCache: Send + Sync {
+    /// Write back the dirty pages of `inode` and sync it, like [`PageCache::fsync`]
+    fn fsync(&self, inode: &Arc<dyn VfsInode>) -> VfsResult<()>;
+    /// Write back the dirty pages of all files in `sb` and sync it, like [`PageCache::sync_fs`]
+    fn sync_fs(&self, sb: &Arc<dyn VfsSuperBlock>, wait: bool) -> VfsResult<()>;
+}
+
+/// The key of a page in the [`PageCache`]
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub struct PageKey {
+    /// The address of the super block
+    pub sb: usize,
+    /// The address of the inode
+    pub inode: usize,
+    /// The index of the page in the file
+    pub index: u64,
+}
+
+struct Page {
+    data: Vec<u8>,
+    dirty: bool,
+    /// The last time the page was used, the key in the lru list
+    stamp: u64,
+}
+
+struct CachedFile {
+    inode: Arc<dyn VfsInode>,
+    /// The size of the file including the data which is not written back
+    size: u64,
+}
+
+/// A page cache which the kernel puts in front of the inodes of any file system
+///
+/// Pages are loaded by [`VfsFile::read_at`](crate::file::VfsFile::read_at) of the inode and
+/// written back by [`VfsFile::write_at`](crate::file::VfsFile::write_at) on
+/// [`PageCache::fsync`], [`PageCache::sync_fs`] or when they are evicted. The cache holds the
+/// inodes until all their pages are evicted, so the addresses in [`PageKey`] stay unique.
+///
+/// The file systems don't know about the cache, the kernel should read and write the files
+/// through it instead of the methods of the inodes. The VFS writes the cache back through
+/// [`VfsPageCache`] where it syncs the files: [`VfsOpenFile::fsync`](crate::fd::VfsOpenFile::fsync)
+/// and the `O_SYNC` writes of an open file created by
+/// [`VfsOpenFile::new_with_cache`](crate::fd::VfsOpenFile::new_with_cache), and unmounting
+/// through a path bound by [`VfsPath::with_page_cache`](crate::path::VfsPath::with_page_cache).
+/// The kernel calls [`PageCache::sync_fs`] for sync(2) itself. The devices of fat-vfs and
+/// lwext4-vfs are cached by [`BufferCache`].
+///
+/// The cache doesn't lock itself, the kernel should wrap it in its own lock.
+pub struct PageCache {
+    pages: BTreeMap<PageKey, Page>,
+    files: BTreeMap<(usize, usize), CachedFile>,
+    /// The pages ordered by the last time they were used
+    lru: BTreeMap<u64, PageKey>,
+    clock: u64,
+    /// The max number of pages
+    limit: usize,
+}
+
+impl PageCache {
+    /// Create a page cache which uses at most `limit` bytes of memory
+    pub fn new(limit: usize) -> Self {
+        Self {
+            pages: BTreeMap::new(),
+            files: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+            limit: (limit / PAGE_SIZE).max(1),
+        }
+    }
+
+    /// Read the data of `inode` from `offset` through the cache
+    ///
+    /// If a page can't be loaded after some data is read, the short count is returned.
+    pub fn read(
+        &mut self,
+        inode: &Arc<dyn VfsInode>,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> VfsResult<usize> {
+        let size = match self.size(inode) {
+            Some(size) => size,
+            None => inode.get_attr()?.st_size,
+        };
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = buf.len().min((size - offset) as usize);
+        let mut count = 0;
+        while count < len {
+            let pos = offset + count as u64;
+            let index = pos / PAGE_SIZE as u64;
+            let start = (pos % PAGE_SIZE as u64) as usize;
+            let n = (PAGE_SIZE - start).min(len - count);
+            let page = match self.page(inode, index) {
+                Ok(page) => page,
+                Err(_) if count > 0 => break,
+                Err(e) => return Err(e),
+            };
+            buf[count..count + n].copy_from_slice(&page.data[start..start + n]);
+            count += n;
+        }
+        // a page which can't be written back stays dirty, fsync reports the error
+        let _ = self.shrink();
+        Ok(count)
+    }
+
+    /// Write the data of `inode` from `offset` into the cache, the pages are marked dirty
+    pub fn write(
+        &mut self,
+        inode: &Arc<dyn VfsInode>,
+        offset: u64,
+        buf: &[u8],
+    ) -> VfsResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut count = 0;
+        while count < buf.len() {
+            let pos = offset + count as u64;
+            let index = pos / PAGE_SIZE as u64;
+            let start = (pos % PAGE_SIZE as u64) as usize;
+            let n = (PAGE_SIZE - start).min(buf.len() - count);
+            let page = self.page(inode, index)?;
+            page.data[start..start + n].copy_from_slice(&buf[count..count + n]);
+            page.dirty = true;
+            count += n;
+        }
+        let file = self.file(inode)?;
+        file.size = file.size.max(offset + count as u64);
+        self.shrink()?;
+        Ok(count)
+    }
+
+    /// Get the size of `inode` including the data which is not written back
+    pub fn size(&self, inode: &Arc<dyn VfsInode>) -> Option<u64> {
+        self.files.get(&file_key(inode)).map(|file| file.size)
+    }
+
+    /// Truncate `inode` to `len` and drop the cached pages beyond it
+    pub fn truncate(&mut self, inode: &Arc<dyn VfsInode>, len: u64) -> VfsResult<()> {
+        inode.truncate(len)?;
+        let key = file_key(inode);
+        let first = len.div_ceil(PAGE_SIZE as u64);
+        let pages = self.file_pages(key);
+        for page in pages.into_iter().filter(|page| page.index >= first) {
+            self.remove(page);
+        }
+        if let Some(file) = self.files.get_mut(&key) {
+            file.size = len;
+            // the tail of the last page is zero after truncating
+            let index = len / PAGE_SIZE as u64;
+            let start = (len % PAGE_SIZE as u64) as usize;
+            let page_key = PageKey {
+                sb: key.0,
+                inode: key.1,
+                index,
+            };
+            if let Some(page) = self.pages.get_mut(&page_key) {
+                page.data[start..].fill(0);
+            }
+        }
+        Ok(())
+    }
+
+    /// Write back the dirty pages of `inode` and sync it
+    pub fn fsync(&mut self, inode: &Arc<dyn VfsInode>) -> VfsResult<()> {
+        for page in self.file_pages(file_key(inode)) {
+            self.write_back(page)?;
+        }
+        inode.fsync()
+    }
+
+    /// Write back the dirty pages of all files in `sb` and sync it
+    pub fn sync_fs(&mut self, sb: &Arc<dyn VfsSuperBlock>, wait: bool) -> VfsResult<()> {
+        let sb_key = Arc::as_ptr(sb) as *const () as usize;
+        let pages = self
+            .pages
+            .keys()
+            .filter(|key| key.sb == sb_key)
+            .copied()
+            .collect::<Vec<_>>();
+        for page in pages {
+            self.write_back(page)?;
+        }
+        sb.sync_fs(wait)
+    }
+
+    /// Drop the pages of `inode` without writing them back, e.g. after the inode is deleted
+    pub fn invalidate(&mut self, inode: &Arc<dyn VfsInode>) {
+        for page in self.file_pages(file_key(inode)) {
+            self.remove(page);
+        }
+    }
+
+    /// The number of cached pages
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    /// The number of pages which are not written back
+    pub fn dirty_pages(&self) -> usize {
+        self.pages.values().filter(|page| page.dirty).count()
+    }
+
+    fn file(&mut self, inode: &Arc<dyn VfsInode>) -> VfsResult<&mut CachedFile> {
+        let file = match self.files.entry(file_key(inode)) {
+            Entry::Occupied(file) => file.into_mut(),
+            Entry::Vacant(entry) => entry.insert(CachedFile {
+                inode: inode.clone(),
+                size: inode.get_attr()?.st_size,
+            }),
+        };
+        Ok(file)
+    }
+
+    /// Get the page and mark it as the most recently used one, load it if it isn't cached
+    fn page(&mut self, inode: &Arc<dyn VfsInode>, index: u64) -> VfsResult<&mut Page> {
+        let size = self.file(inode)?.size;
+        let (sb, ino) = file_key(inode);
+        let key = PageKey {
+            sb,
+            inode: ino,
+            index,
+        };
+        self.clock += 1;
+        let stamp = self.clock;
+        match self.pages.get_mut(&key) {
+            Some(page) => {
+                self.lru.remove(&page.stamp);
+                page.stamp = stamp;
+            }
+            None => {
+                let mut data = vec![0; PAGE_SIZE];
+                let offset = index * PAGE_SIZE as u64;
+                if offset < size {
+                    let len = PAGE_SIZE.min((size - offset) as usize);
+                    let mut count = 0;
+                    while count < len {
+                        let n = inode.read_at(offset + count as u64, &mut data[count..len])?;
+                        if n == 0 {
+                            break;
+                        }
+                        count += n;
+                    }
+                }
+                self.pages.insert(
+                    key,
+                    Page {
+                        data,
+                        dirty: false,
+                        stamp,
+                    },
+                );
+            }
+        }
+        self.lru.insert(stamp, key);
+        Ok(self.pages.get_mut(&key).unwrap())
+    }
+
+    /// Evict the least recently used pages until the cache is within the limit
+    fn shrink(&mut self) -> VfsResult<()> {
+        while self.pages.len() > self.limit {
+            let (_, key) = self.lru.pop_first().unwrap();
+            if let Err(e) = self.write_back(key) {
+                let stamp = self.pages[&key].stamp;
+                self.lru.insert(stamp, key);
+                return Err(e);
+            }
+            self.remove(key);
+        }
+        Ok(())
+    }
+
+    fn write_back(&mut self, key: PageKey) -> VfsResult<()> {
+        let page = match self.pages.get_mut(&key) {
+            Some(page) if page.dirty => page,
+            _ => return Ok(()),
+        };
+        let file = &self.files[&(key.sb, key.inode)];
+        let offset = key.index * PAGE_SIZE as u64;
+        if offset < file.size {
+            let len = PAGE_SIZE.min((file.size - offset) as usize);
+            file.inode.write_at(offset, &page.data[..len])?;
+        }
+        page.dirty = false;
+        Ok(())
+    }
+
+    fn remove(&mut self, key: PageKey) {
+        if let Some(page) = self.pages.remove(&key) {
+            self.lru.remove(&page.stamp);
+        }
+        let file = (key.sb, key.inode);
+        if self.pages.range(file_range(file)).next().is_none() {
+            self.files.remove(&file);
+        }
+    }
+
+    fn file_pages(&self, file: (usize, usize)) -> Vec<PageKey> {
+        self.pages
+            .range(file_range(file))
+            .map(|(key, _)| *key)
+            .collect()
+    }
+}
+
+/// The keys of all pages of the file
+fn file_range((sb, inode): (usize, usize)) -> RangeInclusive<PageKey> {
+    let start = PageKey {
+        sb,
+        inode,
+        index: 0,
+    };
+    let end = PageKey {
+        sb,
+        inode,
+        index: u64::MAX,
+    };
+    start..=end
+}
+
+/// The addresses of the super block and the inode
+fn file_key(inode: &Arc<dyn VfsInode>) -> (usize, usize) {
+    let sb = inode
+        .get_super_block()
+        .map_or(0, |sb| Arc::as_ptr(&sb) as *const () as usize);
+    (sb, Arc::as_ptr(inode) as *const () as usize)
+}
+
+/// The default max number of blocks which are read ahead at once
+pub const DEFAULT_READ_AHEAD: usize = 32;
+
+/// The default number of blocks in the buffer cache of a device
+pub const DEFAULT_CACHE_BLOCKS: usize = 1024;
+
+/// The statistics of a [`BufferCache`]
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct BufferCacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    /// The number of read requests sent to the device
+    pub device_reads: usize,
+    /// The number of write requests sent to the device
+    pub device_writes: usize,
+}
+
+/// A buffer cache for block devices
+///
+/// Reads of blocks which are not cached are sent to the device in one request per run of
+/// missing blocks. When the reads are sequential, the following blocks are read ahead, the
+/// read-ahead window doubles on every sequential miss up to the max. Writes stay in the cache
+/// until [`BufferCache::flush`], which writes adjacent dirty blocks in one request, or until
+/// the block is evicted.
+///
+/// The cache doesn't lock itself, the file system should wrap it in its own lock.
+pub struct BufferCache {
+    device: Arc<dyn VfsInode>,
+    block_size: usize,
+    /// The number of blocks of the device
+    block_count: u64,
+    blocks: BTreeMap<u64, Page>,
+    lru: BTreeMap<u64, u64>,
+    clock: u64,
+    /// The max number of cached blocks
+    capacity: usize,
+    /// The block after the last read, a miss there means the reads are sequential
+    next_block: u64,
+    window: usize,
+    max_read_ahead: usize,
+    stats: BufferCacheStats,
+}
+
+impl BufferCache {
+    /// Create a buffer cache of a device with `block_count` blocks of `block_size` bytes, which
+    /// holds at most `capacity` blocks
+    pub fn new(
+        device: Arc<dyn VfsInode>,
+        block_size: usize,
+        block_count: u64,
+        capacity: usize,
+    ) -> Self {
+        Self {
+            device,
+            block_size,
+            block_count,
+            blocks: BTreeMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+            capacity: capacity.max(1),
+            next_block: u64::MAX,
+            window: 0,
+            max_read_ahead: DEFAULT_READ_AHEAD,
+            stats: BufferCacheStats::default(),
+        }
+    }
+
+    /// Set the number of blocks of the device, e.g. after it is resized
+    pub fn set_block_count(&mut self, block_count: u64) {
+        self.block_count = block_count;
+    }
+
+    /// Set the max number of blocks which are read ahead at once, 0 disables read-ahead
+    pub fn set_read_ahead(&mut self, max_blocks: usize) {
+        self.max_read_ahead = max_blocks;
+        self.window = self.window.min(max_blocks);
+    }
+
+    pub fn device(&self) -> &Arc<dyn VfsInode> {
+        &self.device
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn block_count(&self) -> u64 {
+        self.block_count
+    }
+
+    pub fn stats(&self) -> BufferCacheStats {
+        self.stats
+    }
+
+    pub fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let size = self.block_count * self.block_size as u64;
+        if offset >= size || buf.is_empty() {
+            return Ok(0);
+        }
+        let len = buf.len().min((size - offset) as usize);
+        let bs = self.block_size as u64;
+        let first = offset / bs;
+        let last = (offset + len as u64 - 1) / bs;
+        let mut block = first;
+        while block <= last {
+            if self.blocks.contains_key(&block) {
+                self.stats.hits += 1;
+                block += 1;
+                continue;
+            }
+            self.stats.misses += 1;
+            let mut end = block + 1;
+            while end <= last && !self.blocks.contains_key(&end) {
+                end += 1;
+            }
+            if block == self.next_block && self.max_read_ahead > 0 {
+                self.window = (self.window * 2).clamp(1, self.max_read_ahead);
+                let limit = (end + self.window as u64).min(self.block_count);
+                while end < limit && !self.blocks.contains_key(&end) {
+                    end += 1;
+                }
+            } else {
+                self.window = 0;
+            }
+            self.load(block, end)?;
+            block = end;
+        }
+        self.next_block = last + 1;
+        let mut count = 0;
+        while count < len {
+            let pos = offset + count as u64;
+            let start = (pos % bs) as usize;
+            let n = (self.block_size - start).min(len - count);
+            let data = &self.touch(pos / bs).data;
+            buf[count..count + n].copy_from_slice(&data[start..start + n]);
+            count += n;
+        }
+        self.shrink()?;
+        Ok(count)
+    }
+
+    pub fn write_at(&mut self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let size = self.block_count * self.block_size as u64;
+        if offset >= size || buf.is_empty() {
+            return Ok(0);
+        }
+        let len = buf.len().min((size - offset) as usize);
+        let bs = self.block_size as u64;
+        let mut count = 0;
+        while count < len {
+            let pos = offset + count as u64;
+            let block = pos / bs;
+            let start = (pos % bs) as usize;
+            let n = (self.block_size - start).min(len - count);
+            if !self.blocks.contains_key(&block) {
+                if n == self.block_size {
+                    // the whole block is overwritten, don't read it
+                    self.insert(block, vec![0; self.block_size]);
+                } else {
+                    self.stats.misses += 1;
+                    self.load(block, block + 1)?;
+                }
+            }
+            let buffer = self.touch(block);
+            buffer.data[start..start + n].copy_from_slice(&buf[count..count + n]);
+            buffer.dirty = true;
+            count += n;
+        }
+        self.shrink()?;
+        Ok(count)
+    }
+
+    /// Write all dirty blocks back to the device
+    pub fn flush(&mut self) -> VfsResult<()> {
+        let dirty = self
+            .blocks
+            .iter()
+            .filter(|(_, buffer)| buffer.dirty)
+            .map(|(block, _)| *block)
+            .collect::<Vec<_>>();
+        let mut index = 0;
+        while index < dirty.len() {
+            let start = dirty[index];
+            let mut end = index + 1;
+            while end < dirty.len() && dirty[end] == start + (end - index) as u64 {
+                end += 1;
+            }
+            let mut data = Vec::with_capacity((end - index) * self.block_size);
+            for block in &dirty[index..end] {
+                data.extend_from_slice(&self.blocks[block].data);
+            }
+            self.stats.device_writes += 1;
+            self.device
+                .write_at(start * self.block_size as u64, &data)?;
+            for block in &dirty[index..end] {
+                self.blocks.get_mut(block).unwrap().dirty = false;
+            }
+            index = end;
+        }
+        Ok(())
+    }
+
+    /// Drop all clean blocks, e.g. after the device is changed by others
+    pub fn invalidate(&mut self) {
+        let lru = &mut self.lru;
+        self.blocks.retain(|_, buffer| {
+            if !buffer.dirty {
+                lru.remove(&buffer.stamp);
+            }
+            buffer.dirty
+        });
+    }
+
+    /// Read the blocks in `start..end` from the device in one request
+    fn load(&mut self, start: u64, end: u64) -> VfsResult<()> {
+        let mut data = vec![0; (end - start) as usize * self.block_size];
+        let offset = start * self.block_size as u64;
+        let mut count = 0;
+        self.stats.device_reads += 1;
+        while count < data.len() {
+            let n = self
+                .device
+                .read_at(offset + count as u64, &mut data[count..])?;
+            if n == 0 {
+                break;
+            }
+            count += n;
+        }
+        for (index, chunk) in data.chunks(self.block_size).enumerate() {
+            self.insert(start + index as u64, chunk.to_vec());
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, block: u64, data: Vec<u8>) {
+        self.clock += 1;
+        self.lru.insert(self.clock, block);
+        self.blocks.insert(
+            block,
+            Page {
+                data,
+                dirty: false,
+                stamp: self.clock,
+            },
+        );
+    }
+
+    /// Get the cached block and mark it as the most recently used one
+    fn touch(&mut self, block: u64) -> &mut Page {
+        self.clock += 1;
+        let buffer = self.blocks.get_mut(&block).unwrap();
+        self.lru.remove(&buffer.stamp);
+        buffer.stamp = self.clock;
+        self.lru.insert(self.clock, block);
+        buffer
+    }
+
+    /// Evict the least recently used blocks until the cache is within the capacity
+    fn shrink(&mut self) -> VfsResult<()> {
+        while self.blocks.len() > self.capacity {
+            let (_, &block) = self.lru.first_key_value().unwrap();
+            let buffer = &self.blocks[&block];
+            if buffer.dirty {
+                self.stats.device_writes += 1;
+                self.device
+                    .write_at(block * self.block_size as u64, &buffer.data)?;
+            }
+            self.lru.pop_first();
+            self.blocks.remove(&block);
+        }
+        Ok(())
+    }
+}
@@ -3,7 +3,7 @@ use alloc::{string::String, sync::Arc};
 use downcast_rs::{impl_downcast, DowncastSync};
 use log::warn;
 
-use crate::{fstype::VfsMountPoint, inode::VfsInode, VfsResult};
+use crate::{error::VfsError, fstype::VfsMountPoint, inode::VfsInode, VfsResult};
 
 pub trait VfsDentry: Send + Sync + DowncastSync {
     /// Return the name of this dentry
@@ -34,8 +34,23 @@ pub trait VfsDentry: Send + Sync + DowncastSync {
         name: &str,
         child: Arc<dyn VfsInode>,
     ) -> VfsResult<Arc<dyn VfsDentry>>;
+    /// Create a dentry of `child` which is not a child of this directory, like the dentry of
+    /// an `O_TMPFILE` file
+    ///
+    /// The dentry has no name and can't be found in this directory, but its parent is this
+    /// directory. The default implementation returns [`VfsError::NoSys`].
+    fn detached(self: Arc<Self>, _child: Arc<dyn VfsInode>) -> VfsResult<Arc<dyn VfsDentry>> {
+        Err(VfsError::NoSys)
+    }
     /// Remove a child from this dentry and return the dentry of the child
     fn remove(&self, name: &str) -> Option<Arc<dyn VfsDentry>>;
+    /// Whether any child is cached in this dentry
+    ///
+    /// A dentry with cached children is never evicted by a [`VfsDentryCache`], the default
+    /// implementation always returns true.
+    fn has_children(&self) -> bool {
+        true
+    }
 
     /// Get the parent of this dentry
     fn parent(&self) -> Option<Arc<dyn VfsDentry>>;
@@ -97,3 +112,30 @@ impl dyn VfsDentry {
 }
 
 impl_downcast!(sync VfsDentry);
+
+/// The result of [`VfsDentryCache::lookup`]
+pub enum VfsDentryLookup {
+    /// The child is in the cache
+    Found(Arc<dyn VfsDentry>),
+    /// The child is known not to exist
+    Negative,
+    /// The child should be looked up in the inode of the directory
+    Miss,
+}
+
+/// A global cache of the lookups in directories
+///
+/// It remembers the names which don't exist (negative dentries), so they are not looked up in
+/// the inodes again, and evicts the dentries which are not used recently.
+/// [`VfsPath`](crate::path::VfsPath) finds the children through it when it is given by
+/// `with_dcache`, and updates it when it creates or removes the children. The kernel should
+/// call [`VfsDentryCache::invalidate`] when it creates a child without the path.
+pub trait VfsDentryCache: Send + Sync {
+    /// Find the child `name` of `parent`
+    fn lookup(&self, parent: &Arc<dyn VfsDentry>, name: &str) -> VfsDentryLookup;
+    /// Record that the child `name` of `parent` is `dentry`, `None` means it doesn't exist
+    fn insert(&self, parent: &Arc<dyn VfsDentry>, name: &str, dentry: Option<&Arc<dyn VfsDentry>>);
+    /// Forget the child `name` of `parent`, called when the child is created in the inode of
+    /// the directory, so a negative entry doesn't hide it even if no dentry is inserted
+    fn invalidate(&self, parent: &Arc<dyn VfsDentry>, name: &str);
+}
@@ -13,6 +13,10 @@ pub enum VfsError {
     EINTR = 4,
     /// EIO 输入输出错误
     IoError = 5,
+    /// ENXIO 没有这样的设备或地址
+    ENXIO = 6,
+    /// EBADF 错误的文件描述符
+    EBADF = 9,
     /// try again
     EAGAIN = 11,
     /// ENOMEM 内存不足
@@ -23,6 +27,8 @@ pub enum VfsError {
     EBUSY = 16,
     /// EEXIST 文件已存在
     EExist = 17,
+    /// EXDEV 跨设备链接
+    CrossDevice = 18,
     /// ENOTDIR 不是目录
     NotDir = 20,
     /// EINVAL 无效参数
@@ -31,20 +37,34 @@ pub enum VfsError {
     NoDev = 19,
     /// IsDir 是目录
     IsDir = 21,
+    /// EMFILE 打开的文件过多
+    EMFILE = 24,
     /// ENOTTY 不是终端
     NoTTY = 25,
     /// ENOSPC 空间不足
     NoSpace = 28,
     /// Illegal seek
     ESPIPE = 29,
+    /// EROFS 只读文件系统
+    ReadOnlyFs = 30,
     /// Broken pipe
     EPIPE = 32,
+    /// EDEADLK 将导致死锁
+    EDEADLK = 35,
     /// ENAMETOOLONG 名称太长
     NameTooLong = 36,
     /// ENOSYS 不支持的系统调用
     NoSys = 38,
     /// ENOTEMPTY  目录非空
     NotEmpty = 39,
+    /// ELOOP 符号链接过多
+    SymLinkLoop = 40,
+    /// ENODATA 没有可用的数据(扩展属性不存在)
+    NoData = 61,
+    /// EOPNOTSUPP 不支持的操作
+    EOPNOTSUPP = 95,
+    /// ECONNREFUSED 连接被拒绝
+    ECONNREFUSED = 111,
 }
 
 impl Display for VfsError {
@@ -62,24 +82,36 @@ impl Display for VfsError {
             VfsError::ESPIPE => {
                 write!(f, "Illegal seek")
             }
+            VfsError::ReadOnlyFs => {
+                write!(f, "Read-only file system")
+            }
             VfsError::PermissionDenied => {
                 write!(f, "Permission denied")
             }
             VfsError::NoEntry => {
                 write!(f, "No such file or directory")
             }
+            VfsError::ENXIO => {
+                write!(f, "No such device or address")
+            }
             VfsError::IoError => {
                 write!(f, "Input/output error")
             }
             VfsError::EExist => {
                 write!(f, "File exists")
             }
+            VfsError::CrossDevice => {
+                write!(f, "Invalid cross-device link")
+            }
             VfsError::NotDir => {
                 write!(f, "Not a directory")
             }
             VfsError::NotEmpty => {
                 write!(f, "Directory not empty")
             }
+            VfsError::SymLinkLoop => {
+                write!(f, "Too many levels of symbolic links")
+            }
             VfsError::NoMem => {
                 write!(f, "Out of memory")
             }
@@ -89,6 +121,9 @@ impl Display for VfsError {
             VfsError::Invalid => {
                 write!(f, "Invalid argument")
             }
+            VfsError::EDEADLK => {
+                write!(f, "Resource deadlock avoided")
+            }
             VfsError::NameTooLong => {
                 write!(f, "File name too long")
             }
@@ -110,6 +145,21 @@ impl Display for VfsError {
             VfsError::EBUSY => {
                 write!(f, "Device or resource busy")
             }
+            VfsError::EBADF => {
+                write!(f, "Bad file descriptor")
+            }
+            VfsError::EMFILE => {
+                write!(f, "Too many open files")
+            }
+            VfsError::NoData => {
+                write!(f, "No data available")
+            }
+            VfsError::EOPNOTSUPP => {
+                write!(f, "Operation not supported")
+            }
+            VfsError::ECONNREFUSED => {
+                write!(f, "Connection refused")
+            }
         }
     }
 }
@@ -129,22 +179,32 @@ impl From<i32> for VfsError {
             2 => VfsError::NoEntry,
             4 => VfsError::EINTR,
             5 => VfsError::IoError,
+            6 => VfsError::ENXIO,
+            9 => VfsError::EBADF,
             11 => VfsError::EAGAIN,
             12 => VfsError::NoMem,
             13 => VfsError::Access,
             16 => VfsError::EBUSY,
             17 => VfsError::EExist,
+            18 => VfsError::CrossDevice,
             20 => VfsError::NotDir,
             22 => VfsError::Invalid,
             19 => VfsError::NoDev,
             21 => VfsError::IsDir,
+            24 => VfsError::EMFILE,
             25 => VfsError::NoTTY,
             28 => VfsError::NoSpace,
             29 => VfsError::ESPIPE,
+            30 => VfsError::ReadOnlyFs,
             32 => VfsError::EPIPE,
+            35 => VfsError::EDEADLK,
             36 => VfsError::NameTooLong,
             38 => VfsError::NoSys,
             39 => VfsError::NotEmpty,
+            40 => VfsError::SymLinkLoop,
+            61 => VfsError::NoData,
+            95 => VfsError::EOPNOTSUPP,
+            111 => VfsError::ECONNREFUSED,
             _ => VfsError::Invalid,
         }
     }
@@ -0,0 +1,560 @@
+//! Open file descriptions and file descriptor tables
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    sync::atomic::{AtomicU32, Ordering},
+    task::Waker,
+};
+
+use crate::{
+    cache::VfsPageCache,
+    dentry::VfsDentry,
+    error::VfsError,
+    file::VfsFile,
+    inode::VfsInode,
+    lock::VfsFileLocks,
+    notify::{notify_dentry, VfsNotifier, VfsNotifyMask},
+    path::{check_modify, SysContext},
+    utils::{VfsAccessMode, VfsDirEntry, VfsNodeType, VfsOpenFlags, VfsPollEvents, VfsSeekFrom},
+    VfsRawMutex, VfsResult,
+};
+
+/// An open file description, which is shared by the file descriptors duplicated from it
+///
+/// The data is read and written at the offset of the description. For directories the
+/// offset is the index of the next entry returned by [`VfsOpenFile::readdir`], so seeking to
+/// 0 rewinds the directory. Fifos are read and written through the pipe opened by
+/// [`VfsInode::open_fifo`].
+///
+/// The offset is locked across the IO which uses it, like `f_pos_lock` of linux, so the
+/// threads sharing the description don't read or write the same range. The lock may be
+/// held while the IO blocks, so `R` should be a sleeping lock of the kernel.
+pub struct VfsOpenFile<R: VfsRawMutex> {
+    dentry: Arc<dyn VfsDentry>,
+    inode: Arc<dyn VfsInode>,
+    /// The file which is read and written, the inode itself or the end of a pipe
+    file: Arc<dyn VfsFile>,
+    offset: lock_api::Mutex<R, u64>,
+    flags: AtomicU32,
+    notifier: Option<Arc<dyn VfsNotifier>>,
+    /// The locks which are released when the description is closed
+    locks: Option<Arc<dyn VfsFileLocks>>,
+    /// The page cache which is written back when the file is synced
+    cache: Option<Arc<dyn VfsPageCache>>,
+}
+
+impl<R: VfsRawMutex> VfsOpenFile<R> {
+    /// Open the file of `dentry`, the permission should have been checked by the caller
+    pub fn new(dentry: Arc<dyn VfsDentry>, flags: VfsOpenFlags) -> VfsResult<Arc<Self>> {
+        Self::open(dentry, flags, None, None, None)
+    }
+
+    /// Open the file of `dentry` like [`VfsOpenFile::new`], and publish `IN_OPEN`,
+    /// `IN_ACCESS`, `IN_MODIFY` and `IN_CLOSE_*` to `notifier`
+    pub fn new_with_notifier(
+        dentry: Arc<dyn VfsDentry>,
+        flags: VfsOpenFlags,
+        notifier: Arc<dyn VfsNotifier>,
+    ) -> VfsResult<Arc<Self>> {
+        Self::open(dentry, flags, Some(notifier), None, None)
+    }
+
+    /// Open the file of `dentry` like [`VfsOpenFile::new_with_notifier`], and release its
+    /// flock(2) lock in `locks` when it is closed
+    pub fn new_with_locks(
+        dentry: Arc<dyn VfsDentry>,
+        flags: VfsOpenFlags,
+        notifier: Option<Arc<dyn VfsNotifier>>,
+        locks: Arc<dyn VfsFileLocks>,
+    ) -> VfsResult<Arc<Self>> {
+        Self::open(dentry, flags, notifier, Some(locks), None)
+    }
+
+    /// Open the file of `dentry` like [`VfsOpenFile::new_with_locks`], and write back the
+    /// pages of the file in `cache` when it is synced by [`VfsOpenFile::fsync`] or an `O_SYNC`
+    /// write
+    pub fn new_with_cache(
+        dentry: Arc<dyn VfsDentry>,
+        flags: VfsOpenFlags,
+        notifier: Option<Arc<dyn VfsNotifier>>,
+        locks: Option<Arc<dyn VfsFileLocks>>,
+        cache: Arc<dyn VfsPageCache>,
+    ) -> VfsResult<Arc<Self>> {
+        Self::open(dentry, flags, notifier, locks, Some(cache))
+    }
+
+    fn open(
+        dentry: Arc<dyn VfsDentry>,
+        flags: VfsOpenFlags,
+        notifier: Option<Arc<dyn VfsNotifier>>,
+        locks: Option<Arc<dyn VfsFileLocks>>,
+        cache: Option<Arc<dyn VfsPageCache>>,
+    ) -> VfsResult<Arc<Self>> {
+        let inode = dentry.inode()?;
+        let ty = inode.inode_type();
+        if ty.is_dir() && flags.writable() {
+            return Err(VfsError::IsDir);
+        }
+        let file = if ty == VfsNodeType::Fifo && !flags.contains(VfsOpenFlags::O_PATH) {
+            let mut mode = VfsAccessMode::empty();
+            if flags.readable() {
+                mode |= VfsAccessMode::R_OK;
+            }
+            if flags.writable() {
+                mode |= VfsAccessMode::W_OK;
+            }
+            inode.open_fifo(mode)?
+        } else {
+            inode.clone() as Arc<dyn VfsFile>
+        };
+        let file = Arc::new(Self {
+            dentry,
+            inode,
+            file,
+            offset: lock_api::Mutex::new(0),
+            flags: AtomicU32::new((flags - VfsOpenFlags::O_CLOEXEC).bits()),
+            notifier,
+            locks,
+            cache,
+        });
+        file.notify(VfsNotifyMask::IN_OPEN);
+        Ok(file)
+    }
+
+    fn notify(&self, mask: VfsNotifyMask) {
+        if let Some(notifier) = &self.notifier {
+            notify_dentry(notifier.as_ref(), &self.dentry, mask);
+        }
+    }
+
+    /// Publish `mask` after `len` bytes are read or written
+    fn notify_io(&self, len: usize, mask: VfsNotifyMask) {
+        if len > 0 {
+            self.notify(mask);
+        }
+    }
+
+    pub fn dentry(&self) -> Arc<dyn VfsDentry> {
+        self.dentry.clone()
+    }
+
+    pub fn inode(&self) -> Arc<dyn VfsInode> {
+        self.inode.clone()
+    }
+
+    /// The id of the description which owns its flock(2) lock in
+    /// [`FileLockManager`](crate::lock::FileLockManager)
+    pub fn lock_id(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    /// The flags of the description, `O_CLOEXEC` belongs to the file descriptors
+    pub fn flags(&self) -> VfsOpenFlags {
+        VfsOpenFlags::from_bits_truncate(self.flags.load(Ordering::Relaxed))
+    }
+
+    /// Change the status flags, called by `F_SETFL` of fcntl(2)
+    ///
+    /// Only the flags in [`VfsOpenFlags::SETFL_MASK`] are changed.
+    pub fn set_flags(&self, flags: VfsOpenFlags) {
+        let mask = VfsOpenFlags::SETFL_MASK;
+        let _ = self
+            .flags
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |old| {
+                let old = VfsOpenFlags::from_bits_truncate(old);
+                Some(((old - mask) | (flags & mask)).bits())
+            });
+    }
+
+    pub fn is_nonblocking(&self) -> bool {
+        self.flags().contains(VfsOpenFlags::O_NONBLOCK)
+    }
+
+    pub fn offset(&self) -> u64 {
+        *self.offset.lock()
+    }
+
+    /// Whether the file has an offset, pipes and sockets don't
+    fn seekable(&self) -> bool {
+        !matches!(
+            self.inode.inode_type(),
+            VfsNodeType::Fifo | VfsNodeType::Socket
+        )
+    }
+
+    /// Read from the offset and advance it, called by read(2)
+    pub fn read(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        let flags = self.flags();
+        if !flags.readable() {
+            return Err(VfsError::EBADF);
+        }
+        if self.inode.inode_type().is_dir() {
+            return Err(VfsError::IsDir);
+        }
+        if !self.seekable() {
+            let len = self.file.read_at(0, buf)?;
+            self.notify_io(len, VfsNotifyMask::IN_ACCESS);
+            return Ok(len);
+        }
+        let mut offset = self.offset.lock();
+        let len = self.file.read_at(*offset, buf)?;
+        *offset += len as u64;
+        drop(offset);
+        self.notify_io(len, VfsNotifyMask::IN_ACCESS);
+        Ok(len)
+    }
+
+    /// Write at the offset and advance it, called by write(2)
+    ///
+    /// With `O_APPEND` the data is written at the end of the file by [`VfsInode::append`], with
+    /// `O_SYNC` or `O_DSYNC` the file is synced before returning. Immutable files can't be
+    /// written, and append-only files can only be written with `O_APPEND`.
+    pub fn write(&self, buf: &[u8]) -> VfsResult<usize> {
+        let flags = self.flags();
+        if !flags.writable() {
+            return Err(VfsError::EBADF);
+        }
+        if !self.seekable() {
+            let len = self.file.write_at(0, buf)?;
+            self.notify_io(len, VfsNotifyMask::IN_MODIFY);
+            return Ok(len);
+        }
+        let append = flags.contains(VfsOpenFlags::O_APPEND);
+        check_modify(&self.inode, append)?;
+        let mut offset = self.offset.lock();
+        let len = if append {
+            let (pos, len) = self.inode.append(buf)?;
+            *offset = pos + len as u64;
+            len
+        } else {
+            let len = self.file.write_at(*offset, buf)?;
+            *offset += len as u64;
+            len
+        };
+        drop(offset);
+        if flags.intersects(VfsOpenFlags::O_SYNC | VfsOpenFlags::O_DSYNC) {
+            self.fsync()?;
+        }
+        self.notify_io(len, VfsNotifyMask::IN_MODIFY);
+        Ok(len)
+    }
+
+    /// Read at `offset` without changing the offset, called by pread(2)
+    pub fn pread(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        if !self.flags().readable() {
+            return Err(VfsError::EBADF);
+        }
+        if !self.seekable() {
+            return Err(VfsError::ESPIPE);
+        }
+        let len = self.file.read_at(offset, buf)?;
+        self.notify_io(len, VfsNotifyMask::IN_ACCESS);
+        Ok(len)
+    }
+
+    /// Write at `offset` without changing the offset, called by pwrite(2)
+    ///
+    /// Immutable and append-only files can't be written at an offset.
+    pub fn pwrite(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let flags = self.flags();
+        if !flags.writable() {
+            return Err(VfsError::EBADF);
+        }
+        if !self.seekable() {
+            return Err(VfsError::ESPIPE);
+        }
+        check_modify(&self.inode, false)?;
+        let len = self.file.write_at(offset, buf)?;
+        if flags.intersects(VfsOpenFlags::O_SYNC | VfsOpenFlags::O_DSYNC) {
+            self.fsync()?;
+        }
+        self.notify_io(len, VfsNotifyMask::IN_MODIFY);
+        Ok(len)
+    }
+
+    /// Move the offset, called by lseek(2)
+    pub fn lseek(&self, pos: VfsSeekFrom) -> VfsResult<u64> {
+        if !self.seekable() {
+            return Err(VfsError::ESPIPE);
+        }
+        let relative =
+            |base: u64, delta: i64| base.checked_add_signed(delta).ok_or(VfsError::Invalid);
+        let mut offset = self.offset.lock();
+        *offset = match pos {
+            VfsSeekFrom::Start(offset) => offset,
+            VfsSeekFrom::Current(delta) => relative(*offset, delta)?,
+            VfsSeekFrom::End(delta) => relative(self.inode.get_attr()?.st_size, delta)?,
+            VfsSeekFrom::Data(offset) => self.inode.seek_data(offset)?,
+            VfsSeekFrom::Hole(offset) => self.inode.seek_hole(offset)?,
+        };
+        Ok(*offset)
+    }
+
+    /// Read the next entry of the directory, returns `None` at the end
+    pub fn readdir(&self) -> VfsResult<Option<VfsDirEntry>> {
+        if !self.inode.inode_type().is_dir() {
+            return Err(VfsError::NotDir);
+        }
+        let mut index = self.offset.lock();
+        let entry = self.inode.readdir(*index as usize)?;
+        if entry.is_some() {
+            *index += 1;
+        }
+        Ok(entry)
+    }
+
+    /// Sync the file, the pages of a regular file are written back from the page cache first
+    pub fn fsync(&self) -> VfsResult<()> {
+        match &self.cache {
+            Some(cache) if self.seekable() => cache.fsync(&self.inode),
+            _ => self.file.fsync(),
+        }
+    }
+
+    pub fn poll(&self, event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
+        self.file.poll(event)
+    }
+
+    pub fn poll_wait(&self, event: VfsPollEvents, waker: &Waker) -> VfsResult<VfsPollEvents> {
+        self.file.poll_wait(event, waker)
+    }
+}
+
+impl<R: VfsRawMutex> Drop for VfsOpenFile<R> {
+    fn drop(&mut self) {
+        let mask = match self.flags().writable() {
+            true => VfsNotifyMask::IN_CLOSE_WRITE,
+            false => VfsNotifyMask::IN_CLOSE_NOWRITE,
+        };
+        self.notify(mask);
+        if let Some(locks) = &self.locks {
+            locks.release_flock(&self.inode, self.lock_id());
+        }
+    }
+}
+
+struct VfsFd<R: VfsRawMutex> {
+    file: Arc<VfsOpenFile<R>>,
+    cloexec: bool,
+}
+
+impl<R: VfsRawMutex> Clone for VfsFd<R> {
+    fn clone(&self) -> Self {
+        Self {
+            file: self.file.clone(),
+            cloexec: self.cloexec,
+        }
+    }
+}
+
+/// The file descriptor table of a process
+///
+/// New file descriptors are always the lowest free ones. The table is cloned by fork(2), the
+/// kernel should set the owner of the new table by [`VfsFdTable::set_owner`]. Dropping the
+/// table closes all its file descriptors, like the exit of its owner.
+///
+/// The table doesn't lock itself, the kernel should wrap it in its own lock.
+pub struct VfsFdTable<R: VfsRawMutex> {
+    fds: Vec<Option<VfsFd<R>>>,
+    /// The max number of file descriptors, `RLIMIT_NOFILE`
+    limit: usize,
+    /// The locks of the process which are released when its files are closed
+    locks: Option<Arc<dyn VfsFileLocks>>,
+    /// The pid of the process which owns the table
+    owner: u64,
+}
+
+impl<R: VfsRawMutex> Clone for VfsFdTable<R> {
+    fn clone(&self) -> Self {
+        Self {
+            fds: self.fds.clone(),
+            limit: self.limit,
+            locks: self.locks.clone(),
+            owner: self.owner,
+        }
+    }
+}
+
+impl<R: VfsRawMutex> VfsFdTable<R> {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            fds: Vec::new(),
+            limit,
+            locks: None,
+            owner: 0,
+        }
+    }
+
+    /// Returns a table which releases the byte-range locks of the process of `context` on an
+    /// inode when any file descriptor of the inode is closed, like close(2)
+    pub fn with_locks(mut self, locks: Arc<dyn VfsFileLocks>, context: &SysContext) -> Self {
+        self.locks = Some(locks);
+        self.owner = context.pid;
+        self
+    }
+
+    /// Set the pid of the process which owns the table, called on the table cloned by fork(2)
+    pub fn set_owner(&mut self, context: &SysContext) {
+        self.owner = context.pid;
+    }
+
+    /// Release the byte-range locks of the owner on the inode of the closed file
+    fn closed(&self, file: &VfsOpenFile<R>) {
+        if let Some(locks) = &self.locks {
+            locks.release_posix(&file.inode, self.owner);
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Change the max number of file descriptors, the opened ones are kept
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
+    /// Find the lowest free file descriptor not less than `min`
+    fn free_fd(&self, min: usize) -> VfsResult<usize> {
+        let fd = (min..self.fds.len())
+            .find(|&fd| self.fds[fd].is_none())
+            .unwrap_or(self.fds.len().max(min));
+        if fd >= self.limit {
+            return Err(VfsError::EMFILE);
+        }
+        Ok(fd)
+    }
+
+    fn set(&mut self, fd: usize, file: Arc<VfsOpenFile<R>>, cloexec: bool) {
+        if fd >= self.fds.len() {
+            self.fds.resize(fd + 1, None);
+        }
+        self.fds[fd] = Some(VfsFd { file, cloexec });
+    }
+
+    /// Install `file` at the lowest free file descriptor, returns the file descriptor
+    pub fn insert(&mut self, file: Arc<VfsOpenFile<R>>, cloexec: bool) -> VfsResult<usize> {
+        let fd = self.free_fd(0)?;
+        self.set(fd, file, cloexec);
+        Ok(fd)
+    }
+
+    /// Get the open file of `fd`, returns `EBADF` if it is not open
+    pub fn get(&self, fd: usize) -> VfsResult<Arc<VfsOpenFile<R>>> {
+        self.fds
+            .get(fd)
+            .and_then(|file| file.as_ref())
+            .map(|file| file.file.clone())
+            .ok_or(VfsError::EBADF)
+    }
+
+    /// Close `fd`, returns the open file which is released when the last reference is dropped
+    pub fn remove(&mut self, fd: usize) -> VfsResult<Arc<VfsOpenFile<R>>> {
+        let file = self
+            .fds
+            .get_mut(fd)
+            .and_then(Option::take)
+            .ok_or(VfsError::EBADF)?;
+        while matches!(self.fds.last(), Some(None)) {
+            self.fds.pop();
+        }
+        self.closed(&file.file);
+        Ok(file.file)
+    }
+
+    /// Duplicate `fd` to the lowest free file descriptor not less than `min`, called by dup(2)
+    /// and `F_DUPFD`/`F_DUPFD_CLOEXEC` of fcntl(2)
+    pub fn dup(&mut self, fd: usize, min: usize, cloexec: bool) -> VfsResult<usize> {
+        let file = self.get(fd)?;
+        if min >= self.limit {
+            return Err(VfsError::Invalid);
+        }
+        let new_fd = self.free_fd(min)?;
+        self.set(new_fd, file, cloexec);
+        Ok(new_fd)
+    }
+
+    /// Duplicate `old_fd` to `new_fd`, closing `new_fd` first if it is open, called by dup2(2)
+    /// and dup3(2)
+    ///
+    /// Returns the open file previously at `new_fd`. Nothing happens if the file descriptors
+    /// are the same.
+    pub fn dup2(
+        &mut self,
+        old_fd: usize,
+        new_fd: usize,
+        cloexec: bool,
+    ) -> VfsResult<Option<Arc<VfsOpenFile<R>>>> {
+        let file = self.get(old_fd)?;
+        if old_fd == new_fd {
+            return Ok(None);
+        }
+        if new_fd >= self.limit {
+            return Err(VfsError::EBADF);
+        }
+        let old = self.get(new_fd).ok();
+        self.set(new_fd, file, cloexec);
+        if let Some(old) = &old {
+            self.closed(old);
+        }
+        Ok(old)
+    }
+
+    pub fn cloexec(&self, fd: usize) -> VfsResult<bool> {
+        self.fds
+            .get(fd)
+            .and_then(|file| file.as_ref())
+            .map(|file| file.cloexec)
+            .ok_or(VfsError::EBADF)
+    }
+
+    /// Set the close-on-exec flag of `fd`, called by `F_SETFD` of fcntl(2)
+    pub fn set_cloexec(&mut self, fd: usize, cloexec: bool) -> VfsResult<()> {
+        let file = self
+            .fds
+            .get_mut(fd)
+            .and_then(|file| file.as_mut())
+            .ok_or(VfsError::EBADF)?;
+        file.cloexec = cloexec;
+        Ok(())
+    }
+
+    /// Close the file descriptors with the close-on-exec flag, called by execve(2)
+    ///
+    /// Returns the closed file descriptors and their open files.
+    pub fn close_on_exec(&mut self) -> Vec<(usize, Arc<VfsOpenFile<R>>)> {
+        let fds = self
+            .iter()
+            .filter(|&(fd, _)| self.cloexec(fd) == Ok(true))
+            .map(|(fd, _)| fd)
+            .collect::<Vec<_>>();
+        fds.into_iter()
+            .map(|fd| (fd, self.remove(fd).unwrap()))
+            .collect()
+    }
+
+    /// The open file descriptors in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &Arc<VfsOpenFile<R>>)> {
+        self.fds
+            .iter()
+            .enumerate()
+            .filter_map(|(fd, file)| file.as_ref().map(|file| (fd, &file.file)))
+    }
+
+    /// The number of open file descriptors
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<R: VfsRawMutex> Drop for VfsFdTable<R> {
+    /// Close all file descriptors when the process exits or replaces its table
+    fn drop(&mut self) {
+        for (_, file) in self.iter() {
+            self.closed(file);
+        }
+    }
+}
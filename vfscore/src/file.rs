@@ -1,11 +1,45 @@
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::{any::Any, task::Waker};
+
 use downcast_rs::{impl_downcast, DowncastSync};
 
 use crate::{
+    cache::PAGE_SIZE,
     error::VfsError,
     utils::{VfsDirEntry, VfsPollEvents},
     VfsResult,
 };
 
+/// The memory which backs a page of a mapping, see [`VfsFile::get_page`]
+///
+/// The page is pinned as long as it is held.
+pub enum VfsMmapPage {
+    /// The page of the file itself, the memory at `addr` stays valid as long as `owner` is held
+    Direct {
+        addr: usize,
+        owner: Arc<dyn Any + Send + Sync>,
+    },
+    /// A copy of the file data, the first `len` bytes are in the file
+    Copy { data: Vec<u8>, len: usize },
+}
+
+impl VfsMmapPage {
+    /// The address of the [`PAGE_SIZE`] bytes of the page
+    pub fn as_ptr(&self) -> *const u8 {
+        match self {
+            VfsMmapPage::Direct { addr, .. } => *addr as *const u8,
+            VfsMmapPage::Copy { data, .. } => data.as_ptr(),
+        }
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        match self {
+            VfsMmapPage::Direct { addr, .. } => *addr as *mut u8,
+            VfsMmapPage::Copy { data, .. } => data.as_mut_ptr(),
+        }
+    }
+}
+
 pub trait VfsFile: Send + Sync + DowncastSync {
     fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
         Err(VfsError::NoSys)
@@ -13,6 +47,34 @@ pub trait VfsFile: Send + Sync + DowncastSync {
     fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
         Err(VfsError::NoSys)
     }
+    /// Read into the buffers in order from `offset`, called by the preadv(2) system call
+    ///
+    /// By default the buffers are read one by one until a short read.
+    fn readv_at(&self, offset: u64, bufs: &mut [&mut [u8]]) -> VfsResult<usize> {
+        let mut count = 0;
+        for buf in bufs.iter_mut() {
+            let len = self.read_at(offset + count as u64, buf)?;
+            count += len;
+            if len < buf.len() {
+                break;
+            }
+        }
+        Ok(count)
+    }
+    /// Write the buffers in order from `offset`, called by the pwritev(2) system call
+    ///
+    /// By default the buffers are written one by one until a short write.
+    fn writev_at(&self, offset: u64, bufs: &[&[u8]]) -> VfsResult<usize> {
+        let mut count = 0;
+        for buf in bufs {
+            let len = self.write_at(offset + count as u64, buf)?;
+            count += len;
+            if len < buf.len() {
+                break;
+            }
+        }
+        Ok(count)
+    }
     /// Read directory entries. This is called by the getdents(2) system call.
     ///
     /// For every call, this function will return an valid entry, or an error. If
@@ -30,6 +92,16 @@ pub trait VfsFile: Send + Sync + DowncastSync {
         }
         Ok(res)
     }
+    /// Return the ready events in `event` like `poll`, and if none is ready, register
+    /// `waker` to be woken when any of them becomes ready
+    ///
+    /// Files which can become ready later should keep a
+    /// [`VfsWaitQueue`](crate::poll::VfsWaitQueue) and register the waker before checking the
+    /// state, so no wakeup is lost. By default the file is always ready and the waker is not
+    /// registered.
+    fn poll_wait(&self, event: VfsPollEvents, _waker: &Waker) -> VfsResult<VfsPollEvents> {
+        self.poll(event)
+    }
     fn ioctl(&self, _cmd: u32, _arg: usize) -> VfsResult<usize> {
         Err(VfsError::NoSys)
     }
@@ -41,6 +113,38 @@ pub trait VfsFile: Send + Sync + DowncastSync {
     fn fsync(&self) -> VfsResult<()> {
         Ok(())
     }
+    /// Get the page at `offset` for mmap(2), `offset` must be aligned to [`PAGE_SIZE`]
+    ///
+    /// File systems which keep the data in memory should return the page itself, so the
+    /// `MAP_SHARED` mappings and `read_at`/`write_at` see each other's changes. By default
+    /// the data is copied by `read_at`.
+    fn get_page(&self, offset: u64) -> VfsResult<VfsMmapPage> {
+        if !offset.is_multiple_of(PAGE_SIZE as u64) {
+            return Err(VfsError::Invalid);
+        }
+        let mut data = vec![0; PAGE_SIZE];
+        let mut len = 0;
+        while len < PAGE_SIZE {
+            let n = self.read_at(offset + len as u64, &mut data[len..])?;
+            if n == 0 {
+                break;
+            }
+            len += n;
+        }
+        Ok(VfsMmapPage::Copy { data, len })
+    }
+    /// Called when the page of a `MAP_SHARED` mapping is dirty, e.g. by msync(2) or munmap(2)
+    ///
+    /// By default the copied data is written back by `write_at`.
+    fn writeback_page(&self, offset: u64, page: &VfsMmapPage) -> VfsResult<()> {
+        match page {
+            VfsMmapPage::Copy { data, len } => {
+                self.write_at(offset, &data[..*len])?;
+                Ok(())
+            }
+            VfsMmapPage::Direct { .. } => Ok(()),
+        }
+    }
 }
 
 impl_downcast!(sync VfsFile);
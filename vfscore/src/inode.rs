@@ -1,16 +1,44 @@
-use alloc::{string::String, sync::Arc, vec::Vec};
+use alloc::{
+    string::String,
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+use core::any::Any;
 
+use bitflags::bitflags;
 use downcast_rs::{impl_downcast, DowncastSync};
 
 use crate::{
     error::VfsError,
     file::VfsFile,
     superblock::VfsSuperBlock,
-    utils::{VfsFileStat, VfsNodePerm, VfsNodeType, VfsRenameFlag, VfsTime, VfsTimeSpec},
+    utils::{
+        VfsAccessMode, VfsFallocateMode, VfsFileStat, VfsNodePerm, VfsNodeType, VfsRenameFlag,
+        VfsStatx, VfsStatxAttr, VfsStatxMask, VfsTime, VfsTimeSpec, VfsXattrFlag,
+    },
     VfsResult,
 };
 
+bitflags! {
+    /// The fields of [`InodeAttr`] which should be applied by [`VfsInode::set_attr`]
+    #[derive(Default)]
+    pub struct InodeAttrValid: u32 {
+        const MODE = 1 << 0;
+        const UID = 1 << 1;
+        const GID = 1 << 2;
+        const SIZE = 1 << 3;
+        const ATIME = 1 << 4;
+        const MTIME = 1 << 5;
+        const CTIME = 1 << 6;
+        const FLAGS = 1 << 7;
+    }
+}
+
+#[derive(Default)]
 pub struct InodeAttr {
+    /// The fields to be applied, other fields are ignored.
+    pub valid: InodeAttrValid,
     /// File mode.
     pub mode: u32,
     pub uid: u32,
@@ -22,6 +50,8 @@ pub struct InodeAttr {
     pub atime: VfsTimeSpec,
     pub mtime: VfsTimeSpec,
     pub ctime: VfsTimeSpec,
+    /// The inode flags of `FS_IOC_SETFLAGS`, e.g. immutable and append only
+    pub flags: VfsStatxAttr,
 }
 
 pub trait VfsInode: DowncastSync + VfsFile {
@@ -46,6 +76,14 @@ pub trait VfsInode: DowncastSync + VfsFile {
         Err(VfsError::NoSys)
     }
 
+    /// Create an unnamed regular file for `O_TMPFILE`
+    ///
+    /// The file has no link, it is freed when it is dropped unless it is linked into a
+    /// directory by [`VfsInode::link`]. `EOPNOTSUPP` is returned by default.
+    fn tmpfile(&self, _perm: VfsNodePerm) -> VfsResult<Arc<dyn VfsInode>> {
+        Err(VfsError::EOPNOTSUPP)
+    }
+
     /// Create a new hard link to the src dentry
     fn link(&self, _name: &str, _src: Arc<dyn VfsInode>) -> VfsResult<Arc<dyn VfsInode>> {
         Err(VfsError::NoSys)
@@ -69,7 +107,8 @@ pub trait VfsInode: DowncastSync + VfsFile {
     }
     /// Set the attributes of the node.
     ///
-    ///  This method is called by chmod(2) and related system calls.
+    /// This method is called by chmod(2), chown(2), truncate(2) and related system calls.
+    /// Only the fields marked in [`InodeAttr::valid`] should be applied.
     fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
         Err(VfsError::NoSys)
     }
@@ -79,12 +118,41 @@ pub trait VfsInode: DowncastSync + VfsFile {
     fn get_attr(&self) -> VfsResult<VfsFileStat> {
         Err(VfsError::NoSys)
     }
+    /// Get the extended attributes of the node.
+    ///
+    /// This method is called by statx(2). The fields in `mask` are requested, but the file
+    /// system may fill more or less fields and mark them in [`VfsStatx::stx_mask`]. The
+    /// default implementation only fills the fields of [`VfsInode::get_attr`].
+    fn get_statx(&self, _mask: VfsStatxMask) -> VfsResult<VfsStatx> {
+        self.get_attr().map(VfsStatx::from)
+    }
     /// Called by the VFS to list all extended attributes for a given file.
     ///
     /// This method is called by the listxattr(2) system call.
     fn list_xattr(&self) -> VfsResult<Vec<String>> {
         Err(VfsError::NoSys)
     }
+    /// Called by the VFS to get the value of an extended attribute.
+    ///
+    /// This method is called by the getxattr(2) system call. If the attribute
+    /// does not exist, [`VfsError::NoData`] should be returned.
+    fn get_xattr(&self, _key: &str) -> VfsResult<Vec<u8>> {
+        Err(VfsError::NoSys)
+    }
+    /// Called by the VFS to set the value of an extended attribute.
+    ///
+    /// This method is called by the setxattr(2) system call.
+    /// - [`VfsXattrFlag::XATTR_CREATE`]: fails with [`VfsError::EExist`] if the attribute already exists.
+    /// - [`VfsXattrFlag::XATTR_REPLACE`]: fails with [`VfsError::NoData`] if the attribute does not exist.
+    fn set_xattr(&self, _key: &str, _value: &[u8], _flag: VfsXattrFlag) -> VfsResult<()> {
+        Err(VfsError::NoSys)
+    }
+    /// Called by the VFS to remove an extended attribute.
+    ///
+    /// This method is called by the removexattr(2) system call.
+    fn remove_xattr(&self, _key: &str) -> VfsResult<()> {
+        Err(VfsError::NoSys)
+    }
     fn inode_type(&self) -> VfsNodeType;
     fn truncate(&self, _len: u64) -> VfsResult<()> {
         Err(VfsError::NoSys)
@@ -108,10 +176,195 @@ pub trait VfsInode: DowncastSync + VfsFile {
     fn update_time(&self, _time: VfsTime, _now: VfsTimeSpec) -> VfsResult<()> {
         Err(VfsError::NoSys)
     }
+    /// Set the access time of the inode without changing the ctime.
+    ///
+    /// This method is called when the file is read, see
+    /// [`update_atime`](crate::path::update_atime). File systems which don't record the access
+    /// time can leave it unimplemented.
+    fn set_atime(&self, _atime: VfsTimeSpec) -> VfsResult<()> {
+        Err(VfsError::NoSys)
+    }
+    /// Set the access and modification times of the inode together.
+    ///
+    /// This method is called by the utimensat(2) system call, a time of `None` is left
+    /// unchanged. The ctime is set to `now`. The default implementation calls
+    /// [`VfsInode::update_time`] for each time.
+    fn set_times(
+        &self,
+        atime: Option<VfsTimeSpec>,
+        mtime: Option<VfsTimeSpec>,
+        now: VfsTimeSpec,
+    ) -> VfsResult<()> {
+        if let Some(atime) = atime {
+            self.update_time(VfsTime::AccessTime(atime), now)?;
+        }
+        if let Some(mtime) = mtime {
+            self.update_time(VfsTime::ModifiedTime(mtime), now)?;
+        }
+        Ok(())
+    }
+    /// Allocate, deallocate or zero the range `offset..offset + len` of the file.
+    ///
+    /// This method is called by the fallocate(2) system call.
+    fn fallocate(&self, _mode: VfsFallocateMode, _offset: u64, _len: u64) -> VfsResult<()> {
+        Err(VfsError::NoSys)
+    }
+    /// Write `buf` at the end of the file, returns the offset where it is written and the
+    /// number of bytes written.
+    ///
+    /// This method is used by the writes of `O_APPEND`. File systems should find the end and
+    /// write under the lock of the inode, so concurrent appends don't overwrite each other. By
+    /// default the size is got by `get_attr` before the write.
+    fn append(&self, buf: &[u8]) -> VfsResult<(u64, usize)> {
+        let offset = self.get_attr()?.st_size;
+        Ok((offset, self.write_at(offset, buf)?))
+    }
+    /// Find the first offset which is not in a hole from `offset`, used by `lseek(SEEK_DATA)`.
+    ///
+    /// [`VfsError::ENXIO`] is returned if `offset` is at or beyond the end of the file. By
+    /// default the whole file is data.
+    fn seek_data(&self, offset: u64) -> VfsResult<u64> {
+        let size = self.get_attr()?.st_size;
+        if offset >= size {
+            return Err(VfsError::ENXIO);
+        }
+        Ok(offset)
+    }
+    /// Find the first offset which is in a hole from `offset`, used by `lseek(SEEK_HOLE)`.
+    ///
+    /// There is an implicit hole at the end of the file. [`VfsError::ENXIO`] is returned if
+    /// `offset` is at or beyond the end of the file.
+    fn seek_hole(&self, offset: u64) -> VfsResult<u64> {
+        let size = self.get_attr()?.st_size;
+        if offset >= size {
+            return Err(VfsError::ENXIO);
+        }
+        Ok(size)
+    }
+    /// Copy `len` bytes from `offset_in` of this file to `offset_out` of `dst`.
+    ///
+    /// This method is called by the copy_file_range(2) system call. File systems can copy the
+    /// data directly when `dst` is in the same file system, by default the data is copied
+    /// through a buffer by [`generic_copy_file_range`].
+    fn copy_file_range(
+        &self,
+        offset_in: u64,
+        dst: Arc<dyn VfsInode>,
+        offset_out: u64,
+        len: usize,
+    ) -> VfsResult<usize> {
+        generic_copy_file_range(self, offset_in, dst.as_ref(), offset_out, len)
+    }
+    /// Open the named pipe, all opens of the fifo share the same pipe.
+    ///
+    /// `mode` contains `R_OK` for the read end and `W_OK` for the write end. The returned file
+    /// holds the ends until it is dropped, so the peers see EOF or `EPIPE` after it is closed.
+    fn open_fifo(&self, _mode: VfsAccessMode) -> VfsResult<Arc<dyn VfsFile>> {
+        Err(VfsError::NoSys)
+    }
+    /// Bind the socket object of the kernel to the socket node, called by bind(2) of unix
+    /// domain sockets.
+    ///
+    /// The file system only keeps a weak reference, so the node refuses connections after the
+    /// socket is closed.
+    fn bind_socket(&self, _socket: Weak<dyn Any + Send + Sync>) -> VfsResult<()> {
+        Err(VfsError::NoSys)
+    }
+    /// Get the socket object bound to the socket node, returns `ECONNREFUSED` if there is no
+    /// live socket.
+    fn get_socket(&self) -> VfsResult<Arc<dyn Any + Send + Sync>> {
+        Err(VfsError::ECONNREFUSED)
+    }
 }
 
 impl_downcast!(sync  VfsInode);
 
+/// The size of the buffer used to copy data between files
+pub const COPY_BUF_SIZE: usize = 64 * 1024;
+
+/// Copy `len` bytes from `src` to `dst` through a buffer, stop at the end of `src`
+pub fn generic_copy_file_range<S: VfsFile + ?Sized, D: VfsFile + ?Sized>(
+    src: &S,
+    offset_in: u64,
+    dst: &D,
+    offset_out: u64,
+    len: usize,
+) -> VfsResult<usize> {
+    let mut buf = vec![0; len.min(COPY_BUF_SIZE)];
+    let mut count = 0;
+    while count < len {
+        let want = (len - count).min(buf.len());
+        let n = src.read_at(offset_in + count as u64, &mut buf[..want])?;
+        if n == 0 {
+            break;
+        }
+        let written = dst.write_at(offset_out + count as u64, &buf[..n])?;
+        count += written;
+        if written < n {
+            break;
+        }
+    }
+    Ok(count)
+}
+
+/// Move `len` bytes from `src` to `dst` like splice(2) and sendfile(2)
+///
+/// The offset of a stream like a pipe or a socket is `None`, it is read or written at offset 0
+/// and the transfer stops when a read or write of it is short. The data read from a stream
+/// can't be put back, so after a short write the rest is written again until `dst` fails or
+/// takes nothing. Between regular files the data is copied by [`VfsInode::copy_file_range`]
+/// of `src`.
+pub fn splice(
+    src: &Arc<dyn VfsInode>,
+    offset_in: Option<u64>,
+    dst: &Arc<dyn VfsInode>,
+    offset_out: Option<u64>,
+    len: usize,
+) -> VfsResult<usize> {
+    if src.inode_type() == VfsNodeType::Dir || dst.inode_type() == VfsNodeType::Dir {
+        return Err(VfsError::IsDir);
+    }
+    if let (Some(offset_in), Some(offset_out)) = (offset_in, offset_out) {
+        if src.inode_type() == VfsNodeType::File && dst.inode_type() == VfsNodeType::File {
+            return src.copy_file_range(offset_in, dst.clone(), offset_out, len);
+        }
+    }
+    let mut buf = vec![0; len.min(COPY_BUF_SIZE)];
+    let mut count = 0;
+    while count < len {
+        let want = (len - count).min(buf.len());
+        let pos = count as u64;
+        let n = src.read_at(offset_in.map_or(0, |offset| offset + pos), &mut buf[..want])?;
+        if n == 0 {
+            break;
+        }
+        let mut written = 0;
+        while written < n {
+            let pos = pos + written as u64;
+            let res = dst.write_at(
+                offset_out.map_or(0, |offset| offset + pos),
+                &buf[written..n],
+            );
+            match res {
+                Ok(0) => break,
+                Ok(len) => written += len,
+                Err(_) if count + written > 0 => break,
+                Err(e) => return Err(e),
+            }
+            // the rest of a file is read again on the next call
+            if offset_in.is_some() {
+                break;
+            }
+        }
+        count += written;
+        // a short read means the stream is drained for now
+        if written < n || (offset_in.is_none() && n < want) {
+            break;
+        }
+    }
+    Ok(count)
+}
+
 /// This macro is used to implement the default methods of `VfsInode` for inode which type is dir.
 #[macro_export]
 macro_rules! impl_dir_inode_default {
@@ -1,14 +1,24 @@
 #![cfg_attr(not(test), no_std)]
 #![feature(error_in_core)]
 #![feature(exclusive_range_pattern)]
+#![feature(trait_alias)]
 extern crate alloc;
+pub mod cache;
 pub mod dentry;
 pub mod error;
+pub mod fd;
 pub mod file;
 pub mod fstype;
 pub mod inode;
+pub mod lock;
+pub mod mount;
+pub mod notify;
 pub mod path;
+pub mod poll;
 pub mod superblock;
 pub mod utils;
 
 pub type VfsResult<T> = Result<T, error::VfsError>;
+
+/// The raw lock used by the structures of the VFS which hold a lock
+pub trait VfsRawMutex = lock_api::RawMutex + Send + Sync + 'static;
@@ -0,0 +1,391 @@
+//! Advisory file locks
+//!
+//! [`FileLockManager`] keeps the locks of flock(2) and the byte-range locks of fcntl(2)
+//! (`F_SETLK`, `F_SETLKW` and `F_GETLK`) for the inodes of every file system. The locks are
+//! advisory, they don't affect `read_at` and `write_at` of the inodes.
+use alloc::{
+    collections::{btree_map::Entry, BTreeMap},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+
+use crate::{
+    error::VfsError, inode::VfsInode, path::SysContext, superblock::VfsSuperBlock, VfsResult,
+};
+
+/// The type of a lock, the values are the same as `F_RDLCK`, `F_WRLCK` and `F_UNLCK`
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VfsLockType {
+    Read = 0,
+    Write = 1,
+    Unlock = 2,
+}
+
+impl TryFrom<u16> for VfsLockType {
+    type Error = VfsError;
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(VfsLockType::Read),
+            1 => Ok(VfsLockType::Write),
+            2 => Ok(VfsLockType::Unlock),
+            _ => Err(VfsError::Invalid),
+        }
+    }
+}
+
+/// A byte-range lock of fcntl(2)
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct VfsFileLock {
+    pub ty: VfsLockType,
+    /// The first byte of the range
+    pub start: u64,
+    /// The length of the range, 0 means the range extends to the end of the file however
+    /// large the file grows
+    pub len: u64,
+    /// The owner of the lock, the pid of the process
+    pub owner: u64,
+}
+
+impl VfsFileLock {
+    /// Create a lock owned by the process of `context`
+    pub fn new(ty: VfsLockType, start: u64, len: u64, context: &SysContext) -> Self {
+        Self::with_owner(ty, start, len, context.pid)
+    }
+
+    fn with_owner(ty: VfsLockType, start: u64, len: u64, owner: u64) -> Self {
+        Self {
+            ty,
+            start,
+            len,
+            owner,
+        }
+    }
+
+    /// The end of the range (exclusive)
+    fn end(&self) -> u64 {
+        match self.len {
+            0 => u64::MAX,
+            len => self.start.saturating_add(len),
+        }
+    }
+
+    fn overlaps(&self, other: &VfsFileLock) -> bool {
+        self.start < other.end() && other.start < self.end()
+    }
+
+    fn conflicts(&self, other: &VfsFileLock) -> bool {
+        self.owner != other.owner
+            && (self.ty == VfsLockType::Write || other.ty == VfsLockType::Write)
+            && self.overlaps(other)
+    }
+
+    fn with_range(&self, start: u64, end: u64) -> Self {
+        let len = match end {
+            u64::MAX => 0,
+            end => end - start,
+        };
+        Self::with_owner(self.ty, start, len, self.owner)
+    }
+}
+
+#[derive(Default)]
+struct InodeLocks {
+    /// The flock(2) locks and the open files which hold them
+    flocks: Vec<(usize, VfsLockType)>,
+    /// The fcntl(2) locks, the locks of an owner never overlap
+    posix: Vec<VfsFileLock>,
+}
+
+struct LockedFile {
+    /// Keep the super block alive, so its address stays unique
+    _sb: Arc<dyn VfsSuperBlock>,
+    locks: InodeLocks,
+}
+
+/// The identity of an inode, the address of its super block and its inode number
+///
+/// Unlike the address of the inode, it stays the same when the inode is dropped and loaded
+/// again, e.g. after its dentry is evicted from the cache.
+pub type VfsInodeId = (usize, u64);
+
+/// Get the identity of the inode
+pub fn inode_id(inode: &Arc<dyn VfsInode>) -> VfsResult<VfsInodeId> {
+    let sb = inode.get_super_block()?;
+    let ino = inode.get_attr()?.st_ino;
+    Ok((Arc::as_ptr(&sb) as *const () as usize, ino))
+}
+
+/// The locks of the kernel, which are released when the files are closed
+///
+/// The kernel implements it for the lock which wraps its [`FileLockManager`], and passes it to
+/// [`VfsOpenFile`](crate::fd::VfsOpenFile) and [`VfsFdTable`](crate::fd::VfsFdTable).
+pub trait VfsFileLocks: Send + Sync {
+    /// See [`FileLockManager::release_flock`]
+    fn release_flock(&self, inode: &Arc<dyn VfsInode>, file: usize);
+    /// See [`FileLockManager::release_posix`]
+    fn release_posix(&self, inode: &Arc<dyn VfsInode>, owner: u64);
+}
+
+/// The advisory locks of all inodes
+///
+/// The inodes are identified by [`inode_id`], the manager holds the super block of an inode as
+/// long as the inode has any lock. flock(2) locks belong to open files, which are identified by
+/// [`VfsOpenFile::lock_id`](crate::fd::VfsOpenFile::lock_id). fcntl(2) locks belong to
+/// processes, the owner is the pid in [`SysContext`].
+///
+/// The manager never blocks. When a lock is held by others, `EAGAIN` is returned and the
+/// caller should sleep and try again if it wants to wait. The waiting owners of `F_SETLKW`
+/// are recorded to detect deadlocks.
+///
+/// The manager doesn't lock itself, the kernel should wrap it in its own lock.
+#[derive(Default)]
+pub struct FileLockManager {
+    files: BTreeMap<VfsInodeId, LockedFile>,
+    /// The owners waiting in `F_SETLKW` and the locks they wait for
+    waiting: BTreeMap<u64, (VfsInodeId, VfsFileLock)>,
+}
+
+impl FileLockManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn locks(&self, id: VfsInodeId) -> Option<&InodeLocks> {
+        self.files.get(&id).map(|file| &file.locks)
+    }
+
+    fn locks_mut(&mut self, inode: &Arc<dyn VfsInode>) -> VfsResult<&mut InodeLocks> {
+        let id = inode_id(inode)?;
+        let file = match self.files.entry(id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(LockedFile {
+                _sb: inode.get_super_block()?,
+                locks: InodeLocks::default(),
+            }),
+        };
+        Ok(&mut file.locks)
+    }
+
+    /// Drop the inode when it has no lock anymore
+    fn put(&mut self, id: VfsInodeId) {
+        if let Some(file) = self.files.get(&id) {
+            if file.locks.flocks.is_empty() && file.locks.posix.is_empty() {
+                self.files.remove(&id);
+            }
+        }
+    }
+
+    /// Apply a flock(2) operation of the open file `file`
+    ///
+    /// A lock held by the file is converted to the new type. Returns `EAGAIN` if the lock
+    /// is held by other open files.
+    pub fn flock(
+        &mut self,
+        inode: &Arc<dyn VfsInode>,
+        file: usize,
+        ty: VfsLockType,
+    ) -> VfsResult<()> {
+        if ty == VfsLockType::Unlock {
+            self.release_flock(inode, file);
+            return Ok(());
+        }
+        let id = inode_id(inode)?;
+        let locks = self.locks_mut(inode)?;
+        let busy = locks.flocks.iter().any(|(other, other_ty)| {
+            *other != file && (ty == VfsLockType::Write || *other_ty == VfsLockType::Write)
+        });
+        if busy {
+            self.put(id);
+            return Err(VfsError::EAGAIN);
+        }
+        match locks.flocks.iter_mut().find(|(other, _)| *other == file) {
+            Some((_, old)) => *old = ty,
+            None => locks.flocks.push((file, ty)),
+        }
+        Ok(())
+    }
+
+    /// Release the flock(2) lock of the open file `file`, called when the file is closed
+    pub fn release_flock(&mut self, inode: &Arc<dyn VfsInode>, file: usize) {
+        let Ok(id) = inode_id(inode) else {
+            return;
+        };
+        if let Some(file_locks) = self.files.get_mut(&id) {
+            file_locks.locks.flocks.retain(|(other, _)| *other != file);
+            self.put(id);
+        }
+    }
+
+    /// Find a lock which prevents `lock` from being placed, called by `F_GETLK`
+    pub fn get_lk(&self, inode: &Arc<dyn VfsInode>, lock: &VfsFileLock) -> Option<VfsFileLock> {
+        if lock.ty == VfsLockType::Unlock {
+            return None;
+        }
+        self.locks(inode_id(inode).ok()?)?
+            .posix
+            .iter()
+            .find(|other| lock.conflicts(other))
+            .copied()
+    }
+
+    /// The owners of the locks which conflict with `lock`
+    fn blockers(&self, id: VfsInodeId, lock: &VfsFileLock) -> Vec<u64> {
+        let mut blockers = self
+            .locks(id)
+            .map(|locks| {
+                locks
+                    .posix
+                    .iter()
+                    .filter(|other| lock.conflicts(other))
+                    .map(|other| other.owner)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        blockers.sort_unstable();
+        blockers.dedup();
+        blockers
+    }
+
+    /// Place or remove a byte-range lock, called by `F_SETLK` and `F_SETLKW`
+    ///
+    /// The locks of the owner in the range are replaced, so a lock can be split, merged or
+    /// converted. If the lock is held by others, `EAGAIN` is returned. With `wait`, the owner
+    /// is recorded as waiting until it places the lock or calls [`FileLockManager::cancel_wait`],
+    /// and `EDEADLK` is returned if the owners holding the lock are waiting for it.
+    pub fn set_lk(
+        &mut self,
+        inode: &Arc<dyn VfsInode>,
+        lock: &VfsFileLock,
+        wait: bool,
+    ) -> VfsResult<()> {
+        if lock.len == 0 && lock.start == u64::MAX {
+            return Err(VfsError::Invalid);
+        }
+        let id = inode_id(inode)?;
+        let blockers = match lock.ty {
+            VfsLockType::Unlock => Vec::new(),
+            _ => self.blockers(id, lock),
+        };
+        if !blockers.is_empty() {
+            if wait {
+                if self.would_deadlock(lock.owner, &blockers) {
+                    self.waiting.remove(&lock.owner);
+                    return Err(VfsError::EDEADLK);
+                }
+                self.waiting.insert(lock.owner, (id, *lock));
+            }
+            return Err(VfsError::EAGAIN);
+        }
+        self.waiting.remove(&lock.owner);
+
+        let locks = self.locks_mut(inode)?;
+        let (mut own, others): (Vec<_>, Vec<_>) = locks
+            .posix
+            .drain(..)
+            .partition(|other| other.owner == lock.owner && other.overlaps(lock));
+        locks.posix = others;
+        // keep the parts outside the new range
+        let mut pieces = vec![];
+        for old in own.drain(..) {
+            if old.start < lock.start {
+                pieces.push(old.with_range(old.start, lock.start));
+            }
+            if old.end() > lock.end() {
+                pieces.push(old.with_range(lock.end(), old.end()));
+            }
+        }
+        if lock.ty != VfsLockType::Unlock {
+            pieces.push(*lock);
+        }
+        // merge the adjacent locks of the same type
+        let mut neighbors: Vec<_> = Vec::new();
+        locks.posix.retain(|other| {
+            let adjacent = other.owner == lock.owner
+                && other.ty == lock.ty
+                && (other.end() == lock.start || other.start == lock.end());
+            if adjacent {
+                neighbors.push(*other);
+            }
+            !adjacent
+        });
+        pieces.extend(neighbors);
+        pieces.sort_unstable_by_key(|piece| piece.start);
+        let mut merged: Vec<VfsFileLock> = Vec::new();
+        for piece in pieces {
+            match merged.last_mut() {
+                Some(last) if last.ty == piece.ty && last.end() >= piece.start => {
+                    let end = last.end().max(piece.end());
+                    *last = last.with_range(last.start, end);
+                }
+                _ => merged.push(piece),
+            }
+        }
+        locks.posix.extend(merged);
+        self.put(id);
+        Ok(())
+    }
+
+    /// Check whether any owner in `blockers` waits for `owner` directly or indirectly
+    ///
+    /// The owners which wait for a lock are looked up again, so a waiter whose lock was
+    /// released before it tried again doesn't count.
+    fn would_deadlock(&self, owner: u64, blockers: &[u64]) -> bool {
+        let mut stack = blockers.to_vec();
+        let mut visited = Vec::new();
+        while let Some(current) = stack.pop() {
+            if current == owner {
+                return true;
+            }
+            if visited.contains(&current) {
+                continue;
+            }
+            visited.push(current);
+            if let Some((id, lock)) = self.waiting.get(&current) {
+                stack.extend(self.blockers(*id, lock));
+            }
+        }
+        false
+    }
+
+    /// Stop waiting for a lock, called when `F_SETLKW` is interrupted
+    pub fn cancel_wait(&mut self, owner: u64) {
+        self.waiting.remove(&owner);
+    }
+
+    /// Release the byte-range locks of `owner` on the inode
+    ///
+    /// Called when the owner closes any file of the inode.
+    pub fn release_posix(&mut self, inode: &Arc<dyn VfsInode>, owner: u64) {
+        let Ok(id) = inode_id(inode) else {
+            return;
+        };
+        if let Some(file) = self.files.get_mut(&id) {
+            file.locks.posix.retain(|lock| lock.owner != owner);
+            self.put(id);
+        }
+    }
+
+    /// Release all byte-range locks of `owner`, called when the process exits
+    pub fn release_owner(&mut self, owner: u64) {
+        self.waiting.remove(&owner);
+        self.files.retain(|_, file| {
+            file.locks.posix.retain(|lock| lock.owner != owner);
+            !file.locks.flocks.is_empty() || !file.locks.posix.is_empty()
+        });
+    }
+
+    /// The byte-range locks of the inode
+    pub fn posix_locks(&self, inode: &Arc<dyn VfsInode>) -> Vec<VfsFileLock> {
+        inode_id(inode)
+            .ok()
+            .and_then(|id| self.locks(id))
+            .map(|locks| locks.posix.clone())
+            .unwrap_or_default()
+    }
+
+    /// Whether no inode is locked
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
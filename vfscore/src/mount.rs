@@ -0,0 +1,413 @@
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::fmt::Write;
+
+use crate::{
+    dentry::VfsDentry,
+    error::VfsError,
+    fstype::VfsMountPoint,
+    notify::VfsNotifyMask,
+    path::{dentry_statx, move_root, VfsPath},
+    utils::{VfsMountFlags, VfsStatx, VfsStatxMask, VfsUmountFlags},
+    VfsResult,
+};
+
+/// A record in the [`MountTable`]
+#[derive(Clone)]
+pub struct VfsMount {
+    /// The unique id of this mount in the table
+    pub id: usize,
+    /// The id of the mount which contains the mount point, `None` for the root mount
+    pub parent: Option<usize>,
+    /// The device or other source of the file system, e.g. `/dev/sda1`
+    pub source: String,
+    /// The name of the file system type, e.g. `ramfs`
+    pub fs_type: String,
+    /// The absolute path of the mount point
+    pub path: String,
+    pub flags: VfsMountFlags,
+    /// The root dentry of the mounted file system
+    pub root: Arc<dyn VfsDentry>,
+    /// The dentry covered by this mount, `None` for the root mount
+    pub point: Option<Arc<dyn VfsDentry>>,
+}
+
+impl VfsMount {
+    /// Check whether this mount is on `dentry`
+    fn covers(&self, dentry: &Arc<dyn VfsDentry>) -> bool {
+        self.point
+            .as_ref()
+            .is_some_and(|point| Arc::ptr_eq(point, dentry))
+    }
+}
+
+/// The mounts seen by a [`VfsPath`], see [`VfsPath::with_namespace`]
+///
+/// The kernel implements it for the locked [`MountTable`] of a process.
+pub trait VfsNamespace: Send + Sync {
+    /// Get the topmost mount on `dentry`, `None` if nothing is mounted on it
+    fn mounted(&self, dentry: &Arc<dyn VfsDentry>) -> Option<VfsMountPoint>;
+}
+
+/// The table of the mounts in the dentry tree, which is the mount namespace of a process
+///
+/// The mounts are only recorded in the table, the dentries are shared by all namespaces.
+/// The paths bound to the table by [`VfsPath::with_namespace`] see its mounts, a clone of
+/// the table is an independent namespace, like `CLONE_NEWNS`.
+///
+/// The table doesn't lock itself, the kernel should wrap it in its own lock. The paths
+/// given to its methods are resolved through a copy of the table, whatever namespace they
+/// are bound to, so the lock is not taken again.
+#[derive(Clone)]
+pub struct MountTable {
+    mounts: Vec<VfsMount>,
+    next_id: usize,
+}
+
+impl VfsNamespace for MountTable {
+    fn mounted(&self, dentry: &Arc<dyn VfsDentry>) -> Option<VfsMountPoint> {
+        self.mounts
+            .iter()
+            .rev()
+            .find(|mnt| mnt.covers(dentry))
+            .map(|mnt| VfsMountPoint {
+                root: mnt.root.clone(),
+                mount_point: Arc::downgrade(dentry),
+                mnt_flags: mnt.flags.bits(),
+            })
+    }
+}
+
+impl MountTable {
+    /// Create a mount table with the root file system mounted at `/`
+    pub fn new(
+        source: &str,
+        fs_type: &str,
+        root: Arc<dyn VfsDentry>,
+        flags: VfsMountFlags,
+    ) -> Self {
+        let mount = VfsMount {
+            id: 0,
+            parent: None,
+            source: source.to_string(),
+            fs_type: fs_type.to_string(),
+            path: "/".to_string(),
+            flags,
+            root,
+            point: None,
+        };
+        Self {
+            mounts: vec![mount],
+            next_id: 1,
+        }
+    }
+
+    /// Mount `root` on the directory `path` and record it, return the id of the new mount
+    pub fn mount(
+        &mut self,
+        path: &VfsPath,
+        source: &str,
+        fs_type: &str,
+        root: Arc<dyn VfsDentry>,
+        flags: VfsMountFlags,
+    ) -> VfsResult<usize> {
+        let path = self.bind_path(path);
+        let point = path.mount_target(&root)?;
+        let ab_path = point.path();
+        root.set_parent(&point);
+        let parent = self.find(&ab_path).map(|mnt| mnt.id);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.mounts.push(VfsMount {
+            id,
+            parent,
+            source: source.to_string(),
+            fs_type: fs_type.to_string(),
+            path: ab_path,
+            flags,
+            root,
+            point: Some(point.clone()),
+        });
+        path.notify_dentry(&point, VfsNotifyMask::IN_MOUNT);
+        Ok(id)
+    }
+
+    /// Bind `source` on `path` and record it, return the id of the new mount
+    ///
+    /// The flags are the same as [`VfsPath::bind_mount`]. With `MS_REC`, the mounts below
+    /// `source` are recorded below `path` too.
+    pub fn bind(
+        &mut self,
+        path: &VfsPath,
+        source: &VfsPath,
+        flags: VfsMountFlags,
+    ) -> VfsResult<usize> {
+        let path = self.bind_path(path);
+        let (src, dir) = path.bind_target(&self.bind_path(source))?;
+        let src_path = src.path();
+        let ab_path = dir.path();
+        let src_mnt = self.find(&src_path).ok_or(VfsError::Invalid)?;
+        let (src_id, src_source, fs_type) =
+            (src_mnt.id, src_mnt.source.clone(), src_mnt.fs_type.clone());
+        let parent = self.find(&ab_path).map(|mnt| mnt.id);
+        let id = self.next_id;
+        self.next_id += 1;
+        let bind = VfsMount {
+            id,
+            parent,
+            source: src_source,
+            fs_type,
+            path: ab_path.clone(),
+            flags: flags | VfsMountFlags::MS_BIND,
+            root: src,
+            point: Some(dir.clone()),
+        };
+        let mut copies = Vec::new();
+        if flags.contains(VfsMountFlags::MS_REC) {
+            // (old id, new id) of the copied mounts, parents come before children
+            let mut ids = vec![(src_id, id)];
+            for mnt in self.mounts.iter() {
+                if mnt.path == src_path || !is_prefix(&src_path, &mnt.path) {
+                    continue;
+                }
+                let new_id = id + ids.len();
+                let parent = ids
+                    .iter()
+                    .find(|(old, _)| Some(*old) == mnt.parent)
+                    .map_or(id, |(_, new)| *new);
+                ids.push((mnt.id, new_id));
+                let mut copy = mnt.clone();
+                copy.id = new_id;
+                copy.parent = Some(parent);
+                copy.path = rebase(&mnt.path, &src_path, &ab_path);
+                copies.push(copy);
+            }
+        }
+        self.next_id += copies.len();
+        self.mounts.push(bind);
+        self.mounts.extend(copies);
+        path.notify_dentry(&dir, VfsNotifyMask::IN_MOUNT);
+        Ok(id)
+    }
+
+    /// Move the mount on `from` to `to` with all mounts below it
+    ///
+    /// The events are the same as [`VfsPath::move_mount`].
+    pub fn move_mount(&mut self, from: &VfsPath, to: &VfsPath) -> VfsResult<()> {
+        let from = self.bind_path(from);
+        let (point, dir) = from.move_target(&self.bind_path(to))?;
+        let mnt = from.mounted(&point).unwrap();
+        let index = self
+            .mounts
+            .iter()
+            .rposition(|mnt| mnt.covers(&point))
+            .ok_or(VfsError::Invalid)?;
+        let old_path = self.mounts[index].path.clone();
+        let new_path = dir.path();
+        move_root(&mnt.root, &point, &dir);
+        let id = self.mounts[index].id;
+        let subtree = self.subtree(id);
+        let parent = self
+            .mounts
+            .iter()
+            .filter(|mnt| !subtree.contains(&mnt.id) && is_prefix(&mnt.path, &new_path))
+            .max_by_key(|mnt| (mnt.path.len(), mnt.id))
+            .map(|mnt| mnt.id);
+        for mnt in self.mounts.iter_mut() {
+            if subtree.contains(&mnt.id) {
+                mnt.path = rebase(&mnt.path, &old_path, &new_path);
+            }
+        }
+        let moved = &mut self.mounts[index];
+        moved.parent = parent;
+        moved.point = Some(dir.clone());
+        from.notify_umount(&mnt);
+        to.notify_dentry(&dir, VfsNotifyMask::IN_MOUNT);
+        Ok(())
+    }
+
+    /// Unmount the file system mounted on `path`
+    ///
+    /// If other file systems are mounted below it, [`VfsError::EBUSY`] is returned unless
+    /// [`VfsUmountFlags::MNT_DETACH`] is given. A lazy unmount detaches the whole subtree
+    /// at once, the file systems stay alive as long as someone holds their dentries.
+    pub fn umount(&mut self, path: &VfsPath, flags: VfsUmountFlags) -> VfsResult<()> {
+        let path = self.bind_path(path);
+        let point = path.mount_point()?;
+        let mnt = path.mounted(&point).unwrap();
+        let index = self
+            .mounts
+            .iter()
+            .rposition(|mnt| mnt.covers(&point))
+            .ok_or(VfsError::Invalid)?;
+        let id = self.mounts[index].id;
+        let busy = self.mounts.iter().any(|mnt| mnt.parent == Some(id));
+        if flags.contains(VfsUmountFlags::MNT_DETACH) {
+            path.notify_umount(&mnt);
+            let subtree = self.subtree(id);
+            self.mounts.retain(|mnt| !subtree.contains(&mnt.id));
+            return Ok(());
+        }
+        if busy {
+            return Err(VfsError::EBUSY);
+        }
+        self.mounts.remove(index);
+        path.notify_umount(&mnt);
+        path.sync_fs(&mnt.root)
+    }
+
+    /// Find the mount which contains the absolute path `path`
+    pub fn find(&self, path: &str) -> Option<&VfsMount> {
+        self.mounts
+            .iter()
+            .filter(|mnt| is_prefix(&mnt.path, path))
+            .max_by_key(|mnt| (mnt.path.len(), mnt.id))
+    }
+
+    /// Get the extended attributes of the file at `path` like [`VfsPath::statx`], with the
+    /// id of the mount containing it
+    pub fn statx(&self, path: &VfsPath, mask: VfsStatxMask, follow: bool) -> VfsResult<VfsStatx> {
+        let path = self.bind_path(path);
+        let dt = if follow {
+            path.open(None)?
+        } else {
+            path.exists()?
+        };
+        let mut stx = dentry_statx(Some(self), &dt, mask)?;
+        if let Some(mnt) = self.find(&dt.path()) {
+            stx.stx_mnt_id = mnt.id as u64;
+            stx.stx_mask |= VfsStatxMask::STATX_MNT_ID;
+        }
+        Ok(stx)
+    }
+
+    /// Get the mount with `id`
+    pub fn get(&self, id: usize) -> Option<&VfsMount> {
+        self.mounts.iter().find(|mnt| mnt.id == id)
+    }
+
+    /// Iterate over all mounts in the order they were mounted
+    pub fn iter(&self) -> impl Iterator<Item = &VfsMount> {
+        self.mounts.iter()
+    }
+
+    /// Format the table like `/proc/mounts`
+    pub fn proc_mounts(&self) -> String {
+        let mut buf = String::new();
+        for mnt in self.mounts.iter() {
+            writeln!(
+                buf,
+                "{} {} {} {} 0 0",
+                mnt.source,
+                mnt.path,
+                mnt.fs_type,
+                mount_options(mnt.flags)
+            )
+            .unwrap();
+        }
+        buf
+    }
+
+    /// Bind `path` to a copy of this namespace
+    fn bind_path(&self, path: &VfsPath) -> VfsPath {
+        path.with_namespace(Arc::new(self.clone()))
+    }
+
+    /// Collect the ids of the mount with `id` and all mounts below it
+    fn subtree(&self, id: usize) -> Vec<usize> {
+        let mut ids = vec![id];
+        let mut index = 0;
+        while index < ids.len() {
+            let parent = ids[index];
+            ids.extend(
+                self.mounts
+                    .iter()
+                    .filter(|mnt| mnt.parent == Some(parent))
+                    .map(|mnt| mnt.id),
+            );
+            index += 1;
+        }
+        ids
+    }
+}
+
+/// Replace the prefix `old` of the mount path `path` with `new`
+fn rebase(path: &str, old: &str, new: &str) -> String {
+    let rest = if old == "/" { path } else { &path[old.len()..] };
+    match (new, rest) {
+        ("/", "") => "/".to_string(),
+        ("/", _) => rest.to_string(),
+        _ => new.to_string() + rest,
+    }
+}
+
+/// Check whether the mount path `prefix` contains `path`
+fn is_prefix(prefix: &str, path: &str) -> bool {
+    if prefix == "/" {
+        return true;
+    }
+    match path.strip_prefix(prefix) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+fn mount_options(flags: VfsMountFlags) -> String {
+    let mut opts = String::from(if flags.contains(VfsMountFlags::MS_RDONLY) {
+        "ro"
+    } else {
+        "rw"
+    });
+    let names = [
+        (VfsMountFlags::MS_NOSUID, "nosuid"),
+        (VfsMountFlags::MS_NODEV, "nodev"),
+        (VfsMountFlags::MS_NOEXEC, "noexec"),
+        (VfsMountFlags::MS_SYNCHRONOUS, "sync"),
+        (VfsMountFlags::MS_DIRSYNC, "dirsync"),
+        (VfsMountFlags::MS_NOSYMFOLLOW, "nosymfollow"),
+        (VfsMountFlags::MS_NOATIME, "noatime"),
+        (VfsMountFlags::MS_NODEIRATIME, "nodiratime"),
+        (VfsMountFlags::MS_RELATIME, "relatime"),
+    ];
+    for (flag, name) in names {
+        if flags.contains(flag) {
+            opts.push(',');
+            opts.push_str(name);
+        }
+    }
+    opts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_prefix() {
+        assert!(is_prefix("/", "/a"));
+        assert!(is_prefix("/a", "/a"));
+        assert!(is_prefix("/a", "/a/b"));
+        assert!(!is_prefix("/a", "/ab"));
+    }
+
+    #[test]
+    fn test_rebase() {
+        assert_eq!(rebase("/a/b", "/a", "/c"), "/c/b");
+        assert_eq!(rebase("/a", "/a", "/"), "/");
+        assert_eq!(rebase("/a/b", "/a", "/"), "/b");
+        assert_eq!(rebase("/b", "/", "/c"), "/c/b");
+    }
+
+    #[test]
+    fn test_mount_options() {
+        assert_eq!(mount_options(VfsMountFlags::empty()), "rw");
+        assert_eq!(
+            mount_options(VfsMountFlags::MS_RDONLY | VfsMountFlags::MS_NODEV),
+            "ro,nodev"
+        );
+    }
+}
@@ -0,0 +1,468 @@
+//! File system change notifications for inotify(7)
+//!
+//! [`VfsNotifyRegistry`] keeps the watches of every inotify instance and the queues of their
+//! events. [`VfsPath`](crate::path::VfsPath) and [`VfsOpenFile`](crate::fd::VfsOpenFile)
+//! publish the events of their operations through the [`VfsNotifier`] given to them, the
+//! kernel implements it by locking the registry.
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    string::String,
+    sync::Arc,
+    vec::Vec,
+};
+use core::task::Waker;
+
+use bitflags::bitflags;
+
+use crate::{
+    dentry::VfsDentry,
+    error::VfsError,
+    inode::VfsInode,
+    lock::{inode_id, VfsInodeId},
+    poll::VfsWaitQueue,
+    utils::VfsPollEvents,
+    VfsResult,
+};
+
+bitflags! {
+    /// The events of inotify(7) and the flags of inotify_add_watch(2)
+    pub struct VfsNotifyMask: u32 {
+        /// File was accessed
+        const IN_ACCESS = 0x1;
+        /// File was modified
+        const IN_MODIFY = 0x2;
+        /// Metadata changed, e.g. permissions, timestamps, extended attributes and link count
+        const IN_ATTRIB = 0x4;
+        /// File opened for writing was closed
+        const IN_CLOSE_WRITE = 0x8;
+        /// File not opened for writing was closed
+        const IN_CLOSE_NOWRITE = 0x10;
+        /// File was opened
+        const IN_OPEN = 0x20;
+        /// File was moved out of the watched directory
+        const IN_MOVED_FROM = 0x40;
+        /// File was moved into the watched directory
+        const IN_MOVED_TO = 0x80;
+        /// File was created in the watched directory
+        const IN_CREATE = 0x100;
+        /// File was deleted from the watched directory
+        const IN_DELETE = 0x200;
+        /// The watched file was deleted
+        const IN_DELETE_SELF = 0x400;
+        /// The watched file was moved
+        const IN_MOVE_SELF = 0x800;
+        /// A file system was mounted on the watched directory, this is not in Linux
+        const IN_MOUNT = 0x1000;
+        /// The file system containing the watched file was unmounted
+        const IN_UNMOUNT = 0x2000;
+        /// The event queue overflowed
+        const IN_Q_OVERFLOW = 0x4000;
+        /// The watch was removed
+        const IN_IGNORED = 0x8000;
+        /// Only watch the path if it is a directory
+        const IN_ONLYDIR = 0x0100_0000;
+        /// Don't follow the path if it is a symlink, the caller resolves the path
+        const IN_DONT_FOLLOW = 0x0200_0000;
+        /// Fail with `EExist` if the file is already watched
+        const IN_MASK_CREATE = 0x1000_0000;
+        /// Add the events to the mask of the existing watch instead of replacing it
+        const IN_MASK_ADD = 0x2000_0000;
+        /// The subject of the event is a directory
+        const IN_ISDIR = 0x4000_0000;
+        /// Remove the watch after the first event
+        const IN_ONESHOT = 0x8000_0000;
+    }
+}
+
+impl VfsNotifyMask {
+    /// The events which can be watched
+    pub const IN_ALL_EVENTS: Self = Self::from_bits_truncate(0x1fff);
+    pub const IN_CLOSE: Self =
+        Self::from_bits_truncate(Self::IN_CLOSE_WRITE.bits | Self::IN_CLOSE_NOWRITE.bits);
+    pub const IN_MOVE: Self =
+        Self::from_bits_truncate(Self::IN_MOVED_FROM.bits | Self::IN_MOVED_TO.bits);
+    /// The events which are always reported
+    const ALWAYS: Self = Self::from_bits_truncate(
+        Self::IN_UNMOUNT.bits | Self::IN_Q_OVERFLOW.bits | Self::IN_IGNORED.bits,
+    );
+}
+
+/// The default max number of queued events of an instance, like `max_queued_events`
+pub const MAX_QUEUED_EVENTS: usize = 16384;
+
+/// The size of `struct inotify_event` without the name
+const EVENT_HEADER: usize = 16;
+
+/// An event read from an inotify instance
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VfsNotifyEvent {
+    /// The watch descriptor, -1 for `IN_Q_OVERFLOW`
+    pub wd: i32,
+    pub mask: VfsNotifyMask,
+    /// The cookie which pairs `IN_MOVED_FROM` and `IN_MOVED_TO` of a rename, or 0
+    pub cookie: u32,
+    /// The name of the child for the events of a watched directory
+    pub name: Option<String>,
+}
+
+impl VfsNotifyEvent {
+    /// The length of the name field, it is null terminated and padded to the header size
+    fn name_len(&self) -> usize {
+        self.name
+            .as_ref()
+            .map_or(0, |name| (name.len() + 1).next_multiple_of(EVENT_HEADER))
+    }
+
+    /// The size of the event in the layout of `struct inotify_event`
+    pub fn size(&self) -> usize {
+        EVENT_HEADER + self.name_len()
+    }
+
+    /// Write the event in the layout of `struct inotify_event`, returns the number of bytes
+    /// written or `None` if `buf` is too small
+    pub fn encode(&self, buf: &mut [u8]) -> Option<usize> {
+        let size = self.size();
+        let buf = buf.get_mut(..size)?;
+        buf[0..4].copy_from_slice(&self.wd.to_ne_bytes());
+        buf[4..8].copy_from_slice(&self.mask.bits().to_ne_bytes());
+        buf[8..12].copy_from_slice(&self.cookie.to_ne_bytes());
+        buf[12..16].copy_from_slice(&(self.name_len() as u32).to_ne_bytes());
+        buf[EVENT_HEADER..].fill(0);
+        if let Some(name) = &self.name {
+            buf[EVENT_HEADER..EVENT_HEADER + name.len()].copy_from_slice(name.as_bytes());
+        }
+        Some(size)
+    }
+}
+
+/// The receiver of the events published by the file system operations
+pub trait VfsNotifier: Send + Sync {
+    /// Publish `mask` on `inode`, `name` is the name of the child for the events of a
+    /// directory, see [`VfsNotifyRegistry::notify`]
+    fn notify(
+        &self,
+        inode: &Arc<dyn VfsInode>,
+        mask: VfsNotifyMask,
+        cookie: u32,
+        name: Option<&str>,
+    );
+    /// Get a new cookie to pair the events of a rename
+    fn next_cookie(&self) -> u32;
+}
+
+/// Publish `mask` on the file of `dentry` and on its parent directory with its name
+///
+/// `IN_ISDIR` is added if the file is a directory. The root of a file system has no parent
+/// in the same mount, so only the file itself gets the event.
+pub fn notify_dentry(notifier: &dyn VfsNotifier, dentry: &Arc<dyn VfsDentry>, mask: VfsNotifyMask) {
+    let Ok(inode) = dentry.inode() else {
+        return;
+    };
+    let mut mask = mask;
+    if inode.inode_type().is_dir() {
+        mask |= VfsNotifyMask::IN_ISDIR;
+    }
+    notifier.notify(&inode, mask, 0, None);
+    let name = dentry.name();
+    if name == "/" {
+        return;
+    }
+    if let Some(parent) = dentry.parent() {
+        if let Ok(parent) = parent.inode() {
+            notifier.notify(&parent, mask, 0, Some(&name));
+        }
+    }
+}
+
+struct Watch {
+    inode: Arc<dyn VfsInode>,
+    /// The identity of the inode, which doesn't change when the inode is loaded again
+    id: VfsInodeId,
+    mask: VfsNotifyMask,
+}
+
+struct NotifyGroup {
+    watches: BTreeMap<i32, Watch>,
+    next_wd: i32,
+    events: VecDeque<VfsNotifyEvent>,
+    max_events: usize,
+    wait: VfsWaitQueue,
+}
+
+impl NotifyGroup {
+    fn push(&mut self, event: VfsNotifyEvent) {
+        // the same event as the last unread one is merged
+        if self.events.back() == Some(&event) {
+            return;
+        }
+        if self.events.len() >= self.max_events {
+            let overflow = VfsNotifyEvent {
+                wd: -1,
+                mask: VfsNotifyMask::IN_Q_OVERFLOW,
+                cookie: 0,
+                name: None,
+            };
+            if self.events.back() != Some(&overflow) {
+                self.events.push_back(overflow);
+            }
+        } else {
+            self.events.push_back(event);
+        }
+        self.wait.wake(VfsPollEvents::IN);
+    }
+
+    fn remove_watch(&mut self, wd: i32) {
+        self.watches.remove(&wd);
+        self.push(VfsNotifyEvent {
+            wd,
+            mask: VfsNotifyMask::IN_IGNORED,
+            cookie: 0,
+            name: None,
+        });
+    }
+}
+
+/// The watches and the event queues of the inotify instances
+///
+/// An instance is created by [`VfsNotifyRegistry::create_group`] for inotify_init(2), the
+/// kernel keeps its id in the file and reads the events by [`VfsNotifyRegistry::read`]. A
+/// watch keeps its inode alive until it is removed. The watches are removed with
+/// `IN_IGNORED` when the inode is deleted or its file system is unmounted.
+///
+/// The registry doesn't lock itself, the kernel should wrap it in its own lock.
+#[derive(Default)]
+pub struct VfsNotifyRegistry {
+    groups: BTreeMap<usize, NotifyGroup>,
+    next_group: usize,
+    /// The watches of every inode, (group, wd)
+    inodes: BTreeMap<VfsInodeId, Vec<(usize, i32)>>,
+    cookie: u32,
+}
+
+impl VfsNotifyRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn group(&self, group: usize) -> VfsResult<&NotifyGroup> {
+        self.groups.get(&group).ok_or(VfsError::EBADF)
+    }
+
+    fn group_mut(&mut self, group: usize) -> VfsResult<&mut NotifyGroup> {
+        self.groups.get_mut(&group).ok_or(VfsError::EBADF)
+    }
+
+    /// Create an inotify instance which queues at most `max_events` events, returns its id
+    pub fn create_group(&mut self, max_events: usize) -> usize {
+        let id = self.next_group;
+        self.next_group += 1;
+        self.groups.insert(
+            id,
+            NotifyGroup {
+                watches: BTreeMap::new(),
+                next_wd: 1,
+                events: VecDeque::new(),
+                max_events: max_events.max(1),
+                wait: VfsWaitQueue::new(),
+            },
+        );
+        id
+    }
+
+    /// Remove the instance and all of its watches, called when the inotify file is released
+    pub fn remove_group(&mut self, group: usize) -> VfsResult<()> {
+        let mut removed = self.groups.remove(&group).ok_or(VfsError::EBADF)?;
+        for watch in removed.watches.values() {
+            self.unindex(watch.id, group);
+        }
+        removed.wait.wake_all();
+        Ok(())
+    }
+
+    fn unindex(&mut self, id: VfsInodeId, group: usize) {
+        if let Some(watches) = self.inodes.get_mut(&id) {
+            watches.retain(|(g, _)| *g != group);
+            if watches.is_empty() {
+                self.inodes.remove(&id);
+            }
+        }
+    }
+
+    /// Watch the events of `mask` on `inode`, called by inotify_add_watch(2)
+    ///
+    /// Watching an inode again replaces the mask of its watch, or adds to it with
+    /// `IN_MASK_ADD`, and returns the same watch descriptor.
+    pub fn add_watch(
+        &mut self,
+        group: usize,
+        inode: &Arc<dyn VfsInode>,
+        mask: VfsNotifyMask,
+    ) -> VfsResult<i32> {
+        let events = mask & VfsNotifyMask::IN_ALL_EVENTS;
+        if events.is_empty()
+            || mask.contains(VfsNotifyMask::IN_MASK_ADD | VfsNotifyMask::IN_MASK_CREATE)
+        {
+            return Err(VfsError::Invalid);
+        }
+        if mask.contains(VfsNotifyMask::IN_ONLYDIR) && !inode.inode_type().is_dir() {
+            return Err(VfsError::NotDir);
+        }
+        let id = inode_id(inode)?;
+        let existing = self
+            .inodes
+            .get(&id)
+            .and_then(|watches| watches.iter().find(|(g, _)| *g == group))
+            .map(|(_, wd)| *wd);
+        let keep = VfsNotifyMask::IN_ALL_EVENTS | VfsNotifyMask::IN_ONESHOT;
+        let group_ref = self.group_mut(group)?;
+        if let Some(wd) = existing {
+            if mask.contains(VfsNotifyMask::IN_MASK_CREATE) {
+                return Err(VfsError::EExist);
+            }
+            let watch = group_ref.watches.get_mut(&wd).unwrap();
+            if mask.contains(VfsNotifyMask::IN_MASK_ADD) {
+                watch.mask |= mask & keep;
+            } else {
+                watch.mask = mask & keep;
+            }
+            return Ok(wd);
+        }
+        let wd = group_ref.next_wd;
+        group_ref.next_wd += 1;
+        group_ref.watches.insert(
+            wd,
+            Watch {
+                inode: inode.clone(),
+                id,
+                mask: mask & keep,
+            },
+        );
+        self.inodes.entry(id).or_default().push((group, wd));
+        Ok(wd)
+    }
+
+    /// Remove the watch `wd`, called by inotify_rm_watch(2), `IN_IGNORED` is queued
+    pub fn rm_watch(&mut self, group: usize, wd: i32) -> VfsResult<()> {
+        let group_ref = self.group_mut(group)?;
+        let id = group_ref.watches.get(&wd).ok_or(VfsError::Invalid)?.id;
+        group_ref.remove_watch(wd);
+        self.unindex(id, group);
+        Ok(())
+    }
+
+    /// Get a new cookie to pair `IN_MOVED_FROM` and `IN_MOVED_TO`, it is never 0
+    pub fn next_cookie(&mut self) -> u32 {
+        self.cookie = self.cookie.wrapping_add(1).max(1);
+        self.cookie
+    }
+
+    /// Queue the event `mask` of `inode` to the watches interested in it
+    ///
+    /// - `IN_ONESHOT` watches are removed after the first event.
+    /// - `IN_DELETE_SELF` removes the watches of the inode.
+    /// - `IN_UNMOUNT` is published with the root inode of the unmounted file system, it
+    ///   removes the watches of every inode in the file system.
+    pub fn notify(
+        &mut self,
+        inode: &Arc<dyn VfsInode>,
+        mask: VfsNotifyMask,
+        cookie: u32,
+        name: Option<&str>,
+    ) {
+        let targets: Vec<(usize, i32)> = if mask.contains(VfsNotifyMask::IN_UNMOUNT) {
+            let Ok(sb) = inode.get_super_block() else {
+                return;
+            };
+            self.groups
+                .iter()
+                .flat_map(|(group, g)| {
+                    g.watches
+                        .iter()
+                        .filter(|(_, watch)| {
+                            watch
+                                .inode
+                                .get_super_block()
+                                .is_ok_and(|other| Arc::ptr_eq(&other, &sb))
+                        })
+                        .map(move |(wd, _)| (*group, *wd))
+                })
+                .collect()
+        } else {
+            match inode_id(inode).ok().and_then(|id| self.inodes.get(&id)) {
+                Some(watches) => watches.clone(),
+                None => return,
+            }
+        };
+        let remove = mask.intersects(VfsNotifyMask::IN_DELETE_SELF | VfsNotifyMask::IN_UNMOUNT);
+        for (group, wd) in targets {
+            let g = self.groups.get_mut(&group).unwrap();
+            let watch = g.watches.get(&wd).unwrap();
+            let watched = watch.mask;
+            let watch_id = watch.id;
+            let events = mask & (watched | VfsNotifyMask::ALWAYS | VfsNotifyMask::IN_ISDIR);
+            let reported = events - VfsNotifyMask::IN_ISDIR;
+            if !reported.is_empty() {
+                g.push(VfsNotifyEvent {
+                    wd,
+                    mask: events,
+                    cookie,
+                    name: name.map(String::from),
+                });
+            }
+            if remove || (!reported.is_empty() && watched.contains(VfsNotifyMask::IN_ONESHOT)) {
+                g.remove_watch(wd);
+                self.unindex(watch_id, group);
+            }
+        }
+    }
+
+    /// Read the queued events into `buf` in the layout of `struct inotify_event`
+    ///
+    /// Returns `EAGAIN` if no event is queued, or `Invalid` if `buf` is too small for the
+    /// first event.
+    pub fn read(&mut self, group: usize, buf: &mut [u8]) -> VfsResult<usize> {
+        let g = self.group_mut(group)?;
+        if g.events.is_empty() {
+            return Err(VfsError::EAGAIN);
+        }
+        let mut len = 0;
+        while let Some(event) = g.events.front() {
+            match event.encode(&mut buf[len..]) {
+                Some(size) => len += size,
+                None => break,
+            }
+            g.events.pop_front();
+        }
+        if len == 0 {
+            return Err(VfsError::Invalid);
+        }
+        Ok(len)
+    }
+
+    /// The number of bytes of the queued events, used by the `FIONREAD` ioctl
+    pub fn pending(&self, group: usize) -> VfsResult<usize> {
+        Ok(self.group(group)?.events.iter().map(|e| e.size()).sum())
+    }
+
+    pub fn poll(&self, group: usize, event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
+        let g = self.group(group)?;
+        let mut res = VfsPollEvents::empty();
+        if !g.events.is_empty() {
+            res |= event & VfsPollEvents::IN;
+        }
+        Ok(res)
+    }
+
+    pub fn poll_wait(
+        &mut self,
+        group: usize,
+        event: VfsPollEvents,
+        waker: &Waker,
+    ) -> VfsResult<VfsPollEvents> {
+        let res = self.poll(group, event)?;
+        if res.is_empty() {
+            self.group_mut(group)?.wait.register(event, waker);
+        }
+        Ok(res)
+    }
+}
@@ -5,9 +5,11 @@ use alloc::{
     format,
     string::{String, ToString},
     sync::Arc,
+    vec,
     vec::Vec,
 };
 use core::{
+    any::Any,
     error::Error,
     fmt::{write, Debug, Formatter, Write},
 };
@@ -15,13 +17,26 @@ use core::{
 use log::{error, trace};
 
 use crate::{
-    dentry::VfsDentry,
+    cache::VfsPageCache,
+    dentry::{VfsDentry, VfsDentryCache, VfsDentryLookup},
     error::VfsError,
-    inode::VfsInode,
-    utils::{VfsDirEntry, VfsInodeMode, VfsNodePerm, VfsNodeType, VfsRenameFlag},
+    fstype::VfsMountPoint,
+    inode::{InodeAttr, InodeAttrValid, VfsInode},
+    mount::VfsNamespace,
+    notify::{self, VfsNotifier, VfsNotifyMask},
+    utils::{
+        VfsAccessMode, VfsDirEntry, VfsInodeMode, VfsMountFlags, VfsNodePerm, VfsNodeType,
+        VfsOpenFlags, VfsRenameFlag, VfsResolveFlags, VfsStatx, VfsStatxAttr, VfsStatxMask,
+        VfsTimeSpec, VfsUtime, VfsXattrFlag, NSEC_PER_SEC,
+    },
     VfsResult,
 };
 
+/// The max number of symlinks followed when walking a path
+pub const MAX_SYMLINK_FOLLOW: usize = 40;
+/// The max length of a path including the terminating null byte, so a symlink target is shorter
+pub const PATH_MAX: usize = 4096;
+
 /// The context of system call
 ///
 /// In VfsPath, we need to check the permission of the user, so we need the context of system call
@@ -33,6 +48,32 @@ pub struct SysContext {
     pub root: Arc<dyn VfsDentry>,
 }
 
+impl SysContext {
+    /// Get the credentials of the caller
+    pub fn cred(&self) -> VfsCred {
+        VfsCred {
+            uid: self.uid,
+            gid: self.gid,
+        }
+    }
+}
+
+/// The credentials used to check the permission of the caller
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct VfsCred {
+    pub uid: u64,
+    pub gid: u64,
+}
+
+impl VfsCred {
+    /// The credentials of the superuser
+    pub const ROOT: Self = Self { uid: 0, gid: 0 };
+
+    pub fn is_root(&self) -> bool {
+        self.uid == 0
+    }
+}
+
 #[derive(Clone)]
 pub struct VfsPath {
     /// The root of the file system
@@ -41,6 +82,18 @@ pub struct VfsPath {
     fs: Arc<dyn VfsDentry>,
     /// The path to search for
     path: String,
+    /// The credentials of the caller, `None` means the kernel itself
+    cred: Option<VfsCred>,
+    /// The restrictions of resolving the path, see [`VfsPath::with_resolve`]
+    resolve_flags: VfsResolveFlags,
+    /// The receiver of the events of the operations, see [`VfsPath::with_notifier`]
+    notifier: Option<Arc<dyn VfsNotifier>>,
+    /// The global dentry cache, see [`VfsPath::with_dcache`]
+    dcache: Option<Arc<dyn VfsDentryCache>>,
+    /// The mounts seen by the path, see [`VfsPath::with_namespace`]
+    namespace: Option<Arc<dyn VfsNamespace>>,
+    /// The page cache written back on unmount, see [`VfsPath::with_page_cache`]
+    page_cache: Option<Arc<dyn VfsPageCache>>,
 }
 
 impl PartialEq for VfsPath {
@@ -63,7 +116,84 @@ impl VfsPath {
             root,
             fs: start,
             path: "".to_string(),
+            cred: None,
+            resolve_flags: VfsResolveFlags::empty(),
+            notifier: None,
+            dcache: None,
+            namespace: None,
+            page_cache: None,
+        }
+    }
+    /// Returns a path which checks the permission with the credentials of `context`
+    pub fn with_context(&self, context: &SysContext) -> Self {
+        self.with_cred(context.cred())
+    }
+    /// Returns a path which checks the permission with `cred`
+    pub fn with_cred(&self, cred: VfsCred) -> Self {
+        let mut path = self.clone();
+        path.cred = Some(cred);
+        path
+    }
+    /// Get the credentials used to check the permission
+    ///
+    /// If the path is not bound to any credentials, the superuser is used.
+    pub fn cred(&self) -> VfsCred {
+        self.cred.unwrap_or(VfsCred::ROOT)
+    }
+    /// Returns a path which is resolved with the restrictions of `flags`, like openat2(2)
+    ///
+    /// The start directory is the directory the restrictions are relative to, e.g. the
+    /// directory of `dirfd`. `RESOLVE_BENEATH` and `RESOLVE_IN_ROOT` can't be used together.
+    pub fn with_resolve(&self, flags: VfsResolveFlags) -> VfsResult<Self> {
+        if flags.contains(VfsResolveFlags::RESOLVE_BENEATH | VfsResolveFlags::RESOLVE_IN_ROOT) {
+            return Err(VfsError::Invalid);
         }
+        let mut path = self.clone();
+        path.resolve_flags = flags;
+        Ok(path)
+    }
+    /// Get the restrictions of resolving the path
+    pub fn resolve_flags(&self) -> VfsResolveFlags {
+        self.resolve_flags
+    }
+    /// Returns a path which publishes the events of its operations to `notifier`
+    ///
+    /// Creating, deleting and renaming files, changing their data and metadata, and
+    /// mounting and unmounting file systems publish the events of inotify(7).
+    pub fn with_notifier(&self, notifier: Arc<dyn VfsNotifier>) -> Self {
+        let mut path = self.clone();
+        path.notifier = Some(notifier);
+        path
+    }
+    /// Get the receiver of the events of the operations
+    pub fn notifier(&self) -> Option<Arc<dyn VfsNotifier>> {
+        self.notifier.clone()
+    }
+    /// Returns a path which finds the children of directories through `dcache`
+    ///
+    /// The names which don't exist are remembered by the cache, so they are not looked up
+    /// in the inodes again until they are created through a path with the same cache.
+    pub fn with_dcache(&self, dcache: Arc<dyn VfsDentryCache>) -> Self {
+        let mut path = self.clone();
+        path.dcache = Some(dcache);
+        path
+    }
+    /// Returns a path which finds the mount points in `namespace` instead of the dentries
+    ///
+    /// The dentries are shared by all namespaces, the mounts of a namespace are made by
+    /// its [`MountTable`](crate::mount::MountTable). Without a namespace, the path sees the
+    /// mounts attached to the dentries by [`VfsPath::mount`].
+    pub fn with_namespace(&self, namespace: Arc<dyn VfsNamespace>) -> Self {
+        let mut path = self.clone();
+        path.namespace = Some(namespace);
+        path
+    }
+    /// Returns a path which writes back the pages of a file system in `cache` before it is
+    /// unmounted
+    pub fn with_page_cache(&self, cache: Arc<dyn VfsPageCache>) -> Self {
+        let mut path = self.clone();
+        path.page_cache = Some(cache);
+        path
     }
     pub fn as_str(&self) -> &str {
         &self.path
@@ -74,6 +204,12 @@ impl VfsPath {
             root: self.root.clone(),
             path: self.path.clone() + "/" + path.as_ref(),
             fs: self.fs.clone(),
+            cred: self.cred,
+            resolve_flags: self.resolve_flags,
+            notifier: self.notifier.clone(),
+            dcache: self.dcache.clone(),
+            namespace: self.namespace.clone(),
+            page_cache: self.page_cache.clone(),
         })
     }
     pub fn root(&self) -> Self {
@@ -81,50 +217,32 @@ impl VfsPath {
             root: self.root.clone(),
             path: "".to_string(),
             fs: self.fs.clone(),
+            cred: self.cred,
+            resolve_flags: self.resolve_flags,
+            notifier: self.notifier.clone(),
+            dcache: self.dcache.clone(),
+            namespace: self.namespace.clone(),
+            page_cache: self.page_cache.clone(),
         }
     }
     pub fn is_root(&self) -> bool {
         self.path.is_empty()
     }
 
-    fn to_symlink(&self, symlink: Arc<dyn VfsDentry>) -> VfsResult<Arc<dyn VfsDentry>> {
-        let inode = symlink.inode()?;
-        let mut buf = [0; 255];
-        let r = inode.readlink(&mut buf)?;
-        if r > 255 {
-            return Err(VfsError::Invalid);
-        }
-        let path = core::str::from_utf8(&buf[..r]).map_err(|_| VfsError::Invalid)?;
-        if path.starts_with("/") {
-            trace!("[to_symlink] absolute path: {}", path);
-            // absolute path
-            let new_path = Self::new(self.root.clone(), self.root.clone()).join(path)?;
-            new_path.open(None)
-        } else {
-            trace!("[to_symlink] relative path: {}", path);
-            // relative path
-            let p = symlink.parent().unwrap();
-            let new_path = Self::new(self.root.clone(), p).join(path)?;
-            new_path.open(None)
-        }
-    }
-    /// It same as [`open`], but it will follow the symlink according to the flag
-    ///
-    /// This function is just to avoid modifications to the code that uses the open function.
+    /// It same as [`open_with_flags`](VfsPath::open_with_flags), but takes the flags of pconst
     #[cfg(feature = "linux_error")]
     pub fn open2(
         &self,
         mode: Option<VfsInodeMode>,
         flag: pconst::io::OpenFlags,
     ) -> VfsResult<Arc<dyn VfsDentry>> {
-        self.__open(mode, !flag.contains(pconst::io::OpenFlags::O_NOFOLLOW))
+        self.open_with_flags(mode, VfsOpenFlags::from_bits_truncate(flag.bits() as u32))
     }
-    // todo!(more flag support and permission check)
     /// Open or create a dentry
     ///
     /// If you need create the file if it doesn't exist, the parameter `mode` should be `Some(mode)`.
     ///
-    /// It will follow the symlink by default, if you don't want to follow the symlink, you can use [`open2`]
+    /// It will follow the symlink by default, if you don't want to follow the symlink, you can use [`open_with_flags`]
     ///
     /// # Example
     /// ```compile_fail
@@ -134,22 +252,156 @@ impl VfsPath {
     /// ```
     ///
     pub fn open(&self, mode: Option<VfsInodeMode>) -> VfsResult<Arc<dyn VfsDentry>> {
-        self.__open(mode, true)
+        self.__open(mode, true, VfsAccessMode::empty())
     }
 
-    fn __open(&self, mode: Option<VfsInodeMode>, symlink: bool) -> VfsResult<Arc<dyn VfsDentry>> {
-        let exist = self.exists();
-        match exist {
-            Ok(d) => {
-                if symlink {
-                    let inode = d.inode()?;
-                    match inode.inode_type() {
-                        VfsNodeType::SymLink => self.to_symlink(d),
-                        _ => Ok(d),
-                    }
-                } else {
-                    Ok(d)
+    /// Open a dentry with the flags of open(2)
+    ///
+    /// - `O_CREAT` creates a regular file with the permission of `mode` if the path doesn't
+    ///   exist, with `O_EXCL` it fails with `EExist` if the path exists. The target of a
+    ///   dangling symlink is created.
+    /// - `O_NOFOLLOW` fails with `SymLinkLoop` if the path is a symlink.
+    /// - `O_DIRECTORY` fails with `NotDir` if the path is not a directory.
+    /// - `O_TRUNC` truncates the regular file to 0.
+    /// - `O_TMPFILE` creates an unnamed regular file in the directory of the path by
+    ///   [`VfsInode::tmpfile`].
+    /// - `O_PATH` only finds the dentry, other flags except `O_DIRECTORY` and `O_NOFOLLOW` are
+    ///   ignored, and the symlink itself is returned with `O_NOFOLLOW`.
+    ///
+    /// Opening a directory for writing or with `O_CREAT` fails with `IsDir`. The permission of
+    /// the access mode is checked unless the file is created. Immutable files can't be opened
+    /// for writing, and append-only files only with `O_APPEND` and without `O_TRUNC`.
+    pub fn open_with_flags(
+        &self,
+        mode: Option<VfsInodeMode>,
+        flags: VfsOpenFlags,
+    ) -> VfsResult<Arc<dyn VfsDentry>> {
+        let nofollow = flags.contains(VfsOpenFlags::O_NOFOLLOW);
+        if flags.contains(VfsOpenFlags::O_PATH) {
+            let dentry = self.__open(None, !nofollow, VfsAccessMode::empty())?;
+            if flags.contains(VfsOpenFlags::O_DIRECTORY) && !dentry.inode()?.inode_type().is_dir() {
+                return Err(VfsError::NotDir);
+            }
+            return Ok(dentry);
+        }
+        if flags.contains(VfsOpenFlags::O_TMPFILE) {
+            return self.open_tmpfile(mode, flags);
+        }
+        let create = flags.contains(VfsOpenFlags::O_CREAT);
+        if create && flags.contains(VfsOpenFlags::O_DIRECTORY) {
+            return Err(VfsError::Invalid);
+        }
+        let (dentry, mnt_flags) = match self.resolve(false) {
+            Ok(found) => {
+                if create && flags.contains(VfsOpenFlags::O_EXCL) {
+                    return Err(VfsError::EExist);
+                }
+                found
+            }
+            Err(VfsError::NoEntry) if create => {
+                if self.path.ends_with('/') {
+                    return Err(VfsError::IsDir);
                 }
+                let perm = mode.map_or(VfsNodePerm::from_bits_truncate(0o666), Into::into);
+                return self.create_file(perm);
+            }
+            Err(e) => return Err(e),
+        };
+        if nofollow && dentry.inode()?.inode_type() == VfsNodeType::SymLink {
+            return Err(VfsError::SymLinkLoop);
+        }
+        let (dentry, mnt_flags) = match self.follow(dentry.clone(), mnt_flags, true) {
+            // the symlink is dangling, its target is created
+            Err(VfsError::NoEntry) if create => {
+                return self.link_target(&dentry)?.open_with_flags(mode, flags);
+            }
+            res => res?,
+        };
+        let inode = dentry.inode()?;
+        let ty = inode.inode_type();
+        if ty.is_dir() {
+            if create || flags.writable() {
+                return Err(VfsError::IsDir);
+            }
+        } else if flags.contains(VfsOpenFlags::O_DIRECTORY) || self.path.ends_with('/') {
+            return Err(VfsError::NotDir);
+        }
+        let mut access = VfsAccessMode::empty();
+        if flags.readable() {
+            access |= VfsAccessMode::R_OK;
+        }
+        if flags.writable() || flags.contains(VfsOpenFlags::O_TRUNC) {
+            access |= VfsAccessMode::W_OK;
+        }
+        self.check_open_access(&dentry, mnt_flags, access)?;
+        if access.contains(VfsAccessMode::W_OK) {
+            let append =
+                flags.contains(VfsOpenFlags::O_APPEND) && !flags.contains(VfsOpenFlags::O_TRUNC);
+            check_modify(&inode, append)?;
+        }
+        if flags.contains(VfsOpenFlags::O_TRUNC) && ty == VfsNodeType::File {
+            inode.truncate(0)?;
+            self.notify_dentry(&dentry, VfsNotifyMask::IN_MODIFY);
+        }
+        Ok(dentry)
+    }
+
+    /// The path of the target of the symlink `dentry`, which is the last component of the path
+    fn link_target(&self, dentry: &Arc<dyn VfsDentry>) -> VfsResult<Self> {
+        let target = read_link(dentry)?;
+        if !target.starts_with('/') {
+            return self.parent().join(target);
+        }
+        let mut path = self.clone();
+        if !self
+            .resolve_flags
+            .contains(VfsResolveFlags::RESOLVE_IN_ROOT)
+        {
+            path.fs = self.root.clone();
+        }
+        path.path = target;
+        Ok(path)
+    }
+
+    /// Create an unnamed regular file in the directory of the path for `O_TMPFILE`
+    ///
+    /// The file is never visible in the directory, it is freed when the returned dentry is
+    /// dropped unless it is linked. `EOPNOTSUPP` is returned if the file system doesn't
+    /// support [`VfsInode::tmpfile`].
+    fn open_tmpfile(
+        &self,
+        mode: Option<VfsInodeMode>,
+        flags: VfsOpenFlags,
+    ) -> VfsResult<Arc<dyn VfsDentry>> {
+        if !flags.writable() {
+            return Err(VfsError::Invalid);
+        }
+        let (dir, mnt_flags) = self.open_mnt()?;
+        let dir_inode = dir.inode()?;
+        if !dir_inode.inode_type().is_dir() {
+            return Err(VfsError::NotDir);
+        }
+        check_writable(mnt_flags)?;
+        check_dir_write(&dir_inode, self.cred())?;
+        let perm = mode.map_or(VfsNodePerm::from_bits_truncate(0o600), Into::into);
+        let inode = dir_inode.tmpfile(perm)?;
+        set_owner(&inode, self.cred())?;
+        dir.detached(inode)
+    }
+
+    /// Open or create a dentry, `access` is checked against the dentry if it already exists
+    fn __open(
+        &self,
+        mode: Option<VfsInodeMode>,
+        symlink: bool,
+        access: VfsAccessMode,
+    ) -> VfsResult<Arc<dyn VfsDentry>> {
+        let exist = self.resolve(false);
+        match exist {
+            Ok((d, flags)) => {
+                let (d, flags) = self.follow(d, flags, symlink)?;
+                self.check_open_access(&d, flags, access)?;
+                Ok(d)
             }
             Err(e) => match e {
                 VfsError::NoEntry if mode.is_some() => {
@@ -168,6 +420,138 @@ impl VfsPath {
         }
     }
 
+    /// Open the existing dentry like [`VfsPath::open`], and get the flags of the mount it is
+    /// reached through
+    fn open_mnt(&self) -> VfsResult<(Arc<dyn VfsDentry>, VfsMountFlags)> {
+        let (d, flags) = self.resolve(false)?;
+        let (d, flags) = self.follow(d, flags, true)?;
+        self.check_open_access(&d, flags, VfsAccessMode::empty())?;
+        Ok((d, flags))
+    }
+
+    /// Follow the dentry of the path if it is a symlink and `symlink` is true
+    fn follow(
+        &self,
+        d: Arc<dyn VfsDentry>,
+        flags: VfsMountFlags,
+        symlink: bool,
+    ) -> VfsResult<(Arc<dyn VfsDentry>, VfsMountFlags)> {
+        if symlink && d.inode()?.inode_type() == VfsNodeType::SymLink {
+            return self.resolve(true);
+        }
+        Ok((d, flags))
+    }
+
+    /// Check the mount flags and the permission of `access` before opening an existing dentry
+    fn check_open_access(
+        &self,
+        d: &Arc<dyn VfsDentry>,
+        flags: VfsMountFlags,
+        access: VfsAccessMode,
+    ) -> VfsResult<()> {
+        let inode = d.inode()?;
+        let ty = inode.inode_type();
+        if (ty.is_char_device() || ty.is_block_device()) && flags.contains(VfsMountFlags::MS_NODEV)
+        {
+            return Err(VfsError::Access);
+        }
+        if access.contains(VfsAccessMode::W_OK) {
+            check_writable(flags)?;
+        }
+        check_access(&inode, self.cred(), access)
+    }
+
+    /// Record the child `name` of `parent` in the dentry cache, `None` if it doesn't exist
+    fn dcache_insert(
+        &self,
+        parent: &Arc<dyn VfsDentry>,
+        name: &str,
+        dentry: Option<&Arc<dyn VfsDentry>>,
+    ) {
+        if let Some(dcache) = &self.dcache {
+            dcache.insert(parent, name, dentry);
+        }
+    }
+
+    /// Forget the child `name` of `parent` in the dentry cache, called as soon as the child is
+    /// created in the inode, before its dentry is inserted
+    fn dcache_invalidate(&self, parent: &Arc<dyn VfsDentry>, name: &str) {
+        if let Some(dcache) = &self.dcache {
+            dcache.invalidate(parent, name);
+        }
+    }
+
+    /// Publish `mask` on the file of `dentry` and on its parent directory
+    pub(crate) fn notify_dentry(&self, dentry: &Arc<dyn VfsDentry>, mask: VfsNotifyMask) {
+        if let Some(notifier) = &self.notifier {
+            notify::notify_dentry(notifier.as_ref(), dentry, mask);
+        }
+    }
+
+    /// Publish `mask` on the directory `dir` for its child `name`
+    fn notify_child(
+        &self,
+        dir: &Arc<dyn VfsDentry>,
+        name: &str,
+        child: &Arc<dyn VfsInode>,
+        mask: VfsNotifyMask,
+        cookie: u32,
+    ) {
+        if let (Some(notifier), Ok(dir)) = (&self.notifier, dir.inode()) {
+            let mut mask = mask;
+            if child.inode_type().is_dir() {
+                mask |= VfsNotifyMask::IN_ISDIR;
+            }
+            notifier.notify(&dir, mask, cookie, Some(name));
+        }
+    }
+
+    /// Publish the event of `inode` after one of its links is removed, `IN_DELETE_SELF`
+    /// if it has no link anymore, otherwise `IN_ATTRIB` for the changed link count
+    fn notify_unlinked(&self, inode: &Arc<dyn VfsInode>) {
+        if let Some(notifier) = &self.notifier {
+            let deleted = inode.inode_type().is_dir()
+                || inode.get_attr().map_or(true, |stat| stat.st_nlink == 0);
+            let mask = match deleted {
+                true => VfsNotifyMask::IN_DELETE_SELF,
+                false => VfsNotifyMask::IN_ATTRIB,
+            };
+            notifier.notify(inode, mask, 0, None);
+        }
+    }
+
+    /// Publish the events of moving `inode` from `old_name` in `old_dir` to `new_name` in
+    /// `new_dir`, the `IN_MOVED_FROM` and `IN_MOVED_TO` events share a cookie
+    fn notify_move(
+        &self,
+        old_dir: &Arc<dyn VfsDentry>,
+        old_name: &str,
+        new_dir: &Arc<dyn VfsDentry>,
+        new_name: &str,
+        inode: &Arc<dyn VfsInode>,
+    ) {
+        if let Some(notifier) = &self.notifier {
+            let cookie = notifier.next_cookie();
+            let from = VfsNotifyMask::IN_MOVED_FROM;
+            self.notify_child(old_dir, old_name, inode, from, cookie);
+            let to = VfsNotifyMask::IN_MOVED_TO;
+            self.notify_child(new_dir, new_name, inode, to, cookie);
+            notifier.notify(inode, VfsNotifyMask::IN_MOVE_SELF, 0, None);
+        }
+    }
+
+    /// Publish `IN_UNMOUNT` for the file system of `mnt`, a bind mount doesn't unmount the
+    /// file system
+    pub(crate) fn notify_umount(&self, mnt: &VfsMountPoint) {
+        let flags = VfsMountFlags::from_bits_truncate(mnt.mnt_flags);
+        if flags.contains(VfsMountFlags::MS_BIND) {
+            return;
+        }
+        if let (Some(notifier), Ok(inode)) = (&self.notifier, mnt.root.inode()) {
+            notifier.notify(&inode, VfsNotifyMask::IN_UNMOUNT, 0, None);
+        }
+    }
+
     fn create_file(&self, perm: VfsNodePerm) -> VfsResult<Arc<dyn VfsDentry>> {
         self.create(VfsNodeType::File, perm, "create file")
     }
@@ -183,9 +567,7 @@ impl VfsPath {
         perm: VfsNodePerm,
         action: &str,
     ) -> VfsResult<Arc<dyn VfsDentry>> {
-        let parent = self.get_parent(action)?;
-        // resolve mount point
-        let dentry = real_dentry_down(parent);
+        let (dentry, mnt_flags) = self.get_parent(action)?;
         let file_name = self.path.rsplit('/').next();
         if file_name.is_none() {
             return Err(VfsError::Invalid);
@@ -198,14 +580,27 @@ impl VfsPath {
             let file_inode = dentry.inode()?.lookup(file_name);
             match file_inode {
                 Ok(x) => {
-                    dentry.insert(file_name, x)?;
+                    let file = dentry.clone().insert(file_name, x)?;
+                    self.dcache_insert(&dentry, file_name, Some(&file));
                     Err(VfsError::EExist)
                 }
                 Err(e) => {
                     if e == VfsError::NoEntry {
+                        check_writable(mnt_flags)?;
+                        check_dir_write(&dentry.inode()?, self.cred())?;
                         // if we can't find the inode, we create a new inode and insert it into dentry cache
                         let file_inode = dentry.inode()?.create(file_name, ty, perm, None)?;
-                        let file = dentry.insert(file_name, file_inode)?;
+                        self.dcache_invalidate(&dentry, file_name);
+                        set_owner(&file_inode, self.cred())?;
+                        let file = dentry.clone().insert(file_name, file_inode.clone())?;
+                        self.dcache_insert(&dentry, file_name, Some(&file));
+                        self.notify_child(
+                            &dentry,
+                            file_name,
+                            &file_inode,
+                            VfsNotifyMask::IN_CREATE,
+                            0,
+                        );
                         Ok(file)
                     } else {
                         Err(e)
@@ -217,15 +612,16 @@ impl VfsPath {
         }
     }
 
-    /// Checks whether parent is a directory
-    fn get_parent(&self, action: &str) -> VfsResult<Arc<dyn VfsDentry>> {
+    /// Checks whether parent is a directory, and get the flags of the mount it is reached
+    /// through
+    fn get_parent(&self, action: &str) -> VfsResult<(Arc<dyn VfsDentry>, VfsMountFlags)> {
         let parent = self.parent();
-        let parent = parent.exists()?;
+        let (parent, flags) = parent.resolve(true)?;
         if !parent.inode()?.inode_type().is_dir() {
             error!("Could not {}, parent path is not a directory", action);
             return Err(VfsError::NotDir);
         }
-        Ok(parent)
+        Ok((parent, flags))
     }
     pub fn parent(&self) -> Self {
         let index = self.path.rfind('/');
@@ -234,15 +630,56 @@ impl VfsPath {
                 root: self.root.clone(),
                 path: self.path[..idx].to_string(),
                 fs: self.fs.clone(),
+                cred: self.cred,
+                resolve_flags: self.resolve_flags,
+                notifier: self.notifier.clone(),
+                dcache: self.dcache.clone(),
+                namespace: self.namespace.clone(),
+                page_cache: self.page_cache.clone(),
             })
             .unwrap_or_else(|| self.root())
     }
 
+    /// Find the dentry of the path, the symlink in the last component is not followed
     pub fn exists(&self) -> VfsResult<Arc<dyn VfsDentry>> {
-        let mut parent = self.fs.clone();
-        let mut path = self.path.as_str();
+        self.resolve(false).map(|(dentry, _)| dentry)
+    }
+
+    /// Walk the path, following the symlinks in the middle of the path and also the last one
+    /// if `follow` is true
+    ///
+    /// The symlinks are followed iteratively, `SymLinkLoop` is returned after
+    /// [`MAX_SYMLINK_FOLLOW`] symlinks.
+    ///
+    /// The restrictions of [`VfsResolveFlags`] are checked on every step, `CrossDevice` is
+    /// returned if the path escapes the start directory or its mount.
+    ///
+    /// The flags of the mount the dentry is reached through are returned with it. The
+    /// dentries of a bind mount are shared with its source, so its flags are only known by
+    /// the walk.
+    fn resolve(&self, follow: bool) -> VfsResult<(Arc<dyn VfsDentry>, VfsMountFlags)> {
+        let flags = self.resolve_flags;
+        let ns = self.namespace.as_deref();
+        // whether the mount points should be crossed, see `enter_mount`
+        let mut cross = true;
+        let mut mnt_flags = mount_flags_in(ns, &self.fs);
+        let mut parent = enter_mount(ns, self.fs.clone(), &mut cross, &mut mnt_flags);
+        // the start directory, which is the root of `RESOLVE_BENEATH` and `RESOLVE_IN_ROOT`
+        let (base, base_cross, base_flags) = (parent.clone(), cross, mnt_flags);
+        let base_mnt = mount_root(ns, &base);
+        let cross_device = |dentry: &Arc<dyn VfsDentry>| {
+            flags.contains(VfsResolveFlags::RESOLVE_NO_XDEV)
+                && !Arc::ptr_eq(&mount_root(ns, dentry), &base_mnt)
+        };
+        // the directories we walked through, so ".." can go back across mount points
+        let mut walked = Vec::new();
+        let mut hops = 0;
+        // the path left to walk starts at `start`, it is replaced when a symlink is followed
+        let mut path = self.path.clone();
+        let mut start = 0;
         loop {
-            let (name, rest) = split_path(path);
+            let (name, rest) = split_path(&path[start..]);
+            let next = rest.map(|rest| path.len() - rest.len());
             let parent_inode = parent.inode()?;
             if !parent_inode.inode_type().is_dir() && !name.is_empty() {
                 return Err(VfsError::NotDir);
@@ -250,39 +687,111 @@ impl VfsPath {
             if name.is_empty() {
                 break;
             }
+            // we need search permission on every directory in the path
+            check_access(&parent_inode, self.cred(), VfsAccessMode::X_OK)?;
             match name {
                 "." => {}
                 ".." => {
-                    let real_parent = real_dentry_up(parent.clone());
-                    if let Some(p) = real_parent.parent() {
+                    if let Some((p, c, f)) = walked.pop() {
                         parent = p;
+                        cross = c;
+                        mnt_flags = f;
+                    } else if flags.contains(VfsResolveFlags::RESOLVE_BENEATH) {
+                        return Err(VfsError::CrossDevice);
+                    } else if flags.contains(VfsResolveFlags::RESOLVE_IN_ROOT) {
+                        // ".." of the root is the root itself
+                    } else {
+                        let real_parent = real_dentry_up(parent.clone());
+                        if let Some(p) = real_parent.parent() {
+                            mnt_flags = mount_flags_in(ns, &p);
+                            parent = p;
+                        }
                     }
                 }
                 _ => {
-                    // resolve mount point
-                    let dentry = real_dentry_down(parent.clone());
                     // first, we find in dentry cache
-                    let sub_dentry = dentry.find(name);
-                    if sub_dentry.is_none() {
+                    let sub_dentry = match &self.dcache {
+                        Some(dcache) => match dcache.lookup(&parent, name) {
+                            VfsDentryLookup::Found(dentry) => Some(dentry),
+                            VfsDentryLookup::Negative => return Err(VfsError::NoEntry),
+                            VfsDentryLookup::Miss => None,
+                        },
+                        None => parent.find(name),
+                    };
+                    let sub_dentry = if sub_dentry.is_none() {
+                        if flags.contains(VfsResolveFlags::RESOLVE_CACHED) {
+                            return Err(VfsError::EAGAIN);
+                        }
                         // second, we find in inode cache or disk
-                        let parent_inode = dentry.inode()?;
-                        let sub_inode = parent_inode.lookup(name)?;
+                        let sub_inode = match parent_inode.lookup(name) {
+                            Err(VfsError::NoEntry) => {
+                                self.dcache_insert(&parent, name, None);
+                                return Err(VfsError::NoEntry);
+                            }
+                            res => res?,
+                        };
                         // if we find the inode, we insert it into dentry cache
-                        let sub_dentry = dentry.insert(name, sub_inode)?;
-                        parent = sub_dentry;
+                        let dentry = parent.i_insert(name, sub_inode)?;
+                        self.dcache_insert(&parent, name, Some(&dentry));
+                        dentry
                     } else {
-                        parent = sub_dentry.unwrap();
+                        sub_dentry.unwrap()
+                    };
+                    let is_link = sub_dentry.inode()?.inode_type() == VfsNodeType::SymLink;
+                    // a trailing slash means the last component is a directory
+                    if is_link && (follow || next.is_some()) {
+                        hops += 1;
+                        if hops > MAX_SYMLINK_FOLLOW
+                            || flags.contains(VfsResolveFlags::RESOLVE_NO_SYMLINKS)
+                            || mnt_flags.contains(VfsMountFlags::MS_NOSYMFOLLOW)
+                        {
+                            return Err(VfsError::SymLinkLoop);
+                        }
+                        let target = read_link(&sub_dentry)?;
+                        trace!("[resolve] follow symlink {} -> {}", name, target);
+                        if target.is_empty() {
+                            return Err(VfsError::NoEntry);
+                        }
+                        if target.starts_with('/') {
+                            if flags.contains(VfsResolveFlags::RESOLVE_BENEATH) {
+                                return Err(VfsError::CrossDevice);
+                            }
+                            if flags.contains(VfsResolveFlags::RESOLVE_IN_ROOT) {
+                                cross = base_cross;
+                                mnt_flags = base_flags;
+                                parent = base.clone();
+                            } else {
+                                cross = true;
+                                mnt_flags = mount_flags_in(ns, &self.root);
+                                parent =
+                                    enter_mount(ns, self.root.clone(), &mut cross, &mut mnt_flags);
+                            }
+                            if cross_device(&parent) {
+                                return Err(VfsError::CrossDevice);
+                            }
+                            walked.clear();
+                        }
+                        path = match next {
+                            Some(next) => format!("{}/{}", target, &path[next..]),
+                            None => target,
+                        };
+                        start = 0;
+                        continue;
                     }
+                    walked.push((parent, cross, mnt_flags));
+                    // resolve mount point
+                    parent = enter_mount(ns, sub_dentry, &mut cross, &mut mnt_flags);
                 }
             }
-            if rest.is_none() {
-                break;
+            if cross_device(&parent) {
+                return Err(VfsError::CrossDevice);
+            }
+            match next {
+                Some(next) => start = next,
+                None => break,
             }
-            path = rest.unwrap();
         }
-        // resolve mount point
-        let dentry = real_dentry_down(parent);
-        Ok(dentry)
+        Ok((parent, mnt_flags))
     }
     pub fn filename(&self) -> String {
         let index = self.path.rfind('/').map(|x| x + 1).unwrap_or(0);
@@ -302,6 +811,78 @@ impl VfsPath {
 
     // todo! permission check
     pub fn mount(&self, root: Arc<dyn VfsDentry>, mount_flag: u32) -> VfsResult<()> {
+        self.check_unshared()?;
+        let dir = self.mount_target(&root)?;
+        root.set_parent(&dir);
+        dir.clone().to_mount_point(root, mount_flag)?;
+        self.notify_dentry(&dir, VfsNotifyMask::IN_MOUNT);
+        Ok(())
+    }
+
+    /// Bind the file or directory `source` on this path, like `MS_BIND`
+    ///
+    /// The bound subtree shares the dentries with `source`, so the mounts below `source`,
+    /// including the ones created later, are visible on this path if `flags` contains
+    /// `MS_REC` and hidden otherwise. The other `flags` apply to the files reached through
+    /// this path, e.g. `MS_RDONLY` makes them read-only here but not below `source`.
+    ///
+    /// `source` can't be bound on itself or below itself, [`VfsError::Invalid`] is returned.
+    pub fn bind_mount(&self, source: &VfsPath, flags: VfsMountFlags) -> VfsResult<()> {
+        self.check_unshared()?;
+        let (src, dir) = self.bind_target(source)?;
+        dir.clone()
+            .to_mount_point(src, (flags | VfsMountFlags::MS_BIND).bits())?;
+        self.notify_dentry(&dir, VfsNotifyMask::IN_MOUNT);
+        Ok(())
+    }
+
+    /// Move the mount on this path to `dest`, like `MS_MOVE`
+    ///
+    /// The events of unmounting it from this path and mounting it on `dest` are published.
+    pub fn move_mount(&self, dest: &VfsPath) -> VfsResult<()> {
+        self.check_unshared()?;
+        let (point, dir) = self.move_target(dest)?;
+        let mnt = point.mount_point().unwrap();
+        point.clear_mount_point();
+        self.notify_umount(&mnt);
+        move_root(&mnt.root, &point, &dir);
+        dir.clone().to_mount_point(mnt.root, mnt.mnt_flags)?;
+        dest.notify_dentry(&dir, VfsNotifyMask::IN_MOUNT);
+        Ok(())
+    }
+
+    // todo! check much things
+    pub fn umount(&self) -> VfsResult<()> {
+        self.check_unshared()?;
+        let dir = self.mount_point()?;
+        let mnt = dir.mount_point().unwrap();
+        dir.clear_mount_point();
+        self.notify_umount(&mnt);
+        self.sync_fs(&mnt.root)
+    }
+
+    /// Sync the file system of `root` after it is unmounted, through the page cache if the
+    /// path has one
+    pub(crate) fn sync_fs(&self, root: &Arc<dyn VfsDentry>) -> VfsResult<()> {
+        let sb = root.inode()?.get_super_block()?;
+        match &self.page_cache {
+            Some(cache) => cache.sync_fs(&sb, false),
+            None => sb.sync_fs(false),
+        }
+    }
+
+    /// Fail with [`VfsError::Invalid`] if the path is bound to a namespace, the mounts of a
+    /// namespace are changed by its [`MountTable`](crate::mount::MountTable)
+    fn check_unshared(&self) -> VfsResult<()> {
+        match self.namespace {
+            Some(_) => Err(VfsError::Invalid),
+            None => Ok(()),
+        }
+    }
+
+    /// Check whether `root` can be mounted on this path, and get the directory it is
+    /// mounted on
+    pub(crate) fn mount_target(&self, root: &Arc<dyn VfsDentry>) -> VfsResult<Arc<dyn VfsDentry>> {
         assert!(root.parent().is_none());
         let dir = self.open(None)?;
         let inode = dir.inode()?;
@@ -314,56 +895,298 @@ impl VfsPath {
         if inode.inode_type() != VfsNodeType::Dir {
             return Err(VfsError::NotDir);
         }
-        root.set_parent(&dir);
-        dir.to_mount_point(root, mount_flag)?;
-        Ok(())
+        Ok(dir)
     }
 
-    // todo! check much things
-    pub fn umount(&self) -> VfsResult<()> {
+    /// Check whether `source` can be bound on this path, and get the dentries of both
+    pub(crate) fn bind_target(
+        &self,
+        source: &VfsPath,
+    ) -> VfsResult<(Arc<dyn VfsDentry>, Arc<dyn VfsDentry>)> {
+        let src = source.open(None)?;
         let dir = self.open(None)?;
-        if !dir.is_mount_point() {
+        check_mount_type(&src, &dir)?;
+        if is_ancestor(&src, &dir) {
             return Err(VfsError::Invalid);
         }
-        let mnt = dir.mount_point().unwrap();
-        dir.clear_mount_point();
-        mnt.root.inode()?.get_super_block()?.sync_fs(false)?;
+        Ok((src, dir))
+    }
+
+    /// Check whether the mount on this path can be moved to `dest`, and get the dentry it
+    /// covers and the dentry it is moved on
+    pub(crate) fn move_target(
+        &self,
+        dest: &VfsPath,
+    ) -> VfsResult<(Arc<dyn VfsDentry>, Arc<dyn VfsDentry>)> {
+        let point = self.mount_point()?;
+        let mnt = self.mounted(&point).unwrap();
+        let dir = dest.open(None)?;
+        check_mount_type(&mnt.root, &dir)?;
+        // the mount can't be moved below itself
+        if is_ancestor(&mnt.root, &dir) {
+            return Err(VfsError::Invalid);
+        }
+        Ok((point, dir))
+    }
+
+    /// Get the topmost mount on `dentry` in the namespace of the path
+    pub(crate) fn mounted(&self, dentry: &Arc<dyn VfsDentry>) -> Option<VfsMountPoint> {
+        mounted(self.namespace.as_deref(), dentry)
+    }
+
+    /// Get the dentry covered by the topmost mount on this path
+    ///
+    /// [`VfsPath::open`] resolves the mount points, so we find the dentry in the parent
+    /// directory instead.
+    pub(crate) fn mount_point(&self) -> VfsResult<Arc<dyn VfsDentry>> {
+        let name = self.filename();
+        if matches!(name.as_str(), "" | "." | "..") {
+            return Err(VfsError::Invalid);
+        }
+        // make sure the dentry is in the cache
+        self.exists()?;
+        let parent = self.parent().exists()?;
+        let mut dir = parent.find(&name).ok_or(VfsError::Invalid)?;
+        loop {
+            let mnt = self.mounted(&dir).ok_or(VfsError::Invalid)?;
+            if self.mounted(&mnt.root).is_none() {
+                return Ok(dir);
+            }
+            dir = mnt.root;
+        }
+    }
+
+    /// Check whether the caller can access the file with `mode`
+    ///
+    /// This is used by the access(2) system call.
+    pub fn access(&self, mode: VfsAccessMode) -> VfsResult<()> {
+        let (dt, mnt_flags) = self.open_mnt()?;
+        let inode = dt.inode()?;
+        if mode.contains(VfsAccessMode::W_OK) {
+            check_writable(mnt_flags)?;
+        }
+        if mode.contains(VfsAccessMode::X_OK)
+            && !inode.inode_type().is_dir()
+            && mnt_flags.contains(VfsMountFlags::MS_NOEXEC)
+        {
+            return Err(VfsError::Access);
+        }
+        check_access(&inode, self.cred(), mode)
+    }
+
+    /// Get the extended attributes of the file with the fields in `mask`, following the last
+    /// symlink if `follow` is true
+    ///
+    /// This is used by the statx(2) system call. [`VfsStatxAttr::STATX_ATTR_MOUNT_ROOT`] is
+    /// set by the VFS, the mount id is only known by [`crate::mount::MountTable::statx`].
+    pub fn statx(&self, mask: VfsStatxMask, follow: bool) -> VfsResult<VfsStatx> {
+        let dt = if follow {
+            self.open(None)?
+        } else {
+            self.exists()?
+        };
+        dentry_statx(self.namespace.as_deref(), &dt, mask)
+    }
+
+    /// Update the access time of the file after it has been read, like [`update_atime`]
+    /// with the flags of the mount the file is reached through
+    pub fn update_atime(&self, now: VfsTimeSpec) -> VfsResult<()> {
+        let (dt, mnt_flags) = self.open_mnt()?;
+        touch_atime(&dt.inode()?, mnt_flags, now)
+    }
+
+    /// Change the permission bits of the file
+    ///
+    /// Only the owner of the file or the superuser can change the mode. The set-group-ID
+    /// bit is cleared if the caller is not in the group of the file. Immutable and
+    /// append-only files can't be changed.
+    pub fn chmod(&self, mode: VfsInodeMode) -> VfsResult<()> {
+        let (dt, mnt_flags) = self.open_mnt()?;
+        check_writable(mnt_flags)?;
+        let inode = dt.inode()?;
+        check_modify(&inode, false)?;
+        let cred = self.cred();
+        let (uid, gid, _) = inode_owner(&inode);
+        let mut mode = mode & !VfsInodeMode::TYPE_MASK;
+        if !cred.is_root() {
+            if cred.uid != uid {
+                return Err(VfsError::PermissionDenied);
+            }
+            if cred.gid != gid {
+                mode.remove(VfsInodeMode::SET_GID);
+            }
+        }
+        inode.set_attr(InodeAttr {
+            valid: InodeAttrValid::MODE,
+            mode: mode.bits(),
+            ..Default::default()
+        })?;
+        self.notify_dentry(&dt, VfsNotifyMask::IN_ATTRIB);
+        Ok(())
+    }
+
+    /// Change the owner and group of the file, `None` means unchanged
+    ///
+    /// Only the superuser can change the owner. The owner of the file can change the group
+    /// to its own group. Immutable and append-only files can't be changed.
+    pub fn chown(&self, uid: Option<u32>, gid: Option<u32>) -> VfsResult<()> {
+        let (dt, mnt_flags) = self.open_mnt()?;
+        check_writable(mnt_flags)?;
+        let inode = dt.inode()?;
+        check_modify(&inode, false)?;
+        let cred = self.cred();
+        if !cred.is_root() {
+            let (owner, group, _) = inode_owner(&inode);
+            let uid_changed = uid.is_some_and(|uid| uid as u64 != owner);
+            let bad_gid = gid.is_some_and(|gid| gid as u64 != group && gid as u64 != cred.gid);
+            if cred.uid != owner || uid_changed || bad_gid {
+                return Err(VfsError::PermissionDenied);
+            }
+        }
+        let mut attr = InodeAttr::default();
+        if let Some(uid) = uid {
+            attr.valid |= InodeAttrValid::UID;
+            attr.uid = uid;
+        }
+        if let Some(gid) = gid {
+            attr.valid |= InodeAttrValid::GID;
+            attr.gid = gid;
+        }
+        if attr.valid.is_empty() {
+            return Ok(());
+        }
+        inode.set_attr(attr)?;
+        self.notify_dentry(&dt, VfsNotifyMask::IN_ATTRIB);
+        Ok(())
+    }
+
+    /// Set the access and modification times of the file, following the last symlink if
+    /// `follow` is true
+    ///
+    /// This is used by the utimensat(2) system call, [`VfsUtime::Now`] is replaced by `now`
+    /// and the ctime is set to `now`. Setting explicit times needs the ownership of the file,
+    /// setting the current time only needs the write permission. Immutable files can't be
+    /// changed, and only the current time can be set on append-only files. An explicit time
+    /// with `nsec` out of `0..1_000_000_000` fails with `Invalid`.
+    pub fn set_times(
+        &self,
+        atime: VfsUtime,
+        mtime: VfsUtime,
+        now: VfsTimeSpec,
+        follow: bool,
+    ) -> VfsResult<()> {
+        let valid = |time: VfsUtime| match time {
+            VfsUtime::Time(time) => time.nsec < NSEC_PER_SEC,
+            _ => true,
+        };
+        if !valid(atime) || !valid(mtime) {
+            return Err(VfsError::Invalid);
+        }
+        let (dt, mnt_flags) = if follow {
+            self.open_mnt()?
+        } else {
+            self.resolve(false)?
+        };
+        if atime == VfsUtime::Omit && mtime == VfsUtime::Omit {
+            return Ok(());
+        }
+        check_writable(mnt_flags)?;
+        let inode = dt.inode()?;
+        let explicit = matches!(atime, VfsUtime::Time(_)) || matches!(mtime, VfsUtime::Time(_));
+        let flags = inode_flags(&inode);
+        if flags.contains(VfsStatxAttr::STATX_ATTR_IMMUTABLE)
+            || (explicit && flags.contains(VfsStatxAttr::STATX_ATTR_APPEND))
+        {
+            return Err(VfsError::PermissionDenied);
+        }
+        let cred = self.cred();
+        let (owner, _, _) = inode_owner(&inode);
+        if !cred.is_root() && cred.uid != owner {
+            if explicit {
+                return Err(VfsError::PermissionDenied);
+            }
+            check_access(&inode, cred, VfsAccessMode::W_OK)?;
+        }
+        let time = |time: VfsUtime| match time {
+            VfsUtime::Now => Some(now),
+            VfsUtime::Omit => None,
+            VfsUtime::Time(time) => Some(time),
+        };
+        inode.set_times(time(atime), time(mtime), now)?;
+        self.notify_dentry(&dt, VfsNotifyMask::IN_ATTRIB);
         Ok(())
     }
 
     pub fn truncate(&self, len: u64) -> VfsResult<()> {
-        let dt = self.open(None).expect("truncate open failed");
+        let (dt, mnt_flags) = self.open_mnt()?;
         let inode = dt.inode()?;
         if inode.inode_type() == VfsNodeType::Dir {
             return Err(VfsError::IsDir);
         }
-        // let fs = inode.get_super_block()?;
-        // todo! access check
-        if inode.node_perm().contains(VfsNodePerm::OTHER_WRITE) {
-            return Err(VfsError::Access);
-        }
+        check_writable(mnt_flags)?;
+        check_access(&inode, self.cred(), VfsAccessMode::W_OK)?;
+        check_modify(&inode, false)?;
         inode.truncate(len)?;
+        self.notify_dentry(&dt, VfsNotifyMask::IN_MODIFY);
         Ok(())
     }
 
     pub fn symlink(&self, target: &str) -> VfsResult<()> {
+        if target.len() >= PATH_MAX {
+            return Err(VfsError::NameTooLong);
+        }
         let this = self.open(None);
         match this {
             Ok(_) => Err(VfsError::EExist),
             Err(e) => match e {
                 VfsError::NoEntry => {
-                    let parent = self.get_parent("create symlink")?;
+                    let (parent, mnt_flags) = self.get_parent("create symlink")?;
+                    check_writable(mnt_flags)?;
                     let parent_inode = parent.inode()?;
+                    check_dir_write(&parent_inode, self.cred())?;
                     let name = self.filename();
                     assert!(!name.is_empty());
                     let inode = parent_inode.symlink(&name, target)?;
-                    let _ = parent.insert(&name, inode)?;
+                    self.dcache_invalidate(&parent, &name);
+                    set_owner(&inode, self.cred())?;
+                    let dentry = parent.clone().insert(&name, inode.clone())?;
+                    self.dcache_insert(&parent, &name, Some(&dentry));
+                    self.notify_child(&parent, &name, &inode, VfsNotifyMask::IN_CREATE, 0);
                     Ok(())
                 }
                 _ => Err(e),
             },
         }
     }
+    /// Create a socket node at the path and bind `socket` to it, called by bind(2) of unix
+    /// domain sockets
+    ///
+    /// Returns `EExist` if the path exists, the kernel should report it as `EADDRINUSE`.
+    pub fn bind_socket(
+        &self,
+        socket: &Arc<dyn Any + Send + Sync>,
+        perm: VfsNodePerm,
+    ) -> VfsResult<Arc<dyn VfsDentry>> {
+        let dentry = self.create(VfsNodeType::Socket, perm, "bind socket")?;
+        if let Err(e) = dentry.inode()?.bind_socket(Arc::downgrade(socket)) {
+            let _ = self.unlink();
+            return Err(e);
+        }
+        Ok(dentry)
+    }
+    /// Find the socket bound to the path, called by connect(2) of unix domain sockets
+    ///
+    /// The caller needs the write permission of the socket node. Returns `ECONNREFUSED` if the
+    /// path is not a socket or no live socket is bound to it.
+    pub fn resolve_socket(&self) -> VfsResult<Arc<dyn Any + Send + Sync>> {
+        let dentry = self.open(None)?;
+        let inode = dentry.inode()?;
+        if inode.inode_type() != VfsNodeType::Socket {
+            return Err(VfsError::ECONNREFUSED);
+        }
+        check_access(&inode, self.cred(), VfsAccessMode::W_OK)?;
+        inode.get_socket()
+    }
     pub fn link(&self, old_dt: Arc<dyn VfsDentry>) -> VfsResult<()> {
         if old_dt.inode()?.inode_type() == VfsNodeType::Dir {
             return Err(VfsError::PermissionDenied);
@@ -373,7 +1196,8 @@ impl VfsPath {
             Ok(_) => Err(VfsError::EExist),
             Err(e) => match e {
                 VfsError::NoEntry => {
-                    let parent = self.get_parent("create hard link")?;
+                    let (parent, mnt_flags) = self.get_parent("create hard link")?;
+                    check_writable(mnt_flags)?;
                     let parent_inode = parent.inode()?;
 
                     let old_fs = old_dt.inode()?.get_super_block()?;
@@ -381,11 +1205,18 @@ impl VfsPath {
                     if !Arc::ptr_eq(&old_fs, &this_fs) {
                         return Err(VfsError::Invalid);
                     }
-                    // todo! access check
+                    check_dir_write(&parent_inode, self.cred())?;
+                    check_modify(&old_dt.inode()?, false)?;
                     let name = self.filename();
                     assert!(!name.is_empty());
                     let inode = parent_inode.link(&name, old_dt.inode()?)?;
-                    let _ = parent.insert(&name, inode)?;
+                    self.dcache_invalidate(&parent, &name);
+                    let dentry = parent.clone().insert(&name, inode.clone())?;
+                    self.dcache_insert(&parent, &name, Some(&dentry));
+                    if let Some(notifier) = &self.notifier {
+                        notifier.notify(&inode, VfsNotifyMask::IN_ATTRIB, 0, None);
+                    }
+                    self.notify_child(&parent, &name, &inode, VfsNotifyMask::IN_CREATE, 0);
                     Ok(())
                 }
                 _ => Err(e),
@@ -399,22 +1230,19 @@ impl VfsPath {
         if inode.inode_type() != VfsNodeType::Dir {
             return Err(VfsError::NotDir);
         }
-        let parent = self.get_parent("rmdir")?;
+        let (parent, mnt_flags) = self.get_parent("rmdir")?;
+        check_writable(mnt_flags)?;
         let parent_inode = parent.inode()?;
-        let perm = parent_inode.node_perm();
-
-        if !perm.contains(VfsNodePerm::OTHER_WRITE)
-            && !perm.contains(VfsNodePerm::GROUP_WRITE)
-            && !perm.contains(VfsNodePerm::OWNER_WRITE)
-        {
-            return Err(VfsError::Access);
-        }
+        check_dir_write(&parent_inode, self.cred())?;
+        check_delete(&parent_inode, &inode, self.cred())?;
         let name = self.filename();
         assert!(!name.is_empty());
-        // todo! access check
         parent_inode.rmdir(&name)?;
         // remove the dentry from cache
         parent.remove(&name);
+        self.dcache_insert(&parent, &name, None);
+        self.notify_child(&parent, &name, &inode, VfsNotifyMask::IN_DELETE, 0);
+        self.notify_unlinked(&inode);
         Ok(())
     }
 
@@ -424,24 +1252,20 @@ impl VfsPath {
         if inode.inode_type() == VfsNodeType::Dir {
             return Err(VfsError::IsDir);
         }
-        let parent = self.get_parent("unlink")?;
+        let (parent, mnt_flags) = self.get_parent("unlink")?;
+        check_writable(mnt_flags)?;
         let parent_inode = parent.inode()?;
-
-        let perm = parent_inode.node_perm();
-
-        if !perm.contains(VfsNodePerm::OTHER_WRITE)
-            && !perm.contains(VfsNodePerm::GROUP_WRITE)
-            && !perm.contains(VfsNodePerm::OWNER_WRITE)
-        {
-            return Err(VfsError::Access);
-        }
+        check_dir_write(&parent_inode, self.cred())?;
+        check_delete(&parent_inode, &inode, self.cred())?;
         let name = self.filename();
         assert!(!name.is_empty());
-        // todo! access check
         parent_inode.unlink(&name)?;
 
         // remove the dentry from cache
         parent.remove(&name);
+        self.dcache_insert(&parent, &name, None);
+        self.notify_child(&parent, &name, &inode, VfsNotifyMask::IN_DELETE, 0);
+        self.notify_unlinked(&inode);
         Ok(())
     }
 
@@ -451,6 +1275,7 @@ impl VfsPath {
         new_vfs_path: VfsPath,
         flag: VfsRenameFlag,
     ) -> VfsResult<()> {
+        let cred = context.cred();
         let old_dt = self.open(None)?;
         checkout_busy(&old_dt, &context)?;
         let new_dt = new_vfs_path.open(None);
@@ -463,16 +1288,16 @@ impl VfsPath {
             if flag.contains(VfsRenameFlag::RENAME_EXCHANGE) {
                 return Err(VfsError::NoEntry);
             }
-            let new_parent = new_vfs_path
-                .get_parent("rename")
-                .expect("get parent of new path failed, this should not happen");
-            let old_parent = self
+            let (new_parent, new_flags) = new_vfs_path
                 .get_parent("rename")
-                .expect("get parent of old path failed, this should not happen");
+                .map_err(|_| VfsError::NoEntry)?;
+            let (old_parent, old_flags) =
+                self.get_parent("rename").map_err(|_| VfsError::NoEntry)?;
 
             check_same_fs(&new_parent, &old_parent)?;
-            checkout_write_perm(&new_parent)?;
-            checkout_write_perm(&old_parent)?;
+            check_writable(old_flags)?;
+            check_writable(new_flags)?;
+            check_rename_perm(&old_parent, &old_dt, &new_parent, None, cred)?;
 
             let old_parent_inode = old_parent.inode()?;
 
@@ -482,11 +1307,23 @@ impl VfsPath {
                 new_vfs_path.filename().as_str(),
                 flag,
             )?;
+            self.dcache_invalidate(&new_parent, &new_vfs_path.filename());
 
             // remove the dentry from cache
             old_parent.remove(self.filename().as_str());
             // insert the dentry into cache
-            new_parent.insert(new_vfs_path.filename().as_str(), old_dt.inode()?)?;
+            let moved = new_parent
+                .clone()
+                .insert(new_vfs_path.filename().as_str(), old_dt.inode()?)?;
+            self.dcache_insert(&old_parent, &self.filename(), None);
+            self.dcache_insert(&new_parent, &new_vfs_path.filename(), Some(&moved));
+            self.notify_move(
+                &old_parent,
+                &self.filename(),
+                &new_parent,
+                &new_vfs_path.filename(),
+                &old_dt.inode()?,
+            );
         } else {
             let new_dt = new_dt.unwrap();
             checkout_busy(&new_dt, &context)?;
@@ -499,16 +1336,16 @@ impl VfsPath {
             {
                 return Err(VfsError::IsDir);
             }
-            let new_parent = new_vfs_path
-                .get_parent("rename")
-                .expect("get parent of new path failed, this should not happen");
-            let old_parent = self
+            let (new_parent, new_flags) = new_vfs_path
                 .get_parent("rename")
-                .expect("get parent of old path failed, this should not happen");
+                .map_err(|_| VfsError::NoEntry)?;
+            let (old_parent, old_flags) =
+                self.get_parent("rename").map_err(|_| VfsError::NoEntry)?;
 
             check_same_fs(&new_parent, &old_parent)?;
-            checkout_write_perm(&new_parent)?;
-            checkout_write_perm(&old_parent)?;
+            check_writable(old_flags)?;
+            check_writable(new_flags)?;
+            check_rename_perm(&old_parent, &old_dt, &new_parent, Some(&new_dt), cred)?;
             let old_parent_inode = old_parent.inode()?;
             let new_parent_inode = new_parent.inode()?;
             old_parent_inode.rename_to(
@@ -521,33 +1358,375 @@ impl VfsPath {
             old_parent.remove(self.filename().as_str());
             new_parent.remove(new_vfs_path.filename().as_str());
             // insert the dentry into cache
-            new_parent.insert(new_vfs_path.filename().as_str(), old_dt.inode()?)?;
+            let moved = new_parent
+                .clone()
+                .insert(new_vfs_path.filename().as_str(), old_dt.inode()?)?;
+            self.dcache_insert(&new_parent, &new_vfs_path.filename(), Some(&moved));
             if flag.contains(VfsRenameFlag::RENAME_EXCHANGE) {
                 // insert the dentry into cache
-                old_parent.insert(self.filename().as_str(), new_dt.inode()?)?;
+                let exchanged = old_parent
+                    .clone()
+                    .insert(self.filename().as_str(), new_dt.inode()?)?;
+                self.dcache_insert(&old_parent, &self.filename(), Some(&exchanged));
+            } else {
+                self.dcache_insert(&old_parent, &self.filename(), None);
             } // 只有在交换的时候才需要插入,否则新的文件已经被覆盖掉了
+            self.notify_move(
+                &old_parent,
+                &self.filename(),
+                &new_parent,
+                &new_vfs_path.filename(),
+                &old_dt.inode()?,
+            );
+            if flag.contains(VfsRenameFlag::RENAME_EXCHANGE) {
+                self.notify_move(
+                    &new_parent,
+                    &new_vfs_path.filename(),
+                    &old_parent,
+                    &self.filename(),
+                    &new_dt.inode()?,
+                );
+            } else {
+                self.notify_unlinked(&new_dt.inode()?);
+            }
         }
         Ok(())
     }
-    pub fn set_xattr(&self, _key: &str, _value: &[u8]) -> VfsResult<()> {
-        unimplemented!();
+    pub fn set_xattr(&self, key: &str, value: &[u8], flag: VfsXattrFlag) -> VfsResult<()> {
+        check_xattr_key(key)?;
+        if flag.contains(VfsXattrFlag::XATTR_CREATE | VfsXattrFlag::XATTR_REPLACE) {
+            return Err(VfsError::Invalid);
+        }
+        let (dt, mnt_flags) = self.open_mnt()?;
+        check_writable(mnt_flags)?;
+        let inode = dt.inode()?;
+        check_xattr_change(&inode, self.cred())?;
+        inode.set_xattr(key, value, flag)?;
+        self.notify_dentry(&dt, VfsNotifyMask::IN_ATTRIB);
+        Ok(())
+    }
+    pub fn get_xattr(&self, key: &str) -> VfsResult<Vec<u8>> {
+        check_xattr_key(key)?;
+        let dt = self.open(None)?;
+        dt.inode()?.get_xattr(key)
+    }
+    pub fn remove_xattr(&self, key: &str) -> VfsResult<()> {
+        check_xattr_key(key)?;
+        let (dt, mnt_flags) = self.open_mnt()?;
+        check_writable(mnt_flags)?;
+        let inode = dt.inode()?;
+        check_xattr_change(&inode, self.cred())?;
+        inode.remove_xattr(key)?;
+        self.notify_dentry(&dt, VfsNotifyMask::IN_ATTRIB);
+        Ok(())
+    }
+    pub fn list_xattr(&self) -> VfsResult<Vec<String>> {
+        let dt = self.open(None)?;
+        dt.inode()?.list_xattr()
+    }
+}
+
+/// Check whether the caller can change the extended attributes of `inode`
+///
+/// Only the owner of the file or the superuser can change them, and not on immutable or
+/// append-only files.
+fn check_xattr_change(inode: &Arc<dyn VfsInode>, cred: VfsCred) -> VfsResult<()> {
+    check_modify(inode, false)?;
+    let (owner, _, _) = inode_owner(inode);
+    if !cred.is_root() && cred.uid != owner {
+        return Err(VfsError::PermissionDenied);
+    }
+    Ok(())
+}
+
+/// The max length of the name of an extended attribute
+const XATTR_NAME_MAX: usize = 255;
+
+/// Check whether the name of an extended attribute is valid
+fn check_xattr_key(key: &str) -> VfsResult<()> {
+    if key.is_empty() {
+        return Err(VfsError::Invalid);
     }
-    pub fn get_xattr(&self, _key: &str) -> VfsResult<Vec<u8>> {
-        unimplemented!();
+    if key.len() > XATTR_NAME_MAX {
+        return Err(VfsError::NameTooLong);
     }
+    Ok(())
+}
+
+/// Get the flags of the mount which `dentry` belongs to
+///
+/// The root of a mounted file system has the dentry it is mounted on as parent, so we
+/// walk up until we find it. The root file system has no mount point and no flags.
+///
+/// The dentries below a bind mount are shared with its source, so this gets the flags of
+/// the mount of the source, [`VfsPath`] checks the flags of the mount it walks through.
+pub fn mount_flags(dentry: &Arc<dyn VfsDentry>) -> VfsMountFlags {
+    mount_flags_in(None, dentry)
 }
 
-/// Check whether the dentry has write permission
-fn checkout_write_perm(dentry: &Arc<dyn VfsDentry>) -> VfsResult<()> {
-    let perm = dentry.inode()?.node_perm();
-    if !perm.contains(VfsNodePerm::OTHER_WRITE)
-        && !perm.contains(VfsNodePerm::GROUP_WRITE)
-        && !perm.contains(VfsNodePerm::OWNER_WRITE)
+/// Get the flags of the mount containing `dentry` in `ns`, like [`mount_flags`]
+fn mount_flags_in(ns: Option<&dyn VfsNamespace>, dentry: &Arc<dyn VfsDentry>) -> VfsMountFlags {
+    mount_root(ns, dentry)
+        .parent()
+        .and_then(|parent| mounted(ns, &parent))
+        .map_or(VfsMountFlags::empty(), |mnt| {
+            VfsMountFlags::from_bits_truncate(mnt.mnt_flags)
+        })
+}
+
+/// Get the topmost mount on `dentry` in `ns`, without a namespace the mount attached to
+/// the dentry
+fn mounted(ns: Option<&dyn VfsNamespace>, dentry: &Arc<dyn VfsDentry>) -> Option<VfsMountPoint> {
+    match ns {
+        Some(ns) => ns.mounted(dentry),
+        None => dentry.mount_point(),
+    }
+}
+
+/// Find the root of the mount containing `dentry` in `ns`
+fn mount_root(ns: Option<&dyn VfsNamespace>, dentry: &Arc<dyn VfsDentry>) -> Arc<dyn VfsDentry> {
+    let mut dentry = dentry.clone();
+    while let Some(parent) = dentry.parent() {
+        if let Some(mnt) = mounted(ns, &parent) {
+            if Arc::ptr_eq(&mnt.root, &dentry) {
+                break;
+            }
+        }
+        dentry = parent;
+    }
+    dentry
+}
+
+/// Get the extended attributes of `dentry` with the fields in `mask`, the mount roots are
+/// found in `ns`
+pub(crate) fn dentry_statx(
+    ns: Option<&dyn VfsNamespace>,
+    dentry: &Arc<dyn VfsDentry>,
+    mask: VfsStatxMask,
+) -> VfsResult<VfsStatx> {
+    let mut stx = dentry.inode()?.get_statx(mask)?;
+    stx.stx_attributes_mask |= VfsStatxAttr::STATX_ATTR_MOUNT_ROOT;
+    if Arc::ptr_eq(&mount_root(ns, dentry), dentry) {
+        stx.stx_attributes |= VfsStatxAttr::STATX_ATTR_MOUNT_ROOT;
+    }
+    Ok(stx)
+}
+
+/// Check whether the files on a mount with `flags` can be modified
+fn check_writable(flags: VfsMountFlags) -> VfsResult<()> {
+    if flags.contains(VfsMountFlags::MS_RDONLY) {
+        Err(VfsError::ReadOnlyFs)
+    } else {
+        Ok(())
+    }
+}
+
+/// The age of the access time after which it is updated on a `MS_RELATIME` mount
+pub const RELATIME_INTERVAL: u64 = 24 * 60 * 60;
+
+/// Update the access time of the file after it has been read
+///
+/// Nothing is done if the file is on a read-only or `MS_NOATIME` mount, or it is a
+/// directory on a `MS_NODIRATIME` mount. On a `MS_RELATIME` mount without
+/// `MS_STRICTATIME`, the access time is only updated if it is not newer than the
+/// modification or change time, or it is older than [`RELATIME_INTERVAL`].
+///
+/// The change time is kept by [`VfsInode::set_atime`], reading a file doesn't change its inode.
+pub fn update_atime(dentry: &Arc<dyn VfsDentry>, now: VfsTimeSpec) -> VfsResult<()> {
+    touch_atime(&dentry.inode()?, mount_flags(dentry), now)
+}
+
+/// Update the access time of `inode` on a mount with `flags`, see [`update_atime`]
+fn touch_atime(inode: &Arc<dyn VfsInode>, flags: VfsMountFlags, now: VfsTimeSpec) -> VfsResult<()> {
+    if flags.intersects(VfsMountFlags::MS_RDONLY | VfsMountFlags::MS_NOATIME)
+        || (inode.inode_type().is_dir() && flags.contains(VfsMountFlags::MS_NODEIRATIME))
     {
-        Err(VfsError::Access)
+        return Ok(());
+    }
+    let stat = inode.get_attr()?;
+    let relatime = flags.contains(VfsMountFlags::MS_RELATIME)
+        && !flags.contains(VfsMountFlags::MS_STRICTATIME);
+    let atime = stat.st_atime;
+    if relatime
+        && atime > stat.st_mtime
+        && atime > stat.st_ctime
+        && now.sec < atime.sec + RELATIME_INTERVAL
+    {
+        return Ok(());
+    }
+    match inode.set_atime(now) {
+        // the file system doesn't record the access time
+        Err(VfsError::NoSys) => Ok(()),
+        res => res,
+    }
+}
+
+/// Make the caller the owner of the newly created inode
+///
+/// The inodes are created with root as owner, file systems which don't support
+/// changing the owner are ignored.
+fn set_owner(inode: &Arc<dyn VfsInode>, cred: VfsCred) -> VfsResult<()> {
+    if cred.is_root() {
+        return Ok(());
+    }
+    let attr = InodeAttr {
+        valid: InodeAttrValid::UID | InodeAttrValid::GID,
+        uid: cred.uid as u32,
+        gid: cred.gid as u32,
+        ..Default::default()
+    };
+    match inode.set_attr(attr) {
+        Err(VfsError::NoSys) => Ok(()),
+        res => res,
+    }
+}
+
+/// Get the owner and the mode of the inode
+///
+/// If the inode doesn't support [`VfsInode::get_attr`], it is treated as owned by root.
+fn inode_owner(inode: &Arc<dyn VfsInode>) -> (u64, u64, u32) {
+    match inode.get_attr() {
+        Ok(stat) => (stat.st_uid as u64, stat.st_gid as u64, stat.st_mode),
+        Err(_) => (0, 0, inode.node_perm().bits() as u32),
+    }
+}
+
+/// Check whether the caller with `cred` is allowed to access the inode with `mode`
+///
+/// Only one of the owner/group/other permission classes is used, the first one that
+/// matches the caller. The superuser can always read and write, and can execute if
+/// the inode is a directory or any execute bit is set.
+pub fn check_access(
+    inode: &Arc<dyn VfsInode>,
+    cred: VfsCred,
+    mode: VfsAccessMode,
+) -> VfsResult<()> {
+    if mode.is_empty() {
+        return Ok(());
+    }
+    if cred.is_root() {
+        if !mode.contains(VfsAccessMode::X_OK) || inode.inode_type().is_dir() {
+            return Ok(());
+        }
+        let (_, _, st_mode) = inode_owner(inode);
+        return if st_mode & 0o111 != 0 {
+            Ok(())
+        } else {
+            Err(VfsError::Access)
+        };
+    }
+    let (uid, gid, st_mode) = inode_owner(inode);
+    let granted = if cred.uid == uid {
+        st_mode >> 6
+    } else if cred.gid == gid {
+        st_mode >> 3
     } else {
+        st_mode
+    } & 0o7;
+    if mode.bits() & !granted == 0 {
         Ok(())
+    } else {
+        Err(VfsError::Access)
+    }
+}
+
+/// Check whether the caller can add or remove entries in the directory
+fn check_dir_write(dir: &Arc<dyn VfsInode>, cred: VfsCred) -> VfsResult<()> {
+    check_access(dir, cred, VfsAccessMode::W_OK | VfsAccessMode::X_OK)?;
+    check_modify(dir, true)
+}
+
+/// The inode flags of `FS_IOC_SETFLAGS`, empty if the file system doesn't support them
+fn inode_flags(inode: &Arc<dyn VfsInode>) -> VfsStatxAttr {
+    inode
+        .get_statx(VfsStatxMask::empty())
+        .map_or(VfsStatxAttr::empty(), |stx| stx.stx_attributes)
+}
+
+/// Check whether the inode flags allow changing the file, `append` is true if the data is
+/// only appended to the file or the entries are only added to the directory
+///
+/// Immutable files can't be changed, and append-only files can only be appended to.
+pub fn check_modify(inode: &Arc<dyn VfsInode>, append: bool) -> VfsResult<()> {
+    let flags = inode_flags(inode);
+    if flags.contains(VfsStatxAttr::STATX_ATTR_IMMUTABLE)
+        || (!append && flags.contains(VfsStatxAttr::STATX_ATTR_APPEND))
+    {
+        return Err(VfsError::PermissionDenied);
+    }
+    Ok(())
+}
+
+/// Check whether `inode` can be removed from `dir` or replaced by a rename
+///
+/// Neither of them can be immutable or append-only, and the sticky bit of `dir` is checked.
+fn check_delete(
+    dir: &Arc<dyn VfsInode>,
+    inode: &Arc<dyn VfsInode>,
+    cred: VfsCred,
+) -> VfsResult<()> {
+    check_modify(dir, false)?;
+    check_modify(inode, false)?;
+    check_sticky(dir, inode, cred)
+}
+
+/// Check whether the caller can remove or rename `inode` in the directory with sticky bit
+///
+/// In a sticky directory, only the owner of the file, the owner of the directory or the
+/// superuser can remove or rename the file.
+fn check_sticky(
+    dir: &Arc<dyn VfsInode>,
+    inode: &Arc<dyn VfsInode>,
+    cred: VfsCred,
+) -> VfsResult<()> {
+    if cred.is_root() {
+        return Ok(());
+    }
+    let (dir_uid, _, dir_mode) = inode_owner(dir);
+    if dir_mode & VfsInodeMode::STICKY.bits() == 0 || cred.uid == dir_uid {
+        return Ok(());
+    }
+    let (uid, _, _) = inode_owner(inode);
+    if cred.uid == uid {
+        Ok(())
+    } else {
+        Err(VfsError::PermissionDenied)
+    }
+}
+
+/// Check whether the caller can move `old_dt` from `old_parent` to `new_parent`
+fn check_rename_perm(
+    old_parent: &Arc<dyn VfsDentry>,
+    old_dt: &Arc<dyn VfsDentry>,
+    new_parent: &Arc<dyn VfsDentry>,
+    new_dt: Option<&Arc<dyn VfsDentry>>,
+    cred: VfsCred,
+) -> VfsResult<()> {
+    let old_parent_inode = old_parent.inode()?;
+    let new_parent_inode = new_parent.inode()?;
+    let old_inode = old_dt.inode()?;
+    check_dir_write(&old_parent_inode, cred)?;
+    check_dir_write(&new_parent_inode, cred)?;
+    check_delete(&old_parent_inode, &old_inode, cred)?;
+    if let Some(new_dt) = new_dt {
+        check_delete(&new_parent_inode, &new_dt.inode()?, cred)?;
+    }
+    // moving a directory to another parent needs to update its ".." entry
+    if old_inode.inode_type().is_dir() && !Arc::ptr_eq(old_parent, new_parent) {
+        check_access(&old_inode, cred, VfsAccessMode::W_OK)?;
+    }
+    Ok(())
+}
+
+/// Read the target of the symlink
+fn read_link(dentry: &Arc<dyn VfsDentry>) -> VfsResult<String> {
+    let mut buf = vec![0; PATH_MAX];
+    let len = dentry.inode()?.readlink(&mut buf)?;
+    if len >= PATH_MAX {
+        return Err(VfsError::NameTooLong);
     }
+    buf.truncate(len);
+    String::from_utf8(buf).map_err(|_| VfsError::Invalid)
 }
 
 /// Check whether the dentry is busy which means it is cwd or root
@@ -569,6 +1748,73 @@ fn check_same_fs(dentry1: &Arc<dyn VfsDentry>, dentry2: &Arc<dyn VfsDentry>) ->
     Ok(())
 }
 
+/// Resolve the mount points in `ns` on `dentry`, `flags` is set to the flags of the
+/// entered mount
+///
+/// After entering a bind mount without `MS_REC`, `cross` is set to false and the mount
+/// points below it are not crossed anymore.
+fn enter_mount(
+    ns: Option<&dyn VfsNamespace>,
+    dentry: Arc<dyn VfsDentry>,
+    cross: &mut bool,
+    flags: &mut VfsMountFlags,
+) -> Arc<dyn VfsDentry> {
+    let mut dentry = dentry;
+    while *cross {
+        match mounted(ns, &dentry) {
+            Some(mnt) => {
+                *flags = VfsMountFlags::from_bits_truncate(mnt.mnt_flags);
+                if flags.contains(VfsMountFlags::MS_BIND) && !flags.contains(VfsMountFlags::MS_REC)
+                {
+                    *cross = false;
+                }
+                dentry = mnt.root;
+            }
+            None => break,
+        }
+    }
+    dentry
+}
+
+/// Make `root` a child of `dir` after its mount is moved from `point` to `dir`, the root
+/// of a bind mount keeps its own parent
+pub(crate) fn move_root(
+    root: &Arc<dyn VfsDentry>,
+    point: &Arc<dyn VfsDentry>,
+    dir: &Arc<dyn VfsDentry>,
+) {
+    if let Some(parent) = root.parent() {
+        if Arc::ptr_eq(&parent, point) {
+            root.set_parent(dir);
+        }
+    }
+}
+
+/// Check whether `dentry` is `ancestor` or below it, the parents of the mounted roots are
+/// their mount points
+fn is_ancestor(ancestor: &Arc<dyn VfsDentry>, dentry: &Arc<dyn VfsDentry>) -> bool {
+    let mut next = Some(dentry.clone());
+    while let Some(dentry) = next {
+        if Arc::ptr_eq(&dentry, ancestor) {
+            return true;
+        }
+        next = dentry.parent();
+    }
+    false
+}
+
+/// Check whether `src` can be mounted on `dir`, a directory can only be mounted on a
+/// directory and a file can only be mounted on a file
+fn check_mount_type(src: &Arc<dyn VfsDentry>, dir: &Arc<dyn VfsDentry>) -> VfsResult<()> {
+    let src_is_dir = src.inode()?.inode_type().is_dir();
+    let dir_is_dir = dir.inode()?.inode_type().is_dir();
+    match (src_is_dir, dir_is_dir) {
+        (true, false) => Err(VfsError::NotDir),
+        (false, true) => Err(VfsError::IsDir),
+        _ => Ok(()),
+    }
+}
+
 fn real_dentry_down(dentry: Arc<dyn VfsDentry>) -> Arc<dyn VfsDentry> {
     if dentry.is_mount_point() {
         let mnt = dentry.mount_point().unwrap();
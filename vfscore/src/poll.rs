@@ -0,0 +1,119 @@
+//! Wait queues for poll(2)
+//!
+//! A file which is not always ready keeps a [`VfsWaitQueue`] and implements
+//! [`VfsFile::poll_wait`](crate::file::VfsFile::poll_wait) by registering the waker in it.
+//! When the state of the file changes, it calls [`VfsWaitQueue::wake`] with the events which
+//! became ready.
+use alloc::{sync::Arc, task::Wake, vec::Vec};
+use core::task::Waker;
+
+use crate::utils::VfsPollEvents;
+
+struct FnWaker<F>(F);
+
+impl<F: Fn() + Send + Sync + 'static> Wake for FnWaker<F> {
+    fn wake(self: Arc<Self>) {
+        (self.0)()
+    }
+    fn wake_by_ref(self: &Arc<Self>) {
+        (self.0)()
+    }
+}
+
+/// Create a waker which calls `f` when it is woken, e.g. to put the task on the ready list
+/// of epoll or to wake up a blocked thread
+pub fn waker_fn(f: impl Fn() + Send + Sync + 'static) -> Waker {
+    Waker::from(Arc::new(FnWaker(f)))
+}
+
+/// The wakers waiting for the events of a file
+///
+/// A waker is woken once and removed from the queue, the poller registers it again if it
+/// still waits. `ERR` and `HUP` always wake the wakers.
+///
+/// The queue doesn't lock itself, the file should keep it under its own lock.
+#[derive(Default)]
+pub struct VfsWaitQueue {
+    wakers: Vec<(VfsPollEvents, Waker)>,
+}
+
+impl VfsWaitQueue {
+    pub const fn new() -> Self {
+        Self { wakers: Vec::new() }
+    }
+
+    /// Wait for `events` with `waker`, registering the same waker again only adds the events
+    pub fn register(&mut self, events: VfsPollEvents, waker: &Waker) {
+        match self.wakers.iter_mut().find(|(_, w)| w.will_wake(waker)) {
+            Some((old, _)) => *old |= events,
+            None => self.wakers.push((events, waker.clone())),
+        }
+    }
+
+    /// Wake the wakers waiting for any of `events`, returns the number of woken wakers
+    pub fn wake(&mut self, events: VfsPollEvents) -> usize {
+        let always = VfsPollEvents::ERR | VfsPollEvents::HUP;
+        let mut count = 0;
+        self.wakers.retain(|(wait, waker)| {
+            if events.intersects(*wait | always) {
+                waker.wake_by_ref();
+                count += 1;
+                false
+            } else {
+                true
+            }
+        });
+        count
+    }
+
+    /// Wake all wakers, e.g. when the file is released
+    pub fn wake_all(&mut self) -> usize {
+        let count = self.wakers.len();
+        self.wakers.drain(..).for_each(|(_, waker)| waker.wake());
+        count
+    }
+
+    /// Remove `waker` from the queue, called when the poller stops waiting
+    pub fn remove(&mut self, waker: &Waker) {
+        self.wakers.retain(|(_, w)| !w.will_wake(waker));
+    }
+
+    pub fn len(&self) -> usize {
+        self.wakers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.wakers.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn test_wait_queue() {
+        let woken = Arc::new(AtomicUsize::new(0));
+        let counter = woken.clone();
+        let waker = waker_fn(move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let mut queue = VfsWaitQueue::new();
+        queue.register(VfsPollEvents::IN, &waker);
+        queue.register(VfsPollEvents::IN, &waker);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.wake(VfsPollEvents::OUT), 0);
+        assert_eq!(queue.wake(VfsPollEvents::IN), 1);
+        assert_eq!(woken.load(Ordering::SeqCst), 1);
+        assert!(queue.is_empty());
+
+        queue.register(VfsPollEvents::OUT, &waker);
+        assert_eq!(queue.wake(VfsPollEvents::HUP), 1);
+        queue.register(VfsPollEvents::OUT, &waker);
+        queue.remove(&waker);
+        assert_eq!(queue.wake_all(), 0);
+        assert_eq!(woken.load(Ordering::SeqCst), 2);
+    }
+}
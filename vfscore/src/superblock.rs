@@ -39,6 +39,14 @@ pub trait VfsSuperBlock: Send + Sync + DowncastSync {
 
     /// Get the root inode of this super block
     fn root_inode(&self) -> VfsResult<Arc<dyn VfsInode>>;
+
+    /// called when the last link and the last user of an inode are gone.
+    ///
+    /// The file system should free the inode and its data. An inode which is unlinked while
+    /// it is still open is an orphan until its last user drops it. Optional.
+    fn evict_inode(&self, _inode_number: u64) -> VfsResult<()> {
+        Ok(())
+    }
 }
 
 impl_downcast!(sync  VfsSuperBlock);
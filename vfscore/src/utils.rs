@@ -315,7 +315,13 @@ impl From<VfsInodeMode> for VfsNodePerm {
 
 impl VfsInodeMode {
     pub fn from(perm: VfsNodePerm, ty: VfsNodeType) -> Self {
-        let mut mode = VfsInodeMode::from_bits_truncate(perm.bits as u32);
+        VfsInodeMode::from_bits_truncate(perm.bits as u32).with_type(ty)
+    }
+    /// Returns the mode with the permission bits of `self` and the type bits of `ty`
+    ///
+    /// Unlike [`VfsInodeMode::from`], the set-user-ID, set-group-ID and sticky bits are kept.
+    pub fn with_type(self, ty: VfsNodeType) -> Self {
+        let mut mode = self & !VfsInodeMode::TYPE_MASK;
         match ty {
             VfsNodeType::Fifo => mode |= VfsInodeMode::FIFO,
             VfsNodeType::CharDevice => mode |= VfsInodeMode::CHAR,
@@ -346,7 +352,7 @@ mod tests {
 }
 
 #[repr(C)]
-#[derive(Default, Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Default, Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub struct VfsTimeSpec {
     pub sec: u64,  /* 秒 */
     pub nsec: u64, /* 纳秒, 范围在0~999999999 */
@@ -364,6 +370,35 @@ pub enum VfsTime {
     ModifiedTime(VfsTimeSpec),
 }
 
+/// The number of nanoseconds in a second, `nsec` of a valid time is less than it
+pub const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+/// The `tv_nsec` of utimensat(2) which sets the time to the current time
+pub const UTIME_NOW: u64 = (1 << 30) - 1;
+/// The `tv_nsec` of utimensat(2) which leaves the time unchanged
+pub const UTIME_OMIT: u64 = (1 << 30) - 2;
+
+/// A time given to utimensat(2)
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum VfsUtime {
+    /// Set the time to the current time
+    Now,
+    /// Leave the time unchanged
+    Omit,
+    Time(VfsTimeSpec),
+}
+
+impl From<VfsTimeSpec> for VfsUtime {
+    /// Decode [`UTIME_NOW`] and [`UTIME_OMIT`] in `nsec`
+    fn from(value: VfsTimeSpec) -> Self {
+        match value.nsec {
+            UTIME_NOW => Self::Now,
+            UTIME_OMIT => Self::Omit,
+            _ => Self::Time(value),
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct VfsFsStat {
@@ -453,13 +488,104 @@ impl From<VfsFileStat> for FileStat {
             st_atime_nsec: value.st_atime.nsec,
             st_mtime_sec: value.st_mtime.sec,
             st_mtime_nsec: value.st_mtime.nsec,
-            st_ctime_sec: value.st_ctime.nsec,
+            st_ctime_sec: value.st_ctime.sec,
             st_ctime_nsec: value.st_ctime.nsec,
             unused: 0,
         }
     }
 }
 
+bitflags! {
+    /// The fields of [`VfsStatx`] which are requested or filled, the `STATX_*` values of
+    /// statx(2)
+    #[derive(Default)]
+    pub struct VfsStatxMask: u32 {
+        const STATX_TYPE = 0x1;
+        const STATX_MODE = 0x2;
+        const STATX_NLINK = 0x4;
+        const STATX_UID = 0x8;
+        const STATX_GID = 0x10;
+        const STATX_ATIME = 0x20;
+        const STATX_MTIME = 0x40;
+        const STATX_CTIME = 0x80;
+        const STATX_INO = 0x100;
+        const STATX_SIZE = 0x200;
+        const STATX_BLOCKS = 0x400;
+        /// The fields of stat(2)
+        const STATX_BASIC_STATS = 0x7ff;
+        const STATX_BTIME = 0x800;
+        const STATX_MNT_ID = 0x1000;
+    }
+}
+
+bitflags! {
+    /// The `STATX_ATTR_*` attributes of a file, the persistent ones have the same values as
+    /// the inode flags of `FS_IOC_SETFLAGS`
+    #[derive(Default)]
+    pub struct VfsStatxAttr: u64 {
+        /// The file is compressed by the file system
+        const STATX_ATTR_COMPRESSED = 0x4;
+        /// The file can't be modified, deleted or renamed
+        const STATX_ATTR_IMMUTABLE = 0x10;
+        /// The file can only be opened for appending
+        const STATX_ATTR_APPEND = 0x20;
+        /// The file is not backed up by dump(8)
+        const STATX_ATTR_NODUMP = 0x40;
+        /// The file is the root of a mount
+        const STATX_ATTR_MOUNT_ROOT = 0x2000;
+    }
+}
+
+/// The extended attributes of a file returned by statx(2)
+///
+/// Only the fields in `stx_mask` are valid, and only the attributes in
+/// `stx_attributes_mask` are supported by the file system.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct VfsStatx {
+    pub stx_mask: VfsStatxMask,
+    pub stx_blksize: u32,
+    pub stx_attributes: VfsStatxAttr,
+    pub stx_nlink: u32,
+    pub stx_uid: u32,
+    pub stx_gid: u32,
+    pub stx_mode: u32,
+    pub stx_ino: u64,
+    pub stx_size: u64,
+    pub stx_blocks: u64,
+    pub stx_attributes_mask: VfsStatxAttr,
+    pub stx_atime: VfsTimeSpec,
+    /// The creation time of the file
+    pub stx_btime: VfsTimeSpec,
+    pub stx_ctime: VfsTimeSpec,
+    pub stx_mtime: VfsTimeSpec,
+    pub stx_rdev: u64,
+    pub stx_dev: u64,
+    /// The id of the mount containing the file, see [`crate::mount::MountTable::statx`]
+    pub stx_mnt_id: u64,
+}
+
+impl From<VfsFileStat> for VfsStatx {
+    fn from(value: VfsFileStat) -> Self {
+        Self {
+            stx_mask: VfsStatxMask::STATX_BASIC_STATS,
+            stx_blksize: value.st_blksize,
+            stx_nlink: value.st_nlink,
+            stx_uid: value.st_uid,
+            stx_gid: value.st_gid,
+            stx_mode: value.st_mode,
+            stx_ino: value.st_ino,
+            stx_size: value.st_size,
+            stx_blocks: value.st_blocks,
+            stx_atime: value.st_atime,
+            stx_ctime: value.st_ctime,
+            stx_mtime: value.st_mtime,
+            stx_rdev: value.st_rdev,
+            stx_dev: value.st_dev,
+            ..Default::default()
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct VfsDirEntry {
     /// ino is an inode number
@@ -546,6 +672,20 @@ bitflags! {
 
 }
 
+bitflags! {
+    /// umount2 flag
+    pub struct VfsUmountFlags: u32 {
+        /// Force unmount even if busy
+        const MNT_FORCE = 1;
+        /// Perform a lazy unmount, detach the file system now and clean up when it is not busy
+        const MNT_DETACH = 2;
+        /// Mark the mount point as expired
+        const MNT_EXPIRE = 4;
+        /// Don't dereference target if it is a symbolic link
+        const UMOUNT_NOFOLLOW = 8;
+    }
+}
+
 bitflags! {
      /// renameat flag
     pub struct VfsRenameFlag: u32 {
@@ -558,3 +698,111 @@ bitflags! {
         const RENAME_WHITEOUT = 1 << 2;
     }
 }
+
+bitflags! {
+    /// setxattr flag
+    pub struct VfsXattrFlag: u32 {
+        /// Perform a pure create, which fails if the named attribute exists already.
+        const XATTR_CREATE = 1;
+        /// Perform a pure replace operation, which fails if the named attribute does not already exist.
+        const XATTR_REPLACE = 2;
+    }
+}
+
+bitflags! {
+    /// fallocate(2) mode
+    pub struct VfsFallocateMode: u32 {
+        /// Don't change the size of the file even if the range is beyond the end of it
+        const FALLOC_FL_KEEP_SIZE = 0x01;
+        /// Deallocate the range, it reads as zero later. Must be used with `FALLOC_FL_KEEP_SIZE`
+        const FALLOC_FL_PUNCH_HOLE = 0x02;
+        /// Zero the range and allocate it
+        const FALLOC_FL_ZERO_RANGE = 0x10;
+    }
+}
+
+bitflags! {
+    /// access(2) mode, which is used to check the permission of a file
+    pub struct VfsAccessMode: u32 {
+        /// Test for execute or search permission.
+        const X_OK = 1;
+        /// Test for write permission.
+        const W_OK = 2;
+        /// Test for read permission.
+        const R_OK = 4;
+    }
+}
+
+bitflags! {
+    /// open(2) flags
+    pub struct VfsOpenFlags: u32 {
+        const O_RDONLY = 0;
+        const O_WRONLY = 0o1;
+        const O_RDWR = 0o2;
+        /// The mask of the access mode
+        const O_ACCMODE = 0o3;
+        const O_CREAT = 0o100;
+        const O_EXCL = 0o200;
+        const O_NOCTTY = 0o400;
+        const O_TRUNC = 0o1000;
+        const O_APPEND = 0o2000;
+        const O_NONBLOCK = 0o4000;
+        const O_DSYNC = 0o10000;
+        const O_DIRECT = 0o40000;
+        const O_LARGEFILE = 0o100000;
+        const O_DIRECTORY = 0o200000;
+        const O_NOFOLLOW = 0o400000;
+        const O_NOATIME = 0o1000000;
+        const O_CLOEXEC = 0o2000000;
+        const O_SYNC = 0o4010000;
+        const O_PATH = 0o10000000;
+        const O_TMPFILE = 0o20200000;
+    }
+}
+
+impl VfsOpenFlags {
+    /// The flags which can be changed by `F_SETFL` of fcntl(2)
+    pub const SETFL_MASK: Self = Self::from_bits_truncate(
+        Self::O_APPEND.bits | Self::O_NONBLOCK.bits | Self::O_DIRECT.bits | Self::O_NOATIME.bits,
+    );
+
+    pub fn readable(&self) -> bool {
+        (*self & Self::O_ACCMODE) != Self::O_WRONLY && !self.contains(Self::O_PATH)
+    }
+
+    pub fn writable(&self) -> bool {
+        let mode = *self & Self::O_ACCMODE;
+        (mode == Self::O_WRONLY || mode == Self::O_RDWR) && !self.contains(Self::O_PATH)
+    }
+}
+
+bitflags! {
+    /// openat2(2) resolve flags, which restrict how a path is resolved
+    pub struct VfsResolveFlags: u64 {
+        /// Don't cross any mount point
+        const RESOLVE_NO_XDEV = 0x01;
+        /// Don't follow any symlink, `ELOOP` is returned instead
+        const RESOLVE_NO_SYMLINKS = 0x04;
+        /// Don't escape the start directory by "..", absolute paths or absolute symlinks
+        const RESOLVE_BENEATH = 0x08;
+        /// Resolve as if the start directory was the root, like chroot(2)
+        const RESOLVE_IN_ROOT = 0x10;
+        /// Only use the dentry cache, `EAGAIN` is returned if a lookup is needed
+        const RESOLVE_CACHED = 0x20;
+    }
+}
+
+/// The position used by lseek(2)
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VfsSeekFrom {
+    /// SEEK_SET
+    Start(u64),
+    /// SEEK_CUR
+    Current(i64),
+    /// SEEK_END
+    End(i64),
+    /// SEEK_DATA, the next data at or after the offset
+    Data(u64),
+    /// SEEK_HOLE, the next hole at or after the offset
+    Hole(u64),
+}
@@ -0,0 +1,188 @@
+use std::sync::Arc;
+
+use ramfs::{RamFs, RamFsProvider};
+use spin::Mutex;
+use vfscore::{
+    cache::{BufferCache, PageCache, VfsPageCache, PAGE_SIZE},
+    dentry::VfsDentry,
+    fd::VfsOpenFile,
+    fstype::VfsFsType,
+    inode::VfsInode,
+    path::VfsPath,
+    superblock::VfsSuperBlock,
+    utils::{VfsNodeType, VfsOpenFlags, VfsTimeSpec},
+    VfsResult,
+};
+
+#[derive(Clone)]
+struct RamFsProviderImpl;
+impl RamFsProvider for RamFsProviderImpl {
+    fn current_time(&self) -> VfsTimeSpec {
+        Default::default()
+    }
+}
+
+fn make_file(fs: &Arc<dyn VfsFsType>) -> (Arc<dyn VfsDentry>, Arc<dyn VfsInode>) {
+    let root = fs.clone().mount(0, "/", None, &[]).unwrap();
+    let file = root
+        .inode()
+        .unwrap()
+        .create("f", VfsNodeType::File, "rw-rw-rw-".into(), None)
+        .unwrap();
+    (root, file)
+}
+
+#[test]
+fn test_page_cache_write_back() {
+    let fs: Arc<dyn VfsFsType> = Arc::new(RamFs::<_, Mutex<()>>::new(RamFsProviderImpl));
+    let (_root, file) = make_file(&fs);
+    file.write_at(0, b"hello").unwrap();
+    let mut cache = PageCache::new(16 * PAGE_SIZE);
+
+    let mut buf = [0; 10];
+    assert_eq!(cache.read(&file, 0, &mut buf).unwrap(), 5);
+    assert_eq!(&buf[..5], b"hello");
+
+    // the write stays in the cache until fsync
+    cache.write(&file, PAGE_SIZE as u64 + 1, b"world").unwrap();
+    assert_eq!(cache.size(&file), Some(PAGE_SIZE as u64 + 6));
+    assert_eq!(cache.dirty_pages(), 1);
+    assert_eq!(file.get_attr().unwrap().st_size, 5);
+    assert_eq!(cache.read(&file, 5, &mut buf).unwrap(), 10);
+    assert_eq!(buf, [0; 10]);
+
+    cache.fsync(&file).unwrap();
+    assert_eq!(cache.dirty_pages(), 0);
+    assert_eq!(file.get_attr().unwrap().st_size, PAGE_SIZE as u64 + 6);
+    file.read_at(PAGE_SIZE as u64 + 1, &mut buf).unwrap();
+    assert_eq!(&buf[..5], b"world");
+
+    cache.truncate(&file, 3).unwrap();
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.read(&file, 0, &mut buf).unwrap(), 3);
+    cache.invalidate(&file);
+    assert!(cache.is_empty());
+}
+
+struct LockedPageCache(Mutex<PageCache>);
+
+impl VfsPageCache for LockedPageCache {
+    fn fsync(&self, inode: &Arc<dyn VfsInode>) -> VfsResult<()> {
+        self.0.lock().fsync(inode)
+    }
+    fn sync_fs(&self, sb: &Arc<dyn VfsSuperBlock>, wait: bool) -> VfsResult<()> {
+        self.0.lock().sync_fs(sb, wait)
+    }
+}
+
+#[test]
+fn test_page_cache_sync_points() {
+    let fs: Arc<dyn VfsFsType> = Arc::new(RamFs::<_, Mutex<()>>::new(RamFsProviderImpl));
+    let (root, file) = make_file(&fs);
+    let cache = Arc::new(LockedPageCache(Mutex::new(PageCache::new(16 * PAGE_SIZE))));
+
+    // fsync of an open file writes back its pages
+    cache.0.lock().write(&file, 0, b"hello").unwrap();
+    assert_eq!(file.get_attr().unwrap().st_size, 0);
+    let path = VfsPath::new(root.clone(), root.clone()).with_page_cache(cache.clone());
+    let dentry = path.join("f").unwrap().open(None).unwrap();
+    let open = VfsOpenFile::<Mutex<()>>::new_with_cache(
+        dentry,
+        VfsOpenFlags::O_RDWR,
+        None,
+        None,
+        cache.clone(),
+    )
+    .unwrap();
+    open.fsync().unwrap();
+    assert_eq!(cache.0.lock().dirty_pages(), 0);
+    let mut buf = [0; 5];
+    file.read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+
+    // unmounting writes back the pages of the file system
+    let (mnt_root, mnt_file) = make_file(&fs);
+    root.inode()
+        .unwrap()
+        .create("mnt", VfsNodeType::Dir, "rwxrwxrwx".into(), None)
+        .unwrap();
+    path.join("mnt").unwrap().mount(mnt_root, 0).unwrap();
+    cache.0.lock().write(&mnt_file, 0, b"world").unwrap();
+    assert_eq!(cache.0.lock().dirty_pages(), 1);
+    path.join("mnt").unwrap().umount().unwrap();
+    assert_eq!(cache.0.lock().dirty_pages(), 0);
+    mnt_file.read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf, b"world");
+}
+
+#[test]
+fn test_page_cache_evict() {
+    let fs: Arc<dyn VfsFsType> = Arc::new(RamFs::<_, Mutex<()>>::new(RamFsProviderImpl));
+    let (root, file) = make_file(&fs);
+    let mut cache = PageCache::new(2 * PAGE_SIZE);
+    for i in 0..4u8 {
+        cache
+            .write(&file, i as u64 * PAGE_SIZE as u64, &[i + 1; PAGE_SIZE])
+            .unwrap();
+    }
+    // the first two pages are written back when they are evicted
+    assert_eq!(cache.len(), 2);
+    let mut buf = [0; 1];
+    file.read_at(PAGE_SIZE as u64, &mut buf).unwrap();
+    assert_eq!(buf, [2]);
+    cache.read(&file, 0, &mut buf).unwrap();
+    assert_eq!(buf, [1]);
+
+    cache
+        .sync_fs(&root.inode().unwrap().get_super_block().unwrap(), true)
+        .unwrap();
+    assert_eq!(cache.dirty_pages(), 0);
+    assert_eq!(file.get_attr().unwrap().st_size, 4 * PAGE_SIZE as u64);
+}
+
+#[test]
+fn test_buffer_cache() {
+    let fs: Arc<dyn VfsFsType> = Arc::new(RamFs::<_, Mutex<()>>::new(RamFsProviderImpl));
+    let (_root, device) = make_file(&fs);
+    let data = (0..64 * 512).map(|i| (i / 512) as u8).collect::<Vec<_>>();
+    device.write_at(0, &data).unwrap();
+    let mut cache = BufferCache::new(device.clone(), 512, 64, 16);
+    cache.set_read_ahead(4);
+
+    let mut buf = [0; 512];
+    cache.read_at(0, &mut buf).unwrap();
+    assert_eq!(cache.stats().device_reads, 1);
+    // the sequential reads are read ahead, 1 + 2 + 4 blocks
+    for block in 1..8 {
+        cache.read_at(block * 512, &mut buf).unwrap();
+        assert_eq!(buf, [block as u8; 512]);
+    }
+    assert_eq!(cache.stats().device_reads, 4);
+    // a random read doesn't read ahead
+    cache.read_at(32 * 512 + 100, &mut buf).unwrap();
+    assert_eq!(&buf[..412], &[32; 412]);
+    assert_eq!(&buf[412..], &[33; 100]);
+    assert_eq!(cache.stats().device_reads, 5);
+
+    // adjacent dirty blocks are written in one request
+    cache.write_at(2 * 512 + 10, &[0xff; 1024]).unwrap();
+    cache.write_at(40 * 512, &[0xee; 512]).unwrap();
+    assert_eq!(device.read_at(2 * 512 + 10, &mut buf).unwrap(), 512);
+    assert_eq!(buf[0], 2);
+    cache.flush().unwrap();
+    assert_eq!(cache.stats().device_writes, 2);
+    device.read_at(2 * 512 + 10, &mut buf).unwrap();
+    assert_eq!(buf, [0xff; 512]);
+    device.read_at(40 * 512, &mut buf).unwrap();
+    assert_eq!(buf, [0xee; 512]);
+
+    // only the evicted block is written back
+    cache.write_at(50 * 512, &[0xdd; 512]).unwrap();
+    cache.write_at(51 * 512, &[0xcc; 512]).unwrap();
+    for block in 8..28 {
+        cache.read_at(block * 512, &mut buf).unwrap();
+    }
+    assert_eq!(cache.stats().device_writes, 4);
+    device.read_at(51 * 512, &mut buf).unwrap();
+    assert_eq!(buf, [0xcc; 512]);
+}
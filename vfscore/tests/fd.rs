@@ -0,0 +1,259 @@
+use std::{sync::Arc, task::Waker, thread};
+
+use ramfs::{RamFs, RamFsProvider};
+use spin::Mutex;
+use vfscore::{
+    dentry::VfsDentry,
+    error::VfsError,
+    fd::{VfsFdTable, VfsOpenFile},
+    fstype::VfsFsType,
+    inode::VfsInode,
+    lock::{FileLockManager, VfsFileLock, VfsFileLocks, VfsLockType},
+    path::{SysContext, VfsPath},
+    utils::{VfsInodeMode, VfsNodeType, VfsOpenFlags, VfsPollEvents, VfsSeekFrom, VfsTimeSpec},
+};
+
+#[derive(Clone)]
+struct RamFsProviderImpl;
+impl RamFsProvider for RamFsProviderImpl {
+    fn current_time(&self) -> VfsTimeSpec {
+        Default::default()
+    }
+}
+
+fn make_ramfs() -> (Arc<dyn VfsFsType>, Arc<dyn VfsDentry>) {
+    let fs: Arc<dyn VfsFsType> = Arc::new(RamFs::<_, Mutex<()>>::new(RamFsProviderImpl));
+    let root = fs.clone().mount(0, "/", None, &[]).unwrap();
+    (fs, root)
+}
+
+fn open(root: &Arc<dyn VfsDentry>, path: &str, flags: VfsOpenFlags) -> Arc<VfsOpenFile<Mutex<()>>> {
+    let mode = VfsInodeMode::FILE | VfsInodeMode::from_bits_truncate(0o644);
+    let dentry = VfsPath::new(root.clone(), root.clone())
+        .join(path)
+        .unwrap()
+        .open(Some(mode))
+        .unwrap();
+    VfsOpenFile::<Mutex<()>>::new(dentry, flags).unwrap()
+}
+
+#[test]
+fn test_open_file() {
+    let (_fs, root) = make_ramfs();
+    let file = open(&root, "f", VfsOpenFlags::O_RDWR);
+    assert_eq!(file.write(b"hello").unwrap(), 5);
+    assert_eq!(file.offset(), 5);
+    assert_eq!(file.lseek(VfsSeekFrom::Current(-3)).unwrap(), 2);
+    let mut buf = [0; 8];
+    assert_eq!(file.read(&mut buf).unwrap(), 3);
+    assert_eq!(&buf[..3], b"llo");
+    assert_eq!(file.lseek(VfsSeekFrom::End(2)).unwrap(), 7);
+    assert_eq!(file.lseek(VfsSeekFrom::Current(-8)), Err(VfsError::Invalid));
+    assert_eq!(file.lseek(VfsSeekFrom::Data(0)).unwrap(), 0);
+    assert_eq!(file.lseek(VfsSeekFrom::Hole(0)).unwrap(), 5);
+    assert_eq!(file.pread(1, &mut buf[..2]).unwrap(), 2);
+    assert_eq!(&buf[..2], b"el");
+    assert_eq!(file.offset(), 5);
+
+    // the writes of O_APPEND go to the end
+    let append = open(&root, "f", VfsOpenFlags::O_WRONLY | VfsOpenFlags::O_APPEND);
+    append.write(b"!").unwrap();
+    assert_eq!(append.offset(), 6);
+    assert_eq!(append.read(&mut buf), Err(VfsError::EBADF));
+    append.set_flags(VfsOpenFlags::O_NONBLOCK | VfsOpenFlags::O_RDWR);
+    assert_eq!(
+        append.flags(),
+        VfsOpenFlags::O_WRONLY | VfsOpenFlags::O_NONBLOCK
+    );
+    append.write(b"?").unwrap();
+    assert_eq!(file.pread(0, &mut buf).unwrap(), 7);
+    assert_eq!(&buf[..7], b"hello!?");
+}
+
+#[test]
+fn test_readdir() {
+    let (_fs, root) = make_ramfs();
+    for name in ["a", "b"] {
+        root.inode()
+            .unwrap()
+            .create(name, VfsNodeType::File, "rw-r--r--".into(), None)
+            .unwrap();
+    }
+    let dir = VfsOpenFile::<Mutex<()>>::new(root.clone(), VfsOpenFlags::O_RDONLY).unwrap();
+    let mut names = Vec::new();
+    while let Some(entry) = dir.readdir().unwrap() {
+        names.push(entry.name);
+    }
+    assert!(names.contains(&"a".to_string()) && names.contains(&"b".to_string()));
+    assert!(dir.readdir().unwrap().is_none());
+    dir.lseek(VfsSeekFrom::Start(0)).unwrap();
+    assert_eq!(dir.readdir().unwrap().unwrap().name, names[0]);
+    let mut buf = [0; 1];
+    assert_eq!(dir.read(&mut buf), Err(VfsError::IsDir));
+    assert!(matches!(
+        VfsOpenFile::<Mutex<()>>::new(root, VfsOpenFlags::O_RDWR),
+        Err(VfsError::IsDir)
+    ));
+}
+
+#[test]
+fn test_fd_table() {
+    let (_fs, root) = make_ramfs();
+    let file = open(&root, "f", VfsOpenFlags::O_RDWR);
+    let mut table = VfsFdTable::<Mutex<()>>::new(4);
+    assert_eq!(table.insert(file.clone(), false).unwrap(), 0);
+    assert_eq!(table.dup(0, 0, true).unwrap(), 1);
+    assert!(table.cloexec(1).unwrap());
+    // duplicated descriptors share the offset
+    table.get(0).unwrap().write(b"abc").unwrap();
+    assert_eq!(table.get(1).unwrap().offset(), 3);
+
+    assert!(table.dup2(0, 3, false).unwrap().is_none());
+    assert_eq!(table.insert(file.clone(), false).unwrap(), 2);
+    assert_eq!(table.insert(file.clone(), false), Err(VfsError::EMFILE));
+    assert_eq!(table.dup(0, 4, false), Err(VfsError::Invalid));
+    // dup2 closes the target
+    let other = open(&root, "g", VfsOpenFlags::O_RDONLY);
+    table.remove(2).unwrap();
+    table.insert(other.clone(), false).unwrap();
+    let old = table.dup2(2, 3, false).unwrap().unwrap();
+    assert!(Arc::ptr_eq(&old, &file));
+    assert!(Arc::ptr_eq(&table.get(3).unwrap(), &other));
+    assert!(table.dup2(2, 2, false).unwrap().is_none());
+
+    let closed = table.close_on_exec();
+    assert_eq!(closed.len(), 1);
+    assert_eq!(closed[0].0, 1);
+    assert_eq!(table.get(1).err(), Some(VfsError::EBADF));
+    assert_eq!(table.len(), 3);
+    assert_eq!(table.remove(1).err(), Some(VfsError::EBADF));
+    assert_eq!(table.insert(file, false).unwrap(), 1);
+}
+
+#[derive(Default)]
+struct Locks(Mutex<FileLockManager>);
+
+impl VfsFileLocks for Locks {
+    fn release_flock(&self, inode: &Arc<dyn VfsInode>, file: usize) {
+        self.0.lock().release_flock(inode, file)
+    }
+    fn release_posix(&self, inode: &Arc<dyn VfsInode>, owner: u64) {
+        self.0.lock().release_posix(inode, owner)
+    }
+}
+
+#[test]
+fn test_close_releases_locks() {
+    let (_fs, root) = make_ramfs();
+    let context = SysContext {
+        pid: 7,
+        uid: 0,
+        gid: 0,
+        cwd: root.clone(),
+        root: root.clone(),
+    };
+    let locks = Arc::new(Locks::default());
+    let mut table = VfsFdTable::<Mutex<()>>::new(16).with_locks(locks.clone(), &context);
+    let dentry = open(&root, "f", VfsOpenFlags::O_RDWR).dentry();
+    let file =
+        VfsOpenFile::<Mutex<()>>::new_with_locks(dentry, VfsOpenFlags::O_RDWR, None, locks.clone())
+            .unwrap();
+    let inode = file.inode();
+    let fd = table.insert(file.clone(), false).unwrap();
+    let dup = table.dup(fd, 0, false).unwrap();
+    {
+        let mut manager = locks.0.lock();
+        manager
+            .flock(&inode, file.lock_id(), VfsLockType::Write)
+            .unwrap();
+        let lock = VfsFileLock::new(VfsLockType::Write, 0, 0, &context);
+        manager.set_lk(&inode, &lock, false).unwrap();
+    }
+    // closing any file descriptor of the inode releases the byte-range locks of the process
+    table.remove(fd).unwrap();
+    assert!(locks.0.lock().posix_locks(&inode).is_empty());
+    assert!(!locks.0.lock().is_empty());
+    // the flock(2) lock is released with the last reference of the description
+    table.remove(dup).unwrap();
+    drop(file);
+    assert!(locks.0.lock().is_empty());
+}
+
+#[test]
+fn test_drop_table_releases_locks() {
+    let (_fs, root) = make_ramfs();
+    let context = |pid| SysContext {
+        pid,
+        uid: 0,
+        gid: 0,
+        cwd: root.clone(),
+        root: root.clone(),
+    };
+    let locks = Arc::new(Locks::default());
+    let mut table = VfsFdTable::<Mutex<()>>::new(16).with_locks(locks.clone(), &context(7));
+    let file = open(&root, "f", VfsOpenFlags::O_RDWR);
+    let inode = file.inode();
+    table.insert(file, false).unwrap();
+    let lock = VfsFileLock::new(VfsLockType::Write, 0, 0, &context(7));
+    locks.0.lock().set_lk(&inode, &lock, false).unwrap();
+    let other = VfsFileLock::new(VfsLockType::Write, 0, 0, &context(8));
+    assert!(locks.0.lock().set_lk(&inode, &other, false).is_err());
+    // the locks of an exited process are released with its table
+    drop(table);
+    locks.0.lock().set_lk(&inode, &other, false).unwrap();
+}
+
+#[test]
+fn test_poll_regular_file() {
+    let (_fs, root) = make_ramfs();
+    let file = open(&root, "f", VfsOpenFlags::O_RDWR);
+    let events = VfsPollEvents::IN | VfsPollEvents::OUT;
+    assert_eq!(file.poll(events).unwrap(), events);
+    assert_eq!(file.poll_wait(events, Waker::noop()).unwrap(), events);
+    assert_eq!(file.poll(VfsPollEvents::OUT).unwrap(), VfsPollEvents::OUT);
+}
+
+/// Write 100 records of 8 bytes from each of 4 threads, every thread writes `files[i]`
+fn write_records(files: Vec<Arc<VfsOpenFile<Mutex<()>>>>) {
+    let threads = files
+        .into_iter()
+        .enumerate()
+        .map(|(i, file)| {
+            thread::spawn(move || {
+                for _ in 0..100 {
+                    assert_eq!(file.write(&[i as u8; 8]).unwrap(), 8);
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
+
+/// Check that the file holds 400 whole records
+fn check_records(root: &Arc<dyn VfsDentry>) {
+    let file = open(root, "f", VfsOpenFlags::O_RDONLY);
+    let mut buf = [0; 8];
+    for _ in 0..400 {
+        assert_eq!(file.read(&mut buf).unwrap(), 8);
+        assert!(buf.iter().all(|b| *b == buf[0]));
+    }
+    assert_eq!(file.read(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn test_concurrent_writes() {
+    // the threads sharing a description don't write at the same offset
+    let (_fs, root) = make_ramfs();
+    let file = open(&root, "f", VfsOpenFlags::O_WRONLY);
+    write_records(vec![file.clone(); 4]);
+    assert_eq!(file.offset(), 3200);
+    check_records(&root);
+
+    // the appends of different descriptions don't overwrite each other
+    let (_fs, root) = make_ramfs();
+    let flags = VfsOpenFlags::O_WRONLY | VfsOpenFlags::O_APPEND;
+    write_records((0..4).map(|_| open(&root, "f", flags)).collect());
+    check_records(&root);
+}
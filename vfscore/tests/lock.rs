@@ -0,0 +1,166 @@
+use std::sync::Arc;
+
+use ramfs::{RamFs, RamFsProvider};
+use spin::Mutex;
+use vfscore::{
+    dentry::VfsDentry,
+    error::VfsError,
+    fstype::VfsFsType,
+    inode::VfsInode,
+    lock::{FileLockManager, VfsFileLock, VfsLockType},
+    path::SysContext,
+    utils::{VfsNodeType, VfsTimeSpec},
+};
+
+#[derive(Clone)]
+struct RamFsProviderImpl;
+impl RamFsProvider for RamFsProviderImpl {
+    fn current_time(&self) -> VfsTimeSpec {
+        Default::default()
+    }
+}
+
+/// The file system, its root and the files `/f` and `/g`
+type Files = (
+    Arc<dyn VfsFsType>,
+    Arc<dyn VfsDentry>,
+    Arc<dyn VfsInode>,
+    Arc<dyn VfsInode>,
+);
+
+fn make_files() -> Files {
+    let fs: Arc<dyn VfsFsType> = Arc::new(RamFs::<_, Mutex<()>>::new(RamFsProviderImpl));
+    let root = fs.clone().mount(0, "/", None, &[]).unwrap();
+    let inode = root.inode().unwrap();
+    let f = inode
+        .create("f", VfsNodeType::File, "rw-rw-rw-".into(), None)
+        .unwrap();
+    let g = inode
+        .create("g", VfsNodeType::File, "rw-rw-rw-".into(), None)
+        .unwrap();
+    (fs, root, f, g)
+}
+
+fn context(root: &Arc<dyn VfsDentry>, pid: u64) -> SysContext {
+    SysContext {
+        pid,
+        uid: 0,
+        gid: 0,
+        cwd: root.clone(),
+        root: root.clone(),
+    }
+}
+
+#[test]
+fn test_flock() {
+    let (_fs, _root, f, _) = make_files();
+    let mut manager = FileLockManager::new();
+    manager.flock(&f, 1, VfsLockType::Read).unwrap();
+    manager.flock(&f, 2, VfsLockType::Read).unwrap();
+    assert_eq!(
+        manager.flock(&f, 2, VfsLockType::Write),
+        Err(VfsError::EAGAIN)
+    );
+    manager.release_flock(&f, 1);
+    manager.flock(&f, 2, VfsLockType::Write).unwrap();
+    assert_eq!(
+        manager.flock(&f, 1, VfsLockType::Read),
+        Err(VfsError::EAGAIN)
+    );
+    manager.flock(&f, 2, VfsLockType::Unlock).unwrap();
+    assert!(manager.is_empty());
+}
+
+#[test]
+fn test_posix_lock() {
+    let (_fs, root, f, _) = make_files();
+    let [p1, p2, p3] = [1, 2, 3].map(|pid| context(&root, pid));
+    let mut manager = FileLockManager::new();
+    manager
+        .set_lk(
+            &f,
+            &VfsFileLock::new(VfsLockType::Write, 0, 100, &p1),
+            false,
+        )
+        .unwrap();
+    // unlocking the middle splits the lock
+    manager
+        .set_lk(
+            &f,
+            &VfsFileLock::new(VfsLockType::Unlock, 40, 20, &p1),
+            false,
+        )
+        .unwrap();
+    let mut locks = manager.posix_locks(&f);
+    locks.sort_by_key(|lock| lock.start);
+    assert_eq!(
+        locks,
+        [
+            VfsFileLock::new(VfsLockType::Write, 0, 40, &p1),
+            VfsFileLock::new(VfsLockType::Write, 60, 40, &p1)
+        ]
+    );
+
+    let query = VfsFileLock::new(VfsLockType::Read, 30, 0, &p2);
+    assert_eq!(
+        manager.get_lk(&f, &query),
+        Some(VfsFileLock::new(VfsLockType::Write, 0, 40, &p1))
+    );
+    assert!(manager
+        .get_lk(&f, &VfsFileLock::new(VfsLockType::Write, 40, 20, &p2))
+        .is_none());
+    assert_eq!(manager.set_lk(&f, &query, false), Err(VfsError::EAGAIN));
+
+    // filling the hole merges the locks
+    manager
+        .set_lk(
+            &f,
+            &VfsFileLock::new(VfsLockType::Write, 40, 20, &p1),
+            false,
+        )
+        .unwrap();
+    assert_eq!(
+        manager.posix_locks(&f),
+        [VfsFileLock::new(VfsLockType::Write, 0, 100, &p1)]
+    );
+
+    // closing the file releases the locks of the owner
+    manager.release_posix(&f, 1);
+    manager.set_lk(&f, &query, false).unwrap();
+    manager
+        .set_lk(&f, &VfsFileLock::new(VfsLockType::Read, 0, 10, &p3), false)
+        .unwrap();
+    manager.release_owner(2);
+    manager.release_owner(3);
+    assert!(manager.is_empty());
+}
+
+#[test]
+fn test_deadlock() {
+    let (_fs, root, f, g) = make_files();
+    let mut manager = FileLockManager::new();
+    let lock_f = |pid| VfsFileLock::new(VfsLockType::Write, 0, 0, &context(&root, pid));
+    let lock_g = |pid| VfsFileLock::new(VfsLockType::Write, 0, 1, &context(&root, pid));
+    manager.set_lk(&f, &lock_f(1), true).unwrap();
+    manager.set_lk(&g, &lock_g(2), true).unwrap();
+    // 1 waits for 2
+    assert_eq!(manager.set_lk(&g, &lock_g(1), true), Err(VfsError::EAGAIN));
+    // 2 would wait for 1
+    assert_eq!(manager.set_lk(&f, &lock_f(2), true), Err(VfsError::EDEADLK));
+    // F_SETLK doesn't wait, so there is no deadlock
+    assert_eq!(manager.set_lk(&f, &lock_f(2), false), Err(VfsError::EAGAIN));
+
+    manager.cancel_wait(1);
+    manager.set_lk(&f, &lock_f(2), true).unwrap_err();
+    manager.release_owner(1);
+    manager.set_lk(&f, &lock_f(2), true).unwrap();
+
+    // 3 waits for 2, then 2 unlocks g before 3 tries again
+    let lock_h = |pid| VfsFileLock::new(VfsLockType::Write, 5, 1, &context(&root, pid));
+    manager.set_lk(&g, &lock_h(3), false).unwrap();
+    assert_eq!(manager.set_lk(&g, &lock_g(3), true), Err(VfsError::EAGAIN));
+    let unlock = VfsFileLock::new(VfsLockType::Unlock, 0, 1, &context(&root, 2));
+    manager.set_lk(&g, &unlock, false).unwrap();
+    // the stale wait of 3 doesn't make 2 waiting for 3 a deadlock
+    assert_eq!(manager.set_lk(&g, &lock_h(2), true), Err(VfsError::EAGAIN));
+}
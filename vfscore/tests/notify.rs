@@ -0,0 +1,385 @@
+use std::sync::Arc;
+
+use ramfs::{RamFs, RamFsProvider};
+use spin::Mutex;
+use vfscore::{
+    dentry::VfsDentry,
+    error::VfsError,
+    fd::VfsOpenFile,
+    fstype::{VfsFsType, VfsMountPoint},
+    inode::VfsInode,
+    mount::{MountTable, VfsNamespace},
+    notify::{VfsNotifier, VfsNotifyMask, VfsNotifyRegistry},
+    path::VfsPath,
+    utils::{
+        VfsInodeMode, VfsMountFlags, VfsOpenFlags, VfsPollEvents, VfsRenameFlag, VfsTimeSpec,
+        VfsUmountFlags,
+    },
+};
+
+#[derive(Clone)]
+struct RamFsProviderImpl;
+impl RamFsProvider for RamFsProviderImpl {
+    fn current_time(&self) -> VfsTimeSpec {
+        Default::default()
+    }
+}
+
+fn make_ramfs() -> (Arc<dyn VfsFsType>, Arc<dyn VfsDentry>) {
+    let fs: Arc<dyn VfsFsType> = Arc::new(RamFs::<_, Mutex<()>>::new(RamFsProviderImpl));
+    let root = fs.clone().mount(0, "/", None, &[]).unwrap();
+    (fs, root)
+}
+
+/// The mount namespace of a process, the kernel keeps its table behind a lock
+struct Namespace(Mutex<MountTable>);
+
+impl VfsNamespace for Namespace {
+    fn mounted(&self, dentry: &Arc<dyn VfsDentry>) -> Option<VfsMountPoint> {
+        self.0.lock().mounted(dentry)
+    }
+}
+
+fn make_namespace(root: &Arc<dyn VfsDentry>) -> Arc<Namespace> {
+    let table = MountTable::new("none", "ramfs", root.clone(), VfsMountFlags::empty());
+    Arc::new(Namespace(Mutex::new(table)))
+}
+
+#[derive(Default)]
+struct Notifier(Mutex<VfsNotifyRegistry>);
+
+impl VfsNotifier for Notifier {
+    fn notify(
+        &self,
+        inode: &Arc<dyn VfsInode>,
+        mask: VfsNotifyMask,
+        cookie: u32,
+        name: Option<&str>,
+    ) {
+        self.0.lock().notify(inode, mask, cookie, name)
+    }
+    fn next_cookie(&self) -> u32 {
+        self.0.lock().next_cookie()
+    }
+}
+
+/// Read the events of `group` as (wd, mask, cookie, name)
+fn read_events(notifier: &Notifier, group: usize) -> Vec<(i32, VfsNotifyMask, u32, String)> {
+    let mut buf = [0; 1024];
+    let len = match notifier.0.lock().read(group, &mut buf) {
+        Ok(len) => len,
+        Err(VfsError::EAGAIN) => return Vec::new(),
+        Err(e) => panic!("{e}"),
+    };
+    let mut events = Vec::new();
+    let mut buf = &buf[..len];
+    while !buf.is_empty() {
+        let field = |i: usize| u32::from_ne_bytes(buf[i * 4..i * 4 + 4].try_into().unwrap());
+        let name_len = field(3) as usize;
+        let name = &buf[16..16 + name_len];
+        let name = name.split(|b| *b == 0).next().unwrap();
+        events.push((
+            field(0) as i32,
+            VfsNotifyMask::from_bits_truncate(field(1)),
+            field(2),
+            String::from_utf8(name.to_vec()).unwrap(),
+        ));
+        buf = &buf[16 + name_len..];
+    }
+    events
+}
+
+fn names(events: &[(i32, VfsNotifyMask, u32, String)]) -> Vec<(VfsNotifyMask, &str)> {
+    events
+        .iter()
+        .map(|(_, mask, _, name)| (*mask, name.as_str()))
+        .collect()
+}
+
+#[test]
+fn test_notify_path() {
+    let (_fs, root) = make_ramfs();
+    let notifier = Arc::new(Notifier::default());
+    let path = VfsPath::new(root.clone(), root.clone()).with_notifier(notifier.clone());
+    let dir_mode = VfsInodeMode::DIR | VfsInodeMode::from_bits_truncate(0o755);
+    let file_mode = VfsInodeMode::FILE | VfsInodeMode::from_bits_truncate(0o644);
+    let dir = path.join("dir").unwrap().open(Some(dir_mode)).unwrap();
+    path.join("other").unwrap().open(Some(dir_mode)).unwrap();
+
+    let group = notifier.0.lock().create_group(16);
+    let wd = notifier
+        .0
+        .lock()
+        .add_watch(group, &dir.inode().unwrap(), VfsNotifyMask::IN_ALL_EVENTS)
+        .unwrap();
+    assert_eq!(
+        notifier.0.lock().poll(group, VfsPollEvents::IN).unwrap(),
+        VfsPollEvents::empty()
+    );
+
+    let f = path.join("dir/f").unwrap();
+    let dentry = f.open(Some(file_mode)).unwrap();
+    let file =
+        VfsOpenFile::<Mutex<()>>::new_with_notifier(dentry, VfsOpenFlags::O_RDWR, notifier.clone())
+            .unwrap();
+    file.write(b"hello").unwrap();
+    file.write(b"world").unwrap();
+    drop(file);
+    f.chmod(VfsInodeMode::from_bits_truncate(0o600)).unwrap();
+    path.join("dir/d").unwrap().open(Some(dir_mode)).unwrap();
+    assert_eq!(
+        notifier.0.lock().poll(group, VfsPollEvents::IN).unwrap(),
+        VfsPollEvents::IN
+    );
+    let events = read_events(&notifier, group);
+    assert!(events.iter().all(|event| event.0 == wd));
+    // the same events in a row are merged
+    assert_eq!(
+        names(&events),
+        vec![
+            (VfsNotifyMask::IN_CREATE, "f"),
+            (VfsNotifyMask::IN_OPEN, "f"),
+            (VfsNotifyMask::IN_MODIFY, "f"),
+            (VfsNotifyMask::IN_CLOSE_WRITE, "f"),
+            (VfsNotifyMask::IN_ATTRIB, "f"),
+            (VfsNotifyMask::IN_CREATE | VfsNotifyMask::IN_ISDIR, "d"),
+        ]
+    );
+
+    // the events of a rename share a cookie
+    let other = path.join("other").unwrap().open(None).unwrap();
+    let other_wd = notifier
+        .0
+        .lock()
+        .add_watch(group, &other.inode().unwrap(), VfsNotifyMask::IN_MOVE)
+        .unwrap();
+    let context = vfscore::path::SysContext {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+        cwd: root.clone(),
+        root: root.clone(),
+    };
+    f.rename_to(
+        context,
+        path.join("other/g").unwrap(),
+        VfsRenameFlag::empty(),
+    )
+    .unwrap();
+    let events = read_events(&notifier, group);
+    assert_eq!(events.len(), 2);
+    assert_eq!(
+        (events[0].0, events[0].1, events[0].3.as_str()),
+        (wd, VfsNotifyMask::IN_MOVED_FROM, "f")
+    );
+    assert_eq!(
+        (events[1].0, events[1].1, events[1].3.as_str()),
+        (other_wd, VfsNotifyMask::IN_MOVED_TO, "g")
+    );
+    assert_ne!(events[0].2, 0);
+    assert_eq!(events[0].2, events[1].2);
+
+    // the watch of a deleted directory is removed
+    path.join("dir/d").unwrap().rmdir().unwrap();
+    path.join("dir").unwrap().rmdir().unwrap();
+    let events = read_events(&notifier, group);
+    assert_eq!(
+        names(&events),
+        vec![
+            (VfsNotifyMask::IN_DELETE | VfsNotifyMask::IN_ISDIR, "d"),
+            (VfsNotifyMask::IN_DELETE_SELF, ""),
+            (VfsNotifyMask::IN_IGNORED, ""),
+        ]
+    );
+    assert_eq!(
+        notifier.0.lock().rm_watch(group, wd),
+        Err(VfsError::Invalid)
+    );
+    notifier.0.lock().rm_watch(group, other_wd).unwrap();
+    assert_eq!(
+        names(&read_events(&notifier, group)),
+        vec![(VfsNotifyMask::IN_IGNORED, "")]
+    );
+    notifier.0.lock().remove_group(group).unwrap();
+    assert_eq!(
+        notifier.0.lock().read(group, &mut [0; 64]),
+        Err(VfsError::EBADF)
+    );
+}
+
+#[test]
+fn test_notify_watch() {
+    let (_fs, root) = make_ramfs();
+    let notifier = Arc::new(Notifier::default());
+    let path = VfsPath::new(root.clone(), root.clone()).with_notifier(notifier.clone());
+    let file_mode = VfsInodeMode::FILE | VfsInodeMode::from_bits_truncate(0o644);
+    let f = path.join("f").unwrap();
+    let inode = f.open(Some(file_mode)).unwrap().inode().unwrap();
+    let mut registry = notifier.0.lock();
+    let group = registry.create_group(2);
+    assert_eq!(
+        registry.add_watch(
+            group,
+            &inode,
+            VfsNotifyMask::IN_ONLYDIR | VfsNotifyMask::IN_MODIFY
+        ),
+        Err(VfsError::NotDir)
+    );
+    assert_eq!(
+        registry.add_watch(group, &inode, VfsNotifyMask::IN_ONESHOT),
+        Err(VfsError::Invalid)
+    );
+    let wd = registry
+        .add_watch(group, &inode, VfsNotifyMask::IN_MODIFY)
+        .unwrap();
+    assert_eq!(
+        registry.add_watch(
+            group,
+            &inode,
+            VfsNotifyMask::IN_ATTRIB | VfsNotifyMask::IN_MASK_CREATE
+        ),
+        Err(VfsError::EExist)
+    );
+    let mask = VfsNotifyMask::IN_ATTRIB | VfsNotifyMask::IN_MASK_ADD | VfsNotifyMask::IN_ONESHOT;
+    assert_eq!(registry.add_watch(group, &inode, mask), Ok(wd));
+    drop(registry);
+
+    // a oneshot watch is removed after the first event
+    f.truncate(1).unwrap();
+    f.truncate(2).unwrap();
+    let events = read_events(&notifier, group);
+    assert_eq!(
+        names(&events),
+        vec![
+            (VfsNotifyMask::IN_MODIFY, ""),
+            (VfsNotifyMask::IN_IGNORED, "")
+        ]
+    );
+
+    // the queue overflows
+    let mut registry = notifier.0.lock();
+    registry
+        .add_watch(
+            group,
+            &inode,
+            VfsNotifyMask::IN_ATTRIB | VfsNotifyMask::IN_MODIFY,
+        )
+        .unwrap();
+    for mask in [
+        VfsNotifyMask::IN_MODIFY,
+        VfsNotifyMask::IN_ATTRIB,
+        VfsNotifyMask::IN_MODIFY,
+        VfsNotifyMask::IN_ATTRIB,
+    ] {
+        registry.notify(&inode, mask, 0, None);
+    }
+    assert_eq!(registry.pending(group).unwrap(), 16 * 3);
+    // the buffer is too small for an event
+    assert_eq!(registry.read(group, &mut [0; 8]), Err(VfsError::Invalid));
+    drop(registry);
+    let events = read_events(&notifier, group);
+    assert_eq!(
+        events.last().unwrap(),
+        &(-1, VfsNotifyMask::IN_Q_OVERFLOW, 0, String::new())
+    );
+}
+
+#[test]
+fn test_notify_umount() {
+    let (_fs, root) = make_ramfs();
+    let notifier = Arc::new(Notifier::default());
+    let path = VfsPath::new(root.clone(), root.clone()).with_notifier(notifier.clone());
+    let dir_mode = VfsInodeMode::DIR | VfsInodeMode::from_bits_truncate(0o755);
+    let file_mode = VfsInodeMode::FILE | VfsInodeMode::from_bits_truncate(0o644);
+    path.join("mnt").unwrap().open(Some(dir_mode)).unwrap();
+    let ns = make_namespace(&root);
+    let path = path.with_namespace(ns.clone());
+
+    let group = notifier.0.lock().create_group(16);
+    let root_wd = notifier
+        .0
+        .lock()
+        .add_watch(group, &root.inode().unwrap(), VfsNotifyMask::IN_ALL_EVENTS)
+        .unwrap();
+    let mnt = path.join("mnt").unwrap();
+    let (_sub_fs, sub_root) = make_ramfs();
+    ns.0.lock()
+        .mount(&mnt, "tmp", "ramfs", sub_root, VfsMountFlags::empty())
+        .unwrap();
+    assert_eq!(
+        read_events(&notifier, group),
+        vec![(
+            root_wd,
+            VfsNotifyMask::IN_MOUNT | VfsNotifyMask::IN_ISDIR,
+            0,
+            "mnt".to_string()
+        )]
+    );
+
+    let f = path.join("mnt/f").unwrap().open(Some(file_mode)).unwrap();
+    let wd = notifier
+        .0
+        .lock()
+        .add_watch(group, &f.inode().unwrap(), VfsNotifyMask::IN_MODIFY)
+        .unwrap();
+    ns.0.lock().umount(&mnt, VfsUmountFlags::empty()).unwrap();
+    assert_eq!(
+        names(&read_events(&notifier, group)),
+        vec![
+            (VfsNotifyMask::IN_UNMOUNT, ""),
+            (VfsNotifyMask::IN_IGNORED, "")
+        ]
+    );
+    assert_eq!(
+        notifier.0.lock().rm_watch(group, wd),
+        Err(VfsError::Invalid)
+    );
+    // the watches of other file systems are kept
+    notifier.0.lock().rm_watch(group, root_wd).unwrap();
+}
+
+#[test]
+fn test_notify_move_mount() {
+    let (_fs, root) = make_ramfs();
+    let notifier = Arc::new(Notifier::default());
+    let path = VfsPath::new(root.clone(), root.clone()).with_notifier(notifier.clone());
+    let dir_mode = VfsInodeMode::DIR | VfsInodeMode::from_bits_truncate(0o755);
+    let file_mode = VfsInodeMode::FILE | VfsInodeMode::from_bits_truncate(0o644);
+    for name in ["a", "b"] {
+        path.join(name).unwrap().open(Some(dir_mode)).unwrap();
+    }
+    let ns = make_namespace(&root);
+    let path = path.with_namespace(ns.clone());
+    let (a, b) = (path.join("a").unwrap(), path.join("b").unwrap());
+    let (_sub_fs, sub_root) = make_ramfs();
+    ns.0.lock()
+        .mount(&a, "tmp", "ramfs", sub_root, VfsMountFlags::empty())
+        .unwrap();
+    let f = path.join("a/f").unwrap().open(Some(file_mode)).unwrap();
+
+    let group = notifier.0.lock().create_group(16);
+    let root_wd = notifier
+        .0
+        .lock()
+        .add_watch(group, &root.inode().unwrap(), VfsNotifyMask::IN_ALL_EVENTS)
+        .unwrap();
+    let wd = notifier
+        .0
+        .lock()
+        .add_watch(group, &f.inode().unwrap(), VfsNotifyMask::IN_MODIFY)
+        .unwrap();
+    // the mount is unmounted from the source and mounted on the destination
+    ns.0.lock().move_mount(&a, &b).unwrap();
+    let events = read_events(&notifier, group);
+    assert_eq!(
+        names(&events),
+        vec![
+            (VfsNotifyMask::IN_UNMOUNT, ""),
+            (VfsNotifyMask::IN_IGNORED, ""),
+            (VfsNotifyMask::IN_MOUNT | VfsNotifyMask::IN_ISDIR, "b")
+        ]
+    );
+    assert_eq!(events[0].0, wd);
+    assert_eq!(events[2].0, root_wd);
+    assert!(path.join("b/f").unwrap().open(None).is_ok());
+}
@@ -1,12 +1,20 @@
-use std::sync::Arc;
+use std::{any::Any, sync::Arc};
 
 use ramfs::{RamFs, RamFsProvider};
 use spin::{Lazy, Mutex};
 use vfscore::{
     dentry::VfsDentry,
-    fstype::VfsFsType,
-    path::VfsPath,
-    utils::{VfsInodeMode, VfsNodeType, VfsTimeSpec},
+    error::VfsError,
+    fd::VfsOpenFile,
+    fstype::{VfsFsType, VfsMountPoint},
+    inode::{InodeAttr, InodeAttrValid, VfsInode},
+    mount::{MountTable, VfsNamespace},
+    path::{mount_flags, update_atime, DirIter, SysContext, VfsCred, VfsPath, RELATIME_INTERVAL},
+    utils::{
+        VfsInodeMode, VfsMountFlags, VfsNodePerm, VfsNodeType, VfsOpenFlags, VfsRenameFlag,
+        VfsResolveFlags, VfsStatxAttr, VfsStatxMask, VfsTimeSpec, VfsUmountFlags, VfsUtime,
+        VfsXattrFlag, UTIME_NOW, UTIME_OMIT,
+    },
     VfsResult,
 };
 
@@ -25,6 +33,20 @@ fn make_ramfs() -> VfsResult<Arc<dyn VfsDentry>> {
     FS.lock().clone().mount(0, "/", None, &[])
 }
 
+/// The mount namespace of a process, the kernel keeps its table behind a lock
+struct Namespace(Mutex<MountTable>);
+
+impl VfsNamespace for Namespace {
+    fn mounted(&self, dentry: &Arc<dyn VfsDentry>) -> Option<VfsMountPoint> {
+        self.0.lock().mounted(dentry)
+    }
+}
+
+fn make_namespace(root: &Arc<dyn VfsDentry>) -> Arc<Namespace> {
+    let table = MountTable::new("none", "ramfs", root.clone(), VfsMountFlags::empty());
+    Arc::new(Namespace(Mutex::new(table)))
+}
+
 #[test]
 fn test_vfs_path() {
     let root = make_ramfs().unwrap();
@@ -88,6 +110,1043 @@ fn test_dentry_path() {
     assert_eq!(path, "/d1/dd1");
 }
 
+#[test]
+fn test_permission() {
+    let root = make_ramfs().unwrap();
+    let path = VfsPath::new(root.clone(), root.clone());
+    let dir = VfsInodeMode::DIR;
+    path.join("private")
+        .unwrap()
+        .open(Some(dir | VfsInodeMode::from_bits_truncate(0o700)))
+        .unwrap();
+    path.join("public")
+        .unwrap()
+        .open(Some(dir | VfsInodeMode::from_bits_truncate(0o777)))
+        .unwrap();
+    path.join("readonly")
+        .unwrap()
+        .open(Some(dir | VfsInodeMode::from_bits_truncate(0o755)))
+        .unwrap();
+
+    let user = path.with_cred(VfsCred {
+        uid: 1000,
+        gid: 1000,
+    });
+    let file = VfsInodeMode::FILE | VfsInodeMode::from_bits_truncate(0o644);
+    // no search permission
+    let res = user.join("private/f1").unwrap().open(Some(file));
+    assert_eq!(res.err(), Some(VfsError::Access));
+    // no write permission
+    let res = user.join("readonly/f1").unwrap().open(Some(file));
+    assert_eq!(res.err(), Some(VfsError::Access));
+    user.join("public/f1").unwrap().open(Some(file)).unwrap();
+    // the superuser is not restricted by the permission bits
+    path.join("readonly/f1").unwrap().open(Some(file)).unwrap();
+    assert_eq!(
+        user.join("readonly/f1").unwrap().unlink().err(),
+        Some(VfsError::Access)
+    );
+    user.join("public/f1").unwrap().unlink().unwrap();
+}
+
+#[test]
+fn test_chown() {
+    let root = make_ramfs().unwrap();
+    let path = VfsPath::new(root.clone(), root.clone());
+    path.join("tmp")
+        .unwrap()
+        .open(Some(
+            VfsInodeMode::DIR | VfsInodeMode::from_bits_truncate(0o777),
+        ))
+        .unwrap();
+    let user = path.with_cred(VfsCred {
+        uid: 1000,
+        gid: 1000,
+    });
+    let file = VfsInodeMode::FILE | VfsInodeMode::from_bits_truncate(0o755);
+    let f1 = user.join("tmp/f1").unwrap();
+    let stat = f1
+        .open(Some(file))
+        .unwrap()
+        .inode()
+        .unwrap()
+        .get_attr()
+        .unwrap();
+    assert_eq!((stat.st_uid, stat.st_gid), (1000, 1000));
+
+    // only the superuser can give the file away
+    assert_eq!(
+        f1.chown(Some(0), None).err(),
+        Some(VfsError::PermissionDenied)
+    );
+    f1.chmod(VfsInodeMode::from_bits_truncate(0o6755)).unwrap();
+    let f1_root = path.join("tmp/f1").unwrap();
+    f1_root.chown(Some(1001), Some(1001)).unwrap();
+    let stat = f1_root
+        .open(None)
+        .unwrap()
+        .inode()
+        .unwrap()
+        .get_attr()
+        .unwrap();
+    assert_eq!((stat.st_uid, stat.st_gid), (1001, 1001));
+    // chown clears the set-user-ID and set-group-ID bits
+    assert_eq!(stat.st_mode, (VfsInodeMode::FILE.bits() | 0o755));
+    assert_eq!(
+        f1.chmod(VfsInodeMode::from_bits_truncate(0o777)).err(),
+        Some(VfsError::PermissionDenied)
+    );
+}
+
+#[test]
+fn test_mount_table() {
+    let root = make_ramfs().unwrap();
+    let path = VfsPath::new(root.clone(), root.clone());
+    let dir = VfsInodeMode::DIR | VfsInodeMode::from_bits_truncate(0o755);
+    path.join("mnt").unwrap().open(Some(dir)).unwrap();
+    let ns = make_namespace(&root);
+    let path = path.with_namespace(ns.clone());
+
+    let mnt = path.join("mnt").unwrap();
+    let id =
+        ns.0.lock()
+            .mount(
+                &mnt,
+                "tmp",
+                "ramfs",
+                make_ramfs().unwrap(),
+                VfsMountFlags::MS_NODEV,
+            )
+            .unwrap();
+    path.join("mnt/sub").unwrap().open(Some(dir)).unwrap();
+    let sub = path.join("mnt/sub").unwrap();
+    let sub_id =
+        ns.0.lock()
+            .mount(
+                &sub,
+                "tmp2",
+                "ramfs",
+                make_ramfs().unwrap(),
+                VfsMountFlags::MS_RDONLY,
+            )
+            .unwrap();
+    assert_eq!(ns.0.lock().get(sub_id).unwrap().parent, Some(id));
+    assert_eq!(ns.0.lock().find("/mnt/sub/f1").unwrap().id, sub_id);
+    assert_eq!(
+        ns.0.lock().proc_mounts(),
+        "none / ramfs rw 0 0\ntmp /mnt ramfs rw,nodev 0 0\ntmp2 /mnt/sub ramfs ro 0 0\n"
+    );
+
+    assert_eq!(
+        ns.0.lock().umount(&mnt, VfsUmountFlags::empty()).err(),
+        Some(VfsError::EBUSY)
+    );
+    ns.0.lock()
+        .umount(&mnt, VfsUmountFlags::MNT_DETACH)
+        .unwrap();
+    assert_eq!(ns.0.lock().iter().count(), 1);
+    assert!(path.join("mnt/sub").unwrap().open(None).is_err());
+}
+
+#[test]
+fn test_mount_namespace() {
+    let root = make_ramfs().unwrap();
+    let path = VfsPath::new(root.clone(), root.clone());
+    let dir = VfsInodeMode::DIR | VfsInodeMode::from_bits_truncate(0o755);
+    let file = VfsInodeMode::FILE | VfsInodeMode::from_bits_truncate(0o644);
+    for name in ["a", "b"] {
+        path.join(name).unwrap().open(Some(dir)).unwrap();
+    }
+    let ns = make_namespace(&root);
+    let path = path.with_namespace(ns.clone());
+    let (a, b) = (path.join("a").unwrap(), path.join("b").unwrap());
+    ns.0.lock()
+        .mount(
+            &a,
+            "tmp",
+            "ramfs",
+            make_ramfs().unwrap(),
+            VfsMountFlags::empty(),
+        )
+        .unwrap();
+    path.join("a/f").unwrap().open(Some(file)).unwrap();
+
+    // the child gets a copy of the namespace
+    let child_ns = Arc::new(Namespace(Mutex::new(ns.0.lock().clone())));
+    let child = path.with_namespace(child_ns.clone());
+    assert!(child.join("a/f").unwrap().open(None).is_ok());
+
+    // the mounts made later in the original are not seen by the copy
+    ns.0.lock()
+        .mount(
+            &b,
+            "tmp2",
+            "ramfs",
+            make_ramfs().unwrap(),
+            VfsMountFlags::empty(),
+        )
+        .unwrap();
+    path.join("b/g").unwrap().open(Some(file)).unwrap();
+    assert!(path.join("b/g").unwrap().open(None).is_ok());
+    assert_eq!(
+        child.join("b/g").unwrap().open(None).err(),
+        Some(VfsError::NoEntry)
+    );
+    assert_eq!(child_ns.0.lock().iter().count(), 2);
+    // and the other way around
+    child_ns
+        .0
+        .lock()
+        .umount(&child.join("a").unwrap(), VfsUmountFlags::empty())
+        .unwrap();
+    assert!(child.join("a/f").unwrap().open(None).is_err());
+    assert!(path.join("a/f").unwrap().open(None).is_ok());
+
+    // the mounts of a namespace are not attached to the dentries
+    let plain = VfsPath::new(root.clone(), root.clone());
+    assert!(plain.join("a/f").unwrap().open(None).is_err());
+    assert_eq!(b.umount(), Err(VfsError::Invalid));
+}
+
+#[test]
+fn test_mount_flags() {
+    let root = make_ramfs().unwrap();
+    let path = VfsPath::new(root.clone(), root.clone());
+    let dir = VfsInodeMode::DIR | VfsInodeMode::from_bits_truncate(0o755);
+    let file = VfsInodeMode::FILE | VfsInodeMode::from_bits_truncate(0o644);
+    path.join("ro").unwrap().open(Some(dir)).unwrap();
+    let fs = make_ramfs().unwrap();
+    let fs_path = VfsPath::new(fs.clone(), fs.clone());
+    fs_path.join("f1").unwrap().open(Some(file)).unwrap();
+    fs_path.join("link").unwrap().symlink("f1").unwrap();
+    let flags =
+        VfsMountFlags::MS_RDONLY | VfsMountFlags::MS_NOSYMFOLLOW | VfsMountFlags::MS_NOATIME;
+    path.join("ro").unwrap().mount(fs, flags.bits()).unwrap();
+
+    let f1 = path.join("ro/f1").unwrap();
+    assert_eq!(mount_flags(&f1.open(None).unwrap()), flags);
+    assert_eq!(
+        path.join("ro/f2").unwrap().open(Some(file)).err(),
+        Some(VfsError::ReadOnlyFs)
+    );
+    assert_eq!(f1.unlink().err(), Some(VfsError::ReadOnlyFs));
+    assert_eq!(f1.truncate(0).err(), Some(VfsError::ReadOnlyFs));
+    assert_eq!(
+        path.join("ro/missing").unwrap().truncate(0),
+        Err(VfsError::NoEntry)
+    );
+    assert_eq!(
+        path.join("ro/link").unwrap().open(None).err(),
+        Some(VfsError::SymLinkLoop)
+    );
+
+    let dt = f1.open(None).unwrap();
+    let now = VfsTimeSpec::new(1, 0);
+    update_atime(&dt, now).unwrap();
+    assert_eq!(dt.inode().unwrap().get_attr().unwrap().st_atime.sec, 0);
+    // the parent file system is not affected
+    let f2 = path.join("f2").unwrap().open(Some(file)).unwrap();
+    update_atime(&f2, now).unwrap();
+    assert_eq!(f2.inode().unwrap().get_attr().unwrap().st_atime.sec, 1);
+}
+
+#[test]
+fn test_set_times() {
+    let root = make_ramfs().unwrap();
+    let path = VfsPath::new(root.clone(), root.clone());
+    let dir = VfsInodeMode::DIR | VfsInodeMode::from_bits_truncate(0o777);
+    let file = VfsInodeMode::FILE | VfsInodeMode::from_bits_truncate(0o644);
+    path.join("public").unwrap().open(Some(dir)).unwrap();
+    let f = path.join("public/f").unwrap();
+    let inode = f.open(Some(file)).unwrap().inode().unwrap();
+    let stat = || inode.get_attr().unwrap();
+
+    let (t1, t2, now) = (
+        VfsTimeSpec::new(1, 100),
+        VfsTimeSpec::new(2, 200),
+        VfsTimeSpec::new(3, 300),
+    );
+    f.set_times(VfsUtime::Time(t1), VfsUtime::Time(t2), now, true)
+        .unwrap();
+    assert_eq!(
+        (stat().st_atime, stat().st_mtime, stat().st_ctime),
+        (t1, t2, now)
+    );
+    // the omitted time is kept
+    let later = VfsTimeSpec::new(4, 0);
+    f.set_times(VfsUtime::Omit, VfsUtime::Now, later, true)
+        .unwrap();
+    assert_eq!(
+        (stat().st_atime, stat().st_mtime, stat().st_ctime),
+        (t1, later, later)
+    );
+    f.set_times(VfsUtime::Omit, VfsUtime::Omit, VfsTimeSpec::new(5, 0), true)
+        .unwrap();
+    assert_eq!(stat().st_ctime, later);
+    assert_eq!(
+        VfsUtime::from(VfsTimeSpec::new(6, UTIME_OMIT)),
+        VfsUtime::Omit
+    );
+    assert_eq!(
+        VfsUtime::from(VfsTimeSpec::new(6, UTIME_NOW)),
+        VfsUtime::Now
+    );
+    // nsec out of range is invalid
+    for nsec in [1_000_000_000, UTIME_OMIT - 1] {
+        let time = VfsUtime::from(VfsTimeSpec::new(6, nsec));
+        assert_eq!(
+            f.set_times(time, VfsUtime::Omit, now, true),
+            Err(VfsError::Invalid)
+        );
+        assert_eq!(
+            f.set_times(VfsUtime::Now, time, now, true),
+            Err(VfsError::Invalid)
+        );
+    }
+    assert_eq!(stat().st_ctime, later);
+
+    // only the owner can set explicit times, others need the write permission to touch
+    let user = path.with_cred(VfsCred {
+        uid: 1000,
+        gid: 1000,
+    });
+    let user_f = user.join("public/f").unwrap();
+    assert_eq!(
+        user_f.set_times(VfsUtime::Time(t1), VfsUtime::Omit, now, true),
+        Err(VfsError::PermissionDenied)
+    );
+    assert_eq!(
+        user_f.set_times(VfsUtime::Now, VfsUtime::Now, now, true),
+        Err(VfsError::Access)
+    );
+    f.chmod(VfsInodeMode::from_bits_truncate(0o666)).unwrap();
+    user_f
+        .set_times(VfsUtime::Now, VfsUtime::Now, now, true)
+        .unwrap();
+    let own = user.join("public/own").unwrap();
+    own.open(Some(file)).unwrap();
+    own.set_times(VfsUtime::Time(t1), VfsUtime::Time(t2), now, true)
+        .unwrap();
+
+    // the immutable and append-only files
+    let set_flags = |flags: VfsStatxAttr| {
+        inode
+            .set_attr(InodeAttr {
+                valid: InodeAttrValid::FLAGS,
+                flags,
+                ..Default::default()
+            })
+            .unwrap()
+    };
+    set_flags(VfsStatxAttr::STATX_ATTR_APPEND);
+    assert_eq!(
+        f.set_times(VfsUtime::Time(t1), VfsUtime::Omit, now, true),
+        Err(VfsError::PermissionDenied)
+    );
+    f.set_times(VfsUtime::Now, VfsUtime::Now, now, true)
+        .unwrap();
+    set_flags(VfsStatxAttr::STATX_ATTR_IMMUTABLE);
+    assert_eq!(
+        f.set_times(VfsUtime::Now, VfsUtime::Now, now, true),
+        Err(VfsError::PermissionDenied)
+    );
+}
+
+#[test]
+fn test_relatime() {
+    let root = make_ramfs().unwrap();
+    let path = VfsPath::new(root.clone(), root.clone());
+    let dir = VfsInodeMode::DIR | VfsInodeMode::from_bits_truncate(0o755);
+    let file = VfsInodeMode::FILE | VfsInodeMode::from_bits_truncate(0o644);
+    path.join("rel").unwrap().open(Some(dir)).unwrap();
+    let fs = make_ramfs().unwrap();
+    path.join("rel")
+        .unwrap()
+        .mount(fs, VfsMountFlags::MS_RELATIME.bits())
+        .unwrap();
+    let dt = path.join("rel/f").unwrap().open(Some(file)).unwrap();
+    let stat = || dt.inode().unwrap().get_attr().unwrap();
+    let ctime = stat().st_ctime;
+
+    // the access time is updated once after the modification, the ctime is kept
+    update_atime(&dt, VfsTimeSpec::new(10, 0)).unwrap();
+    assert_eq!(stat().st_atime.sec, 10);
+    assert_eq!(stat().st_ctime, ctime);
+    update_atime(&dt, VfsTimeSpec::new(20, 0)).unwrap();
+    assert_eq!(stat().st_atime.sec, 10);
+    // or when it is too old
+    let old = 10 + RELATIME_INTERVAL;
+    update_atime(&dt, VfsTimeSpec::new(old, 0)).unwrap();
+    assert_eq!(stat().st_atime.sec, old);
+    update_atime(&dt, VfsTimeSpec::new(old + 1, 0)).unwrap();
+    assert_eq!(stat().st_atime.sec, old);
+}
+
+#[test]
+fn test_bind_mount() {
+    let root = make_ramfs().unwrap();
+    let path = VfsPath::new(root.clone(), root.clone());
+    let dir = VfsInodeMode::DIR | VfsInodeMode::from_bits_truncate(0o755);
+    let file = VfsInodeMode::FILE | VfsInodeMode::from_bits_truncate(0o644);
+    for name in ["data", "data/sub", "data/d", "b1", "b2", "ro", "m1", "m2"] {
+        path.join(name).unwrap().open(Some(dir)).unwrap();
+    }
+    path.join("data/f1").unwrap().open(Some(file)).unwrap();
+    let ns = make_namespace(&root);
+    let path = path.with_namespace(ns.clone());
+    let sub = path.join("data/sub").unwrap();
+    ns.0.lock()
+        .mount(
+            &sub,
+            "tmp",
+            "ramfs",
+            make_ramfs().unwrap(),
+            VfsMountFlags::empty(),
+        )
+        .unwrap();
+    path.join("data/sub/s1").unwrap().open(Some(file)).unwrap();
+
+    let data = path.join("data").unwrap();
+    let b1 = path.join("b1").unwrap();
+    ns.0.lock()
+        .bind(&b1, &data, VfsMountFlags::empty())
+        .unwrap();
+    let b2 = path.join("b2").unwrap();
+    ns.0.lock().bind(&b2, &data, VfsMountFlags::MS_REC).unwrap();
+    assert!(path.join("b1/f1").unwrap().open(None).is_ok());
+    // the mounts below the source are only visible with MS_REC
+    assert!(path.join("b1/sub/s1").unwrap().open(None).is_err());
+    assert!(path.join("b2/sub/s1").unwrap().open(None).is_ok());
+    // ".." goes back to the mount point, not the parent of the source
+    let f1 = path.join("b1/../b2/f1").unwrap().open(None).unwrap();
+    assert!(Arc::ptr_eq(
+        &f1,
+        &data.join("f1").unwrap().open(None).unwrap()
+    ));
+    assert_eq!(
+        ns.0.lock().proc_mounts(),
+        "none / ramfs rw 0 0\ntmp /data/sub ramfs rw 0 0\nnone /b1 ramfs rw 0 0\n\
+         none /b2 ramfs rw 0 0\ntmp /b2/sub ramfs rw 0 0\n"
+    );
+
+    // the source can't be bound on itself or below itself
+    for target in ["data", "data/d", "data/sub", "b2/d"] {
+        let target = path.join(target).unwrap();
+        assert_eq!(
+            ns.0.lock()
+                .bind(&target, &data, VfsMountFlags::MS_REC)
+                .err(),
+            Some(VfsError::Invalid)
+        );
+    }
+    // the flags of the bind apply to the files reached through it
+    let ro = path.join("ro").unwrap();
+    ns.0.lock()
+        .bind(&ro, &data, VfsMountFlags::MS_RDONLY)
+        .unwrap();
+    assert_eq!(
+        path.join("ro/f2").unwrap().open(Some(file)).err(),
+        Some(VfsError::ReadOnlyFs)
+    );
+    assert_eq!(
+        path.join("ro/f1").unwrap().unlink(),
+        Err(VfsError::ReadOnlyFs)
+    );
+    assert_eq!(
+        path.join("ro/f1")
+            .unwrap()
+            .open_with_flags(None, VfsOpenFlags::O_WRONLY)
+            .err(),
+        Some(VfsError::ReadOnlyFs)
+    );
+    assert_eq!(
+        path.join("ro/d/../f1").unwrap().truncate(0),
+        Err(VfsError::ReadOnlyFs)
+    );
+    path.join("data/f1")
+        .unwrap()
+        .open_with_flags(None, VfsOpenFlags::O_WRONLY)
+        .unwrap();
+    path.join("b1/f2").unwrap().open(Some(file)).unwrap();
+    path.join("ro/f2").unwrap().open(None).unwrap();
+
+    let m1 = path.join("m1").unwrap();
+    let m2 = path.join("m2").unwrap();
+    ns.0.lock()
+        .mount(
+            &m1,
+            "tmp2",
+            "ramfs",
+            make_ramfs().unwrap(),
+            VfsMountFlags::empty(),
+        )
+        .unwrap();
+    path.join("m1/x").unwrap().open(Some(file)).unwrap();
+    path.join("m1/y").unwrap().open(Some(dir)).unwrap();
+    assert_eq!(
+        ns.0.lock()
+            .move_mount(&m1, &path.join("m1/y").unwrap())
+            .err(),
+        Some(VfsError::Invalid)
+    );
+    ns.0.lock().move_mount(&m1, &m2).unwrap();
+    assert!(path.join("m1/x").unwrap().open(None).is_err());
+    assert!(path.join("m2/x").unwrap().open(None).is_ok());
+    assert_eq!(ns.0.lock().find("/m2/x").unwrap().source, "tmp2");
+    ns.0.lock().umount(&m2, VfsUmountFlags::empty()).unwrap();
+}
+
+#[test]
+fn test_unix_socket() {
+    let root = make_ramfs().unwrap();
+    let path = VfsPath::new(root.clone(), root.clone());
+    let sock_path = path.join("sock").unwrap();
+    let socket: Arc<dyn Any + Send + Sync> = Arc::new(42usize);
+    let dentry = sock_path
+        .bind_socket(&socket, VfsNodePerm::from_bits_truncate(0o777))
+        .unwrap();
+    assert_eq!(dentry.inode().unwrap().inode_type(), VfsNodeType::Socket);
+    assert_eq!(
+        sock_path
+            .bind_socket(&socket, VfsNodePerm::from_bits_truncate(0o777))
+            .err(),
+        Some(VfsError::EExist)
+    );
+    let found = sock_path.resolve_socket().unwrap();
+    assert!(Arc::ptr_eq(&found, &socket));
+    assert_eq!(found.downcast_ref::<usize>(), Some(&42));
+    drop(found);
+
+    // a closed socket refuses connections
+    drop(socket);
+    assert_eq!(
+        sock_path.resolve_socket().err(),
+        Some(VfsError::ECONNREFUSED)
+    );
+    // so does a path which is not a socket
+    let file = path.join("not_sock").unwrap();
+    file.open(Some(
+        VfsInodeMode::FILE | VfsInodeMode::from_bits_truncate(0o666),
+    ))
+    .unwrap();
+    assert_eq!(file.resolve_socket().err(), Some(VfsError::ECONNREFUSED));
+    sock_path.unlink().unwrap();
+    file.unlink().unwrap();
+}
+
+#[test]
+fn test_open_flags() {
+    let root = make_ramfs().unwrap();
+    let path = VfsPath::new(root.clone(), root.clone());
+    let mode = Some(VfsInodeMode::from_bits_truncate(0o644));
+    let dir = path.join("open_flags").unwrap();
+    dir.open(Some(
+        VfsInodeMode::DIR | VfsInodeMode::from_bits_truncate(0o755),
+    ))
+    .unwrap();
+    let file = dir.join("f").unwrap();
+    let open = |path: &VfsPath, flags| path.open_with_flags(mode, flags).err();
+
+    assert_eq!(open(&file, VfsOpenFlags::O_RDONLY), Some(VfsError::NoEntry));
+    let create = VfsOpenFlags::O_CREAT | VfsOpenFlags::O_EXCL | VfsOpenFlags::O_RDWR;
+    let f = file.open_with_flags(mode, create).unwrap();
+    assert_eq!(open(&file, create), Some(VfsError::EExist));
+    f.inode().unwrap().write_at(0, b"data").unwrap();
+    assert_eq!(open(&file, VfsOpenFlags::O_CREAT), None);
+    assert_eq!(f.inode().unwrap().get_attr().unwrap().st_size, 4);
+    assert_eq!(
+        open(&file, VfsOpenFlags::O_WRONLY | VfsOpenFlags::O_TRUNC),
+        None
+    );
+    assert_eq!(f.inode().unwrap().get_attr().unwrap().st_size, 0);
+
+    assert_eq!(
+        open(&file, VfsOpenFlags::O_DIRECTORY),
+        Some(VfsError::NotDir)
+    );
+    assert_eq!(
+        open(&dir.join("f/").unwrap(), VfsOpenFlags::O_RDONLY),
+        Some(VfsError::NotDir)
+    );
+    assert_eq!(open(&dir, VfsOpenFlags::O_DIRECTORY), None);
+    assert_eq!(open(&dir, VfsOpenFlags::O_WRONLY), Some(VfsError::IsDir));
+    assert_eq!(open(&dir, VfsOpenFlags::O_CREAT), Some(VfsError::IsDir));
+    assert_eq!(
+        open(
+            &dir.join("new").unwrap(),
+            VfsOpenFlags::O_CREAT | VfsOpenFlags::O_DIRECTORY
+        ),
+        Some(VfsError::Invalid)
+    );
+    assert_eq!(
+        open(&dir.join("new/").unwrap(), VfsOpenFlags::O_CREAT),
+        Some(VfsError::IsDir)
+    );
+
+    // symlinks
+    let link = dir.join("link").unwrap();
+    link.symlink("f").unwrap();
+    assert_eq!(
+        open(&link, VfsOpenFlags::O_NOFOLLOW),
+        Some(VfsError::SymLinkLoop)
+    );
+    let d = link.open_with_flags(None, VfsOpenFlags::O_RDONLY).unwrap();
+    assert_eq!(d.inode().unwrap().inode_type(), VfsNodeType::File);
+    let d = link
+        .open_with_flags(None, VfsOpenFlags::O_PATH | VfsOpenFlags::O_NOFOLLOW)
+        .unwrap();
+    assert_eq!(d.inode().unwrap().inode_type(), VfsNodeType::SymLink);
+    assert_eq!(
+        open(&link, VfsOpenFlags::O_CREAT | VfsOpenFlags::O_EXCL),
+        Some(VfsError::EExist)
+    );
+    // O_CREAT through a dangling symlink creates its target
+    let dangling = dir.join("dangling").unwrap();
+    dangling.symlink("target").unwrap();
+    assert_eq!(
+        open(&dangling, VfsOpenFlags::O_RDONLY),
+        Some(VfsError::NoEntry)
+    );
+    let d = dangling
+        .open_with_flags(mode, VfsOpenFlags::O_CREAT | VfsOpenFlags::O_RDWR)
+        .unwrap();
+    assert_eq!(d.name(), "target");
+    assert!(dir.join("target").unwrap().exists().is_ok());
+
+    // O_PATH doesn't need the permission of the file
+    let user = dir.with_cred(VfsCred {
+        uid: 1000,
+        gid: 1000,
+    });
+    let user_file = user.join("f").unwrap();
+    assert_eq!(
+        open(&user_file, VfsOpenFlags::O_WRONLY),
+        Some(VfsError::Access)
+    );
+    assert_eq!(
+        open(&user_file, VfsOpenFlags::O_PATH | VfsOpenFlags::O_WRONLY),
+        None
+    );
+
+    // the unnamed file is not in the directory
+    let tmp = dir
+        .open_with_flags(mode, VfsOpenFlags::O_TMPFILE | VfsOpenFlags::O_RDWR)
+        .unwrap();
+    let tmp_inode = tmp.inode().unwrap();
+    assert_eq!(tmp_inode.inode_type(), VfsNodeType::File);
+    tmp_inode.write_at(0, b"tmp").unwrap();
+    let dir_dentry = dir.open(None).unwrap();
+    assert_eq!(tmp.name(), "");
+    assert!(Arc::ptr_eq(&tmp.parent().unwrap(), &dir_dentry));
+    assert!(dir_dentry.find("").is_none());
+    let dir_inode = dir_dentry.inode().unwrap();
+    let count = dir_inode.children().count();
+    assert!(dir_inode.children().all(|entry| entry.name != tmp.name()));
+    // it can be linked into the directory
+    dir_inode.link("linked", tmp_inode.clone()).unwrap();
+    let mut buf = [0; 3];
+    let linked = dir.join("linked").unwrap().open(None).unwrap();
+    linked.inode().unwrap().read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf, b"tmp");
+    assert_eq!(dir_inode.children().count(), count + 1);
+    assert_eq!(open(&dir, VfsOpenFlags::O_TMPFILE), Some(VfsError::Invalid));
+    assert_eq!(
+        open(&file, VfsOpenFlags::O_TMPFILE | VfsOpenFlags::O_RDWR),
+        Some(VfsError::NotDir)
+    );
+}
+
+#[test]
+fn test_symlink_resolve() {
+    let root = make_ramfs().unwrap();
+    let path = VfsPath::new(root.clone(), root.clone());
+    let base = path.join("symlink_resolve").unwrap();
+    let dir_mode = VfsInodeMode::DIR | VfsInodeMode::from_bits_truncate(0o755);
+    base.open(Some(dir_mode)).unwrap();
+    base.join("dir").unwrap().open(Some(dir_mode)).unwrap();
+    base.join("dir/f")
+        .unwrap()
+        .open(Some(
+            VfsInodeMode::FILE | VfsInodeMode::from_bits_truncate(0o644),
+        ))
+        .unwrap();
+
+    // symlinks in the middle of the path
+    base.join("rel").unwrap().symlink("dir").unwrap();
+    base.join("abs")
+        .unwrap()
+        .symlink("/symlink_resolve/dir")
+        .unwrap();
+    for p in ["rel/f", "abs/f", "abs/../dir/f", "rel/./f"] {
+        let d = base.join(p).unwrap().open(None).unwrap();
+        assert_eq!(d.inode().unwrap().inode_type(), VfsNodeType::File, "{p}");
+    }
+    let link = base.join("rel").unwrap();
+    assert_eq!(
+        link.exists().unwrap().inode().unwrap().inode_type(),
+        VfsNodeType::SymLink
+    );
+    let d = base.join("rel/").unwrap().exists().unwrap();
+    assert_eq!(d.inode().unwrap().inode_type(), VfsNodeType::Dir);
+    // the parent of a new file can be a symlink
+    base.join("rel/g")
+        .unwrap()
+        .open(Some(
+            VfsInodeMode::FILE | VfsInodeMode::from_bits_truncate(0o644),
+        ))
+        .unwrap();
+    base.join("dir/g").unwrap().exists().unwrap();
+
+    // a target longer than 255 bytes
+    let long = "./".repeat(200) + "dir/f";
+    base.join("long").unwrap().symlink(&long).unwrap();
+    let d = base.join("long").unwrap().open(None).unwrap();
+    assert_eq!(d.inode().unwrap().inode_type(), VfsNodeType::File);
+    assert_eq!(
+        base.join("too_long").unwrap().symlink(&"a".repeat(4096)),
+        Err(VfsError::NameTooLong)
+    );
+
+    // 40 symlinks can be followed, but not 41
+    base.join("l0").unwrap().symlink("dir/f").unwrap();
+    for i in 1..=40 {
+        base.join(format!("l{i}"))
+            .unwrap()
+            .symlink(&format!("l{}", i - 1))
+            .unwrap();
+    }
+    base.join("l39").unwrap().open(None).unwrap();
+    assert_eq!(
+        base.join("l40").unwrap().open(None).err(),
+        Some(VfsError::SymLinkLoop)
+    );
+    // a loop
+    base.join("a").unwrap().symlink("b").unwrap();
+    base.join("b").unwrap().symlink("a/f").unwrap();
+    assert_eq!(
+        base.join("a").unwrap().open(None).err(),
+        Some(VfsError::SymLinkLoop)
+    );
+    assert_eq!(
+        base.join("a/f").unwrap().exists().err(),
+        Some(VfsError::SymLinkLoop)
+    );
+}
+
+#[test]
+fn test_resolve_flags() {
+    let root = make_ramfs().unwrap();
+    let path = VfsPath::new(root.clone(), root.clone());
+    let dir = VfsInodeMode::DIR | VfsInodeMode::from_bits_truncate(0o755);
+    let file = VfsInodeMode::FILE | VfsInodeMode::from_bits_truncate(0o644);
+    path.join("jail").unwrap().open(Some(dir)).unwrap();
+    path.join("jail/dir").unwrap().open(Some(dir)).unwrap();
+    path.join("jail/dir/f").unwrap().open(Some(file)).unwrap();
+    path.join("jail/mnt").unwrap().open(Some(dir)).unwrap();
+    path.join("f").unwrap().open(Some(file)).unwrap();
+    path.join("jail/rel").unwrap().symlink("dir/f").unwrap();
+    path.join("jail/abs").unwrap().symlink("/dir/f").unwrap();
+    path.join("jail/up").unwrap().symlink("../f").unwrap();
+    let fs = make_ramfs().unwrap();
+    VfsPath::new(fs.clone(), fs.clone())
+        .join("g")
+        .unwrap()
+        .open(Some(file))
+        .unwrap();
+    path.join("jail/mnt").unwrap().mount(fs, 0).unwrap();
+
+    let jail = path.join("jail").unwrap().open(None).unwrap();
+    let start = VfsPath::new(root.clone(), jail.clone());
+    let resolve = |flags: VfsResolveFlags, p: &str| {
+        start
+            .with_resolve(flags)
+            .unwrap()
+            .join(p)
+            .unwrap()
+            .open(None)
+            .map(|d| d.name())
+    };
+    assert_eq!(
+        start
+            .with_resolve(VfsResolveFlags::RESOLVE_BENEATH | VfsResolveFlags::RESOLVE_IN_ROOT)
+            .err(),
+        Some(VfsError::Invalid)
+    );
+
+    let beneath = VfsResolveFlags::RESOLVE_BENEATH;
+    assert_eq!(resolve(beneath, "dir/../rel"), Ok("f".to_string()));
+    assert_eq!(resolve(beneath, ".."), Err(VfsError::CrossDevice));
+    assert_eq!(resolve(beneath, "dir/../../f"), Err(VfsError::CrossDevice));
+    assert_eq!(resolve(beneath, "up"), Err(VfsError::CrossDevice));
+    assert_eq!(resolve(beneath, "abs"), Err(VfsError::CrossDevice));
+
+    // ".." and absolute symlinks stay in the start directory
+    let in_root = VfsResolveFlags::RESOLVE_IN_ROOT;
+    assert_eq!(resolve(in_root, "../../dir/f"), Ok("f".to_string()));
+    assert_eq!(resolve(in_root, "abs"), Ok("f".to_string()));
+    assert_eq!(resolve(in_root, "up"), Err(VfsError::NoEntry));
+    // without the flags, the symlinks escape
+    assert_eq!(resolve(VfsResolveFlags::empty(), "up"), Ok("f".to_string()));
+    assert_eq!(
+        resolve(VfsResolveFlags::empty(), "abs"),
+        Err(VfsError::NoEntry)
+    );
+
+    let no_symlinks = VfsResolveFlags::RESOLVE_NO_SYMLINKS;
+    assert_eq!(resolve(no_symlinks, "rel"), Err(VfsError::SymLinkLoop));
+    let link = start
+        .with_resolve(no_symlinks)
+        .unwrap()
+        .join("rel")
+        .unwrap();
+    assert_eq!(
+        link.open_with_flags(None, VfsOpenFlags::O_PATH | VfsOpenFlags::O_NOFOLLOW)
+            .unwrap()
+            .inode()
+            .unwrap()
+            .inode_type(),
+        VfsNodeType::SymLink
+    );
+
+    let no_xdev = VfsResolveFlags::RESOLVE_NO_XDEV;
+    assert_eq!(resolve(no_xdev, "dir/f"), Ok("f".to_string()));
+    assert_eq!(resolve(no_xdev, "mnt/g"), Err(VfsError::CrossDevice));
+    assert!(resolve(VfsResolveFlags::empty(), "mnt/g").is_ok());
+    let mnt = path.join("jail/mnt").unwrap().open(None).unwrap();
+    let in_mnt = VfsPath::new(root.clone(), mnt)
+        .with_resolve(no_xdev)
+        .unwrap();
+    assert!(in_mnt.join("g").unwrap().open(None).is_ok());
+    assert_eq!(
+        in_mnt.join("../dir").unwrap().open(None).err(),
+        Some(VfsError::CrossDevice)
+    );
+
+    // the entries which are not in the dentry cache can't be found
+    let cached = VfsResolveFlags::RESOLVE_CACHED;
+    assert_eq!(resolve(cached, "dir/f"), Ok("f".to_string()));
+    jail.inode()
+        .unwrap()
+        .create(
+            "uncached",
+            VfsNodeType::File,
+            VfsNodePerm::from_bits_truncate(0o644),
+            None,
+        )
+        .unwrap();
+    assert_eq!(resolve(cached, "uncached"), Err(VfsError::EAGAIN));
+    assert!(resolve(VfsResolveFlags::empty(), "uncached").is_ok());
+}
+
+#[test]
+fn test_statx() {
+    let root = make_ramfs().unwrap();
+    let path = VfsPath::new(root.clone(), root.clone());
+    let dir = VfsInodeMode::DIR | VfsInodeMode::from_bits_truncate(0o755);
+    let file = VfsInodeMode::FILE | VfsInodeMode::from_bits_truncate(0o644);
+    path.join("mnt").unwrap().open(Some(dir)).unwrap();
+    let ns = make_namespace(&root);
+    let path = path.with_namespace(ns.clone());
+    let mnt = path.join("mnt").unwrap();
+    let id =
+        ns.0.lock()
+            .mount(
+                &mnt,
+                "tmp",
+                "ramfs",
+                make_ramfs().unwrap(),
+                VfsMountFlags::empty(),
+            )
+            .unwrap();
+    let f = path.join("mnt/f").unwrap();
+    f.open(Some(file)).unwrap();
+    path.join("mnt/link").unwrap().symlink("f").unwrap();
+
+    let mask = VfsStatxMask::STATX_BASIC_STATS | VfsStatxMask::STATX_BTIME;
+    let stx = ns.0.lock().statx(&f, mask, true).unwrap();
+    assert!(stx.stx_mask.contains(mask | VfsStatxMask::STATX_MNT_ID));
+    assert_eq!(stx.stx_mnt_id, id as u64);
+    assert_eq!(stx.stx_mode, file.bits());
+    assert!(stx
+        .stx_attributes_mask
+        .contains(VfsStatxAttr::STATX_ATTR_IMMUTABLE | VfsStatxAttr::STATX_ATTR_MOUNT_ROOT));
+    assert_eq!(stx.stx_attributes, VfsStatxAttr::empty());
+    // the roots of the mounts
+    let stx = ns.0.lock().statx(&mnt, mask, true).unwrap();
+    assert_eq!(stx.stx_mnt_id, id as u64);
+    assert_eq!(stx.stx_attributes, VfsStatxAttr::STATX_ATTR_MOUNT_ROOT);
+    let stx = ns.0.lock().statx(&path, mask, true).unwrap();
+    assert_eq!(stx.stx_mnt_id, 0);
+    assert_eq!(stx.stx_attributes, VfsStatxAttr::STATX_ATTR_MOUNT_ROOT);
+
+    // the symlink itself
+    let link = path.join("mnt/link").unwrap();
+    let stx = link.statx(mask, false).unwrap();
+    assert_eq!(
+        VfsInodeMode::from_bits_truncate(stx.stx_mode) & VfsInodeMode::TYPE_MASK,
+        VfsInodeMode::LINK
+    );
+    assert_eq!(link.statx(mask, true).unwrap().stx_mode, file.bits());
+
+    // the unsupported inode flags are dropped
+    let inode = f.open(None).unwrap().inode().unwrap();
+    inode
+        .set_attr(InodeAttr {
+            valid: InodeAttrValid::FLAGS,
+            flags: VfsStatxAttr::STATX_ATTR_APPEND | VfsStatxAttr::STATX_ATTR_COMPRESSED,
+            ..Default::default()
+        })
+        .unwrap();
+    assert_eq!(
+        f.statx(mask, true).unwrap().stx_attributes,
+        VfsStatxAttr::STATX_ATTR_APPEND
+    );
+}
+
+#[test]
+fn test_inode_flags() {
+    let root = make_ramfs().unwrap();
+    let path = VfsPath::new(root.clone(), root.clone());
+    let dir = VfsInodeMode::DIR | VfsInodeMode::from_bits_truncate(0o755);
+    let file = VfsInodeMode::FILE | VfsInodeMode::from_bits_truncate(0o644);
+    path.join("flags").unwrap().open(Some(dir)).unwrap();
+    let f = path.join("flags/f").unwrap();
+    let dentry = f.open(Some(file)).unwrap();
+    let inode = dentry.inode().unwrap();
+    let set_flags = |inode: &Arc<dyn VfsInode>, flags: VfsStatxAttr| {
+        inode
+            .set_attr(InodeAttr {
+                valid: InodeAttrValid::FLAGS,
+                flags,
+                ..Default::default()
+            })
+            .unwrap()
+    };
+    let context = || SysContext {
+        pid: 1,
+        uid: 0,
+        gid: 0,
+        cwd: root.clone(),
+        root: root.clone(),
+    };
+    let opened = VfsOpenFile::<Mutex<()>>::new(dentry.clone(), VfsOpenFlags::O_RDWR).unwrap();
+    let appending = VfsOpenFile::<Mutex<()>>::new(
+        dentry.clone(),
+        VfsOpenFlags::O_RDWR | VfsOpenFlags::O_APPEND,
+    )
+    .unwrap();
+
+    // the append-only file can only be appended to
+    set_flags(&inode, VfsStatxAttr::STATX_ATTR_APPEND);
+    assert_eq!(opened.write(b"a"), Err(VfsError::PermissionDenied));
+    assert_eq!(opened.pwrite(0, b"a"), Err(VfsError::PermissionDenied));
+    assert_eq!(appending.write(b"ab"), Ok(2));
+    assert_eq!(
+        f.open_with_flags(None, VfsOpenFlags::O_WRONLY).err(),
+        Some(VfsError::PermissionDenied)
+    );
+    assert_eq!(
+        f.open_with_flags(
+            None,
+            VfsOpenFlags::O_WRONLY | VfsOpenFlags::O_APPEND | VfsOpenFlags::O_TRUNC
+        )
+        .err(),
+        Some(VfsError::PermissionDenied)
+    );
+    f.open_with_flags(None, VfsOpenFlags::O_WRONLY | VfsOpenFlags::O_APPEND)
+        .unwrap();
+    assert_eq!(f.truncate(0), Err(VfsError::PermissionDenied));
+    assert_eq!(f.unlink(), Err(VfsError::PermissionDenied));
+    assert_eq!(
+        path.join("flags/g").unwrap().link(dentry.clone()),
+        Err(VfsError::PermissionDenied)
+    );
+    assert_eq!(
+        f.rename_to(
+            context(),
+            path.join("flags/g").unwrap(),
+            VfsRenameFlag::empty()
+        ),
+        Err(VfsError::PermissionDenied)
+    );
+    let perm = VfsInodeMode::from_bits_truncate(0o600);
+    assert_eq!(f.chmod(perm), Err(VfsError::PermissionDenied));
+    assert_eq!(f.chown(Some(1), None), Err(VfsError::PermissionDenied));
+    assert_eq!(
+        f.set_xattr("user.a", b"1", VfsXattrFlag::empty()),
+        Err(VfsError::PermissionDenied)
+    );
+    assert_eq!(f.remove_xattr("user.a"), Err(VfsError::PermissionDenied));
+
+    // the immutable file can't be changed at all
+    set_flags(&inode, VfsStatxAttr::STATX_ATTR_IMMUTABLE);
+    assert_eq!(appending.write(b"c"), Err(VfsError::PermissionDenied));
+    assert_eq!(
+        f.open_with_flags(None, VfsOpenFlags::O_WRONLY | VfsOpenFlags::O_APPEND)
+            .err(),
+        Some(VfsError::PermissionDenied)
+    );
+    f.open_with_flags(None, VfsOpenFlags::O_RDONLY).unwrap();
+    assert_eq!(f.unlink(), Err(VfsError::PermissionDenied));
+    assert_eq!(f.chmod(perm), Err(VfsError::PermissionDenied));
+    assert_eq!(
+        f.set_xattr("user.a", b"1", VfsXattrFlag::empty()),
+        Err(VfsError::PermissionDenied)
+    );
+    set_flags(&inode, VfsStatxAttr::empty());
+    assert_eq!(opened.pwrite(2, b"c"), Ok(1));
+    assert_eq!(inode.get_attr().unwrap().st_size, 3);
+
+    // only the owner can change the extended attributes
+    f.set_xattr("user.a", b"1", VfsXattrFlag::empty()).unwrap();
+    let user = f.with_cred(VfsCred {
+        uid: 1000,
+        gid: 1000,
+    });
+    assert_eq!(
+        user.set_xattr("user.a", b"2", VfsXattrFlag::empty()),
+        Err(VfsError::PermissionDenied)
+    );
+    assert_eq!(user.remove_xattr("user.a"), Err(VfsError::PermissionDenied));
+    assert_eq!(user.get_xattr("user.a").unwrap(), b"1");
+    f.remove_xattr("user.a").unwrap();
+
+    // entries can be added to an append-only directory but not removed from it
+    let dir_inode = path
+        .join("flags")
+        .unwrap()
+        .open(None)
+        .unwrap()
+        .inode()
+        .unwrap();
+    set_flags(&dir_inode, VfsStatxAttr::STATX_ATTR_APPEND);
+    path.join("flags/h").unwrap().open(Some(file)).unwrap();
+    assert_eq!(
+        path.join("flags/h").unwrap().unlink(),
+        Err(VfsError::PermissionDenied)
+    );
+    assert_eq!(
+        f.rename_to(
+            context(),
+            path.join("flags/h").unwrap(),
+            VfsRenameFlag::empty()
+        ),
+        Err(VfsError::PermissionDenied)
+    );
+    // nothing can be created in an immutable directory
+    set_flags(&dir_inode, VfsStatxAttr::STATX_ATTR_IMMUTABLE);
+    assert_eq!(
+        path.join("flags/i").unwrap().open(Some(file)).err(),
+        Some(VfsError::PermissionDenied)
+    );
+    assert_eq!(
+        path.join("flags/i").unwrap().symlink("f"),
+        Err(VfsError::PermissionDenied)
+    );
+    set_flags(&dir_inode, VfsStatxAttr::empty());
+    f.unlink().unwrap();
+}
+
 #[test]
 fn test_link() {}
 